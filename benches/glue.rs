@@ -0,0 +1,152 @@
+//! Benchmarks of the FFI and async glue around libtailscale connections.
+//!
+//! Each group measures a tailnet connection next to a loopback
+//! `TcpStream` baseline, so a regression shows up as the gap between the
+//! two growing:
+//!
+//! - `throughput`: writing a chunk on one end and reading it on the other.
+//! - `connect`: dialing and accepting a fresh connection. `accept_glue`
+//!   times only what `Listener::accept` spends setting up a connection once
+//!   one is ready, read from `Metrics::total_accept_time`.
+//!
+//! The tailnet benchmarks start a [`TestNet`] and are skipped unless
+//! `TAILSCALE_TEST_AUTH_KEY` is set:
+//!
+//! ```bash
+//! TAILSCALE_TEST_AUTH_KEY=tskey-... cargo bench --bench glue
+//! ```
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use tailscale2::{NetworkType, testing::TestNet};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    runtime::Runtime,
+};
+
+/// Bytes moved per iteration of the throughput benchmarks.
+const CHUNK: usize = 64 * 1024;
+const THROUGHPUT_PORT: u16 = 7001;
+const CONNECT_PORT: u16 = 7002;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build runtime")
+}
+
+/// Writes `iters` chunks from `client` to `server`, returning how long
+/// reading them back took.
+async fn transfer<C, S>(client: &mut C, server: &mut S, iters: u64) -> Duration
+where
+    C: AsyncWrite + Unpin,
+    S: AsyncRead + Unpin,
+{
+    let out = vec![0x5a; CHUNK];
+    let mut buf = vec![0; CHUNK];
+    let start = Instant::now();
+    for _ in 0..iters {
+        let (written, read) = tokio::join!(client.write_all(&out), server.read_exact(&mut buf));
+        written.expect("write failed");
+        read.expect("read failed");
+    }
+    start.elapsed()
+}
+
+async fn tcp_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    (client.unwrap(), server.unwrap().0)
+}
+
+fn throughput(c: &mut Criterion, rt: &Runtime, net: Option<&TestNet>) {
+    let mut group = c.benchmark_group("throughput");
+    group.throughput(Throughput::Bytes(CHUNK as u64));
+
+    let (mut client, mut server) = rt.block_on(tcp_pair());
+    group.bench_function("tcp", |b| {
+        b.iter_custom(|iters| rt.block_on(transfer(&mut client, &mut server, iters)))
+    });
+
+    if let Some(net) = net {
+        let (mut client, mut server) = rt.block_on(net.connect(THROUGHPUT_PORT)).unwrap();
+        group.bench_function("tailscale", |b| {
+            b.iter_custom(|iters| rt.block_on(transfer(&mut client, &mut server, iters)))
+        });
+    }
+    group.finish();
+}
+
+fn connect(c: &mut Criterion, rt: &Runtime, net: Option<&TestNet>) {
+    let mut group = c.benchmark_group("connect");
+
+    let listener = rt
+        .block_on(TcpListener::bind((Ipv4Addr::LOCALHOST, 0)))
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    group.bench_function("tcp", |b| {
+        b.to_async(rt).iter(|| async {
+            let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+            (client.unwrap(), server.unwrap())
+        })
+    });
+
+    if let Some(net) = net {
+        let (first, second) = (net.first(), net.second());
+        let listener = rt
+            .block_on(second.listener(NetworkType::Tcp, CONNECT_PORT))
+            .unwrap();
+        let ip = second.ips().unwrap().expect("second node has no IP").ipv4;
+        let addr = SocketAddr::from((ip, CONNECT_PORT));
+        group.bench_function("tailscale", |b| {
+            b.to_async(rt).iter(|| async {
+                let (client, server) =
+                    tokio::join!(first.connect(NetworkType::Tcp, addr), listener.accept());
+                (client.unwrap(), server.unwrap())
+            })
+        });
+
+        group.bench_function("accept_glue", |b| {
+            b.iter_custom(|iters| {
+                rt.block_on(async {
+                    let before = second.metrics().total_accept_time;
+                    for _ in 0..iters {
+                        let (client, server) =
+                            tokio::join!(first.connect(NetworkType::Tcp, addr), listener.accept());
+                        drop((client.unwrap(), server.unwrap()));
+                    }
+                    second.metrics().total_accept_time - before
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    let rt = runtime();
+    let net = rt
+        .block_on(TestNet::from_env())
+        .expect("failed to start test network");
+    if net.is_none() {
+        eprintln!("TAILSCALE_TEST_AUTH_KEY not set, only running the TcpStream baselines");
+    }
+
+    throughput(c, &rt, net.as_ref());
+    connect(c, &rt, net.as_ref());
+
+    if let Some(net) = net {
+        rt.block_on(net.close())
+            .expect("failed to close test network");
+    }
+}
+
+criterion_group!(glue, benches);
+criterion_main!(glue);
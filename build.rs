@@ -1,6 +1,17 @@
 fn main() {
-    println!("cargo:rustc-link-search=.");
-    println!("cargo:rustc-link-lib=static=tailscale2");
+    #[cfg(feature = "bindgen")]
+    bindings::generate();
+
+    // With dlopen the library is loaded at runtime, so nothing is linked.
+    if cfg!(feature = "dlopen") {
+        return;
+    }
+
+    #[cfg(feature = "vendored")]
+    vendored::build();
+
+    #[cfg(not(feature = "vendored"))]
+    prebuilt::link();
 
     if cfg!(target_os = "macos") {
         println!("cargo:rustc-link-lib=framework=CoreFoundation");
@@ -8,3 +19,133 @@ fn main() {
         println!("cargo:rustc-link-lib=framework=Security");
     }
 }
+
+/// Generates the FFI declarations in `src/sys.rs` from `tailscale.h`.
+#[cfg(feature = "bindgen")]
+mod bindings {
+    use std::{env, path::PathBuf};
+
+    pub fn generate() {
+        let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        bindgen::Builder::default()
+            .header("tailscale.h")
+            .allowlist_function("tailscale_.*")
+            .ctypes_prefix("libc")
+            // The hand-written declarations in src/sys.rs carry the docs.
+            .generate_comments(false)
+            .rust_edition(bindgen::RustEdition::Edition2024)
+            .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+            .generate()
+            .expect("generating bindings from tailscale.h failed, is libclang installed?")
+            .write_to_file(out_dir.join("bindings.rs"))
+            .expect("writing bindings failed");
+    }
+}
+
+/// Finds a prebuilt `libtailscale2`, in order of preference:
+///
+/// 1. In `LIBTAILSCALE_LIB_DIR`, if set.
+/// 2. Through pkg-config, as the `tailscale2` package.
+/// 3. In the current directory, where `make build` puts it.
+///
+/// The library is linked statically unless `LIBTAILSCALE_STATIC` is `0`.
+#[cfg(not(feature = "vendored"))]
+mod prebuilt {
+    use std::env;
+
+    pub fn link() {
+        println!("cargo:rerun-if-env-changed=LIBTAILSCALE_LIB_DIR");
+        println!("cargo:rerun-if-env-changed=LIBTAILSCALE_STATIC");
+        let statik = env::var("LIBTAILSCALE_STATIC").map_or(true, |v| v != "0");
+        let kind = if statik { "static" } else { "dylib" };
+
+        if let Some(dir) = env::var_os("LIBTAILSCALE_LIB_DIR") {
+            println!("cargo:rustc-link-search=native={}", dir.to_string_lossy());
+            println!("cargo:rustc-link-lib={kind}=tailscale2");
+            return;
+        }
+
+        let probed = pkg_config::Config::new()
+            .statik(statik)
+            .cargo_metadata(true)
+            .probe("tailscale2");
+        if probed.is_ok() {
+            return;
+        }
+
+        println!("cargo:rustc-link-search=.");
+        println!("cargo:rustc-link-lib={kind}=tailscale2");
+    }
+}
+
+/// Builds libtailscale from the Go and C sources shipped with the crate, so
+/// no prebuilt `libtailscale2.a` is needed.
+#[cfg(feature = "vendored")]
+mod vendored {
+    use std::{env, path::PathBuf, process::Command};
+
+    pub fn build() {
+        let src_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+        let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        for file in [
+            "tailscale.go",
+            "tailscale.c",
+            "tailscale.h",
+            "go.mod",
+            "go.sum",
+        ] {
+            println!("cargo:rerun-if-changed={file}");
+        }
+        println!("cargo:rerun-if-env-changed=GO");
+
+        // The C wrappers call into the Go archive, so they are linked first.
+        let mut build = cc::Build::new();
+        build
+            .file(src_dir.join("tailscale.c"))
+            .include(&src_dir)
+            .opt_level(2);
+        build.compile("tailscale2");
+
+        let go = env::var_os("GO").unwrap_or_else(|| "go".into());
+        let mut cmd = Command::new(&go);
+        cmd.current_dir(&src_dir)
+            .args(["build", "-buildmode=c-archive", "-o"])
+            .arg(out_dir.join("libtailscale.a"))
+            .env("CGO_ENABLED", "1")
+            // cgo must use the same C compiler as the target, e.g. when cross compiling.
+            .env("CC", build.get_compiler().path());
+        if let Some(goarch) = goarch() {
+            cmd.env("GOARCH", goarch);
+        }
+        if let Ok(os) = env::var("CARGO_CFG_TARGET_OS") {
+            cmd.env("GOOS", if os == "macos" { "darwin" } else { &os });
+        }
+
+        let status = cmd.status().unwrap_or_else(|e| {
+            panic!(
+                "the vendored feature needs Go to build libtailscale, failed to run {}: {e}",
+                go.to_string_lossy()
+            )
+        });
+        if !status.success() {
+            panic!("go build of libtailscale failed: {status}");
+        }
+
+        println!("cargo:rustc-link-search=native={}", out_dir.display());
+        println!("cargo:rustc-link-lib=static=tailscale");
+    }
+
+    /// Maps the Rust target architecture to Go's name for it.
+    fn goarch() -> Option<&'static str> {
+        let arch = match env::var("CARGO_CFG_TARGET_ARCH").ok()?.as_str() {
+            "x86_64" => "amd64",
+            "x86" => "386",
+            "aarch64" => "arm64",
+            "arm" => "arm",
+            "riscv64" => "riscv64",
+            "s390x" => "s390x",
+            _ => return None,
+        };
+        Some(arch)
+    }
+}
@@ -15,9 +15,9 @@ async fn main() {
     let ts = Tailscale::builder()
         .ephemeral(true)
         .hostname("foo")
-        .build()
+        .build_and_up()
+        .await
         .unwrap();
-    ts.up().await.unwrap();
 
     let mut conn = ts.connect(NetworkType::Tcp, "mm:8000").await.unwrap();
     info!("connection established");
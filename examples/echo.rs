@@ -30,15 +30,15 @@ async fn main() {
     let ts = Tailscale::builder()
         .ephemeral(true)
         .hostname("foo")
-        .build()
+        .build_and_up()
+        .await
         .unwrap();
-    ts.up().await.unwrap();
 
     let listener = ts.listener(NetworkType::Tcp, ":1999").await.unwrap();
     info!("listening for connections");
     loop {
         let conn = listener.accept().await.unwrap();
-        if let Some(addr) = conn.remote_addr().unwrap() {
+        if let Ok(addr) = conn.remote_addr() {
             info!("got connection from {}", addr);
         }
         // Spawn a new task to handle this connection concurrently
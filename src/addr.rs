@@ -0,0 +1,418 @@
+//! Typed addresses accepted by [`Tailscale::listener`](crate::Tailscale::listener)
+//! and [`Tailscale::connect`](crate::Tailscale::connect).
+//!
+//! Both take anything convertible into an address, in the spirit of
+//! [`std::net::ToSocketAddrs`]: a port, a [`SocketAddr`], an `(ip, port)` or
+//! `(host, port)` tuple, or a string such as `":8080"` or `"host:443"`.
+//! Addresses are validated before they reach libtailscale, so a malformed one
+//! fails with [`TailscaleError::MalformedAddress`] naming the problem.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use tailscale2::*;
+//! # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+//! let listener = ts.listener(NetworkType::Tcp, 8080).await?;
+//! let conn = ts.connect(NetworkType::Tcp, ("other-node", 22)).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+};
+
+use crate::tailscale::{Result, TailscaleError};
+
+/// An address to listen on: a port, optionally restricted to one of this
+/// node's tailnet IP addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenAddr {
+    ip: Option<IpAddr>,
+    port: u16,
+}
+
+impl ListenAddr {
+    /// Creates an address listening on `port` on `ip`, or on all of this
+    /// node's addresses if `ip` is `None`.
+    pub fn new(ip: Option<IpAddr>, port: u16) -> Self {
+        ListenAddr { ip, port }
+    }
+
+    /// Returns the IP address listened on, or `None` for all of them.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.ip
+    }
+
+    /// Returns the port listened on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip {
+            None => write!(f, ":{}", self.port),
+            Some(ip) => SocketAddr::new(ip, self.port).fmt(f),
+        }
+    }
+}
+
+impl From<u16> for ListenAddr {
+    fn from(port: u16) -> Self {
+        ListenAddr::new(None, port)
+    }
+}
+
+impl From<SocketAddr> for ListenAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ListenAddr {
+            ip: Some(addr.ip()),
+            port: addr.port(),
+        }
+    }
+}
+
+/// Types that can be turned into a [`ListenAddr`].
+///
+/// Strings are of the form `":port"`, `"ip:port"` or `"[ipv6]:port"`; tsnet
+/// can only listen on this node's own addresses, so host names are rejected.
+pub trait ToListenAddr {
+    /// Converts `self` into a validated listen address.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if `self` is not a valid
+    /// listen address.
+    fn to_listen_addr(&self) -> Result<ListenAddr>;
+}
+
+impl ToListenAddr for ListenAddr {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        Ok(*self)
+    }
+}
+
+impl ToListenAddr for u16 {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        Ok(ListenAddr::new(None, *self))
+    }
+}
+
+impl ToListenAddr for SocketAddr {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        Ok(ListenAddr::from(*self))
+    }
+}
+
+impl ToListenAddr for SocketAddrV4 {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        Ok(ListenAddr::from(SocketAddr::V4(*self)))
+    }
+}
+
+impl ToListenAddr for SocketAddrV6 {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        Ok(ListenAddr::from(SocketAddr::V6(*self)))
+    }
+}
+
+impl<I: Into<IpAddr> + Copy> ToListenAddr for (I, u16) {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        Ok(ListenAddr {
+            ip: Some(self.0.into()),
+            port: self.1,
+        })
+    }
+}
+
+impl ToListenAddr for str {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        let (host, port) = split_host_port(self)?;
+        let ip = match host {
+            "" => None,
+            host => Some(host.parse().map_err(|_| {
+                malformed(self, "host must be empty or an IP address of this node")
+            })?),
+        };
+        Ok(ListenAddr { ip, port })
+    }
+}
+
+impl ToListenAddr for String {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        self.as_str().to_listen_addr()
+    }
+}
+
+impl<T: ToListenAddr + ?Sized> ToListenAddr for &T {
+    fn to_listen_addr(&self) -> Result<ListenAddr> {
+        (**self).to_listen_addr()
+    }
+}
+
+/// An address to connect to: a host name or IP address and a port.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DialAddr {
+    host: String,
+    port: u16,
+}
+
+impl DialAddr {
+    /// Returns the host name or IP address.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Returns the port.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl fmt::Display for DialAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl From<SocketAddr> for DialAddr {
+    fn from(addr: SocketAddr) -> Self {
+        DialAddr {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+        }
+    }
+}
+
+/// Types that can be turned into a [`DialAddr`].
+///
+/// Strings are of the form `"host:port"`, `"ip:port"` or `"[ipv6]:port"`,
+/// where the host may be a MagicDNS name.
+pub trait ToDialAddr {
+    /// Converts `self` into a validated dial address.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if `self` is not a valid
+    /// dial address.
+    fn to_dial_addr(&self) -> Result<DialAddr>;
+}
+
+impl ToDialAddr for DialAddr {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        Ok(self.clone())
+    }
+}
+
+impl ToDialAddr for SocketAddr {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        dial_addr(&self.to_string(), &self.ip().to_string(), self.port())
+    }
+}
+
+impl ToDialAddr for SocketAddrV4 {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        SocketAddr::V4(*self).to_dial_addr()
+    }
+}
+
+impl ToDialAddr for SocketAddrV6 {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        SocketAddr::V6(*self).to_dial_addr()
+    }
+}
+
+impl ToDialAddr for (IpAddr, u16) {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        SocketAddr::from(*self).to_dial_addr()
+    }
+}
+
+impl ToDialAddr for (Ipv4Addr, u16) {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        SocketAddr::from(*self).to_dial_addr()
+    }
+}
+
+impl ToDialAddr for (Ipv6Addr, u16) {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        SocketAddr::from(*self).to_dial_addr()
+    }
+}
+
+impl ToDialAddr for (&str, u16) {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        let (host, port) = *self;
+        dial_addr(&format!("{host}:{port}"), host, port)
+    }
+}
+
+impl ToDialAddr for (String, u16) {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        (self.0.as_str(), self.1).to_dial_addr()
+    }
+}
+
+impl ToDialAddr for str {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        let (host, port) = split_host_port(self)?;
+        dial_addr(self, host, port)
+    }
+}
+
+impl ToDialAddr for String {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        self.as_str().to_dial_addr()
+    }
+}
+
+impl<T: ToDialAddr + ?Sized> ToDialAddr for &T {
+    fn to_dial_addr(&self) -> Result<DialAddr> {
+        (**self).to_dial_addr()
+    }
+}
+
+/// Splits `host:port` or `[v6]:port`, with an empty host allowed.
+fn split_host_port(addr: &str) -> Result<(&str, u16)> {
+    let (host, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| malformed(addr, "missing port"))?;
+    let host = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed
+            .strip_suffix(']')
+            .ok_or_else(|| malformed(addr, "unclosed '['"))?,
+        None if host.contains(':') => {
+            return Err(malformed(addr, "IPv6 addresses must be in brackets"));
+        }
+        None => host,
+    };
+    let port = port.parse().map_err(|_| malformed(addr, "invalid port"))?;
+    Ok((host, port))
+}
+
+fn dial_addr(addr: &str, host: &str, port: u16) -> Result<DialAddr> {
+    if host.is_empty() {
+        return Err(malformed(addr, "missing host"));
+    }
+    if host
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '/' | '[' | ']'))
+    {
+        return Err(malformed(addr, "invalid host"));
+    }
+    if port == 0 {
+        return Err(malformed(addr, "port must not be 0"));
+    }
+    Ok(DialAddr {
+        host: host.to_string(),
+        port,
+    })
+}
+
+fn malformed(addr: &str, reason: &'static str) -> TailscaleError {
+    TailscaleError::MalformedAddress {
+        addr: addr.to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(res: Result<impl fmt::Debug>) -> &'static str {
+        match res {
+            Err(TailscaleError::MalformedAddress { reason, .. }) => reason,
+            other => panic!("expected MalformedAddress, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn split_host_port_forms() {
+        assert_eq!(split_host_port("host:80").unwrap(), ("host", 80));
+        assert_eq!(split_host_port(":8080").unwrap(), ("", 8080));
+        assert_eq!(split_host_port("[fd7a::1]:443").unwrap(), ("fd7a::1", 443));
+        assert_eq!(split_host_port("[]:1").unwrap(), ("", 1));
+    }
+
+    #[test]
+    fn split_host_port_errors() {
+        assert_eq!(reason(split_host_port("host")), "missing port");
+        assert_eq!(reason(split_host_port("host:")), "invalid port");
+        assert_eq!(reason(split_host_port("host:http")), "invalid port");
+        assert_eq!(reason(split_host_port("host:65536")), "invalid port");
+        assert_eq!(reason(split_host_port("[fd7a::1:443")), "unclosed '['");
+        assert_eq!(
+            reason(split_host_port("fd7a::1:443")),
+            "IPv6 addresses must be in brackets"
+        );
+    }
+
+    #[test]
+    fn listen_addr_from_str() {
+        let addr = ":8080".to_listen_addr().unwrap();
+        assert_eq!((addr.ip(), addr.port()), (None, 8080));
+        assert_eq!(addr.to_string(), ":8080");
+
+        let addr = "100.64.0.1:80".to_listen_addr().unwrap();
+        assert_eq!(addr.ip(), Some(IpAddr::V4(Ipv4Addr::new(100, 64, 0, 1))));
+        assert_eq!(addr.to_string(), "100.64.0.1:80");
+
+        let addr = "[fd7a:115c:a1e0::1]:80".to_listen_addr().unwrap();
+        assert_eq!(addr.to_string(), "[fd7a:115c:a1e0::1]:80");
+    }
+
+    #[test]
+    fn listen_addr_rejects_host_names() {
+        assert_eq!(
+            reason("node:80".to_listen_addr()),
+            "host must be empty or an IP address of this node"
+        );
+        assert_eq!(reason("80".to_listen_addr()), "missing port");
+    }
+
+    #[test]
+    fn listen_addr_from_other_types() {
+        assert_eq!(8080.to_listen_addr().unwrap(), ListenAddr::new(None, 8080));
+        let ip = Ipv4Addr::new(100, 64, 0, 1);
+        assert_eq!(
+            (ip, 80).to_listen_addr().unwrap(),
+            ListenAddr::new(Some(ip.into()), 80)
+        );
+    }
+
+    #[test]
+    fn dial_addr_from_str() {
+        let addr = "node:22".to_dial_addr().unwrap();
+        assert_eq!((addr.host(), addr.port()), ("node", 22));
+        assert_eq!(addr.to_string(), "node:22");
+
+        let addr = "[fd7a::1]:22".to_dial_addr().unwrap();
+        assert_eq!(addr.host(), "fd7a::1");
+        assert_eq!(addr.to_string(), "[fd7a::1]:22");
+    }
+
+    #[test]
+    fn dial_addr_errors() {
+        assert_eq!(reason(":22".to_dial_addr()), "missing host");
+        assert_eq!(reason("node:0".to_dial_addr()), "port must not be 0");
+        assert_eq!(reason("no de:22".to_dial_addr()), "invalid host");
+        assert_eq!(reason(("a/b", 22).to_dial_addr()), "invalid host");
+        assert_eq!(reason("node".to_dial_addr()), "missing port");
+    }
+
+    #[test]
+    fn dial_addr_from_socket_addr() {
+        let addr: SocketAddr = "[fd7a::1]:22".parse().unwrap();
+        assert_eq!(addr.to_dial_addr().unwrap().to_string(), "[fd7a::1]:22");
+        let addr = (Ipv4Addr::new(100, 64, 0, 1), 22).to_dial_addr().unwrap();
+        assert_eq!(addr.to_string(), "100.64.0.1:22");
+    }
+}
@@ -0,0 +1,58 @@
+//! A typed wrapper for Tailscale authentication keys.
+
+use std::fmt;
+
+/// A Tailscale authentication key.
+///
+/// The key is redacted from `Debug` output so it cannot leak into logs by
+/// accident. With the `zeroize` feature enabled, the memory holding the key is
+/// wiped when it is dropped.
+///
+/// # Examples
+///
+/// ```
+/// # use tailscale2::AuthKey;
+/// let key = AuthKey::new("tskey-auth-secret");
+/// assert_eq!(format!("{:?}", key), "AuthKey(<redacted>)");
+/// assert_eq!(key.expose_secret(), "tskey-auth-secret");
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(transparent))]
+pub struct AuthKey(String);
+
+impl AuthKey {
+    /// Wraps an authentication key.
+    pub fn new(key: impl Into<String>) -> Self {
+        AuthKey(key.into())
+    }
+
+    /// Returns the key itself. Take care not to log the result.
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for AuthKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthKey(<redacted>)")
+    }
+}
+
+impl From<String> for AuthKey {
+    fn from(key: String) -> Self {
+        AuthKey(key)
+    }
+}
+
+impl From<&str> for AuthKey {
+    fn from(key: &str) -> Self {
+        AuthKey(key.to_string())
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for AuthKey {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
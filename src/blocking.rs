@@ -0,0 +1,709 @@
+//! Blocking counterparts of the async API, for applications without an async runtime.
+//!
+//! Each type wraps its async equivalent and drives it on a small
+//! current-thread tokio runtime owned by the [`Tailscale`] instance, so method
+//! names and arguments match the async API one to one.
+//!
+//! These types must not be used from within an async runtime; calling them
+//! from async code panics.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::io::{Read, Write};
+//! use tailscale2::{NetworkType, blocking};
+//!
+//! let ts = blocking::Tailscale::new(tailscale2::Tailscale::builder().hostname("my-host"))?;
+//! ts.up()?;
+//! let listener = ts.listener(NetworkType::Tcp, ":8080")?;
+//! let mut conn = listener.accept()?;
+//! let mut buf = [0; 1024];
+//! let n = conn.read(&mut buf)?;
+//! conn.write_all(&buf[..n])?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{Read, Write},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    runtime::Runtime,
+};
+
+use crate::{
+    addr::{ToDialAddr, ToListenAddr},
+    cert::CertPair,
+    dial_queue::DialQueueStats,
+    events::{IpnEvent, UpProgress},
+    health::Health,
+    identity::{Decision, PeerIdentity, PeerInfo, SelfInfo},
+    metrics::Metrics,
+    netmap::{DnsConfig, NetMap},
+    ping::{PingKind, PingResult},
+    preflight::PreflightReport,
+    status::Peer,
+    tags::TagStats,
+    tailscale::{
+        BackendState, IpPair, NetworkType, QuickState, Result, TailscaleBuilder, TailscaleError,
+    },
+};
+
+/// Blocking handle to a Tailscale instance. See [`crate::Tailscale`].
+pub struct Tailscale {
+    inner: Arc<crate::Tailscale>,
+    rt: Arc<Runtime>,
+}
+
+impl Tailscale {
+    /// Builds a Tailscale instance from the given builder.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime cannot be started or building fails.
+    pub fn new(builder: &mut TailscaleBuilder) -> Result<Self> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(TailscaleError::Runtime)?;
+        let inner = {
+            let _guard = rt.enter();
+            builder.build()?
+        };
+        Ok(Tailscale {
+            inner,
+            rt: Arc::new(rt),
+        })
+    }
+
+    /// Returns the async instance this handle drives.
+    pub fn as_async(&self) -> &Arc<crate::Tailscale> {
+        &self.inner
+    }
+
+    /// See [`crate::Tailscale::preflight`].
+    pub fn preflight(&self) -> Result<PreflightReport> {
+        self.rt.block_on(self.inner.preflight())
+    }
+
+    /// See [`crate::Tailscale::health`].
+    pub fn health(&self) -> Result<Health> {
+        self.rt.block_on(self.inner.health())
+    }
+
+    /// See [`crate::Tailscale::key_expiry`].
+    pub fn key_expiry(&self) -> Result<Option<SystemTime>> {
+        self.rt.block_on(self.inner.key_expiry())
+    }
+
+    /// See [`crate::Tailscale::key_expiring`].
+    pub fn key_expiring(&self, warn_before: Duration) -> Result<SystemTime> {
+        self.rt.block_on(self.inner.key_expiring(warn_before))
+    }
+
+    /// See [`crate::Tailscale::ping`].
+    pub fn ping(&self, peer: &str, kind: PingKind) -> Result<PingResult> {
+        self.rt.block_on(self.inner.ping(peer, kind))
+    }
+
+    /// See [`crate::Tailscale::ping_timeout`].
+    pub fn ping_timeout(
+        &self,
+        peer: &str,
+        kind: PingKind,
+        timeout: Duration,
+    ) -> Result<PingResult> {
+        self.rt
+            .block_on(self.inner.ping_timeout(peer, kind, timeout))
+    }
+
+    /// See [`crate::Tailscale::start`].
+    pub fn start(&self) -> Result<()> {
+        self.rt.block_on(self.inner.start())
+    }
+
+    /// See [`crate::Tailscale::up`].
+    pub fn up(&self) -> Result<()> {
+        self.rt.block_on(self.inner.up())
+    }
+
+    /// See [`crate::Tailscale::up_with_progress`].
+    pub fn up_with_progress(&self, on_progress: impl FnMut(UpProgress)) -> Result<()> {
+        self.rt.block_on(self.inner.up_with_progress(on_progress))
+    }
+
+    /// See [`crate::Tailscale::up_with_timeout`].
+    pub fn up_with_timeout(&self, timeout: Duration) -> Result<()> {
+        self.rt.block_on(self.inner.up_with_timeout(timeout))
+    }
+
+    /// See [`crate::Tailscale::listener`].
+    pub fn listener(&self, network: NetworkType, addr: impl ToListenAddr) -> Result<Listener> {
+        let inner = self.rt.block_on(self.inner.listener(network, addr))?;
+        Ok(Listener {
+            inner,
+            rt: Arc::clone(&self.rt),
+        })
+    }
+
+    /// See [`crate::Tailscale::listen_funnel`].
+    pub fn listen_funnel(&self, addr: impl ToListenAddr, funnel_only: bool) -> Result<Listener> {
+        let inner = self
+            .rt
+            .block_on(self.inner.listen_funnel(addr, funnel_only))?;
+        Ok(Listener {
+            inner,
+            rt: Arc::clone(&self.rt),
+        })
+    }
+
+    /// See [`crate::Tailscale::listen_tls`].
+    ///
+    /// The certificate is only refreshed while a blocking call on this
+    /// instance is in progress, which is enough for a server that keeps accepting.
+    #[cfg(feature = "tls")]
+    pub fn listen_tls(&self, addr: impl ToListenAddr) -> Result<TlsListener> {
+        let inner = self.rt.block_on(self.inner.listen_tls(addr))?;
+        Ok(TlsListener {
+            inner,
+            rt: Arc::clone(&self.rt),
+        })
+    }
+
+    /// See [`crate::Tailscale::listen_packet`].
+    pub fn listen_packet(&self, addr: impl ToListenAddr) -> Result<UdpSocket> {
+        let inner = self.rt.block_on(self.inner.listen_packet(addr))?;
+        Ok(UdpSocket {
+            inner,
+            rt: Arc::clone(&self.rt),
+        })
+    }
+
+    /// See [`crate::Tailscale::connect`].
+    pub fn connect(&self, network: NetworkType, addr: impl ToDialAddr) -> Result<Connection> {
+        let inner = self.rt.block_on(self.inner.connect(network, addr))?;
+        Ok(self.wrap(inner))
+    }
+
+    /// See [`crate::Tailscale::connect_happy_eyeballs`].
+    pub fn connect_happy_eyeballs(
+        &self,
+        network: NetworkType,
+        addr: impl ToDialAddr,
+    ) -> Result<Connection> {
+        let inner = self
+            .rt
+            .block_on(self.inner.connect_happy_eyeballs(network, addr))?;
+        Ok(self.wrap(inner))
+    }
+
+    /// See [`crate::Tailscale::connect_timeout`].
+    pub fn connect_timeout(
+        &self,
+        network: NetworkType,
+        addr: impl ToDialAddr,
+        timeout: Duration,
+    ) -> Result<Connection> {
+        let inner = self
+            .rt
+            .block_on(self.inner.connect_timeout(network, addr, timeout))?;
+        Ok(self.wrap(inner))
+    }
+
+    /// See [`crate::Tailscale::dial_queue_stats`].
+    pub fn dial_queue_stats(&self) -> DialQueueStats {
+        self.inner.dial_queue_stats()
+    }
+
+    /// See [`crate::Tailscale::metrics`].
+    pub fn metrics(&self) -> Metrics {
+        self.inner.metrics()
+    }
+
+    /// See [`crate::Tailscale::tag_stats`].
+    pub fn tag_stats(&self) -> HashMap<String, TagStats> {
+        self.inner.tag_stats()
+    }
+
+    /// See [`crate::Tailscale::ips`].
+    pub fn ips(&self) -> Result<Option<IpPair>> {
+        self.inner.ips()
+    }
+
+    /// See [`crate::Tailscale::state`].
+    pub fn state(&self) -> Result<BackendState> {
+        self.inner.state()
+    }
+
+    /// See [`crate::Tailscale::quick_state`].
+    pub fn quick_state(&self) -> Result<QuickState> {
+        self.inner.quick_state()
+    }
+
+    /// See [`crate::Tailscale::deregistered`].
+    pub fn deregistered(&self) -> Result<()> {
+        self.rt.block_on(self.inner.deregistered())
+    }
+
+    /// See [`crate::Tailscale::logout`].
+    pub fn logout(&self) -> Result<()> {
+        self.rt.block_on(self.inner.logout())
+    }
+
+    /// See [`crate::Tailscale::suspend`].
+    pub fn suspend(&self) -> Result<()> {
+        self.rt.block_on(self.inner.suspend())
+    }
+
+    /// See [`crate::Tailscale::resume`].
+    pub fn resume(&self) -> Result<()> {
+        self.rt.block_on(self.inner.resume())
+    }
+
+    /// See [`crate::Tailscale::advertise_routes`].
+    pub fn advertise_routes<I, S>(&self, routes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.rt.block_on(self.inner.advertise_routes(routes))
+    }
+
+    /// See [`crate::Tailscale::set_accept_routes`].
+    pub fn set_accept_routes(&self, accept: bool) -> Result<()> {
+        self.rt.block_on(self.inner.set_accept_routes(accept))
+    }
+
+    /// See [`crate::Tailscale::set_shields_up`].
+    pub fn set_shields_up(&self, shields_up: bool) -> Result<()> {
+        self.rt.block_on(self.inner.set_shields_up(shields_up))
+    }
+
+    /// See [`crate::Tailscale::self_info`].
+    pub fn self_info(&self) -> Result<SelfInfo> {
+        self.rt.block_on(self.inner.self_info())
+    }
+
+    /// See [`crate::Tailscale::peers`].
+    pub fn peers(&self) -> Result<Vec<Peer>> {
+        self.rt.block_on(self.inner.peers())
+    }
+
+    /// See [`crate::Tailscale::exit_nodes`].
+    pub fn exit_nodes(&self) -> Result<Vec<Peer>> {
+        self.rt.block_on(self.inner.exit_nodes())
+    }
+
+    /// See [`crate::Tailscale::use_exit_node`].
+    pub fn use_exit_node(&self, node: &str) -> Result<()> {
+        self.rt.block_on(self.inner.use_exit_node(node))
+    }
+
+    /// See [`crate::Tailscale::clear_exit_node`].
+    pub fn clear_exit_node(&self) -> Result<()> {
+        self.rt.block_on(self.inner.clear_exit_node())
+    }
+
+    /// See [`crate::Tailscale::events`].
+    pub fn events(&self) -> Result<Events> {
+        let inner = self.rt.block_on(self.inner.events())?;
+        Ok(Events {
+            inner,
+            rt: Arc::clone(&self.rt),
+        })
+    }
+
+    /// See [`crate::Tailscale::whois`].
+    pub fn whois(&self, addr: SocketAddr) -> Result<PeerIdentity> {
+        self.rt.block_on(self.inner.whois(addr))
+    }
+
+    /// See [`crate::Tailscale::cert_pair`].
+    pub fn cert_pair(&self, domain: &str) -> Result<CertPair> {
+        self.rt.block_on(self.inner.cert_pair(domain))
+    }
+
+    /// See [`crate::Tailscale::netmap`].
+    pub fn netmap(&self) -> Result<NetMap> {
+        self.rt.block_on(self.inner.netmap())
+    }
+
+    /// See [`crate::Tailscale::dns_config`].
+    pub fn dns_config(&self) -> Result<DnsConfig> {
+        self.rt.block_on(self.inner.dns_config())
+    }
+
+    /// See [`crate::Tailscale::add_to_drain_group`].
+    pub fn add_to_drain_group(&self, group: &str, conn: &mut Connection) {
+        self.inner.add_to_drain_group(group, &mut conn.inner)
+    }
+
+    /// See [`crate::Tailscale::drain_group`].
+    pub fn drain_group(&self, group: &str) -> usize {
+        self.rt.block_on(self.inner.drain_group(group))
+    }
+
+    /// See [`crate::Tailscale::close_group`].
+    pub fn close_group(&self, group: &str) -> usize {
+        self.rt.block_on(self.inner.close_group(group))
+    }
+
+    /// See [`crate::Tailscale::close`].
+    pub fn close(self) -> Result<()> {
+        self.rt.block_on(Arc::clone(&self.inner).close())
+    }
+
+    /// See [`crate::Tailscale::force_close`].
+    pub fn force_close(&self) {
+        self.inner.force_close()
+    }
+
+    fn wrap(&self, inner: crate::Connection) -> Connection {
+        Connection {
+            inner,
+            rt: Arc::clone(&self.rt),
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+}
+
+/// Blocking listener. See [`crate::Listener`].
+pub struct Listener {
+    inner: Arc<crate::Listener>,
+    rt: Arc<Runtime>,
+}
+
+impl Listener {
+    /// See [`crate::Listener::local_addr`].
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// See [`crate::Listener::accept`], including the
+    /// [`on_incoming`](TailscaleBuilder::on_incoming) identity check.
+    pub fn accept(&self) -> Result<Connection> {
+        let inner = self.rt.block_on(self.inner.accept())?;
+        Ok(self.wrap(inner))
+    }
+
+    /// See [`crate::Listener::accept_timeout`].
+    pub fn accept_timeout(&self, timeout: Duration) -> Result<Connection> {
+        let inner = self.rt.block_on(self.inner.accept_timeout(timeout))?;
+        Ok(self.wrap(inner))
+    }
+
+    /// See [`crate::Listener::with_filter`].
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&PeerInfo) -> Decision + Send + Sync + 'static,
+    {
+        self.inner = self.inner.with_filter(filter);
+        self
+    }
+
+    /// See [`crate::Listener::limit_connections`].
+    pub fn limit_connections(mut self, max: usize) -> Self {
+        self.inner = self.inner.limit_connections(max);
+        self
+    }
+
+    /// See [`crate::Listener::shutdown`].
+    pub fn shutdown(&self, grace: Duration) -> usize {
+        self.rt.block_on(self.inner.shutdown(grace))
+    }
+
+    /// Returns an iterator that accepts connections forever.
+    /// See [`crate::Listener::incoming`].
+    pub fn incoming(&self) -> impl Iterator<Item = Result<Connection>> + '_ {
+        std::iter::repeat_with(|| self.accept())
+    }
+
+    fn wrap(&self, inner: crate::Connection) -> Connection {
+        Connection {
+            inner,
+            rt: Arc::clone(&self.rt),
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+}
+
+/// Blocking iterator of node events. See [`crate::Events`].
+pub struct Events {
+    inner: crate::Events,
+    rt: Arc<Runtime>,
+}
+
+impl Iterator for Events {
+    type Item = Result<IpnEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rt.block_on(self.inner.next())
+    }
+}
+
+/// Blocking connection. See [`crate::Connection`].
+///
+/// Unlike the async type, [`Read`] and [`Write`] block until the operation
+/// can complete instead of returning [`WouldBlock`](std::io::ErrorKind::WouldBlock).
+pub struct Connection {
+    inner: crate::Connection,
+    rt: Arc<Runtime>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl Connection {
+    /// See [`crate::Connection::remote_addr`].
+    pub fn remote_addr(&self) -> Result<SocketAddr> {
+        self.inner.remote_addr()
+    }
+
+    /// See [`crate::Connection::local_addr`].
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// See [`crate::Connection::set_nodelay`].
+    pub fn set_nodelay(&self, nodelay: bool) -> std::io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    /// See [`crate::Connection::set_keepalive`].
+    pub fn set_keepalive(&self, keepalive: bool) -> std::io::Result<()> {
+        self.inner.set_keepalive(keepalive)
+    }
+
+    /// See [`crate::Connection::set_linger`].
+    pub fn set_linger(&self, linger: Option<Duration>) -> std::io::Result<()> {
+        self.inner.set_linger(linger)
+    }
+
+    /// Sets how long a read may block, or disables the timeout with `None`,
+    /// like [`std::net::TcpStream::set_read_timeout`].
+    ///
+    /// A read that times out fails with
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `timeout` is zero.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.read_timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Sets how long a write or flush may block, or disables the timeout
+    /// with `None`, like [`std::net::TcpStream::set_write_timeout`].
+    ///
+    /// A write that times out fails with
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `timeout` is zero.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.write_timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the read timeout, if any.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    /// Returns the write timeout, if any.
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    /// See [`crate::Connection::peek`].
+    pub fn peek(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.rt.block_on(self.inner.peek(buf))
+    }
+
+    /// See [`crate::Connection::try_clone`].
+    pub fn try_clone(&self) -> Result<Connection> {
+        let _guard = self.rt.enter();
+        Ok(Connection {
+            inner: self.inner.try_clone()?,
+            rt: Arc::clone(&self.rt),
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+        })
+    }
+
+    /// See [`crate::Connection::send_file`].
+    pub fn send_file(
+        &mut self,
+        file: &std::fs::File,
+        range: impl std::ops::RangeBounds<u64>,
+    ) -> std::io::Result<u64> {
+        self.rt.block_on(self.inner.send_file(file, range))
+    }
+
+    /// See [`crate::Connection::set_recv_buffer_size`].
+    pub fn set_recv_buffer_size(&self, size: usize) -> std::io::Result<()> {
+        self.inner.set_recv_buffer_size(size)
+    }
+
+    /// See [`crate::Connection::recv_buffer_size`].
+    pub fn recv_buffer_size(&self) -> std::io::Result<usize> {
+        self.inner.recv_buffer_size()
+    }
+
+    /// See [`crate::Connection::set_send_buffer_size`].
+    pub fn set_send_buffer_size(&self, size: usize) -> std::io::Result<()> {
+        self.inner.set_send_buffer_size(size)
+    }
+
+    /// See [`crate::Connection::send_buffer_size`].
+    pub fn send_buffer_size(&self) -> std::io::Result<usize> {
+        self.inner.send_buffer_size()
+    }
+
+    /// See [`crate::Connection::set_tag`].
+    pub fn set_tag(&mut self, tag: impl Into<String>) {
+        self.inner.set_tag(tag)
+    }
+
+    /// See [`crate::Connection::tag`].
+    pub fn tag(&self) -> Option<&str> {
+        self.inner.tag()
+    }
+
+    /// See [`crate::Connection::bytes_read`].
+    pub fn bytes_read(&self) -> u64 {
+        self.inner.bytes_read()
+    }
+
+    /// See [`crate::Connection::bytes_written`].
+    pub fn bytes_written(&self) -> u64 {
+        self.inner.bytes_written()
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = AsyncReadExt::read(&mut self.inner, buf);
+        self.rt.block_on(with_timeout(self.read_timeout, read))
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let write = AsyncWriteExt::write(&mut self.inner, buf);
+        self.rt.block_on(with_timeout(self.write_timeout, write))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let flush = AsyncWriteExt::flush(&mut self.inner);
+        self.rt.block_on(with_timeout(self.write_timeout, flush))
+    }
+}
+
+/// Rejects a zero timeout, as the standard library does.
+fn check_timeout(timeout: Option<Duration>) -> std::io::Result<Option<Duration>> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "cannot set a 0 duration timeout",
+        ));
+    }
+    Ok(timeout)
+}
+
+/// Runs `io`, failing with `TimedOut` if it takes longer than `timeout`.
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    io: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, io)
+            .await
+            .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into())),
+        None => io.await,
+    }
+}
+
+/// Blocking UDP socket. See [`crate::UdpSocket`].
+pub struct UdpSocket {
+    inner: crate::UdpSocket,
+    rt: Arc<Runtime>,
+}
+
+impl UdpSocket {
+    /// See [`crate::UdpSocket::local_addr`].
+    pub fn local_addr(&self) -> SocketAddr {
+        self.inner.local_addr()
+    }
+
+    /// See [`crate::UdpSocket::send_to`].
+    pub fn send_to(&self, buf: &[u8], target: SocketAddr) -> std::io::Result<usize> {
+        self.rt.block_on(self.inner.send_to(buf, target))
+    }
+
+    /// See [`crate::UdpSocket::recv_from`].
+    pub fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        self.rt.block_on(self.inner.recv_from(buf))
+    }
+}
+
+/// Blocking TLS listener. See [`crate::TlsListener`].
+#[cfg(feature = "tls")]
+pub struct TlsListener {
+    inner: crate::TlsListener,
+    rt: Arc<Runtime>,
+}
+
+#[cfg(feature = "tls")]
+impl TlsListener {
+    /// See [`crate::TlsListener::accept`].
+    pub fn accept(&self) -> Result<TlsConnection> {
+        let inner = self.rt.block_on(self.inner.accept())?;
+        Ok(TlsConnection {
+            inner,
+            rt: Arc::clone(&self.rt),
+        })
+    }
+}
+
+/// Blocking TLS connection. See [`crate::TlsConnection`].
+#[cfg(feature = "tls")]
+pub struct TlsConnection {
+    inner: crate::TlsConnection,
+    rt: Arc<Runtime>,
+}
+
+#[cfg(feature = "tls")]
+impl TlsConnection {
+    /// Returns the underlying connection.
+    pub fn get_ref(&self) -> &crate::Connection {
+        self.inner.get_ref().0
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Read for TlsConnection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.rt.block_on(AsyncReadExt::read(&mut self.inner, buf))
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Write for TlsConnection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.rt.block_on(AsyncWriteExt::write(&mut self.inner, buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.rt.block_on(AsyncWriteExt::flush(&mut self.inner))
+    }
+}
@@ -0,0 +1,64 @@
+//! TLS certificates issued to the node by Tailscale.
+
+use std::fmt;
+
+use crate::tailscale::{Result, TailscaleError};
+
+/// A TLS certificate chain and its private key, PEM encoded.
+///
+/// Returned by [`Tailscale::cert_pair`](crate::Tailscale::cert_pair). The
+/// private key is redacted from `Debug` output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CertPair {
+    cert_pem: String,
+    key_pem: String,
+}
+
+impl CertPair {
+    /// Splits the certificate chain followed by the private key, as written
+    /// by `tailscale_cert_pair`.
+    pub(crate) fn from_combined_pem(mut pem: String) -> Result<Self> {
+        let key_start = pem
+            .find("PRIVATE KEY-----")
+            .and_then(|label| pem[..label].rfind("-----BEGIN"))
+            .ok_or_else(|| TailscaleError::Tls("no private key returned".to_string()))?;
+        let key_pem = pem.split_off(key_start);
+        Ok(CertPair {
+            cert_pem: pem,
+            key_pem,
+        })
+    }
+
+    /// Returns the PEM encoded certificate chain, leaf first.
+    pub fn cert_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    /// Returns the PEM encoded private key. Take care not to log the result.
+    pub fn key_pem(&self) -> &str {
+        &self.key_pem
+    }
+
+    /// Converts the pair into a rustls [`CertifiedKey`](tokio_rustls::rustls::sign::CertifiedKey),
+    /// e.g. for a custom certificate resolver.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::Tls`] if the PEM cannot be parsed.
+    #[cfg(feature = "tls")]
+    pub fn certified_key(&self) -> Result<tokio_rustls::rustls::sign::CertifiedKey> {
+        crate::tls::certified_key(
+            &tokio_rustls::rustls::crypto::ring::default_provider(),
+            self,
+        )
+    }
+}
+
+impl fmt::Debug for CertPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertPair")
+            .field("cert_pem", &self.cert_pem)
+            .field("key_pem", &"<redacted>")
+            .finish()
+    }
+}
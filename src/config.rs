@@ -0,0 +1,158 @@
+//! Builder settings loaded from configuration files.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    auth_key::AuthKey,
+    tailscale::{AddressFamily, Result, TailscaleBuilder, TailscaleError},
+};
+
+/// Node settings as read from a configuration file.
+///
+/// Every field is optional and unknown fields are rejected, so typos are
+/// reported rather than silently ignored. In TOML:
+///
+/// ```toml
+/// hostname = "my-service"
+/// dir = "/var/lib/my-service/tailscale"
+/// auth_key = "tskey-auth-..."
+/// control_url = "https://headscale.example.com"
+/// ephemeral = true
+/// advertise_tags = ["tag:server"]
+/// wireguard_port = 41641
+/// listen_backlog = 1024
+/// recv_buffer_size = 4194304
+/// send_buffer_size = 4194304
+/// discard_logs = true
+/// dial_family = "ipv4"
+/// max_concurrent_dials = 64
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TailscaleConfig {
+    /// See [`TailscaleBuilder::hostname`].
+    pub hostname: Option<String>,
+    /// See [`TailscaleBuilder::dir`].
+    pub dir: Option<PathBuf>,
+    /// See [`TailscaleBuilder::auth_key`].
+    pub auth_key: Option<AuthKey>,
+    /// See [`TailscaleBuilder::control_url`].
+    pub control_url: Option<String>,
+    /// See [`TailscaleBuilder::ephemeral`].
+    pub ephemeral: bool,
+    /// See [`TailscaleBuilder::advertise_tags`].
+    pub advertise_tags: Vec<String>,
+    /// See [`TailscaleBuilder::wireguard_port`].
+    pub wireguard_port: Option<u16>,
+    /// See [`TailscaleBuilder::listen_backlog`].
+    pub listen_backlog: Option<u16>,
+    /// See [`TailscaleBuilder::recv_buffer_size`].
+    pub recv_buffer_size: Option<usize>,
+    /// See [`TailscaleBuilder::send_buffer_size`].
+    pub send_buffer_size: Option<usize>,
+    /// See [`TailscaleBuilder::log_discard`].
+    pub discard_logs: bool,
+    /// See [`TailscaleBuilder::dial_family`].
+    pub dial_family: AddressFamily,
+    /// See [`TailscaleBuilder::max_concurrent_dials`].
+    pub max_concurrent_dials: Option<usize>,
+    /// See [`TailscaleBuilder::strict`].
+    pub strict: bool,
+}
+
+impl TailscaleConfig {
+    /// Reads a config file, choosing the format from its extension
+    /// (`.toml` or `.json`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, has an unsupported
+    /// extension or does not parse.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let config_error = |message: String| TailscaleError::ConfigFile {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        let contents = std::fs::read_to_string(path).map_err(|e| config_error(e.to_string()))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| config_error(e.to_string())),
+            Some("json") => {
+                serde_json::from_str(&contents).map_err(|e| config_error(e.to_string()))
+            }
+            _ => Err(config_error(
+                "unsupported extension, expected .toml or .json".to_string(),
+            )),
+        }
+    }
+}
+
+impl TailscaleBuilder {
+    /// Creates a builder from a TOML or JSON config file.
+    ///
+    /// Further settings can be applied to the returned builder before calling
+    /// [`build`](Self::build).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::TailscaleBuilder;
+    /// let ts = TailscaleBuilder::from_config_file("tailscale.toml")?
+    ///     .log_discard()
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be loaded, see [`TailscaleConfig::from_file`].
+    pub fn from_config_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_config(TailscaleConfig::from_file(path)?))
+    }
+
+    /// Creates a builder from already loaded settings.
+    pub fn from_config(config: TailscaleConfig) -> Self {
+        let mut builder = TailscaleBuilder::default();
+        builder
+            .ephemeral(config.ephemeral)
+            .dial_family(config.dial_family)
+            .strict(config.strict);
+        if let Some(hostname) = config.hostname {
+            builder.hostname(hostname);
+        }
+        if let Some(dir) = config.dir {
+            builder.dir(dir);
+        }
+        if let Some(auth_key) = config.auth_key {
+            builder.auth_key(auth_key);
+        }
+        if let Some(control_url) = config.control_url {
+            builder.control_url(control_url);
+        }
+        if !config.advertise_tags.is_empty() {
+            builder.advertise_tags(config.advertise_tags);
+        }
+        if let Some(port) = config.wireguard_port {
+            builder.wireguard_port(port);
+        }
+        if let Some(backlog) = config.listen_backlog {
+            builder.listen_backlog(backlog);
+        }
+        if let Some(size) = config.recv_buffer_size {
+            builder.recv_buffer_size(size);
+        }
+        if let Some(size) = config.send_buffer_size {
+            builder.send_buffer_size(size);
+        }
+        if config.discard_logs {
+            builder.log_discard();
+        }
+        if let Some(limit) = config.max_concurrent_dials {
+            builder.max_concurrent_dials(limit);
+        }
+        builder
+    }
+}
@@ -0,0 +1,130 @@
+//! Instance-wide cap on concurrently in-flight dials.
+//!
+//! Dials beyond the cap wait in a fair FIFO queue, so an application opening
+//! thousands of connections at once does not swamp the Go backend or the
+//! blocking thread pool.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+
+/// A snapshot of an instance's dial queue, returned by
+/// [`Tailscale::dial_queue_stats`](crate::Tailscale::dial_queue_stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DialQueueStats {
+    /// The configured cap, if any.
+    pub limit: Option<usize>,
+    /// Dials currently running.
+    pub in_flight: usize,
+    /// Dials currently waiting for a slot.
+    pub queued: usize,
+    /// Dials started since the instance was built.
+    pub started: u64,
+    /// Total time dials have spent waiting for a slot.
+    pub total_queue_time: Duration,
+    /// Longest time a single dial has waited for a slot.
+    pub max_queue_time: Duration,
+}
+
+pub(crate) struct DialQueue {
+    limit: Option<usize>,
+    permits: Option<Arc<Semaphore>>,
+    in_flight: AtomicUsize,
+    queued: AtomicUsize,
+    started: AtomicU64,
+    total_queue_nanos: AtomicU64,
+    max_queue_nanos: AtomicU64,
+}
+
+impl DialQueue {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        DialQueue {
+            limit,
+            permits: limit.map(|n| Arc::new(Semaphore::new(n))),
+            in_flight: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            started: AtomicU64::new(0),
+            total_queue_nanos: AtomicU64::new(0),
+            max_queue_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Waits for a dial slot. The slot is held until the returned permit is dropped.
+    pub(crate) async fn acquire(self: &Arc<Self>) -> DialPermit {
+        let permit = match &self.permits {
+            Some(permits) => {
+                let start = Instant::now();
+                let permit = {
+                    let _queued = Counted::new(&self.queued);
+                    // The semaphore is never closed.
+                    Arc::clone(permits).acquire_owned().await.unwrap()
+                };
+                let waited = start.elapsed();
+                self.record_wait(waited);
+                debug!(?waited, "dial slot acquired");
+                Some(permit)
+            }
+            None => None,
+        };
+        self.started.fetch_add(1, Ordering::Relaxed);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        DialPermit {
+            queue: Arc::clone(self),
+            _permit: permit,
+        }
+    }
+
+    pub(crate) fn stats(&self) -> DialQueueStats {
+        DialQueueStats {
+            limit: self.limit,
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            started: self.started.load(Ordering::Relaxed),
+            total_queue_time: Duration::from_nanos(self.total_queue_nanos.load(Ordering::Relaxed)),
+            max_queue_time: Duration::from_nanos(self.max_queue_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn record_wait(&self, waited: Duration) {
+        let nanos = u64::try_from(waited.as_nanos()).unwrap_or(u64::MAX);
+        self.total_queue_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_queue_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+}
+
+/// A dial slot, released when dropped.
+pub(crate) struct DialPermit {
+    queue: Arc<DialQueue>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for DialPermit {
+    fn drop(&mut self) {
+        self.queue.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Increments a counter for as long as it is alive, including when the
+/// owning future is dropped.
+struct Counted<'a>(&'a AtomicUsize);
+
+impl<'a> Counted<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Counted(counter)
+    }
+}
+
+impl Drop for Counted<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,301 @@
+//! A reusable dialer for clients making many connections to the same peers.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::debug;
+
+use crate::{
+    addr::{DialAddr, ToDialAddr},
+    tailscale::{Connection, NetworkType, Result, Tailscale},
+};
+
+/// How long resolved addresses are cached by default.
+const DEFAULT_ADDRESS_TTL: Duration = Duration::from_secs(60);
+
+/// Dials tailnet peers, caching the addresses their names resolve to and
+/// optionally keeping idle connections for reuse.
+///
+/// Names are resolved from the network map, so repeated dials to the same
+/// peer skip name resolution. Names that are not in the network map are
+/// passed to libtailscale unresolved. With [`pool_idle`](Self::pool_idle),
+/// connections returned by [`connect`](Self::connect) go back into a
+/// per-target pool when dropped, and the next dial to that target reuses one
+/// instead of opening a new connection.
+///
+/// Cloning a `Dialer` is cheap; clones share their caches.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::{sync::Arc, time::Duration};
+/// # use tailscale2::*;
+/// # async fn example(ts: Arc<Tailscale>) -> Result<()> {
+/// let dialer = Dialer::new(ts).pool_idle(8, Duration::from_secs(30));
+/// for _ in 0..100 {
+///     let mut conn = dialer.connect("api:8080").await?;
+///     // ... one request/response exchange ...
+///     // Dropping `conn` returns it to the pool.
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Dialer {
+    tailscale: Arc<Tailscale>,
+    network: NetworkType,
+    address_ttl: Duration,
+    addresses: Arc<Mutex<HashMap<String, (IpAddr, Instant)>>>,
+    pool: Option<Arc<Pool>>,
+}
+
+impl Dialer {
+    /// Creates a dialer opening TCP connections through `tailscale`, caching
+    /// resolved addresses for a minute and not pooling connections.
+    pub fn new(tailscale: Arc<Tailscale>) -> Self {
+        Dialer {
+            tailscale,
+            network: NetworkType::Tcp,
+            address_ttl: DEFAULT_ADDRESS_TTL,
+            addresses: Arc::default(),
+            pool: None,
+        }
+    }
+
+    /// Sets the network type to dial.
+    pub fn network(mut self, network: NetworkType) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Sets how long a resolved address is reused before the name is looked
+    /// up again. [`Duration::ZERO`] disables the cache.
+    pub fn address_ttl(mut self, ttl: Duration) -> Self {
+        self.address_ttl = ttl;
+        self
+    }
+
+    /// Keeps up to `max_idle` idle connections per target for reuse,
+    /// discarding those idle for longer than `idle_timeout`.
+    ///
+    /// A pooled connection is only reused if the peer has not closed it or
+    /// sent anything in the meantime. Drop a [`PooledConnection`] only once
+    /// its protocol exchange is complete; use
+    /// [`PooledConnection::into_inner`] to keep it out of the pool.
+    pub fn pool_idle(mut self, max_idle: usize, idle_timeout: Duration) -> Self {
+        self.pool = Some(Arc::new(Pool {
+            max_idle,
+            idle_timeout,
+            idle: Mutex::new(HashMap::new()),
+        }));
+        self
+    }
+
+    /// Returns a connection to `addr`, reusing an idle one from the pool if
+    /// there is one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `addr` is invalid or dialing fails.
+    pub async fn connect(&self, addr: impl ToDialAddr) -> Result<PooledConnection> {
+        let addr = addr.to_dial_addr()?;
+        if let Some(pool) = &self.pool
+            && let Some(conn) = pool.take(&addr)
+        {
+            debug!(%addr, "reusing pooled connection");
+            return Ok(PooledConnection::new(
+                conn,
+                Some((Arc::downgrade(pool), addr)),
+            ));
+        }
+
+        let target = self.resolve(&addr).await;
+        let conn = self.tailscale.connect(self.network, target).await?;
+        let pool = self.pool.as_ref().map(|pool| (Arc::downgrade(pool), addr));
+        Ok(PooledConnection::new(conn, pool))
+    }
+
+    /// Forgets all cached addresses and idle connections.
+    pub fn clear(&self) {
+        self.addresses.lock().unwrap().clear();
+        if let Some(pool) = &self.pool {
+            pool.idle.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns the address to dial for `addr`, with the host replaced by its
+    /// tailnet IP address if it names a node in the network map.
+    async fn resolve(&self, addr: &DialAddr) -> DialAddr {
+        let host = addr.host();
+        if self.address_ttl.is_zero() || host.parse::<IpAddr>().is_ok() {
+            return addr.clone();
+        }
+        let key = host.trim_end_matches('.').to_ascii_lowercase();
+        if let Some((ip, resolved)) = self.addresses.lock().unwrap().get(&key)
+            && resolved.elapsed() < self.address_ttl
+        {
+            return DialAddr::from(SocketAddr::new(*ip, addr.port()));
+        }
+
+        let netmap = match self.tailscale.netmap().await {
+            Ok(netmap) => netmap,
+            Err(e) => {
+                debug!(%host, error = %e, "could not resolve from network map");
+                return addr.clone();
+            }
+        };
+        let want_ipv6 = matches!(self.network, NetworkType::Tcp6 | NetworkType::Udp6);
+        let want_ipv4 = matches!(self.network, NetworkType::Tcp4 | NetworkType::Udp4);
+        let ip = netmap.find_node(&key).and_then(|node| {
+            node.addresses
+                .iter()
+                .find(|ip| (!want_ipv6 || ip.is_ipv6()) && (!want_ipv4 || ip.is_ipv4()))
+                .copied()
+        });
+        let Some(ip) = ip else {
+            return addr.clone();
+        };
+        debug!(%host, %ip, "resolved from network map");
+        self.addresses
+            .lock()
+            .unwrap()
+            .insert(key, (ip, Instant::now()));
+        DialAddr::from(SocketAddr::new(ip, addr.port()))
+    }
+}
+
+/// Idle connections of a [`Dialer`], keyed by target.
+struct Pool {
+    max_idle: usize,
+    idle_timeout: Duration,
+    idle: Mutex<HashMap<DialAddr, Vec<(Connection, Instant)>>>,
+}
+
+impl Pool {
+    /// Takes the most recently used reusable connection to `addr`, dropping
+    /// expired and closed ones on the way.
+    fn take(&self, addr: &DialAddr) -> Option<Connection> {
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.get_mut(addr)?;
+        while let Some((conn, since)) = conns.pop() {
+            if since.elapsed() < self.idle_timeout && conn.is_idle() {
+                return Some(conn);
+            }
+        }
+        idle.remove(addr);
+        None
+    }
+
+    fn put(&self, addr: DialAddr, conn: Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.entry(addr).or_default();
+        conns.retain(|(_, since)| since.elapsed() < self.idle_timeout);
+        if conns.len() < self.max_idle && conn.is_idle() {
+            conns.push((conn, Instant::now()));
+        }
+    }
+}
+
+/// A connection returned by [`Dialer::connect`], which goes back into the
+/// dialer's pool when dropped if pooling is enabled.
+///
+/// Dereferences to the underlying [`Connection`] and implements `AsyncRead`
+/// and `AsyncWrite`.
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    pool: Option<(Weak<Pool>, DialAddr)>,
+}
+
+impl PooledConnection {
+    fn new(conn: Connection, pool: Option<(Weak<Pool>, DialAddr)>) -> Self {
+        PooledConnection {
+            conn: Some(conn),
+            pool,
+        }
+    }
+
+    /// Takes the connection out of the pool's care, so it is closed rather
+    /// than reused when dropped.
+    pub fn into_inner(mut self) -> Connection {
+        self.conn.take().expect("connection is only taken on drop")
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn
+            .as_mut()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let (Some(conn), Some((pool, addr))) = (self.conn.take(), self.pool.take())
+            && let Some(pool) = pool.upgrade()
+        {
+            pool.put(addr, conn);
+        }
+    }
+}
+
+impl AsyncRead for PooledConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut **self.get_mut()).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PooledConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut **self.get_mut()).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut **self.get_mut()).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        (**self).is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut **self.get_mut()).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        // A connection whose write half is shut down cannot be reused.
+        let this = self.get_mut();
+        this.pool = None;
+        Pin::new(&mut **this).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,97 @@
+//! Loading libtailscale at runtime, enabled by the `dlopen` feature.
+//!
+//! Instead of linking the Go archive into the binary, the C functions are
+//! resolved from the shared library built by `make shared`
+//! (`libtailscale.so`, or `libtailscale.dylib` on macOS) when the first
+//! instance is built. Binaries can then be shipped without it and check
+//! [`is_available`] to turn Tailscale support off when it is missing.
+
+use std::{env, ffi::OsStr, sync::OnceLock};
+
+use libloading::Library;
+use tracing::debug;
+
+use crate::tailscale::{Result, TailscaleError};
+
+/// Environment variable holding the path of the shared library to load,
+/// used when [`load_library`] has not been called.
+///
+/// Defaults to `libtailscale.so` (or `libtailscale.dylib` on macOS), looked
+/// up in the system library search path.
+pub const LIBRARY_PATH_ENV: &str = "LIBTAILSCALE_PATH";
+
+static LIBRARY: OnceLock<std::result::Result<Library, String>> = OnceLock::new();
+
+/// Loads libtailscale from `path`.
+///
+/// Call this before building the first instance to load the library from a
+/// specific location instead of the default one.
+///
+/// # Errors
+///
+/// Returns [`TailscaleError::LibraryUnavailable`] if the library cannot be
+/// loaded, or [`TailscaleError::Misuse`] if a library has already been
+/// loaded.
+pub fn load_library(path: impl AsRef<OsStr>) -> Result<()> {
+    let mut loaded = false;
+    let library = LIBRARY.get_or_init(|| {
+        loaded = true;
+        open(path.as_ref())
+    });
+    if !loaded {
+        return Err(TailscaleError::Misuse(
+            "libtailscale has already been loaded".to_string(),
+        ));
+    }
+    library
+        .as_ref()
+        .map(|_| ())
+        .map_err(|e| TailscaleError::LibraryUnavailable(e.clone()))
+}
+
+/// Returns true if libtailscale has been or can be loaded.
+///
+/// Tries the default location the first time it is called, so later calls
+/// are cheap.
+pub fn is_available() -> bool {
+    library().is_ok()
+}
+
+/// Returns the loaded library, loading it from the default location first if
+/// needed.
+pub(crate) fn library() -> Result<&'static Library> {
+    LIBRARY
+        .get_or_init(|| {
+            let path = env::var_os(LIBRARY_PATH_ENV)
+                .unwrap_or_else(|| libloading::library_filename("tailscale"));
+            open(&path)
+        })
+        .as_ref()
+        .map_err(|e| TailscaleError::LibraryUnavailable(e.clone()))
+}
+
+fn open(path: &OsStr) -> std::result::Result<Library, String> {
+    debug!(path = %path.to_string_lossy(), "loading libtailscale");
+    // SAFETY: loading runs the library's initializers, which for a Go shared
+    // library only start the Go runtime.
+    unsafe { Library::new(path) }.map_err(|e| e.to_string())
+}
+
+/// Resolves the function `name`, which must be NUL-terminated.
+///
+/// # Safety
+///
+/// `F` must be the function pointer type matching the C declaration.
+///
+/// # Panics
+///
+/// Panics if the library is not loaded or lacks the symbol. Building an
+/// instance checks that the library is loaded, so this only happens with a
+/// library older than this crate.
+pub(crate) unsafe fn symbol<F: Copy>(name: &str) -> F {
+    let library = library().unwrap_or_else(|e| panic!("{e}"));
+    match unsafe { library.get::<F>(name.as_bytes()) } {
+        Ok(symbol) => *symbol,
+        Err(e) => panic!("libtailscale lacks {}: {e}", name.trim_end_matches('\0')),
+    }
+}
@@ -0,0 +1,143 @@
+//! Named groups of connections that can be drained or closed on demand.
+//!
+//! Applications add connections to a group (for example one per tenant) via
+//! [`Tailscale::add_to_drain_group`](crate::Tailscale::add_to_drain_group) and
+//! later drain the whole group at once with
+//! [`Tailscale::drain_group`](crate::Tailscale::drain_group).
+
+use std::{
+    collections::HashMap,
+    os::fd::RawFd,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use nix::sys::socket::Shutdown;
+use tokio::sync::watch;
+use tracing::{debug, error};
+
+/// Registry of the drain groups belonging to a single Tailscale instance.
+#[derive(Default)]
+pub(crate) struct DrainGroups {
+    groups: Mutex<HashMap<String, Group>>,
+    next_id: AtomicU64,
+}
+
+struct Group {
+    members: HashMap<u64, RawFd>,
+    /// Set once the group is being drained, so late joiners are shut down too.
+    shutdown: Option<Shutdown>,
+    count: watch::Sender<usize>,
+}
+
+impl DrainGroups {
+    /// Registers `fd` as a member of the group called `name`.
+    pub(crate) fn join(self: &Arc<Self>, name: &str, fd: RawFd) -> DrainMembership {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut groups = self.groups.lock().unwrap();
+        let group = groups.entry(name.to_string()).or_insert_with(|| Group {
+            members: HashMap::new(),
+            shutdown: None,
+            count: watch::Sender::new(0),
+        });
+        if let Some(how) = group.shutdown {
+            shutdown_member(name, fd, how);
+        }
+        group.members.insert(id, fd);
+        group.count.send_replace(group.members.len());
+        debug!(group = name, fd, "connection joined drain group");
+
+        DrainMembership {
+            groups: Arc::clone(self),
+            name: name.to_string(),
+            id,
+        }
+    }
+
+    /// Shuts down every member of the group and waits until all of them have
+    /// been dropped. Returns the number of connections that were in the group.
+    pub(crate) async fn drain(&self, name: &str, how: Shutdown) -> usize {
+        let Some((members, mut rx)) = self.shutdown_members(name, how) else {
+            return 0;
+        };
+
+        debug!(group = name, members, "waiting for drain group to empty");
+        // The sender is dropped once the last member leaves, which also ends the wait.
+        let _ = rx.wait_for(|count| *count == 0).await;
+        debug!(group = name, "drain group empty");
+        members
+    }
+
+    /// Shuts down every member of the group without waiting for them to be
+    /// dropped. Returns the number of connections that were in the group.
+    pub(crate) fn shutdown_now(&self, name: &str, how: Shutdown) -> usize {
+        self.shutdown_members(name, how)
+            .map_or(0, |(members, _)| members)
+    }
+
+    /// Waits until the group has no members.
+    pub(crate) async fn wait_empty(&self, name: &str) {
+        let mut rx = {
+            let groups = self.groups.lock().unwrap();
+            let Some(group) = groups.get(name) else {
+                return;
+            };
+            group.count.subscribe()
+        };
+        let _ = rx.wait_for(|count| *count == 0).await;
+    }
+
+    fn shutdown_members(
+        &self,
+        name: &str,
+        how: Shutdown,
+    ) -> Option<(usize, watch::Receiver<usize>)> {
+        let mut groups = self.groups.lock().unwrap();
+        let group = groups.get_mut(name)?;
+        group.shutdown = Some(how);
+        for fd in group.members.values() {
+            shutdown_member(name, *fd, how);
+        }
+        Some((group.members.len(), group.count.subscribe()))
+    }
+
+    fn leave(&self, name: &str, id: u64) {
+        let mut groups = self.groups.lock().unwrap();
+        let Some(group) = groups.get_mut(name) else {
+            return;
+        };
+        group.members.remove(&id);
+        group.count.send_replace(group.members.len());
+        if group.members.is_empty() {
+            groups.remove(name);
+        }
+    }
+}
+
+fn shutdown_member(name: &str, fd: RawFd, how: Shutdown) {
+    if let Err(e) = nix::sys::socket::shutdown(fd, how) {
+        error!(group = name, fd, error = %e, "error shutting down drain group member");
+    }
+}
+
+/// Membership of a connection in a drain group, released when dropped.
+pub(crate) struct DrainMembership {
+    groups: Arc<DrainGroups>,
+    name: String,
+    id: u64,
+}
+
+impl DrainMembership {
+    /// Returns the name of the group.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for DrainMembership {
+    fn drop(&mut self) {
+        self.groups.leave(&self.name, self.id);
+    }
+}
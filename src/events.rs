@@ -0,0 +1,160 @@
+//! Stream of changes to a node's state, read from the IPN bus.
+
+use std::{
+    os::fd::OwnedFd,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::SystemTime,
+};
+
+use futures_core::Stream;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines},
+    net::UnixStream,
+};
+
+use crate::{
+    json::unix_time,
+    tailscale::{BackendState, Result, TailscaleError},
+};
+
+/// A change to a node's state, yielded by [`Events`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum IpnEvent {
+    /// The backend state changed. The current state is always sent first.
+    StateChanged {
+        /// The new state.
+        state: BackendState,
+    },
+    /// Interactive login is required.
+    AuthUrl {
+        /// The URL the user must visit to authenticate the node.
+        url: String,
+    },
+    /// A peer came online.
+    PeerOnline {
+        /// The stable node ID of the peer.
+        id: String,
+        /// The MagicDNS name of the peer, without a trailing dot.
+        #[serde(default)]
+        dns_name: String,
+    },
+    /// A peer went offline or left the tailnet.
+    PeerOffline {
+        /// The stable node ID of the peer.
+        id: String,
+        /// The MagicDNS name of the peer, without a trailing dot.
+        #[serde(default)]
+        dns_name: String,
+    },
+    /// The node key expires within a day and must be renewed by
+    /// re-authenticating the node.
+    KeyExpiring {
+        /// When the node key expires.
+        #[serde(deserialize_with = "unix_time")]
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::json::serialize_unix_time")
+        )]
+        expiry: Option<SystemTime>,
+    },
+}
+
+/// A stage of bringing a node up, reported by
+/// [`Tailscale::up_with_progress`](crate::Tailscale::up_with_progress).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "stage", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum UpProgress {
+    /// Starting the backend and connecting to the control plane.
+    Connecting,
+    /// Waiting for the node to be authenticated.
+    WaitingForLogin {
+        /// The URL the user must visit, once known.
+        auth_url: Option<String>,
+    },
+    /// Waiting for an administrator to approve the node.
+    WaitingForApproval,
+    /// Authenticated, waiting for the network map.
+    FetchingNetMap,
+    /// The node is connected and usable.
+    Running,
+}
+
+impl UpProgress {
+    /// Maps an event to the stage it indicates, if any.
+    pub(crate) fn from_event(event: &IpnEvent) -> Option<Self> {
+        let progress = match event {
+            IpnEvent::StateChanged { state } => match state {
+                BackendState::NoState | BackendState::Stopped | BackendState::InUseOtherUser => {
+                    UpProgress::Connecting
+                }
+                BackendState::NeedsLogin => UpProgress::WaitingForLogin { auth_url: None },
+                BackendState::NeedsMachineAuth => UpProgress::WaitingForApproval,
+                BackendState::Starting => UpProgress::FetchingNetMap,
+                BackendState::Running => UpProgress::Running,
+            },
+            IpnEvent::AuthUrl { url } => UpProgress::WaitingForLogin {
+                auth_url: Some(url.clone()),
+            },
+            _ => return None,
+        };
+        Some(progress)
+    }
+}
+
+/// A [`Stream`] of [`IpnEvent`]s.
+///
+/// Peer events are only sent for changes after the subscription; use
+/// [`Tailscale::peers`](crate::Tailscale::peers) for the initial set. The
+/// stream ends once the instance is closed, and dropping it unsubscribes.
+///
+/// Created by [`Tailscale::events`](crate::Tailscale::events).
+pub struct Events {
+    lines: Lines<BufReader<UnixStream>>,
+}
+
+impl Events {
+    /// Wraps the descriptor returned by `tailscale_watch_events`.
+    pub(crate) fn from_owned_fd(fd: OwnedFd) -> Result<Self> {
+        let stream = std::os::unix::net::UnixStream::from(fd);
+        let stream = stream
+            .set_nonblocking(true)
+            .and_then(|()| UnixStream::from_std(stream))
+            .map_err(|e| TailscaleError::Tailscale(format!("registering events failed: {}", e)))?;
+        Ok(Events {
+            lines: BufReader::new(stream).lines(),
+        })
+    }
+
+    /// Waits for the next event.
+    pub(crate) async fn next(&mut self) -> Option<Result<IpnEvent>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for Events {
+    type Item = Result<IpnEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let line = match ready!(Pin::new(&mut this.lines).poll_next_line(cx)) {
+            Ok(Some(line)) => line,
+            Ok(None) => return Poll::Ready(None),
+            Err(e) => {
+                return Poll::Ready(Some(Err(TailscaleError::Tailscale(format!(
+                    "reading events failed: {}",
+                    e
+                )))));
+            }
+        };
+        Poll::Ready(Some(
+            serde_json::from_str(&line).map_err(TailscaleError::Json),
+        ))
+    }
+}
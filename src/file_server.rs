@@ -0,0 +1,400 @@
+//! Serving a directory over HTTP, enabled by the `hyper` feature.
+
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use hyper::{
+    Method, Request, Response, StatusCode,
+    body::{Body, Bytes, Frame, Incoming},
+    header,
+    server::conn::http1,
+    service::service_fn,
+};
+use tokio_util::io::ReaderStream;
+use tracing::debug;
+
+use crate::{
+    forward::Forwarder,
+    tailscale::{Listener, TailscaleError},
+};
+
+/// Serves the files under `root` over HTTP on `listener` until the returned
+/// [`Forwarder`] is stopped or dropped.
+///
+/// `GET` and `HEAD` requests for a file return its contents. Requests for a
+/// directory return its `index.html` if there is one, or else a listing of
+/// its entries. Paths escaping `root` are rejected, but symbolic links inside
+/// it are followed.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use tailscale2::*;
+/// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+/// // Share a folder with the tailnet.
+/// let listener = ts.listener(NetworkType::Tcp, ":80").await?;
+/// let server = serve_dir(listener, "/srv/share");
+/// # Ok(())
+/// # }
+/// ```
+pub fn serve_dir(listener: Arc<Listener>, root: impl Into<PathBuf>) -> Forwarder {
+    let root = Arc::new(root.into());
+    Forwarder::spawn(
+        None,
+        move || {
+            let listener = Arc::clone(&listener);
+            async move { listener.accept().await }
+        },
+        move |conn, _state| {
+            let root = Arc::clone(&root);
+            async move {
+                let service = service_fn(move |req| {
+                    let root = Arc::clone(&root);
+                    async move { Ok::<_, TailscaleError>(respond(&root, req).await) }
+                });
+                http1::Builder::new()
+                    .serve_connection(conn, service)
+                    .await
+                    .map_err(|e| format!("HTTP connection failed: {e}"))
+            }
+        },
+    )
+}
+
+async fn respond(root: &Path, req: Request<Incoming>) -> Response<FileBody> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        let mut res = status(StatusCode::METHOD_NOT_ALLOWED);
+        res.headers_mut()
+            .insert(header::ALLOW, header::HeaderValue::from_static("GET, HEAD"));
+        return res;
+    }
+    let url_path = req.uri().path();
+    let Some(path) = resolve(root, url_path) else {
+        return status(StatusCode::BAD_REQUEST);
+    };
+    debug!(path = %path.display(), "serving file");
+
+    let res = match tokio::fs::metadata(&path).await {
+        Ok(meta) if meta.is_dir() => {
+            if !url_path.ends_with('/') {
+                let mut res = status(StatusCode::MOVED_PERMANENTLY);
+                if let Ok(location) = header::HeaderValue::from_str(&dir_location(url_path)) {
+                    res.headers_mut().insert(header::LOCATION, location);
+                }
+                return res;
+            }
+            let index = path.join("index.html");
+            if tokio::fs::metadata(&index).await.is_ok_and(|m| m.is_file()) {
+                file(&index).await
+            } else {
+                listing(&path, url_path).await
+            }
+        }
+        Ok(_) => file(&path).await,
+        Err(e) => Err(e),
+    };
+    let mut res = res.unwrap_or_else(|e| match e.kind() {
+        io::ErrorKind::NotFound => status(StatusCode::NOT_FOUND),
+        io::ErrorKind::PermissionDenied => status(StatusCode::FORBIDDEN),
+        _ => status(StatusCode::INTERNAL_SERVER_ERROR),
+    });
+    if req.method() == Method::HEAD {
+        *res.body_mut() = FileBody::Empty;
+    }
+    res
+}
+
+/// Returns where to redirect a request for a directory missing its trailing
+/// slash. Leading slashes are collapsed, as `//host/` would send the client
+/// to another host.
+fn dir_location(url_path: &str) -> String {
+    format!("/{}/", url_path.trim_start_matches('/'))
+}
+
+/// Maps a request path onto `root`, returning `None` if it is not valid
+/// percent-encoded UTF-8 or tries to leave `root`.
+fn resolve(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(url_path)?;
+    let mut path = root.to_path_buf();
+    for component in Path::new(&decoded).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(path)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = tail.get(..2)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let hex = std::str::from_utf8(hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+async fn file(path: &Path) -> io::Result<Response<FileBody>> {
+    let file = tokio::fs::File::open(path).await?;
+    let len = file.metadata().await?.len();
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type(path))
+        .header(header::CONTENT_LENGTH, len)
+        .body(FileBody::File(ReaderStream::new(file)))
+        .expect("valid response"))
+}
+
+async fn listing(dir: &Path, url_path: &str) -> io::Result<Response<FileBody>> {
+    let mut names = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let mut name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type().await?.is_dir() {
+            name.push('/');
+        }
+        names.push(name);
+    }
+    names.sort();
+
+    let title = escape_html(url_path);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<ul>\n"
+    );
+    if url_path != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for name in names {
+        let href = format!("./{}", percent_encode(&name));
+        let name = escape_html(&name);
+        html.push_str(&format!("<li><a href=\"{href}\">{name}</a></li>\n"));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::CONTENT_LENGTH, html.len())
+        .body(FileBody::Full(Some(Bytes::from(html))))
+        .expect("valid response"))
+}
+
+/// Percent-encodes a path segment, keeping a trailing `/` and the
+/// characters RFC 3986 leaves unreserved.
+fn percent_encode(segment: &str) -> String {
+    let (segment, slash) = match segment.strip_suffix('/') {
+        Some(segment) => (segment, "/"),
+        None => (segment, ""),
+    };
+    let mut encoded = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded + slash
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt" | "md" | "log") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("zip") => "application/zip",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+fn status(code: StatusCode) -> Response<FileBody> {
+    let body = code.canonical_reason().unwrap_or_default().to_string() + "\n";
+    Response::builder()
+        .status(code)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(FileBody::Full(Some(Bytes::from(body))))
+        .expect("valid response")
+}
+
+/// A response body streamed from a file or held in memory.
+enum FileBody {
+    Empty,
+    Full(Option<Bytes>),
+    File(ReaderStream<tokio::fs::File>),
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Frame<Bytes>>>> {
+        match self.get_mut() {
+            FileBody::Empty => Poll::Ready(None),
+            FileBody::Full(data) => Poll::Ready(data.take().map(|d| Ok(Frame::data(d)))),
+            FileBody::File(stream) => Pin::new(stream)
+                .poll_next(cx)
+                .map(|chunk| chunk.map(|c| c.map(Frame::data))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        matches!(self, FileBody::Empty | FileBody::Full(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_stays_under_root() {
+        let root = Path::new("/srv/share");
+        assert_eq!(
+            resolve(root, "/docs/a.txt"),
+            Some(PathBuf::from("/srv/share/docs/a.txt"))
+        );
+        assert_eq!(resolve(root, "/"), Some(PathBuf::from("/srv/share")));
+        assert_eq!(
+            resolve(root, "/./docs//a.txt"),
+            Some(PathBuf::from("/srv/share/docs/a.txt"))
+        );
+        assert_eq!(
+            resolve(root, "/my%20file.txt"),
+            Some(PathBuf::from("/srv/share/my file.txt"))
+        );
+    }
+
+    #[test]
+    fn resolve_rejects_traversal() {
+        let root = Path::new("/srv/share");
+        assert_eq!(resolve(root, "/../etc/passwd"), None);
+        assert_eq!(resolve(root, "/docs/../../etc/passwd"), None);
+        assert_eq!(resolve(root, "/%2e%2e/etc/passwd"), None);
+        assert_eq!(resolve(root, "/%2E%2E%2Fetc%2Fpasswd"), None);
+        assert_eq!(resolve(root, "/docs%2f..%2f..%2fetc"), None);
+    }
+
+    #[test]
+    fn resolve_keeps_absolute_paths_under_root() {
+        let root = Path::new("/srv/share");
+        assert_eq!(
+            resolve(root, "//etc/passwd"),
+            Some(PathBuf::from("/srv/share/etc/passwd"))
+        );
+        assert_eq!(
+            resolve(root, "/%2fetc%2fpasswd"),
+            Some(PathBuf::from("/srv/share/etc/passwd"))
+        );
+    }
+
+    #[test]
+    fn resolve_rejects_malformed_encoding() {
+        let root = Path::new("/srv/share");
+        assert_eq!(resolve(root, "/a%"), None);
+        assert_eq!(resolve(root, "/a%2"), None);
+        assert_eq!(resolve(root, "/a%zz"), None);
+        assert_eq!(resolve(root, "/a%ff"), None);
+        assert_eq!(resolve(root, "/a%c3%28"), None);
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(percent_decode("a%20b%2Fc").as_deref(), Some("a b/c"));
+        assert_eq!(percent_decode("%c3%a9").as_deref(), Some("é"));
+        assert_eq!(percent_decode("%+1"), None);
+        assert_eq!(percent_decode("%"), None);
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(percent_encode("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(
+            percent_encode("javascript:alert(1)"),
+            "javascript%3Aalert%281%29"
+        );
+        assert_eq!(percent_encode("a #?%b"), "a%20%23%3F%25b");
+        assert_eq!(percent_encode("dir/"), "dir/");
+        assert_eq!(percent_encode("é"), "%C3%A9");
+        assert_eq!(
+            percent_decode(&percent_encode("a #?%/b")).as_deref(),
+            Some("a #?%/b")
+        );
+    }
+
+    #[test]
+    fn directory_redirect_stays_on_host() {
+        assert_eq!(dir_location("/docs"), "/docs/");
+        assert_eq!(dir_location("//evil.com"), "/evil.com/");
+        assert_eq!(dir_location("///docs"), "/docs/");
+    }
+
+    #[tokio::test]
+    async fn listing_links_are_relative_and_encoded() {
+        let dir = std::env::temp_dir().join(format!("tailscale2-listing-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub dir")).unwrap();
+        std::fs::write(dir.join("javascript:alert(1)"), "").unwrap();
+        std::fs::write(dir.join("a\"b<c>"), "").unwrap();
+
+        let res = listing(&dir, "/").await.unwrap();
+        let FileBody::Full(Some(body)) = res.into_body() else {
+            panic!("listing is not in memory");
+        };
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(html.contains("<a href=\"./a%22b%3Cc%3E\">a&quot;b&lt;c&gt;</a>"));
+        assert!(html.contains("<a href=\"./javascript%3Aalert%281%29\">javascript:alert(1)</a>"));
+        assert!(html.contains("<a href=\"./sub%20dir/\">sub dir/</a>"));
+        assert!(!html.contains("../"));
+    }
+}
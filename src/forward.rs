@@ -0,0 +1,774 @@
+//! Port forwarding between the tailnet and local addresses.
+//!
+//! See [`Tailscale::forward_inbound`](crate::Tailscale::forward_inbound) and
+//! [`Tailscale::forward_outbound`](crate::Tailscale::forward_outbound) for TCP,
+//! and [`Tailscale::forward_udp_inbound`](crate::Tailscale::forward_udp_inbound)
+//! and [`Tailscale::forward_udp_outbound`](crate::Tailscale::forward_udp_outbound)
+//! for UDP.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{mpsc, oneshot},
+    task::{JoinHandle, JoinSet},
+};
+use tracing::{Instrument, Span, debug, warn};
+
+use crate::{
+    identity::PeerInfo,
+    io::copy_bidirectional,
+    proxy_protocol,
+    tailscale::{Connection, Listener, NetworkType, Result, Tailscale, TailscaleError},
+    udp,
+};
+
+/// A snapshot of a forwarder's counters, returned by [`Forwarder::stats`].
+///
+/// For UDP forwarders, every flow between a pair of addresses counts as a
+/// connection until it has been idle for the configured timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ForwarderStats {
+    /// Connections currently being forwarded.
+    pub active_connections: usize,
+    /// Connections accepted since the forwarder was started.
+    pub total_connections: u64,
+    /// Connections that ended with an error, including those whose target
+    /// could not be reached.
+    pub failed_connections: u64,
+    /// Bytes copied to the target by connections that have finished.
+    pub bytes_to_target: u64,
+    /// Bytes copied from the target by connections that have finished.
+    pub bytes_from_target: u64,
+}
+
+/// Where a proxy such as [`Socks5Server`](crate::socks5::Socks5Server) or
+/// [`HttpConnectServer`](crate::http_connect::HttpConnectServer) dials the
+/// targets its clients ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Upstream {
+    /// Dial Tailscale IPs and MagicDNS names (single-label names and names
+    /// under `.ts.net`) on the tailnet, and everything else on the local
+    /// network.
+    #[default]
+    Auto,
+    /// Dial every target on the tailnet.
+    Tailnet,
+    /// Dial every target on the local network.
+    Local,
+}
+
+impl Upstream {
+    /// Returns true if `host` is dialed on the tailnet.
+    fn is_tailnet(self, host: &str) -> bool {
+        match self {
+            Upstream::Tailnet => true,
+            Upstream::Local => false,
+            Upstream::Auto => match host.parse::<IpAddr>() {
+                Ok(ip) => is_tailscale_ip(ip),
+                Err(_) => {
+                    let host = host.trim_end_matches('.');
+                    !host.contains('.') || host.ends_with(".ts.net")
+                }
+            },
+        }
+    }
+
+    /// Connects to `host` and `port` on the tailnet or the local network.
+    ///
+    /// Unless `allow_local` is set, targets that are or resolve to a
+    /// [host-local address](is_host_local) fail with
+    /// [`PermissionDenied`](io::ErrorKind::PermissionDenied).
+    pub(crate) async fn dial(
+        self,
+        tailscale: &Tailscale,
+        host: &str,
+        port: u16,
+        allow_local: bool,
+    ) -> io::Result<UpstreamStream> {
+        if !allow_local && is_local_host(host) {
+            return Err(local_target_denied());
+        }
+        if self.is_tailnet(host) {
+            let addr = match host.parse::<Ipv6Addr>() {
+                Ok(_) => format!("[{host}]:{port}"),
+                Err(_) => format!("{host}:{port}"),
+            };
+            let conn = tailscale.connect(NetworkType::Tcp, &addr).await?;
+            return Ok(UpstreamStream::Tailnet(Box::new(conn)));
+        }
+
+        // Resolve here rather than in `TcpStream::connect`, so names such as
+        // `localhost` are checked too.
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await?
+            .filter(|addr| allow_local || !is_host_local(addr.ip()))
+            .collect();
+        if addrs.is_empty() {
+            return Err(local_target_denied());
+        }
+        let stream = TcpStream::connect(&addrs[..]).await?;
+        Ok(UpstreamStream::Local(stream))
+    }
+}
+
+/// Returns true if `ip` only reaches this host or its link, such as a
+/// loopback address or the `169.254.169.254` metadata service of cloud
+/// hosts. Proxies refuse such targets unless allowed.
+fn is_host_local(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_link_local() || ip.is_unspecified(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.is_unicast_link_local() || ip.is_unspecified(),
+    }
+}
+
+/// Returns true if `host` names this host without needing resolution: a
+/// host-local IP or `localhost`, which libtailscale could also resolve when
+/// dialing on the tailnet.
+fn is_local_host(host: &str) -> bool {
+    let name = host.trim_end_matches('.').to_ascii_lowercase();
+    name == "localhost"
+        || name.ends_with(".localhost")
+        || host.parse::<IpAddr>().is_ok_and(is_host_local)
+}
+
+fn local_target_denied() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "target is a loopback or link-local address",
+    )
+}
+
+/// Decides whether a proxy client may connect to a target, given the
+/// client's identity, the host as requested and the port.
+pub(crate) type TargetFilter = Arc<dyn Fn(&PeerInfo, &str, u16) -> bool + Send + Sync>;
+
+/// The targets a proxy such as [`Socks5Server`](crate::socks5::Socks5Server)
+/// lets its clients connect to.
+#[derive(Clone, Default)]
+pub(crate) struct TargetPolicy {
+    pub(crate) filter: Option<TargetFilter>,
+    pub(crate) allow_local: bool,
+}
+
+impl TargetPolicy {
+    /// Checks the request of the client on `conn` against the filter and
+    /// dials the target through `upstream`.
+    ///
+    /// Rejected requests fail with
+    /// [`PermissionDenied`](io::ErrorKind::PermissionDenied).
+    pub(crate) async fn dial(
+        &self,
+        tailscale: &Tailscale,
+        conn: &Connection,
+        upstream: Upstream,
+        host: &str,
+        port: u16,
+    ) -> io::Result<UpstreamStream> {
+        if let Some(filter) = &self.filter {
+            let addr = conn.remote_addr()?;
+            // Funnel clients from the public internet have no identity.
+            let identity = tailscale.whois(addr).await.ok();
+            if !filter(&PeerInfo { addr, identity }, host, port) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{host}:{port} is not allowed for {addr}"),
+                ));
+            }
+        }
+        upstream.dial(tailscale, host, port, self.allow_local).await
+    }
+}
+
+/// Returns true if `ip` is in Tailscale's CGNAT or ULA range.
+fn is_tailscale_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            a == 100 && (64..128).contains(&b)
+        }
+        IpAddr::V6(ip) => ip.segments()[..3] == [0xfd7a, 0x115c, 0xa1e0],
+    }
+}
+
+/// A connection dialed by [`Upstream::dial`].
+pub(crate) enum UpstreamStream {
+    Tailnet(Box<Connection>),
+    Local(TcpStream),
+}
+
+impl UpstreamStream {
+    /// Returns the local address of the dialed connection, if known.
+    pub(crate) fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            UpstreamStream::Tailnet(_) => None,
+            UpstreamStream::Local(stream) => stream.local_addr().ok(),
+        }
+    }
+
+    /// Writes data the client sent ahead of the proxy's reply to the
+    /// upstream.
+    pub(crate) async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            UpstreamStream::Tailnet(upstream) => upstream.write_all(buf).await,
+            UpstreamStream::Local(upstream) => upstream.write_all(buf).await,
+        }
+    }
+
+    /// Proxies `conn` to the upstream until both sides reach EOF, returning
+    /// the bytes copied to and from the upstream.
+    pub(crate) async fn proxy(self, conn: &mut Connection) -> io::Result<(u64, u64)> {
+        match self {
+            UpstreamStream::Tailnet(mut upstream) => {
+                tokio::io::copy_bidirectional(conn, &mut *upstream).await
+            }
+            UpstreamStream::Local(mut upstream) => copy_bidirectional(conn, &mut upstream).await,
+        }
+    }
+}
+
+/// A running port forward, created by
+/// [`Tailscale::forward_inbound`](crate::Tailscale::forward_inbound),
+/// [`Tailscale::forward_outbound`](crate::Tailscale::forward_outbound) or
+/// their UDP counterparts.
+///
+/// Use [`stop`](Self::stop) to shut it down gracefully. Dropping the handle
+/// stops accepting and aborts the forwarded connections immediately.
+pub struct Forwarder {
+    local_addr: Option<SocketAddr>,
+    state: Arc<State>,
+    stop: Option<oneshot::Sender<Duration>>,
+    task: Option<JoinHandle<Result<()>>>,
+}
+
+#[derive(Default)]
+pub(crate) struct State {
+    accepting: AtomicBool,
+    active: AtomicUsize,
+    total: AtomicU64,
+    failed: AtomicU64,
+    bytes_to_target: AtomicU64,
+    bytes_from_target: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl State {
+    fn record_error(&self, error: String) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+
+    pub(crate) fn record_bytes(&self, to_target: u64, from_target: u64) {
+        self.bytes_to_target.fetch_add(to_target, Ordering::Relaxed);
+        self.bytes_from_target
+            .fetch_add(from_target, Ordering::Relaxed);
+    }
+}
+
+/// Builds the PROXY protocol header for a connection accepted from the
+/// tailnet.
+async fn proxy_header(
+    tailscale: &Tailscale,
+    conn: &Connection,
+    version: proxy_protocol::Version,
+) -> std::result::Result<Vec<u8>, String> {
+    let source = conn
+        .remote_addr()
+        .map_err(|e| format!("getting the peer address failed: {e}"))?;
+    let destination = conn
+        .local_addr()
+        .map_err(|e| format!("getting the local address failed: {e}"))?;
+    let identity = match version {
+        proxy_protocol::Version::V1 => None,
+        // Funnel connections from the public internet have no identity.
+        proxy_protocol::Version::V2 => tailscale.whois(source).await.ok(),
+    };
+    Ok(proxy_protocol::encode(
+        version,
+        source,
+        destination,
+        identity.as_ref(),
+    ))
+}
+
+/// The largest UDP payload relayed.
+const MAX_DATAGRAM: usize = 65535;
+
+/// How many datagrams a UDP session buffers before dropping new ones.
+const SESSION_QUEUE: usize = 64;
+
+/// A UDP flow from a new source address.
+struct UdpSession {
+    source: SocketAddr,
+    rx: mpsc::Receiver<Vec<u8>>,
+}
+
+/// The UDP flows of a forwarder, by source address.
+#[derive(Default)]
+struct UdpSessions(Mutex<HashMap<SocketAddr, mpsc::Sender<Vec<u8>>>>);
+
+impl UdpSessions {
+    /// Hands `datagram` to the session of `source`, returning a new session
+    /// if there was none.
+    fn dispatch(&self, source: SocketAddr, datagram: Vec<u8>) -> Option<UdpSession> {
+        let mut sessions = self.0.lock().unwrap();
+        sessions.retain(|_, tx| !tx.is_closed());
+        if let Some(tx) = sessions.get(&source) {
+            // Drop the datagram if the session is backed up, as the network
+            // would.
+            let _ = tx.try_send(datagram);
+            return None;
+        }
+        let (tx, rx) = mpsc::channel(SESSION_QUEUE);
+        let _ = tx.try_send(datagram);
+        sessions.insert(source, tx);
+        debug!(%source, "new UDP session");
+        Some(UdpSession { source, rx })
+    }
+}
+
+/// Binds a local UDP socket of the same address family as `target` and
+/// connects it there.
+async fn connect_udp(target: &str) -> std::result::Result<UdpSocket, String> {
+    let addr = tokio::net::lookup_host(target)
+        .await
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("could not resolve {target}"))?;
+    let bind: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind)
+        .await
+        .map_err(|e| format!("binding a UDP socket failed: {e}"))?;
+    socket
+        .connect(addr)
+        .await
+        .map_err(|e| format!("connecting to {target} failed: {e}"))?;
+    Ok(socket)
+}
+
+/// Something accepted by a forwarder's accept loop.
+pub(crate) trait Accepted {
+    /// Returns the span to run the handler in.
+    fn span(&self) -> Span;
+}
+
+impl Accepted for Connection {
+    fn span(&self) -> Span {
+        Connection::span(self).clone()
+    }
+}
+
+impl Accepted for TcpStream {
+    fn span(&self) -> Span {
+        Span::current()
+    }
+}
+
+impl Accepted for UdpSession {
+    fn span(&self) -> Span {
+        Span::current()
+    }
+}
+
+/// Counts a connection as active until dropped.
+struct Active<'a>(&'a State);
+
+impl<'a> Active<'a> {
+    fn new(state: &'a State) -> Self {
+        state.active.fetch_add(1, Ordering::Relaxed);
+        state.total.fetch_add(1, Ordering::Relaxed);
+        Active(state)
+    }
+}
+
+impl Drop for Active<'_> {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Forwarder {
+    /// Forwards connections accepted on the tailnet `listener` to `target`
+    /// on the local network.
+    ///
+    /// With `proxy_protocol`, each connection to `target` starts with a PROXY
+    /// protocol header describing the tailnet peer.
+    pub(crate) fn inbound(
+        listener: Arc<Listener>,
+        tailscale: Arc<Tailscale>,
+        target: String,
+        proxy_protocol: Option<proxy_protocol::Version>,
+    ) -> Self {
+        Self::spawn(
+            None,
+            move || {
+                let listener = Arc::clone(&listener);
+                async move { listener.accept().await }
+            },
+            move |mut conn, state| {
+                let tailscale = Arc::clone(&tailscale);
+                let target = target.clone();
+                async move {
+                    let header = match proxy_protocol {
+                        Some(version) => Some(proxy_header(&tailscale, &conn, version).await?),
+                        None => None,
+                    };
+                    let mut stream = TcpStream::connect(&target)
+                        .await
+                        .map_err(|e| format!("connecting to {target} failed: {e}"))?;
+                    if let Some(header) = header {
+                        stream
+                            .write_all(&header)
+                            .await
+                            .map_err(|e| format!("sending PROXY header to {target} failed: {e}"))?;
+                    }
+                    let (to_target, from_target) = copy_bidirectional(&mut conn, &mut stream)
+                        .await
+                        .map_err(|e| format!("forwarding to {target} failed: {e}"))?;
+                    state.record_bytes(to_target, from_target);
+                    Ok(())
+                }
+            },
+        )
+    }
+
+    /// Forwards connections accepted on the local `listener` to `target` on
+    /// the tailnet.
+    pub(crate) fn outbound(
+        listener: TcpListener,
+        tailscale: Arc<Tailscale>,
+        target: String,
+    ) -> Self {
+        let local_addr = listener.local_addr().ok();
+        let listener = Arc::new(listener);
+        Self::spawn(
+            local_addr,
+            move || {
+                let listener = Arc::clone(&listener);
+                async move {
+                    let (stream, _) = listener
+                        .accept()
+                        .await
+                        .map_err(|e| TailscaleError::AcceptFailed(e.to_string()))?;
+                    Ok(stream)
+                }
+            },
+            move |mut stream, state| {
+                let tailscale = Arc::clone(&tailscale);
+                let target = target.clone();
+                async move {
+                    let mut conn = tailscale
+                        .connect(NetworkType::Tcp, &target)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let (from_target, to_target) = copy_bidirectional(&mut conn, &mut stream)
+                        .await
+                        .map_err(|e| format!("forwarding to {target} failed: {e}"))?;
+                    state.record_bytes(to_target, from_target);
+                    Ok(())
+                }
+            },
+        )
+    }
+
+    /// Relays UDP datagrams received on the tailnet `socket` to `target` on
+    /// the local network, from one local socket per tailnet peer address.
+    pub(crate) fn udp_inbound(
+        socket: udp::UdpSocket,
+        target: String,
+        idle_timeout: Duration,
+    ) -> Self {
+        let socket = Arc::new(socket);
+        let sessions = Arc::new(UdpSessions::default());
+        let accept_socket = Arc::clone(&socket);
+        Self::spawn(
+            None,
+            move || {
+                let socket = Arc::clone(&accept_socket);
+                let sessions = Arc::clone(&sessions);
+                async move {
+                    let mut buf = vec![0; MAX_DATAGRAM];
+                    loop {
+                        let (n, peer) = socket
+                            .recv_from(&mut buf)
+                            .await
+                            .map_err(|e| TailscaleError::AcceptFailed(e.to_string()))?;
+                        if let Some(session) = sessions.dispatch(peer, buf[..n].to_vec()) {
+                            return Ok(session);
+                        }
+                    }
+                }
+            },
+            move |session: UdpSession, state| {
+                let socket = Arc::clone(&socket);
+                let target = target.clone();
+                async move {
+                    let UdpSession {
+                        source: peer,
+                        mut rx,
+                    } = session;
+                    let local = connect_udp(&target).await?;
+                    let mut buf = vec![0; MAX_DATAGRAM];
+                    let (mut sent, mut received) = (0, 0);
+                    let res = loop {
+                        tokio::select! {
+                            datagram = rx.recv() => {
+                                let Some(datagram) = datagram else { break Ok(()) };
+                                if let Err(e) = local.send(&datagram).await {
+                                    break Err(format!("sending to {target} failed: {e}"));
+                                }
+                                sent += datagram.len() as u64;
+                            }
+                            n = local.recv(&mut buf) => match n {
+                                Ok(n) => {
+                                    if let Err(e) = socket.send_to(&buf[..n], peer).await {
+                                        break Err(format!("replying to {peer} failed: {e}"));
+                                    }
+                                    received += n as u64;
+                                }
+                                Err(e) => break Err(format!("receiving from {target} failed: {e}")),
+                            },
+                            () = tokio::time::sleep(idle_timeout) => break Ok(()),
+                        }
+                    };
+                    debug!(%peer, "UDP session ended");
+                    state.record_bytes(sent, received);
+                    res
+                }
+            },
+        )
+    }
+
+    /// Relays UDP datagrams received on the local `socket` to `target` on the
+    /// tailnet, from one tailnet socket bound to `bind` per local source
+    /// address.
+    pub(crate) fn udp_outbound(
+        socket: UdpSocket,
+        tailscale: Arc<Tailscale>,
+        bind: SocketAddr,
+        target: SocketAddr,
+        idle_timeout: Duration,
+    ) -> Self {
+        let local_addr = socket.local_addr().ok();
+        let socket = Arc::new(socket);
+        let sessions = Arc::new(UdpSessions::default());
+        let accept_socket = Arc::clone(&socket);
+        Self::spawn(
+            local_addr,
+            move || {
+                let socket = Arc::clone(&accept_socket);
+                let sessions = Arc::clone(&sessions);
+                async move {
+                    let mut buf = vec![0; MAX_DATAGRAM];
+                    loop {
+                        let (n, source) = socket
+                            .recv_from(&mut buf)
+                            .await
+                            .map_err(|e| TailscaleError::AcceptFailed(e.to_string()))?;
+                        if let Some(session) = sessions.dispatch(source, buf[..n].to_vec()) {
+                            return Ok(session);
+                        }
+                    }
+                }
+            },
+            move |session: UdpSession, state| {
+                let tailscale = Arc::clone(&tailscale);
+                let socket = Arc::clone(&socket);
+                async move {
+                    let UdpSession { source, mut rx } = session;
+                    let tailnet = tailscale
+                        .listen_packet(bind)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let mut buf = vec![0; MAX_DATAGRAM];
+                    let (mut sent, mut received) = (0, 0);
+                    let res = loop {
+                        tokio::select! {
+                            datagram = rx.recv() => {
+                                let Some(datagram) = datagram else { break Ok(()) };
+                                if let Err(e) = tailnet.send_to(&datagram, target).await {
+                                    break Err(format!("sending to {target} failed: {e}"));
+                                }
+                                sent += datagram.len() as u64;
+                            }
+                            n = tailnet.recv_from(&mut buf) => match n {
+                                // Other peers may find the port; only relay
+                                // replies from the target.
+                                Ok((_, from)) if from != target => {}
+                                Ok((n, _)) => {
+                                    if let Err(e) = socket.send_to(&buf[..n], source).await {
+                                        break Err(format!("replying to {source} failed: {e}"));
+                                    }
+                                    received += n as u64;
+                                }
+                                Err(e) => break Err(format!("receiving from {target} failed: {e}")),
+                            },
+                            () = tokio::time::sleep(idle_timeout) => break Ok(()),
+                        }
+                    };
+                    debug!(%source, "UDP session ended");
+                    state.record_bytes(sent, received);
+                    res
+                }
+            },
+        )
+    }
+
+    pub(crate) fn spawn<C, A, AF, H, HF>(
+        local_addr: Option<SocketAddr>,
+        accept: A,
+        handle: H,
+    ) -> Self
+    where
+        C: Accepted + Send + 'static,
+        A: FnMut() -> AF + Send + 'static,
+        AF: Future<Output = Result<C>> + Send + 'static,
+        H: Fn(C, Arc<State>) -> HF + Send + 'static,
+        HF: Future<Output = std::result::Result<(), String>> + Send + 'static,
+    {
+        let state = Arc::<State>::default();
+        state.accepting.store(true, Ordering::Relaxed);
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let task = tokio::spawn(run(Arc::clone(&state), stop_rx, accept, handle));
+        Forwarder {
+            local_addr,
+            state,
+            stop: Some(stop_tx),
+            task: Some(task),
+        }
+    }
+
+    /// Returns the local address connections are accepted on, for forwarders
+    /// created with
+    /// [`Tailscale::forward_outbound`](crate::Tailscale::forward_outbound).
+    ///
+    /// Useful when listening on port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Returns the forwarder's counters.
+    pub fn stats(&self) -> ForwarderStats {
+        ForwarderStats {
+            active_connections: self.state.active.load(Ordering::Relaxed),
+            total_connections: self.state.total.load(Ordering::Relaxed),
+            failed_connections: self.state.failed.load(Ordering::Relaxed),
+            bytes_to_target: self.state.bytes_to_target.load(Ordering::Relaxed),
+            bytes_from_target: self.state.bytes_from_target.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the error that ended the most recent failed connection, if any.
+    pub fn last_error(&self) -> Option<String> {
+        self.state.last_error.lock().unwrap().clone()
+    }
+
+    /// Returns true while the forwarder is accepting connections.
+    pub fn is_running(&self) -> bool {
+        self.state.accepting.load(Ordering::Relaxed)
+    }
+
+    /// Stops accepting connections and waits up to `grace` for the forwarded
+    /// connections to finish, aborting any that remain.
+    ///
+    /// Returns the final counters.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that stopped the forwarder from accepting
+    /// connections, if it failed before being stopped.
+    pub async fn stop(mut self, grace: Duration) -> Result<ForwarderStats> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(grace);
+        }
+        if let Some(task) = self.task.take() {
+            task.await??;
+        }
+        Ok(self.stats())
+    }
+}
+
+impl Drop for Forwarder {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+async fn run<C, A, AF, H, HF>(
+    state: Arc<State>,
+    mut stop: oneshot::Receiver<Duration>,
+    mut accept: A,
+    handle: H,
+) -> Result<()>
+where
+    C: Accepted + Send + 'static,
+    A: FnMut() -> AF,
+    AF: Future<Output = Result<C>>,
+    H: Fn(C, Arc<State>) -> HF,
+    HF: Future<Output = std::result::Result<(), String>> + Send + 'static,
+{
+    let mut handlers = JoinSet::new();
+    let (res, grace) = loop {
+        tokio::select! {
+            accepted = accept() => match accepted {
+                Ok(conn) => {
+                    let state = Arc::clone(&state);
+                    let span = conn.span();
+                    let forward = handle(conn, Arc::clone(&state));
+                    handlers.spawn(
+                        async move {
+                            let _active = Active::new(&state);
+                            if let Err(e) = forward.await {
+                                warn!(error = %e, "forwarded connection failed");
+                                state.record_error(e);
+                            }
+                        }
+                        .instrument(span),
+                    );
+                }
+                Err(e) => {
+                    warn!(error = %e, "forwarder stopped accepting connections");
+                    break (Err(e), None);
+                }
+            },
+            Some(_) = handlers.join_next(), if !handlers.is_empty() => {}
+            grace = &mut stop => break (Ok(()), Some(grace.unwrap_or_default())),
+        }
+    };
+
+    state.accepting.store(false, Ordering::Relaxed);
+
+    // After a failed accept, open connections are kept until stopped.
+    let grace = match grace {
+        Some(grace) => grace,
+        None => (&mut stop).await.unwrap_or_default(),
+    };
+    debug!(?grace, open = handlers.len(), "stopping forwarder");
+    let drained = tokio::time::timeout(grace, async {
+        while handlers.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        debug!(aborted = handlers.len(), "aborting forwarded connections");
+        handlers.shutdown().await;
+    }
+    res
+}
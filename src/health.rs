@@ -0,0 +1,68 @@
+//! Health reporting for liveness and readiness probes.
+
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+use crate::{
+    json::{empty_as_none, null_as_default, unix_time},
+    tailscale::BackendState,
+};
+
+/// A snapshot of a node's health, returned by
+/// [`Tailscale::health`](crate::Tailscale::health).
+///
+/// # Examples
+///
+/// Serving a readiness probe:
+///
+/// ```no_run
+/// # async fn example(ts: tailscale2::Tailscale) -> tailscale2::Result<()> {
+/// let health = ts.health().await?;
+/// let status = if health.is_ready() { 200 } else { 503 };
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Health {
+    /// The state of the node's backend.
+    pub backend_state: BackendState,
+    /// Whether the control plane reports the node as connected.
+    pub control: bool,
+    /// The home DERP region, or `None` if no DERP relay is reachable.
+    #[serde(deserialize_with = "empty_as_none")]
+    pub derp: Option<String>,
+    /// When the node key expires, or `None` if key expiry is disabled.
+    #[serde(deserialize_with = "unix_time")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::json::serialize_unix_time")
+    )]
+    pub key_expiry: Option<SystemTime>,
+    /// Whether the node key has expired.
+    pub key_expired: bool,
+    /// The current health warnings, such as DNS or network problems.
+    #[serde(deserialize_with = "null_as_default")]
+    pub warnings: Vec<String>,
+}
+
+impl Health {
+    /// Returns true if the node is running with a valid key, suitable for a
+    /// liveness probe.
+    ///
+    /// Unlike [`is_ready`](Self::is_ready) this does not depend on reaching
+    /// the control plane or a DERP relay, so short network outages do not get
+    /// the process restarted.
+    pub fn is_live(&self) -> bool {
+        self.backend_state.is_running() && !self.key_expired
+    }
+
+    /// Returns true if the node is live and connected to both the control
+    /// plane and a DERP relay, suitable for a readiness probe.
+    ///
+    /// Warnings are not taken into account.
+    pub fn is_ready(&self) -> bool {
+        self.is_live() && self.control && self.derp.is_some()
+    }
+}
@@ -0,0 +1,311 @@
+//! An HTTP `CONNECT` proxy server for tailnet clients.
+//!
+//! [`HttpConnectServer`] accepts HTTP proxy clients on a tailnet listener and
+//! tunnels each `CONNECT host:port` request to its target, either on the
+//! tailnet or on this node's local network. This suits browsers and tools
+//! that only speak HTTP proxies; see [`socks5`](crate::socks5) for SOCKS5
+//! clients. Like the SOCKS5 server, it does not ask clients to authenticate,
+//! filters targets per client with [`allow`](HttpConnectServer::allow) and
+//! refuses loopback and link-local targets unless enabled with
+//! [`allow_local_addresses`](HttpConnectServer::allow_local_addresses).
+//!
+//! Other methods, i.e. plain-HTTP proxying, are answered with
+//! `405 Method Not Allowed`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use tailscale2::*;
+//! # async fn example(ts: Arc<Tailscale>) -> Result<()> {
+//! let listener = ts.listener(NetworkType::Tcp, ":3128").await?;
+//! let proxy = http_connect::HttpConnectServer::new(Arc::clone(&ts)).serve(listener);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{io, sync::Arc};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tracing::debug;
+
+use crate::{
+    forward::{Forwarder, TargetPolicy, Upstream},
+    identity::PeerInfo,
+    tailscale::{Connection, Listener, Tailscale},
+};
+
+/// The largest request head accepted, in bytes.
+const MAX_HEAD: usize = 8 * 1024;
+
+/// An HTTP `CONNECT` proxy server, started on a listener with
+/// [`serve`](Self::serve).
+#[derive(Clone)]
+pub struct HttpConnectServer {
+    tailscale: Arc<Tailscale>,
+    upstream: Upstream,
+    targets: TargetPolicy,
+}
+
+impl HttpConnectServer {
+    /// Creates a server that dials targets through `tailscale` or the local
+    /// network according to [`Upstream::Auto`], letting every client
+    /// connect to any target except loopback and link-local addresses.
+    pub fn new(tailscale: Arc<Tailscale>) -> Self {
+        HttpConnectServer {
+            tailscale,
+            upstream: Upstream::default(),
+            targets: TargetPolicy::default(),
+        }
+    }
+
+    /// Sets where targets are dialed.
+    pub fn upstream(mut self, upstream: Upstream) -> Self {
+        self.upstream = upstream;
+        self
+    }
+
+    /// Sets a filter deciding which targets each client may connect to,
+    /// replacing any previous filter.
+    ///
+    /// See [`Socks5Server::allow`](crate::socks5::Socks5Server::allow).
+    /// Requests it returns false for are answered with `403 Forbidden`.
+    pub fn allow<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&PeerInfo, &str, u16) -> bool + Send + Sync + 'static,
+    {
+        self.targets.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Sets whether clients may connect to loopback and link-local
+    /// addresses of this node, which are refused by default.
+    ///
+    /// See
+    /// [`Socks5Server::allow_local_addresses`](crate::socks5::Socks5Server::allow_local_addresses).
+    pub fn allow_local_addresses(mut self, allow: bool) -> Self {
+        self.targets.allow_local = allow;
+        self
+    }
+
+    /// Serves proxy clients accepted on `listener` until the returned
+    /// [`Forwarder`] is stopped or dropped.
+    ///
+    /// The forwarder's counters cover tunnelled connections, including
+    /// rejected requests.
+    pub fn serve(self, listener: Arc<Listener>) -> Forwarder {
+        let server = Arc::new(self);
+        Forwarder::spawn(
+            None,
+            move || {
+                let listener = Arc::clone(&listener);
+                async move { listener.accept().await }
+            },
+            move |mut conn, state| {
+                let server = Arc::clone(&server);
+                async move {
+                    let (sent, received) = server
+                        .handle(&mut conn)
+                        .await
+                        .map_err(|e| format!("HTTP CONNECT failed: {e}"))?;
+                    state.record_bytes(sent, received);
+                    Ok(())
+                }
+            },
+        )
+    }
+
+    async fn handle(&self, conn: &mut Connection) -> io::Result<(u64, u64)> {
+        let (head, early_data) = match read_head(conn).await {
+            Ok(head) => head,
+            Err(e) => {
+                respond(conn, "400 Bad Request").await?;
+                return Err(e);
+            }
+        };
+
+        let mut parts = head.lines().next().unwrap_or_default().split(' ');
+        let (method, authority) = (parts.next().unwrap_or_default(), parts.next());
+        if method != "CONNECT" {
+            respond(conn, "405 Method Not Allowed\r\nAllow: CONNECT").await?;
+            return Err(invalid(format!("unsupported method {method}")));
+        }
+        let Some((host, port)) = authority.and_then(parse_authority) else {
+            respond(conn, "400 Bad Request").await?;
+            return Err(invalid(format!("invalid CONNECT target {authority:?}")));
+        };
+
+        debug!(%host, port, "HTTP CONNECT");
+        let mut upstream = match self
+            .targets
+            .dial(&self.tailscale, conn, self.upstream, host, port)
+            .await
+        {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                let status = match e.kind() {
+                    io::ErrorKind::PermissionDenied => "403 Forbidden",
+                    io::ErrorKind::TimedOut => "504 Gateway Timeout",
+                    _ => "502 Bad Gateway",
+                };
+                respond(conn, status).await?;
+                return Err(e);
+            }
+        };
+        respond(conn, "200 Connection Established").await?;
+        upstream.write_all(&early_data).await?;
+        let (sent, received) = upstream.proxy(conn).await?;
+        Ok((sent + early_data.len() as u64, received))
+    }
+}
+
+/// Reads the request head up to the blank line, returning it along with any
+/// bytes the client sent after it.
+async fn read_head<S>(conn: &mut S) -> io::Result<(String, Vec<u8>)>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0; 1024];
+    loop {
+        let n = conn.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        // Resume the search a few bytes back in case the terminator spans
+        // two reads.
+        let from = buf.len().saturating_sub(3);
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(end) = buf[from..].windows(4).position(|w| w == b"\r\n\r\n") {
+            let end = from + end + 4;
+            let early_data = buf.split_off(end);
+            let head = String::from_utf8(buf)
+                .map_err(|_| invalid("request head is not UTF-8".to_string()))?;
+            return Ok((head, early_data));
+        }
+        if buf.len() > MAX_HEAD {
+            return Err(invalid("request head too large".to_string()));
+        }
+    }
+}
+
+/// Splits `host:port` or `[v6]:port`.
+fn parse_authority(authority: &str) -> Option<(&str, u16)> {
+    let (host, port) = authority.rsplit_once(':')?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
+
+async fn respond(conn: &mut Connection, status: &str) -> io::Result<()> {
+    conn.write_all(format!("HTTP/1.1 {status}\r\n\r\n").as_bytes())
+        .await
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::ReadBuf;
+
+    use super::*;
+
+    /// A stream returning one of its chunks per read, then EOF.
+    struct Chunks(VecDeque<Vec<u8>>);
+
+    impl Chunks {
+        fn new<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> Self {
+            Chunks(chunks.into_iter().map(<[u8]>::to_vec).collect())
+        }
+    }
+
+    impl AsyncRead for Chunks {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if let Some(chunk) = self.0.pop_front() {
+                buf.put_slice(&chunk);
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn head_with_early_data() {
+        let mut conn =
+            Chunks::new([&b"CONNECT peer:443 HTTP/1.1\r\nHost: peer:443\r\n\r\nhello"[..]]);
+        let (head, early_data) = read_head(&mut conn).await.unwrap();
+        assert_eq!(head, "CONNECT peer:443 HTTP/1.1\r\nHost: peer:443\r\n\r\n");
+        assert_eq!(early_data, b"hello");
+    }
+
+    #[tokio::test]
+    async fn terminator_split_across_reads() {
+        for split in 1..4 {
+            let request = b"CONNECT peer:443 HTTP/1.1\r\n\r\nhello";
+            let at = request.len() - 5 - split;
+            let mut conn = Chunks::new([&request[..at], &request[at..]]);
+            let (head, early_data) = read_head(&mut conn).await.unwrap();
+            assert_eq!(head, "CONNECT peer:443 HTTP/1.1\r\n\r\n", "split {split}");
+            assert_eq!(early_data, b"hello");
+        }
+
+        let mut conn = Chunks::new([&b"CONNECT peer:443 HTTP/1.1\r"[..], b"\n", b"\r", b"\n"]);
+        let (head, early_data) = read_head(&mut conn).await.unwrap();
+        assert_eq!(head, "CONNECT peer:443 HTTP/1.1\r\n\r\n");
+        assert!(early_data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn oversize_head() {
+        let chunk = [b'a'; 1024];
+        let mut conn = Chunks::new(std::iter::repeat_n(&chunk[..], MAX_HEAD / 1024 + 2));
+        let e = read_head(&mut conn).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "request head too large");
+    }
+
+    #[tokio::test]
+    async fn eof_before_head_ends() {
+        let mut conn = Chunks::new([&b"CONNECT peer:443 HTTP/1.1\r\n"[..]]);
+        let e = read_head(&mut conn).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn head_not_utf8() {
+        let mut conn = Chunks::new([&b"CONNECT \xff:443 HTTP/1.1\r\n\r\n"[..]]);
+        let e = read_head(&mut conn).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn authority() {
+        assert_eq!(parse_authority("peer:443"), Some(("peer", 443)));
+        assert_eq!(parse_authority("100.64.0.1:80"), Some(("100.64.0.1", 80)));
+        assert_eq!(
+            parse_authority("[fd7a:115c:a1e0::1]:8080"),
+            Some(("fd7a:115c:a1e0::1", 8080))
+        );
+        assert_eq!(parse_authority("peer"), None);
+        assert_eq!(parse_authority(":443"), None);
+        assert_eq!(parse_authority("[]:443"), None);
+        assert_eq!(parse_authority("peer:http"), None);
+        assert_eq!(parse_authority("peer:65536"), None);
+    }
+}
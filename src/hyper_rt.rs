@@ -0,0 +1,83 @@
+//! hyper's IO traits for [`Connection`], enabled by the `hyper` feature.
+//!
+//! hyper 1.x reads and writes through its own [`hyper::rt::Read`] and
+//! [`hyper::rt::Write`] traits instead of tokio's. Implementing them here
+//! lets a `Connection` be passed straight to hyper's connection builders,
+//! without wrapping it in hyper-util's `TokioIo`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use std::convert::Infallible;
+//! # use tailscale2::*;
+//! use hyper::{Response, server::conn::http1, service::service_fn};
+//!
+//! # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+//! let listener = ts.listener(NetworkType::Tcp, 80).await?;
+//! let conn = listener.accept().await?;
+//! let service = service_fn(|_req| async { Ok::<_, Infallible>(Response::new(String::from("hi"))) });
+//! http1::Builder::new().serve_connection(conn, service).await.ok();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use hyper::rt::ReadBufCursor;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::tailscale::Connection;
+
+impl hyper::rt::Read for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        // Safety: the bytes tokio's `ReadBuf` reports as filled were
+        // initialized by the read, and only that many are advanced over.
+        let n = unsafe {
+            let mut tbuf = ReadBuf::uninit(buf.as_mut());
+            match AsyncRead::poll_read(self, cx, &mut tbuf) {
+                Poll::Ready(Ok(())) => tbuf.filled().len(),
+                other => return other,
+            }
+        };
+        unsafe { buf.advance(n) };
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl hyper::rt::Write for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(self, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_shutdown(self, cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        AsyncWrite::is_write_vectored(self)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write_vectored(self, cx, bufs)
+    }
+}
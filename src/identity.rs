@@ -0,0 +1,109 @@
+//! Identity of peers on the tailnet and authorization of incoming connections.
+
+use std::{future::Future, net::SocketAddr, pin::Pin, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::json::null_as_default;
+
+/// The identity of a peer on the tailnet, as resolved by [`Tailscale::whois`](crate::Tailscale::whois).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeerIdentity {
+    /// The address the peer connected from.
+    pub addr: SocketAddr,
+    /// The stable ID of the peer's node.
+    pub node_id: String,
+    /// The MagicDNS name of the peer's node.
+    pub node_name: String,
+    /// The login name of the user owning the node, e.g. `alice@example.com`.
+    pub login_name: String,
+    /// The display name of the user owning the node.
+    pub display_name: String,
+    /// The ACL tags assigned to the node, e.g. `tag:server`.
+    pub tags: Vec<String>,
+}
+
+/// The identity of this node, as returned by [`Tailscale::self_info`](crate::Tailscale::self_info).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SelfInfo {
+    /// The stable ID of this node.
+    pub node_id: String,
+    /// The MagicDNS name of this node without the trailing dot, e.g.
+    /// `my-host.tail1234.ts.net`.
+    pub dns_name: String,
+    /// The name of the tailnet the node belongs to.
+    pub tailnet_name: String,
+    /// The MagicDNS suffix of the tailnet, e.g. `tail1234.ts.net`.
+    pub magic_dns_suffix: String,
+    /// The ACL tags assigned to this node, e.g. `tag:server`.
+    #[serde(deserialize_with = "null_as_default")]
+    pub tags: Vec<String>,
+}
+
+/// What a [`Listener::with_filter`](crate::Listener::with_filter) filter knows
+/// about the peer of an incoming connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeerInfo {
+    /// The address the peer connected from.
+    pub addr: SocketAddr,
+    /// The peer's tailnet identity, or `None` for connections from the
+    /// public internet via Funnel.
+    pub identity: Option<PeerIdentity>,
+}
+
+impl PeerInfo {
+    /// Returns true if the connection came from the public internet via
+    /// Funnel rather than from the tailnet.
+    pub fn is_funnel(&self) -> bool {
+        self.identity.is_none()
+    }
+
+    /// Returns the peer node's ACL tags, which are empty for untagged nodes
+    /// and Funnel connections.
+    pub fn tags(&self) -> &[String] {
+        self.identity.as_ref().map_or(&[], |id| &id.tags)
+    }
+}
+
+/// The result of an authorization check on an incoming connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Decision {
+    /// Hand the connection to the application.
+    Allow,
+    /// Close the connection before the application sees it.
+    Deny,
+}
+
+/// Callback evaluated for every incoming connection before `accept()` returns it.
+pub(crate) type IncomingCallback =
+    Arc<dyn Fn(PeerIdentity) -> Pin<Box<dyn Future<Output = Decision> + Send>> + Send + Sync>;
+
+/// Filter set with [`Listener::with_filter`](crate::Listener::with_filter).
+pub(crate) type ListenerFilter = Arc<dyn Fn(&PeerInfo) -> Decision + Send + Sync>;
+
+/// JSON written by `tailscale_whois`.
+#[derive(Deserialize)]
+pub(crate) struct WhoIsResponse {
+    node_id: String,
+    node_name: String,
+    login_name: String,
+    display_name: String,
+    tags: Option<Vec<String>>,
+}
+
+impl WhoIsResponse {
+    pub(crate) fn into_identity(self, addr: SocketAddr) -> PeerIdentity {
+        PeerIdentity {
+            addr,
+            node_id: self.node_id,
+            node_name: self.node_name,
+            login_name: self.login_name,
+            display_name: self.display_name,
+            tags: self.tags.unwrap_or_default(),
+        }
+    }
+}
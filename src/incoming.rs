@@ -0,0 +1,57 @@
+//! Adapter exposing a [`Listener`] as a stream of connections.
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+use futures_core::Stream;
+
+use crate::tailscale::{Connection, Listener, Result};
+
+type AcceptFuture = Pin<Box<dyn Future<Output = Result<Connection>> + Send>>;
+
+/// A [`Stream`] of connections accepted from a [`Listener`].
+///
+/// Errors are converted to [`io::Error`] so the stream can be handed to any
+/// server that consumes a generic incoming stream of
+/// `io::Result<impl AsyncRead + AsyncWrite>`. The stream never ends on its own.
+///
+/// Created by [`Listener::incoming`].
+pub struct Incoming {
+    listener: Arc<Listener>,
+    accept: Option<AcceptFuture>,
+}
+
+impl Incoming {
+    /// Wraps a listener in a stream of accepted connections.
+    pub fn new(listener: Arc<Listener>) -> Self {
+        Incoming {
+            listener,
+            accept: None,
+        }
+    }
+
+    /// Returns the underlying listener.
+    pub fn listener(&self) -> &Arc<Listener> {
+        &self.listener
+    }
+}
+
+impl Stream for Incoming {
+    type Item = io::Result<Connection>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let accept = this.accept.get_or_insert_with(|| {
+            let listener = Arc::clone(&this.listener);
+            Box::pin(async move { listener.accept().await })
+        });
+        let res = ready!(accept.as_mut().poll(cx));
+        this.accept = None;
+        Poll::Ready(Some(res.map_err(io::Error::from)))
+    }
+}
@@ -0,0 +1,314 @@
+//! Helpers for proxying between tailnet connections and local sockets.
+
+use std::{
+    fs::File,
+    future::Future,
+    io,
+    ops::{Bound, RangeBounds},
+    os::fd::AsFd,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, Interest},
+    net::{TcpStream, UnixStream},
+};
+
+use crate::tailscale::Connection;
+
+/// A local socket that [`copy_bidirectional`] can proxy to.
+///
+/// Implemented for tokio's [`TcpStream`] and [`UnixStream`].
+pub trait LocalStream: AsyncRead + AsyncWrite + AsFd + Unpin + sealed::Sealed {}
+
+impl LocalStream for TcpStream {}
+impl LocalStream for UnixStream {}
+
+mod sealed {
+    use super::*;
+
+    pub trait Sealed {
+        /// Waits for `interest` and runs `f`, retrying while it would block.
+        fn ready_io<R>(
+            &self,
+            interest: Interest,
+            f: impl FnMut() -> io::Result<R>,
+        ) -> impl Future<Output = io::Result<R>>;
+    }
+
+    impl Sealed for TcpStream {
+        fn ready_io<R>(
+            &self,
+            interest: Interest,
+            f: impl FnMut() -> io::Result<R>,
+        ) -> impl Future<Output = io::Result<R>> {
+            self.async_io(interest, f)
+        }
+    }
+
+    impl Sealed for UnixStream {
+        fn ready_io<R>(
+            &self,
+            interest: Interest,
+            f: impl FnMut() -> io::Result<R>,
+        ) -> impl Future<Output = io::Result<R>> {
+            self.async_io(interest, f)
+        }
+    }
+}
+
+/// Copies data in both directions between a tailnet connection and a local
+/// socket until both sides have reached EOF.
+///
+/// On Linux the data is moved with `splice(2)` through a kernel pipe, so it
+/// never passes through userspace; elsewhere this is
+/// [`tokio::io::copy_bidirectional`]. On Linux, when one side reaches EOF
+/// the write half of the other is shut down, so the peer sees EOF as well.
+///
+/// Returns the number of bytes copied from `conn` to `stream` and from
+/// `stream` to `conn`, which are also counted towards the connection's tag.
+///
+/// # Errors
+///
+/// Returns the first IO error on either side; the copy in the other
+/// direction is abandoned.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn example(listener: std::sync::Arc<tailscale2::Listener>) -> std::io::Result<()> {
+/// let mut conn = listener.accept().await.unwrap();
+/// let mut upstream = tokio::net::TcpStream::connect("127.0.0.1:8080").await?;
+/// tailscale2::io::copy_bidirectional(&mut conn, &mut upstream).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn copy_bidirectional<S: LocalStream>(
+    conn: &mut Connection,
+    stream: &mut S,
+) -> io::Result<(u64, u64)> {
+    #[cfg(target_os = "linux")]
+    {
+        splice::copy_bidirectional(conn, stream).await
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        tokio::io::copy_bidirectional(conn, stream).await
+    }
+}
+
+/// The most bytes [`Connection::send_file`] sends per system call.
+const SEND_FILE_CHUNK: usize = 1 << 20;
+
+impl Connection {
+    /// Sends the `range` of bytes of `file` over the connection, returning
+    /// the number of bytes sent.
+    ///
+    /// On Linux the data is moved with `sendfile(2)`, so file servers skip
+    /// copying it through userspace; elsewhere it is read in chunks and
+    /// written. The file's own offset is left unchanged. An unbounded range
+    /// ends at the file's current length. `MSG_ZEROCOPY` is not used, as
+    /// the connection's socket is a Unix socket, which does not support it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the file or writing to the connection
+    /// fails, or [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) if the file
+    /// ends before the range does. Bytes sent before the error are not
+    /// reported.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn example(listener: std::sync::Arc<tailscale2::Listener>) -> std::io::Result<()> {
+    /// let mut conn = listener.accept().await.unwrap();
+    /// let file = std::fs::File::open("index.html")?;
+    /// conn.send_file(&file, ..).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_file(
+        &mut self,
+        file: &File,
+        range: impl RangeBounds<u64>,
+    ) -> io::Result<u64> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => file.metadata()?.len(),
+        };
+
+        let mut offset = start;
+        while offset < end {
+            let len =
+                usize::try_from(end - offset).map_or(SEND_FILE_CHUNK, |n| n.min(SEND_FILE_CHUNK));
+            let n = self.send_file_chunk(file, offset, len).await?;
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file ended before the requested range",
+                ));
+            }
+            self.record_write(n);
+            offset += n as u64;
+        }
+        Ok(offset.saturating_sub(start))
+    }
+
+    /// Sends up to `len` bytes of `file` from `offset`, returning 0 at the
+    /// end of the file.
+    #[cfg(target_os = "linux")]
+    async fn send_file_chunk(&self, file: &File, offset: u64, len: usize) -> io::Result<usize> {
+        let mut off = offset as libc::off64_t;
+        self.async_io(Interest::WRITABLE, |fd| {
+            Ok(nix::sys::sendfile::sendfile64(
+                fd,
+                file,
+                Some(&mut off),
+                len,
+            )?)
+        })
+        .await
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn send_file_chunk(&self, file: &File, offset: u64, len: usize) -> io::Result<usize> {
+        use std::os::unix::fs::FileExt;
+
+        let mut buf = vec![0; len];
+        let n = file.read_at(&mut buf, offset)?;
+        let mut written = 0;
+        while written < n {
+            written += self
+                .async_io(Interest::WRITABLE, |fd| {
+                    Ok(nix::unistd::write(fd, &buf[written..n])?)
+                })
+                .await?;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod splice {
+    use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+
+    use nix::{
+        fcntl::{OFlag, SpliceFFlags},
+        sys::socket::Shutdown,
+    };
+
+    use super::*;
+
+    /// The most bytes moved by a single `splice(2)` call, matching the
+    /// default pipe capacity.
+    const CHUNK: usize = 64 * 1024;
+
+    pub(super) async fn copy_bidirectional<S: LocalStream>(
+        conn: &Connection,
+        stream: &S,
+    ) -> io::Result<(u64, u64)> {
+        let conn_to_stream = async {
+            let pipe = Pipe::new()?;
+            let mut total = 0;
+            loop {
+                let n = conn
+                    .async_io(Interest::READABLE, |fd| pipe.fill_from(fd))
+                    .await?;
+                if n == 0 {
+                    break;
+                }
+                conn.record_read(n);
+                let mut left = n;
+                while left > 0 {
+                    left -= stream
+                        .ready_io(Interest::WRITABLE, || pipe.drain_into(stream.as_fd(), left))
+                        .await?;
+                }
+                total += n as u64;
+            }
+            shutdown_write(stream.as_fd())?;
+            Ok::<_, io::Error>(total)
+        };
+
+        let stream_to_conn = async {
+            let pipe = Pipe::new()?;
+            let mut total = 0;
+            loop {
+                let n = stream
+                    .ready_io(Interest::READABLE, || pipe.fill_from(stream.as_fd()))
+                    .await?;
+                if n == 0 {
+                    break;
+                }
+                let mut left = n;
+                while left > 0 {
+                    let written = conn
+                        .async_io(Interest::WRITABLE, |fd| pipe.drain_into(fd, left))
+                        .await?;
+                    conn.record_write(written);
+                    left -= written;
+                }
+                total += n as u64;
+            }
+            conn.shutdown_write()?;
+            Ok::<_, io::Error>(total)
+        };
+
+        tokio::try_join!(conn_to_stream, stream_to_conn)
+    }
+
+    /// A kernel pipe that data is spliced through on its way between sockets.
+    struct Pipe {
+        read: OwnedFd,
+        write: OwnedFd,
+    }
+
+    impl Pipe {
+        fn new() -> io::Result<Self> {
+            let (read, write) = nix::unistd::pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC)?;
+            Ok(Pipe { read, write })
+        }
+
+        /// Moves up to [`CHUNK`] bytes from `src` into the empty pipe,
+        /// returning 0 at EOF.
+        fn fill_from(&self, src: BorrowedFd<'_>) -> io::Result<usize> {
+            Ok(nix::fcntl::splice(
+                src,
+                None,
+                &self.write,
+                None,
+                CHUNK,
+                SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_NONBLOCK,
+            )?)
+        }
+
+        /// Moves up to `len` buffered bytes from the pipe into `dst`.
+        fn drain_into(&self, dst: BorrowedFd<'_>, len: usize) -> io::Result<usize> {
+            let n = nix::fcntl::splice(
+                &self.read,
+                None,
+                dst,
+                None,
+                len,
+                SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_NONBLOCK,
+            )?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            Ok(n)
+        }
+    }
+
+    fn shutdown_write(fd: BorrowedFd<'_>) -> io::Result<()> {
+        match nix::sys::socket::shutdown(fd.as_raw_fd(), Shutdown::Write) {
+            // The peer may already have closed the connection.
+            Ok(()) | Err(nix::errno::Errno::ENOTCONN) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
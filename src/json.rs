@@ -0,0 +1,47 @@
+//! Helpers for deserializing the JSON written by libtailscale.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Deserializer};
+
+/// Deserializes a value that Go may encode as `null` (e.g. a nil slice),
+/// falling back to the type's default.
+pub(crate) fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Deserializes a string that Go encodes as `""` when unset.
+pub(crate) fn empty_as_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.filter(|s| !s.is_empty()))
+}
+
+/// Serializes a timestamp as unix seconds, the inverse of [`unix_time`].
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_unix_time<S>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let secs = time
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64);
+    serializer.serialize_i64(secs)
+}
+
+/// Deserializes a timestamp in unix seconds, where `0` means "no timestamp".
+pub(crate) fn unix_time<'de, D>(deserializer: D) -> Result<Option<SystemTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let secs = i64::deserialize(deserializer)?;
+    Ok((secs > 0).then(|| UNIX_EPOCH + Duration::from_secs(secs as u64)))
+}
@@ -35,16 +35,14 @@
 //!
 //! #[tokio::main]
 //! async fn main() {
-//!     // Create and configure a Tailscale instance
+//!     // Create a Tailscale instance and bring up its connection
 //!     let ts = Tailscale::builder()
 //!         .ephemeral(true)
 //!         .hostname("foo")
-//!         .build()
+//!         .build_and_up()
+//!         .await
 //!         .unwrap();
 //!
-//!     // Bring up the Tailscale connection
-//!     ts.up().await.unwrap();
-//!
 //!     // Create a TCP listener on port 1999
 //!     let listener = ts.listener(NetworkType::Tcp, ":1999").await.unwrap();
 //!     eprintln!("listening for connections");
@@ -52,7 +50,7 @@
 //!     // Accept and handle connections
 //!     loop {
 //!         let conn = listener.accept().await.unwrap();
-//!         if let Some(addr) = conn.remote_addr().unwrap() {
+//!         if let Ok(addr) = conn.remote_addr() {
 //!             eprintln!("got connection from {}", addr);
 //!         }
 //!         tokio::spawn(async move {
@@ -65,10 +63,105 @@
 //! # Basic Usage
 //!
 //! 1. Create a Tailscale instance using the builder pattern
-//! 2. Call `up()` to establish the connection
+//! 2. Call `up()` to establish the connection, or build and connect in one
+//!    step with `build_and_up()`
 //! 3. Create listeners or dialers as needed
 //! 4. Handle connections using standard Rust I/O traits
 
+pub use addr::*;
+pub use auth_key::*;
+pub use cert::*;
+#[cfg(feature = "serde")]
+pub use config::*;
+pub use dial_queue::DialQueueStats;
+pub use dialer::{Dialer, PooledConnection};
+#[cfg(feature = "dlopen")]
+pub use dlopen::{LIBRARY_PATH_ENV, is_available, load_library};
+pub use events::*;
+#[cfg(feature = "hyper")]
+pub use file_server::serve_dir;
+pub use forward::*;
+pub use health::*;
+pub use identity::*;
+pub use incoming::*;
+pub use logs::LogRecord;
+pub use metrics::Metrics;
+pub use netmap::*;
+pub use on_demand::*;
+pub use ping::*;
+pub use preflight::*;
+#[cfg(feature = "quic")]
+pub use quic::*;
+pub use retry::RetryPolicy;
+pub use serve::Serve;
+pub use status::*;
+pub use tags::TagStats;
 pub use tailscale::*;
+pub use timeout::TimeoutConnection;
+#[cfg(feature = "tls")]
+pub use tls::*;
+pub use udp::UdpSocket;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub use uring::*;
+#[cfg(feature = "websocket")]
+pub use websocket::*;
+mod addr;
+mod auth_key;
+pub mod blocking;
+mod cert;
+#[cfg(feature = "serde")]
+mod config;
+mod dial_queue;
+mod dialer;
+#[cfg(feature = "dlopen")]
+mod dlopen;
+mod drain;
+mod events;
+#[cfg(feature = "hyper")]
+mod file_server;
+mod forward;
+mod health;
+pub mod http_connect;
+#[cfg(feature = "hyper")]
+mod hyper_rt;
+mod identity;
+mod incoming;
+pub mod io;
+mod json;
+mod logs;
+mod metrics;
+#[cfg(feature = "test-util")]
+pub mod mock;
+mod netmap;
+mod on_demand;
+#[cfg(feature = "otel")]
+mod otel;
+mod ping;
+mod preflight;
+pub mod proxy_protocol;
+#[cfg(feature = "quic")]
+mod quic;
+mod resources;
+mod retry;
+mod serve;
+mod shutdown;
+pub mod socks5;
+pub mod state;
+mod status;
+#[cfg(feature = "unsafe-sys")]
+pub mod sys;
+#[cfg(not(feature = "unsafe-sys"))]
 mod sys;
+mod tags;
 mod tailscale;
+pub mod testing;
+mod timeout;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tower")]
+mod tower;
+mod udp;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "websocket")]
+mod websocket;
@@ -0,0 +1,212 @@
+//! Forwarding tsnet's log output to `tracing`, see
+//! [`TailscaleBuilder::log_to_tracing`](crate::TailscaleBuilder::log_to_tracing).
+//!
+//! Lines are parsed into [`LogRecord`]s, whose component becomes the
+//! `component` field of the event and whose severity becomes its level.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    os::fd::OwnedFd,
+    thread,
+};
+
+use tracing::{Level, debug, error, info, trace, warn};
+
+/// Target of the events emitted for tsnet log lines.
+pub(crate) const TARGET: &str = "tsnet";
+
+/// Creates a pipe whose lines are re-emitted as `tracing` events by a
+/// background thread, and returns its write end for libtailscale. Lines less
+/// severe than `min_level` are dropped.
+///
+/// The thread exits once the write end has been closed, i.e. after the
+/// instance has shut down.
+pub(crate) fn forward_to_tracing(min_level: Level) -> io::Result<OwnedFd> {
+    let (read, write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
+    thread::Builder::new()
+        .name("tailscale-logs".to_string())
+        .spawn(move || {
+            for line in BufReader::new(File::from(read)).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if !line.is_empty() {
+                    emit(&line, min_level);
+                }
+            }
+            debug!("tsnet log pipe closed");
+        })?;
+    Ok(write)
+}
+
+/// A tsnet log line split into its parts.
+///
+/// Tailscale's log lines are free-form, but mostly follow the convention
+/// `[marker] component: message`, e.g.
+/// `[v1] magicsock: disco: node [abc] now using 10.0.0.1:41641`. The marker
+/// gives the severity: `[v1]` and `[v2]` mark verbose output, `[unexpected]`
+/// marks problems, and unmarked lines are informational.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// The subsystem that logged the line, e.g. `magicsock` or `control`, if
+    /// it starts with one.
+    pub component: Option<String>,
+    /// The severity given by the line's marker.
+    pub level: Level,
+    /// The rest of the line, without marker and component.
+    pub message: String,
+}
+
+impl LogRecord {
+    /// Parses a single log line.
+    pub fn parse(line: &str) -> LogRecord {
+        let mut level = None;
+        let mut rest = line.trim_end();
+        let mut component = None;
+        loop {
+            if let Some((marker_level, tail)) = strip_marker(rest) {
+                // The most severe marker wins, e.g. `[v1] [unexpected]`.
+                // Less verbose levels compare lower.
+                level = Some(level.map_or(marker_level, |l: Level| l.min(marker_level)));
+                rest = tail;
+            } else if let Some((name, tail)) = rest.split_once(": ")
+                && component.is_none()
+                && is_component(name)
+            {
+                component = Some(name.to_string());
+                rest = tail;
+            } else {
+                break;
+            }
+        }
+        LogRecord {
+            component,
+            level: level.unwrap_or(Level::INFO),
+            message: rest.to_string(),
+        }
+    }
+}
+
+fn strip_marker(s: &str) -> Option<(Level, &str)> {
+    [
+        ("[unexpected]", Level::WARN),
+        ("[v1]", Level::DEBUG),
+        ("[v2]", Level::TRACE),
+    ]
+    .into_iter()
+    .find_map(|(marker, level)| Some((level, s.strip_prefix(marker)?.trim_start())))
+}
+
+/// Returns true if `name` looks like a component such as `magicsock` or
+/// `derphttp.Client.Connect`, rather than the start of a sentence.
+fn is_component(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 40
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/'))
+}
+
+/// Emits `line` if it is at least as severe as `min_level`.
+fn emit(line: &str, min_level: Level) {
+    let record = LogRecord::parse(line);
+    if record.level > min_level {
+        return;
+    }
+    let component = record.component.as_deref().unwrap_or_default();
+    let message = &record.message;
+    match record.level {
+        Level::ERROR => error!(target: TARGET, component, "{message}"),
+        Level::WARN => warn!(target: TARGET, component, "{message}"),
+        Level::INFO => info!(target: TARGET, component, "{message}"),
+        Level::DEBUG => debug!(target: TARGET, component, "{message}"),
+        Level::TRACE => trace!(target: TARGET, component, "{message}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(component: Option<&str>, level: Level, message: &str) -> LogRecord {
+        LogRecord {
+            component: component.map(str::to_string),
+            level,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn verbose_with_component() {
+        assert_eq!(
+            LogRecord::parse("[v1] magicsock: disco: node [abc] now using 10.0.0.1:41641"),
+            record(
+                Some("magicsock"),
+                Level::DEBUG,
+                "disco: node [abc] now using 10.0.0.1:41641"
+            )
+        );
+        assert_eq!(
+            LogRecord::parse("[v2] derphttp.Client.Connect: connecting\n"),
+            record(Some("derphttp.Client.Connect"), Level::TRACE, "connecting")
+        );
+    }
+
+    #[test]
+    fn unexpected() {
+        assert_eq!(
+            LogRecord::parse("[unexpected] control: map response missing"),
+            record(Some("control"), Level::WARN, "map response missing")
+        );
+        assert_eq!(
+            LogRecord::parse("[unexpected] something odd happened"),
+            record(None, Level::WARN, "something odd happened")
+        );
+    }
+
+    #[test]
+    fn most_severe_stacked_marker_wins() {
+        assert_eq!(
+            LogRecord::parse("[v1] [unexpected] netmap: stale peer"),
+            record(Some("netmap"), Level::WARN, "stale peer")
+        );
+        assert_eq!(
+            LogRecord::parse("[unexpected] [v2] stale peer"),
+            record(None, Level::WARN, "stale peer")
+        );
+    }
+
+    #[test]
+    fn marker_after_component() {
+        assert_eq!(
+            LogRecord::parse("wgengine: [v1] reconfig"),
+            record(Some("wgengine"), Level::DEBUG, "reconfig")
+        );
+    }
+
+    #[test]
+    fn sentence_is_not_a_component() {
+        assert_eq!(
+            LogRecord::parse("Received error: context deadline exceeded"),
+            record(
+                None,
+                Level::INFO,
+                "Received error: context deadline exceeded"
+            )
+        );
+        assert_eq!(
+            LogRecord::parse("[v1] health: failed to reach control: EOF"),
+            record(Some("health"), Level::DEBUG, "failed to reach control: EOF")
+        );
+    }
+
+    #[test]
+    fn plain_line() {
+        assert_eq!(
+            LogRecord::parse("  starting up  "),
+            record(None, Level::INFO, "  starting up")
+        );
+        assert_eq!(LogRecord::parse(""), record(None, Level::INFO, ""));
+    }
+}
@@ -0,0 +1,299 @@
+//! Instance-wide connection metrics.
+//!
+//! Every instance counts the connections it accepts and dials, accept and
+//! dial errors, the bytes moved over its connections and how long accepts
+//! and dials take, readable through [`Tailscale::metrics`](crate::Tailscale::metrics).
+//! Each connection also counts its own bytes, see
+//! [`Connection::bytes_read`](crate::Connection::bytes_read).
+//!
+//! With the `metrics` feature the same values are reported through the
+//! [metrics] facade, so an exporter installed by the application, such as
+//! `metrics-exporter-prometheus`, publishes them:
+//!
+//! | Metric | Type |
+//! |--------|------|
+//! | `tailscale_connections_accepted_total` | counter |
+//! | `tailscale_connections_dialed_total` | counter |
+//! | `tailscale_connections_open` | gauge |
+//! | `tailscale_accept_errors_total` | counter |
+//! | `tailscale_dial_errors_total` | counter |
+//! | `tailscale_bytes_read_total` | counter |
+//! | `tailscale_bytes_written_total` | counter |
+//! | `tailscale_dial_duration_seconds` | histogram |
+//! | `tailscale_accept_duration_seconds` | histogram |
+//!
+//! Each carries a `hostname` label when the instance was built with a
+//! [hostname](crate::TailscaleBuilder::hostname). The exporter must be
+//! installed before the instance is built.
+//!
+//! The `otel` feature reports similar metrics through OpenTelemetry, see
+//! the `otel` module.
+//!
+//! [metrics]: https://docs.rs/metrics
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+#[cfg(feature = "otel")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "otel")]
+use opentelemetry::trace::SpanKind;
+
+#[cfg(feature = "otel")]
+use crate::otel::{Addrs, ConnectionSpan, Telemetry};
+
+/// A snapshot of an instance's connection metrics, returned by
+/// [`Tailscale::metrics`](crate::Tailscale::metrics).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metrics {
+    /// Connections accepted from listeners since the instance was built.
+    pub connections_accepted: u64,
+    /// Connections dialed since the instance was built.
+    pub connections_dialed: u64,
+    /// Accepted and dialed connections currently open.
+    pub open_connections: usize,
+    /// Failed accepts, not counting connections rejected by a filter.
+    pub accept_errors: u64,
+    /// Failed dial attempts.
+    pub dial_errors: u64,
+    /// Bytes read from all connections.
+    pub bytes_read: u64,
+    /// Bytes written to all connections.
+    pub bytes_written: u64,
+    /// Total time spent in successful dials, excluding time waiting in the
+    /// dial queue.
+    pub total_dial_time: Duration,
+    /// Longest time a single successful dial took.
+    pub max_dial_time: Duration,
+    /// Total time spent setting up accepted connections, from the listener
+    /// becoming readable until the connection is registered with the
+    /// runtime. This covers the FFI and async glue of accepting, excluding
+    /// waiting for peers, filters and connection limits.
+    pub total_accept_time: Duration,
+    /// Longest time setting up a single accepted connection took.
+    pub max_accept_time: Duration,
+}
+
+/// Counters of a single Tailscale instance.
+pub(crate) struct MetricsRegistry {
+    accepted: AtomicU64,
+    dialed: AtomicU64,
+    open: AtomicUsize,
+    accept_errors: AtomicU64,
+    dial_errors: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    total_dial_nanos: AtomicU64,
+    max_dial_nanos: AtomicU64,
+    total_accept_nanos: AtomicU64,
+    max_accept_nanos: AtomicU64,
+    #[cfg(feature = "metrics")]
+    exported: Exported,
+    #[cfg(feature = "otel")]
+    pub(crate) otel: Telemetry,
+}
+
+impl MetricsRegistry {
+    #[cfg_attr(
+        not(any(feature = "metrics", feature = "otel")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn new(hostname: Option<&str>) -> Self {
+        MetricsRegistry {
+            accepted: AtomicU64::new(0),
+            dialed: AtomicU64::new(0),
+            open: AtomicUsize::new(0),
+            accept_errors: AtomicU64::new(0),
+            dial_errors: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            total_dial_nanos: AtomicU64::new(0),
+            max_dial_nanos: AtomicU64::new(0),
+            total_accept_nanos: AtomicU64::new(0),
+            max_accept_nanos: AtomicU64::new(0),
+            #[cfg(feature = "metrics")]
+            exported: Exported::new(hostname),
+            #[cfg(feature = "otel")]
+            otel: Telemetry::new(hostname),
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            connections_accepted: self.accepted.load(Ordering::Relaxed),
+            connections_dialed: self.dialed.load(Ordering::Relaxed),
+            open_connections: self.open.load(Ordering::Relaxed),
+            accept_errors: self.accept_errors.load(Ordering::Relaxed),
+            dial_errors: self.dial_errors.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            total_dial_time: Duration::from_nanos(self.total_dial_nanos.load(Ordering::Relaxed)),
+            max_dial_time: Duration::from_nanos(self.max_dial_nanos.load(Ordering::Relaxed)),
+            total_accept_time: Duration::from_nanos(
+                self.total_accept_nanos.load(Ordering::Relaxed),
+            ),
+            max_accept_time: Duration::from_nanos(self.max_accept_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub(crate) fn record_accept(&self) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.exported.accepted.increment(1);
+    }
+
+    pub(crate) fn record_accept_time(&self, took: Duration) {
+        let nanos = took.as_nanos() as u64;
+        self.total_accept_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_accept_nanos.fetch_max(nanos, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.exported.accept_duration.record(took.as_secs_f64());
+    }
+
+    pub(crate) fn record_accept_error(&self) {
+        self.accept_errors.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.exported.accept_errors.increment(1);
+    }
+
+    pub(crate) fn record_dial(&self, took: Duration) {
+        let nanos = took.as_nanos() as u64;
+        self.dialed.fetch_add(1, Ordering::Relaxed);
+        self.total_dial_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_dial_nanos.fetch_max(nanos, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            self.exported.dialed.increment(1);
+            self.exported.dial_duration.record(took.as_secs_f64());
+        }
+    }
+
+    pub(crate) fn record_dial_error(&self) {
+        self.dial_errors.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.exported.dial_errors.increment(1);
+    }
+
+    /// Counts a connection as open until the returned counters are dropped.
+    pub(crate) fn open_connection(self: &Arc<Self>) -> ConnectionMetrics {
+        self.open.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.exported.open.increment(1.0);
+        ConnectionMetrics {
+            registry: Arc::clone(self),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            #[cfg(feature = "otel")]
+            span: OnceLock::new(),
+        }
+    }
+}
+
+/// Byte counters of a single connection, which also count towards the
+/// instance's.
+pub(crate) struct ConnectionMetrics {
+    registry: Arc<MetricsRegistry>,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    /// Started once the connection's addresses are known, and ended when
+    /// the connection and all its clones are dropped.
+    #[cfg(feature = "otel")]
+    span: OnceLock<ConnectionSpan>,
+}
+
+impl ConnectionMetrics {
+    pub(crate) fn record_read(&self, n: usize) {
+        self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        self.registry
+            .bytes_read
+            .fetch_add(n as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.registry.exported.bytes_read.increment(n as u64);
+        #[cfg(feature = "otel")]
+        self.registry.otel.record_io("receive", n);
+    }
+
+    pub(crate) fn record_write(&self, n: usize) {
+        self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+        self.registry
+            .bytes_written
+            .fetch_add(n as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.registry.exported.bytes_written.increment(n as u64);
+        #[cfg(feature = "otel")]
+        self.registry.otel.record_io("transmit", n);
+    }
+
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "otel")]
+    pub(crate) fn start_span(&self, parent: &opentelemetry::Context, kind: SpanKind, addrs: Addrs) {
+        self.span
+            .get_or_init(|| self.registry.otel.start_connection(parent, kind, addrs));
+    }
+
+    #[cfg(feature = "otel")]
+    pub(crate) fn otel_context(&self) -> Option<&opentelemetry::Context> {
+        self.span.get().map(ConnectionSpan::context)
+    }
+}
+
+impl Drop for ConnectionMetrics {
+    fn drop(&mut self) {
+        self.registry.open.fetch_sub(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.registry.exported.open.decrement(1.0);
+        #[cfg(feature = "otel")]
+        if let Some(span) = self.span.get() {
+            span.end(&self.registry.otel, self.bytes_read(), self.bytes_written());
+        }
+    }
+}
+
+/// Handles registered with the `metrics` recorder when the instance is built.
+#[cfg(feature = "metrics")]
+struct Exported {
+    accepted: metrics::Counter,
+    dialed: metrics::Counter,
+    open: metrics::Gauge,
+    accept_errors: metrics::Counter,
+    dial_errors: metrics::Counter,
+    bytes_read: metrics::Counter,
+    bytes_written: metrics::Counter,
+    dial_duration: metrics::Histogram,
+    accept_duration: metrics::Histogram,
+}
+
+#[cfg(feature = "metrics")]
+impl Exported {
+    fn new(hostname: Option<&str>) -> Self {
+        let labels: Vec<metrics::Label> = hostname
+            .map(|h| metrics::Label::new("hostname", h.to_string()))
+            .into_iter()
+            .collect();
+        Exported {
+            accepted: metrics::counter!("tailscale_connections_accepted_total", labels.clone()),
+            dialed: metrics::counter!("tailscale_connections_dialed_total", labels.clone()),
+            open: metrics::gauge!("tailscale_connections_open", labels.clone()),
+            accept_errors: metrics::counter!("tailscale_accept_errors_total", labels.clone()),
+            dial_errors: metrics::counter!("tailscale_dial_errors_total", labels.clone()),
+            bytes_read: metrics::counter!("tailscale_bytes_read_total", labels.clone()),
+            bytes_written: metrics::counter!("tailscale_bytes_written_total", labels.clone()),
+            dial_duration: metrics::histogram!("tailscale_dial_duration_seconds", labels.clone()),
+            accept_duration: metrics::histogram!("tailscale_accept_duration_seconds", labels),
+        }
+    }
+}
@@ -0,0 +1,778 @@
+//! A fake tailnet over loopback TCP, enabled by the `test-util` feature.
+//!
+//! [`Tailscale`], [`Listener`] and [`Connection`] here mirror the core of
+//! the real API: building a node, [`up`](Tailscale::up),
+//! [`listener`](Tailscale::listener), [`connect`](Tailscale::connect),
+//! [`accept`](Listener::accept) and async IO on connections. Nodes are
+//! assigned addresses in `100.64.0.0/10` and `fd7a:115c:a1e0::/48` and can
+//! reach each other by those addresses or by hostname, while the bytes
+//! travel over `127.0.0.1`. This lets unit tests of a service exercise its
+//! networking code without a control server, auth key or running Go
+//! runtime.
+//!
+//! Nodes join a [`Tailnet`], the process-wide default one unless set with
+//! [`TailscaleBuilder::tailnet`]; use a fresh tailnet per test to keep tests
+//! running in parallel apart. Only TCP is supported.
+//!
+//! Code written against this module can be pointed at the real types with a
+//! `cfg(test)` import. Nothing in here calls into libtailscale, so with the
+//! `dlopen` feature, which links nothing at build time, tests that only use
+//! the mock need neither the Go archive nor the shared library.
+//!
+//! # Examples
+//!
+//! ```
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! use tailscale2::{NetworkType, mock::{Tailnet, Tailscale}};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> tailscale2::Result<()> {
+//! let tailnet = Tailnet::new();
+//! let server = Tailscale::builder().hostname("server").tailnet(&tailnet).build()?;
+//! let client = Tailscale::builder().hostname("client").tailnet(&tailnet).build()?;
+//!
+//! let listener = server.listener(NetworkType::Tcp, 8080).await?;
+//! let mut conn = client.connect(NetworkType::Tcp, "server:8080").await?;
+//! conn.write_all(b"ping").await?;
+//!
+//! let mut accepted = listener.accept().await?;
+//! let mut buf = [0; 4];
+//! accepted.read_exact(&mut buf).await?;
+//! assert_eq!(&buf, b"ping");
+//! assert_eq!(accepted.remote_addr()?.ip(), client.ips()?.unwrap().ipv4);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock},
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpSocket, TcpStream},
+};
+use tracing::debug;
+
+use crate::{
+    addr::{ToDialAddr, ToListenAddr},
+    auth_key::AuthKey,
+    tailscale::{IpPair, NetworkType, Result, TailscaleError},
+};
+
+/// A fake tailnet that [`Tailscale`] nodes join.
+///
+/// Cloning a `Tailnet` gives another handle to the same network.
+#[derive(Debug, Clone, Default)]
+pub struct Tailnet {
+    inner: Arc<Mutex<Registry>>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Number of nodes ever added, used to assign addresses.
+    added: u32,
+    /// Node IPv4 addresses by hostname.
+    nodes: HashMap<String, Ipv4Addr>,
+    /// Loopback addresses of listeners by node IPv4 address and port.
+    listeners: HashMap<(Ipv4Addr, u16), SocketAddr>,
+    /// Tailnet source addresses of dialing connections by their loopback
+    /// address, until accepted.
+    dialing: HashMap<SocketAddr, SocketAddr>,
+}
+
+impl Tailnet {
+    /// Creates an empty tailnet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the tailnet nodes join unless given another one.
+    pub fn global() -> &'static Tailnet {
+        static GLOBAL: OnceLock<Tailnet> = OnceLock::new();
+        GLOBAL.get_or_init(Tailnet::new)
+    }
+
+    /// Returns the hostnames of the nodes currently on the tailnet.
+    pub fn hostnames(&self) -> Vec<String> {
+        self.registry().nodes.keys().cloned().collect()
+    }
+
+    fn registry(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.inner.lock().unwrap()
+    }
+
+    fn add_node(&self, hostname: Option<&str>) -> Result<(String, IpPair)> {
+        let mut registry = self.registry();
+        registry.added += 1;
+        let n = registry.added;
+        let hostname = hostname.map_or_else(|| format!("node-{n}"), str::to_string);
+        if registry.nodes.contains_key(&hostname) {
+            return Err(TailscaleError::StartFailed(format!(
+                "hostname {hostname:?} is already on the mock tailnet"
+            )));
+        }
+        let [_, a, b, c] = n.to_be_bytes();
+        let ipv4 = Ipv4Addr::new(100, 64 | (a & 0x3f), b, c);
+        let ipv6 = ipv6_of(ipv4);
+        registry.nodes.insert(hostname.clone(), ipv4);
+        Ok((hostname, IpPair { ipv4, ipv6 }))
+    }
+
+    /// Finds the node `host` refers to: one of its addresses, its hostname
+    /// or a MagicDNS name starting with it.
+    fn resolve(&self, host: &str) -> Option<Ipv4Addr> {
+        let registry = self.registry();
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => registry.nodes.values().copied().find(|&v4| v4 == ip),
+            Ok(IpAddr::V6(ip)) => registry
+                .nodes
+                .values()
+                .copied()
+                .find(|&v4| ipv6_of(v4) == ip),
+            Err(_) => {
+                let name = host.trim_end_matches('.').split('.').next()?;
+                registry.nodes.get(name).copied()
+            }
+        }
+    }
+}
+
+/// Returns the IPv6 address of the node with IPv4 address `ipv4`.
+fn ipv6_of(ipv4: Ipv4Addr) -> Ipv6Addr {
+    let [_, a, b, c] = ipv4.octets();
+    Ipv6Addr::new(
+        0xfd7a,
+        0x115c,
+        0xa1e0,
+        0,
+        0,
+        0,
+        u16::from(a & 0x3f),
+        u16::from_be_bytes([b, c]),
+    )
+}
+
+/// Builder for a mock [`Tailscale`] node, mirroring
+/// [`crate::TailscaleBuilder`].
+#[derive(Debug, Default)]
+pub struct TailscaleBuilder {
+    hostname: Option<String>,
+    tailnet: Option<Tailnet>,
+}
+
+impl TailscaleBuilder {
+    /// Builds the node and adds it to its tailnet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::StartFailed`] if another node on the tailnet
+    /// has the same hostname.
+    pub fn build(&mut self) -> Result<Arc<Tailscale>> {
+        let tailnet = self
+            .tailnet
+            .clone()
+            .unwrap_or_else(|| Tailnet::global().clone());
+        let (hostname, ips) = tailnet.add_node(self.hostname.as_deref())?;
+        debug!(%hostname, ipv4 = %ips.ipv4, "mock node added");
+        Ok(Arc::new(Tailscale {
+            hostname,
+            ips,
+            tailnet,
+        }))
+    }
+
+    /// Moves the configuration out into an owned builder, see
+    /// [`crate::TailscaleBuilder::take`].
+    pub fn take(&mut self) -> TailscaleBuilder {
+        std::mem::take(self)
+    }
+
+    /// Builds the node, which is on its tailnet straight away.
+    ///
+    /// # Errors
+    ///
+    /// See [`build`](Self::build).
+    pub async fn build_and_up(&mut self) -> Result<Arc<Tailscale>> {
+        self.build()
+    }
+
+    /// Sets the hostname peers reach the node by, `node-<n>` by default.
+    pub fn hostname(&mut self, hostname: impl Into<String>) -> &mut Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// Sets the tailnet the node joins, [`Tailnet::global`] by default.
+    pub fn tailnet(&mut self, tailnet: &Tailnet) -> &mut Self {
+        self.tailnet = Some(tailnet.clone());
+        self
+    }
+
+    /// Accepted for compatibility and ignored.
+    pub fn auth_key(&mut self, _key: impl Into<AuthKey>) -> &mut Self {
+        self
+    }
+
+    /// Accepted for compatibility and ignored.
+    pub fn ephemeral(&mut self, _ephemeral: bool) -> &mut Self {
+        self
+    }
+}
+
+/// A mock node, mirroring [`crate::Tailscale`].
+///
+/// The node is removed from its tailnet once it and its listeners are
+/// dropped.
+#[derive(Debug)]
+pub struct Tailscale {
+    hostname: String,
+    ips: IpPair,
+    tailnet: Tailnet,
+}
+
+impl Tailscale {
+    /// Creates a builder for a mock node.
+    pub fn builder() -> TailscaleBuilder {
+        TailscaleBuilder::default()
+    }
+
+    /// Returns the node's hostname.
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// Does nothing, as mock nodes are on their tailnet once built.
+    ///
+    /// # Errors
+    ///
+    /// Never fails; returns a `Result` to match [`crate::Tailscale::up`].
+    pub async fn up(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the node's addresses, which are always assigned.
+    ///
+    /// # Errors
+    ///
+    /// Never fails; returns a `Result` to match [`crate::Tailscale::ips`].
+    pub fn ips(&self) -> Result<Option<IpPair>> {
+        Ok(Some(IpPair {
+            ipv4: self.ips.ipv4,
+            ipv6: self.ips.ipv6,
+        }))
+    }
+
+    /// Listens for connections from other nodes on the tailnet, see
+    /// [`crate::Tailscale::listener`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if `addr` is invalid, or
+    /// [`TailscaleError::ListenFailed`] if `network` is not TCP, the address
+    /// is not one of this node's or the port is taken.
+    pub async fn listener(
+        self: &Arc<Tailscale>,
+        network: NetworkType,
+        addr: impl ToListenAddr,
+    ) -> Result<Arc<Listener>> {
+        let addr = addr.to_listen_addr()?;
+        let failed = |message: &str| TailscaleError::ListenFailed {
+            network: network.to_string(),
+            addr: addr.to_string(),
+            message: message.to_string(),
+        };
+        if !is_tcp(network) {
+            return Err(failed("the mock tailnet only supports TCP"));
+        }
+        let local_ip = match addr.ip() {
+            None => IpAddr::V4(self.ips.ipv4),
+            Some(ip) if ip == self.ips.ipv4 || ip == self.ips.ipv6 => ip,
+            Some(_) => return Err(failed("not an address of this node")),
+        };
+        let key = (self.ips.ipv4, addr.port());
+        if self.tailnet.registry().listeners.contains_key(&key) {
+            return Err(failed("address already in use"));
+        }
+
+        let inner = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .map_err(|e| failed(&e.to_string()))?;
+        let loopback = inner.local_addr().map_err(|e| failed(&e.to_string()))?;
+        {
+            let mut registry = self.tailnet.registry();
+            if registry.listeners.contains_key(&key) {
+                return Err(failed("address already in use"));
+            }
+            registry.listeners.insert(key, loopback);
+        }
+        debug!(%addr, %loopback, "mock listener created");
+        Ok(Arc::new(Listener {
+            inner,
+            key,
+            local_addr: SocketAddr::new(local_ip, addr.port()),
+            tailnet: self.tailnet.clone(),
+            _tailscale: self.clone(),
+        }))
+    }
+
+    /// Connects to a listener on another node, see
+    /// [`crate::Tailscale::connect`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if `addr` is invalid, or
+    /// [`TailscaleError::DialFailed`] if `network` is not TCP, the host is
+    /// not on the tailnet or nothing listens on the port.
+    pub async fn connect(&self, network: NetworkType, addr: impl ToDialAddr) -> Result<Connection> {
+        let addr = addr.to_dial_addr()?;
+        let failed = |message: String| TailscaleError::DialFailed {
+            network: network.to_string(),
+            addr: addr.to_string(),
+            message,
+        };
+        if !is_tcp(network) {
+            return Err(failed("the mock tailnet only supports TCP".to_string()));
+        }
+        let peer = self
+            .tailnet
+            .resolve(addr.host())
+            .ok_or_else(|| failed(format!("no node {:?} on the mock tailnet", addr.host())))?;
+        let remote_ip = match network {
+            NetworkType::Tcp6 => IpAddr::V6(ipv6_of(peer)),
+            _ => match addr.host().parse::<IpAddr>() {
+                Ok(ip @ IpAddr::V6(_)) => ip,
+                _ => IpAddr::V4(peer),
+            },
+        };
+        let local_ip = match remote_ip {
+            IpAddr::V4(_) => IpAddr::V4(self.ips.ipv4),
+            IpAddr::V6(_) => IpAddr::V6(self.ips.ipv6),
+        };
+
+        let socket = TcpSocket::new_v4().map_err(|e| failed(e.to_string()))?;
+        let loopback = socket
+            .bind((Ipv4Addr::LOCALHOST, 0).into())
+            .and_then(|()| socket.local_addr())
+            .map_err(|e| failed(e.to_string()))?;
+        let local_addr = SocketAddr::new(local_ip, loopback.port());
+        let target = {
+            let mut registry = self.tailnet.registry();
+            let target = *registry
+                .listeners
+                .get(&(peer, addr.port()))
+                .ok_or_else(|| failed("connection refused".to_string()))?;
+            registry.dialing.insert(loopback, local_addr);
+            target
+        };
+
+        let stream = socket.connect(target).await;
+        let stream = stream.map_err(|e| {
+            self.tailnet.registry().dialing.remove(&loopback);
+            failed(e.to_string())
+        })?;
+        Ok(Connection {
+            stream,
+            local_addr,
+            remote_addr: SocketAddr::new(remote_ip, addr.port()),
+        })
+    }
+
+    /// Removes the node from its tailnet, once its listeners are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Never fails; returns a `Result` to match [`crate::Tailscale::close`].
+    pub async fn close(self: Arc<Self>) -> Result<()> {
+        drop(self);
+        Ok(())
+    }
+}
+
+impl Drop for Tailscale {
+    fn drop(&mut self) {
+        self.tailnet.registry().nodes.remove(&self.hostname);
+    }
+}
+
+fn is_tcp(network: NetworkType) -> bool {
+    matches!(
+        network,
+        NetworkType::Tcp | NetworkType::Tcp4 | NetworkType::Tcp6
+    )
+}
+
+/// A mock listener, mirroring [`crate::Listener`].
+///
+/// The port is released when the listener is dropped.
+#[derive(Debug)]
+pub struct Listener {
+    inner: TcpListener,
+    key: (Ipv4Addr, u16),
+    local_addr: SocketAddr,
+    tailnet: Tailnet,
+    _tailscale: Arc<Tailscale>,
+}
+
+impl Listener {
+    /// Returns the tailnet address the listener is bound to.
+    ///
+    /// # Errors
+    ///
+    /// Never fails; returns a `Result` to match
+    /// [`Listener::local_addr`](crate::Listener::local_addr).
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    /// Waits for a connection from another node.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::AcceptFailed`] if accepting fails.
+    pub async fn accept(self: &Arc<Self>) -> Result<Connection> {
+        loop {
+            let (stream, loopback) = self
+                .inner
+                .accept()
+                .await
+                .map_err(|e| TailscaleError::AcceptFailed(e.to_string()))?;
+            // Only connections dialed through the mock carry a tailnet
+            // address; anything else found the loopback port by chance.
+            let Some(remote_addr) = self.tailnet.registry().dialing.remove(&loopback) else {
+                debug!(%loopback, "dropping connection from outside the mock tailnet");
+                continue;
+            };
+            let local_addr = match remote_addr {
+                SocketAddr::V6(_) => {
+                    SocketAddr::new(IpAddr::V6(ipv6_of(self.key.0)), self.local_addr.port())
+                }
+                SocketAddr::V4(_) => {
+                    SocketAddr::new(IpAddr::V4(self.key.0), self.local_addr.port())
+                }
+            };
+            return Ok(Connection {
+                stream,
+                local_addr,
+                remote_addr,
+            });
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.tailnet.registry().listeners.remove(&self.key);
+    }
+}
+
+/// A mock connection, mirroring [`crate::Connection`].
+///
+/// Implements `AsyncRead` and `AsyncWrite`.
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    local_addr: SocketAddr,
+    remote_addr: SocketAddr,
+}
+
+impl Connection {
+    /// Returns the tailnet address of the other end.
+    ///
+    /// # Errors
+    ///
+    /// Never fails; returns a `Result` to match
+    /// [`crate::Connection::remote_addr`].
+    pub fn remote_addr(&self) -> Result<SocketAddr> {
+        Ok(self.remote_addr)
+    }
+
+    /// Returns the tailnet address of this end.
+    ///
+    /// # Errors
+    ///
+    /// Never fails; returns a `Result` to match
+    /// [`crate::Connection::local_addr`].
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    /// Sets `TCP_NODELAY` on the underlying loopback socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the option cannot be set.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.stream.set_nodelay(nodelay)
+    }
+
+    /// See [`Connection::peek`](crate::Connection::peek).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if receiving from the socket fails.
+    pub async fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.peek(buf).await
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    fn node(tailnet: &Tailnet, hostname: &str) -> Arc<Tailscale> {
+        Tailscale::builder()
+            .hostname(hostname)
+            .tailnet(tailnet)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn nodes_get_distinct_addresses() {
+        let tailnet = Tailnet::new();
+        let a = node(&tailnet, "a");
+        let b = node(&tailnet, "b");
+        let unnamed = Tailscale::builder().tailnet(&tailnet).build().unwrap();
+        assert_eq!(unnamed.hostname(), "node-3");
+
+        let (a_ips, b_ips) = (a.ips().unwrap().unwrap(), b.ips().unwrap().unwrap());
+        assert_ne!(a_ips.ipv4, b_ips.ipv4);
+        assert_ne!(a_ips.ipv6, b_ips.ipv6);
+        assert_eq!(a_ips.ipv4.octets()[0], 100);
+        assert_eq!(a_ips.ipv6.segments()[..3], [0xfd7a, 0x115c, 0xa1e0]);
+
+        let mut hostnames = tailnet.hostnames();
+        hostnames.sort();
+        assert_eq!(hostnames, ["a", "b", "node-3"]);
+    }
+
+    #[test]
+    fn duplicate_hostname() {
+        let tailnet = Tailnet::new();
+        let _a = node(&tailnet, "a");
+        let res = Tailscale::builder().hostname("a").tailnet(&tailnet).build();
+        assert!(matches!(res, Err(TailscaleError::StartFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn connect_and_accept() {
+        let tailnet = Tailnet::new();
+        let server = node(&tailnet, "server");
+        let client = node(&tailnet, "client");
+        let (server_ips, client_ips) = (
+            server.ips().unwrap().unwrap(),
+            client.ips().unwrap().unwrap(),
+        );
+        let listener = server.listener(NetworkType::Tcp, 8080).await.unwrap();
+        assert_eq!(
+            listener.local_addr().unwrap(),
+            SocketAddr::new(server_ips.ipv4.into(), 8080)
+        );
+
+        let mut conn = client
+            .connect(NetworkType::Tcp, "server:8080")
+            .await
+            .unwrap();
+        let mut accepted = listener.accept().await.unwrap();
+        assert_eq!(
+            conn.remote_addr().unwrap(),
+            SocketAddr::new(server_ips.ipv4.into(), 8080)
+        );
+        assert_eq!(conn.local_addr().unwrap().ip(), client_ips.ipv4);
+        assert_eq!(accepted.remote_addr().unwrap(), conn.local_addr().unwrap());
+        assert_eq!(accepted.local_addr().unwrap(), conn.remote_addr().unwrap());
+
+        conn.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        accepted.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        accepted.write_all(b"pong").await.unwrap();
+        conn.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        drop(conn);
+        assert_eq!(accepted.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn connect_by_address_and_name() {
+        let tailnet = Tailnet::new();
+        let server = node(&tailnet, "server");
+        let client = node(&tailnet, "client");
+        let ips = server.ips().unwrap().unwrap();
+        let listener = server.listener(NetworkType::Tcp, 80).await.unwrap();
+
+        for addr in [
+            format!("{}:80", ips.ipv4),
+            "server.tail1234.ts.net.:80".to_string(),
+            format!("[{}]:80", ips.ipv6),
+        ] {
+            let conn = client.connect(NetworkType::Tcp, &addr).await.unwrap();
+            let accepted = listener.accept().await.unwrap();
+            assert_eq!(
+                accepted.remote_addr().unwrap(),
+                conn.local_addr().unwrap(),
+                "{addr}"
+            );
+        }
+
+        let conn = client
+            .connect(NetworkType::Tcp6, "server:80")
+            .await
+            .unwrap();
+        let accepted = listener.accept().await.unwrap();
+        assert_eq!(conn.remote_addr().unwrap().ip(), ips.ipv6);
+        assert_eq!(accepted.local_addr().unwrap().ip(), ips.ipv6);
+        assert_eq!(
+            accepted.remote_addr().unwrap().ip(),
+            client.ips().unwrap().unwrap().ipv6
+        );
+    }
+
+    #[tokio::test]
+    async fn dial_failures() {
+        let tailnet = Tailnet::new();
+        let server = node(&tailnet, "server");
+        let client = node(&tailnet, "client");
+        let _listener = server.listener(NetworkType::Tcp, 80).await.unwrap();
+
+        for (network, addr) in [
+            (NetworkType::Tcp, "server:81"),
+            (NetworkType::Tcp, "nobody:80"),
+            (NetworkType::Tcp, "100.100.100.100:80"),
+            (NetworkType::Udp, "server:80"),
+        ] {
+            let res = client.connect(network, addr).await;
+            assert!(
+                matches!(res, Err(TailscaleError::DialFailed { .. })),
+                "{network} {addr}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn listen_failures() {
+        let tailnet = Tailnet::new();
+        let server = node(&tailnet, "server");
+        let other = node(&tailnet, "other");
+        let _listener = server.listener(NetworkType::Tcp, 80).await.unwrap();
+
+        let other_ip = other.ips().unwrap().unwrap().ipv4;
+        for (network, addr) in [
+            (NetworkType::Tcp, ":80".to_string()),
+            (NetworkType::Tcp, format!("{other_ip}:81")),
+            (NetworkType::Udp, ":82".to_string()),
+        ] {
+            let res = server.listener(network, addr.as_str()).await;
+            assert!(
+                matches!(res, Err(TailscaleError::ListenFailed { .. })),
+                "{network} {addr}"
+            );
+        }
+        // The same port on another node is free.
+        other.listener(NetworkType::Tcp, 80).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropping_listener_releases_port() {
+        let tailnet = Tailnet::new();
+        let server = node(&tailnet, "server");
+        let client = node(&tailnet, "client");
+
+        let listener = server.listener(NetworkType::Tcp, 80).await.unwrap();
+        drop(listener);
+        let res = client.connect(NetworkType::Tcp, "server:80").await;
+        assert!(matches!(res, Err(TailscaleError::DialFailed { .. })));
+        server.listener(NetworkType::Tcp, 80).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn close_removes_node() {
+        let tailnet = Tailnet::new();
+        let server = node(&tailnet, "server");
+        let client = node(&tailnet, "client");
+
+        let listener = server.listener(NetworkType::Tcp, 80).await.unwrap();
+        Arc::clone(&server).close().await.unwrap();
+        drop(server);
+        // The listener keeps the node on the tailnet.
+        assert!(tailnet.hostnames().contains(&"server".to_string()));
+        client.connect(NetworkType::Tcp, "server:80").await.unwrap();
+
+        drop(listener);
+        assert_eq!(tailnet.hostnames(), ["client"]);
+        let res = client.connect(NetworkType::Tcp, "server:80").await;
+        assert!(matches!(res, Err(TailscaleError::DialFailed { .. })));
+    }
+
+    #[tokio::test]
+    async fn accept_ignores_connections_from_outside() {
+        let tailnet = Tailnet::new();
+        let server = node(&tailnet, "server");
+        let client = node(&tailnet, "client");
+        let listener = server.listener(NetworkType::Tcp, 80).await.unwrap();
+        let loopback = tailnet.registry().listeners[&listener.key];
+
+        let _stray = TcpStream::connect(loopback).await.unwrap();
+        let conn = client.connect(NetworkType::Tcp, "server:80").await.unwrap();
+        let accepted = listener.accept().await.unwrap();
+        assert_eq!(accepted.remote_addr().unwrap(), conn.local_addr().unwrap());
+    }
+
+    #[test]
+    fn tailnets_are_separate() {
+        let (one, two) = (Tailnet::new(), Tailnet::new());
+        let _a = node(&one, "a");
+        let _b = node(&two, "a");
+        assert!(one.resolve("a").is_some());
+        assert!(two.resolve("b").is_none());
+    }
+}
@@ -0,0 +1,122 @@
+//! Snapshot of the network map as seen by this node.
+
+use std::{net::IpAddr, time::SystemTime};
+
+use serde::Deserialize;
+
+use crate::json::{null_as_default, unix_time};
+
+/// The network map of a node, as returned by [`Tailscale::netmap`](crate::Tailscale::netmap).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NetMap {
+    /// This node.
+    #[serde(rename = "self")]
+    pub self_node: Node,
+    /// All peers visible to this node.
+    #[serde(deserialize_with = "null_as_default")]
+    pub peers: Vec<Node>,
+    /// The DNS configuration pushed by the control plane.
+    pub dns: DnsConfig,
+    /// The DERP relay regions available to this node, ordered by ID.
+    #[serde(deserialize_with = "null_as_default")]
+    pub derp_regions: Vec<DerpRegion>,
+    /// The name of the tailnet.
+    pub domain: String,
+}
+
+impl NetMap {
+    /// Returns the node `host` names, matching this node and its peers by
+    /// their MagicDNS name or its first label, ignoring case and a trailing
+    /// dot.
+    pub(crate) fn find_node(&self, host: &str) -> Option<&Node> {
+        let host = host.trim_end_matches('.');
+        self.peers.iter().chain([&self.self_node]).find(|node| {
+            let name = node.name.trim_end_matches('.');
+            name.eq_ignore_ascii_case(host)
+                || name
+                    .split('.')
+                    .next()
+                    .is_some_and(|label| label.eq_ignore_ascii_case(host))
+        })
+    }
+}
+
+/// A node in the network map.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Node {
+    /// The stable ID of the node.
+    pub id: String,
+    /// The MagicDNS name of the node, including the trailing dot.
+    pub name: String,
+    /// The hostname the node reported for itself.
+    pub hostname: String,
+    /// The operating system of the node.
+    pub os: String,
+    /// The Tailscale IP addresses of the node.
+    #[serde(deserialize_with = "null_as_default")]
+    pub addresses: Vec<IpAddr>,
+    /// The ACL tags assigned to the node.
+    #[serde(deserialize_with = "null_as_default")]
+    pub tags: Vec<String>,
+    /// Whether the node is connected to the control plane, if known.
+    pub online: Option<bool>,
+    /// When the node key expires, or `None` if key expiry is disabled.
+    #[serde(deserialize_with = "unix_time")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::json::serialize_unix_time")
+    )]
+    pub key_expiry: Option<SystemTime>,
+}
+
+/// The tailnet DNS configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DnsConfig {
+    /// The MagicDNS suffix of the tailnet, e.g. `tail1234.ts.net`.
+    pub magic_dns_suffix: String,
+    /// Search domains pushed to the node.
+    #[serde(deserialize_with = "null_as_default")]
+    pub search_domains: Vec<String>,
+    /// Addresses of the configured nameservers.
+    #[serde(deserialize_with = "null_as_default")]
+    pub nameservers: Vec<String>,
+}
+
+impl DnsConfig {
+    /// Returns the fully qualified MagicDNS name of the node called `host`,
+    /// e.g. `db.tail1234.ts.net`, without the trailing dot.
+    ///
+    /// Names that already end in the suffix, and all names when MagicDNS is
+    /// disabled, are returned unchanged.
+    pub fn fqdn(&self, host: &str) -> String {
+        let host = host.trim_end_matches('.');
+        let suffix = self.magic_dns_suffix.trim_matches('.');
+        if suffix.is_empty()
+            || host
+                .strip_suffix(suffix)
+                .is_some_and(|name| name.ends_with('.'))
+        {
+            host.to_string()
+        } else {
+            format!("{host}.{suffix}")
+        }
+    }
+}
+
+/// A DERP relay region.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DerpRegion {
+    /// The numeric region ID.
+    pub id: i32,
+    /// The short region code, e.g. `nyc`.
+    pub code: String,
+    /// The human-readable region name.
+    pub name: String,
+    /// The hostnames of the DERP servers in this region.
+    #[serde(deserialize_with = "null_as_default")]
+    pub hosts: Vec<String>,
+}
@@ -0,0 +1,145 @@
+//! Scale-to-zero services that are only started when a connection arrives.
+//!
+//! See [`Tailscale::listen_on_demand`](crate::Tailscale::listen_on_demand).
+
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{Instrument, debug, error};
+
+use crate::tailscale::{Connection, Listener};
+
+/// A listener whose service is started on the first connection and torn
+/// down after a period without connections.
+///
+/// Dropping the handle stops accepting and aborts the connection handlers.
+///
+/// Created by [`Tailscale::listen_on_demand`](crate::Tailscale::listen_on_demand).
+pub struct OnDemand {
+    listener: Arc<Listener>,
+    state: Arc<State>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct State {
+    warm: AtomicBool,
+    starts: AtomicU64,
+}
+
+impl OnDemand {
+    pub(crate) fn spawn<F, Fut, S, E, H>(
+        listener: Arc<Listener>,
+        idle_timeout: Duration,
+        factory: F,
+    ) -> Self
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = std::result::Result<S, E>> + Send + 'static,
+        S: Fn(Connection) -> H + Send + Sync + 'static,
+        H: Future<Output = ()> + Send + 'static,
+        E: Display + 'static,
+    {
+        let state = Arc::<State>::default();
+        let task = tokio::spawn(run(
+            Arc::clone(&listener),
+            Arc::clone(&state),
+            idle_timeout,
+            factory,
+        ));
+        OnDemand {
+            listener,
+            state,
+            task,
+        }
+    }
+
+    /// Returns the listener connections are accepted from.
+    pub fn listener(&self) -> &Arc<Listener> {
+        &self.listener
+    }
+
+    /// Returns true while the service is running.
+    pub fn is_warm(&self) -> bool {
+        self.state.warm.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many times the service has been started.
+    pub fn starts(&self) -> u64 {
+        self.state.starts.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for OnDemand {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run<F, Fut, S, E, H>(
+    listener: Arc<Listener>,
+    state: Arc<State>,
+    idle_timeout: Duration,
+    factory: F,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = std::result::Result<S, E>>,
+    S: Fn(Connection) -> H + Send + Sync + 'static,
+    H: Future<Output = ()> + Send + 'static,
+    E: Display,
+{
+    let mut service: Option<Arc<S>> = None;
+    let mut handlers = JoinSet::new();
+    loop {
+        let idle = service.is_some() && handlers.is_empty();
+        tokio::select! {
+            res = listener.accept() => {
+                let conn = match res {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        error!(error = %e, "on-demand listener failed, stopping");
+                        break;
+                    }
+                };
+                let handler = match &service {
+                    Some(service) => Arc::clone(service),
+                    None => {
+                        debug!("starting on-demand service");
+                        match factory().await {
+                            Ok(started) => {
+                                state.warm.store(true, Ordering::Relaxed);
+                                state.starts.fetch_add(1, Ordering::Relaxed);
+                                Arc::clone(service.insert(Arc::new(started)))
+                            }
+                            Err(e) => {
+                                error!(error = %e, "starting on-demand service failed, closing connection");
+                                continue;
+                            }
+                        }
+                    }
+                };
+                let span = conn.span().clone();
+                handlers.spawn(async move { handler(conn).await }.instrument(span));
+            }
+            Some(res) = handlers.join_next(), if !handlers.is_empty() => {
+                if let Err(e) = res {
+                    error!(error = %e, "on-demand connection handler failed");
+                }
+            }
+            () = tokio::time::sleep(idle_timeout), if idle => {
+                debug!(?idle_timeout, "tearing down idle on-demand service");
+                service = None;
+                state.warm.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+    state.warm.store(false, Ordering::Relaxed);
+}
@@ -0,0 +1,310 @@
+//! OpenTelemetry spans and metrics (`otel` feature).
+//!
+//! Each instance reports its work through the tracer and meter providers
+//! installed with [`opentelemetry::global`], under the instrumentation scope
+//! `tailscale2`, so tailnet services show up in the application's
+//! distributed traces. The providers must be installed before the instance
+//! is built.
+//!
+//! | Span | Kind | Covers |
+//! |------|------|--------|
+//! | `tailscale.up` | internal | [`Tailscale::up`](crate::Tailscale::up), including retries |
+//! | `tailscale.dial` | client | [`Tailscale::connect`](crate::Tailscale::connect), including retries |
+//! | `tailscale.accept` | server | setting up a connection taken off a listener |
+//! | `tailscale.connection` | client or server | a connection, until it and its clones are dropped |
+//!
+//! `up` and dial spans are children of the current OpenTelemetry context of
+//! the caller; accept spans start new traces. Connection spans are children
+//! of the span that opened the connection, and
+//! [`Connection::otel_context`](crate::Connection::otel_context) returns a
+//! context for parenting the application's own spans under them. Spans
+//! carry the semantic-convention attributes `server.address`,
+//! `server.port`, `network.transport`, `network.type`,
+//! `network.peer.address`, `network.peer.port`, `network.local.address`,
+//! `network.local.port` and `error.type` where they apply.
+//!
+//! | Metric | Instrument | Unit |
+//! |--------|------------|------|
+//! | `tailscale.up.duration` | histogram | `s` |
+//! | `tailscale.dial.duration` | histogram | `s` |
+//! | `tailscale.accept.duration` | histogram | `s` |
+//! | `tailscale.connection.duration` | histogram | `s` |
+//! | `tailscale.connection.active` | up-down counter | `{connection}` |
+//! | `tailscale.connection.io` | counter | `By` |
+//!
+//! Durations of failed operations carry `error.type`, and
+//! `tailscale.connection.io` carries `network.io.direction`. Each metric
+//! carries `tailscale.hostname` when the instance was built with a
+//! [hostname](crate::TailscaleBuilder::hostname).
+
+use std::{
+    net::SocketAddr,
+    time::{Instant, SystemTime},
+};
+
+use opentelemetry::{
+    Context, KeyValue, global,
+    global::{BoxedSpan, BoxedTracer},
+    metrics::{Counter, Histogram, UpDownCounter},
+    trace::{Span, SpanKind, Status, TraceContextExt, Tracer},
+};
+
+use crate::tailscale::{NetworkType, TailscaleError};
+
+/// Instrumentation scope of the tracer and meter.
+const SCOPE: &str = "tailscale2";
+
+/// Tracer and instruments taken from the global providers when the instance
+/// is built.
+pub(crate) struct Telemetry {
+    tracer: BoxedTracer,
+    hostname: Option<KeyValue>,
+    up_duration: Histogram<f64>,
+    dial_duration: Histogram<f64>,
+    accept_duration: Histogram<f64>,
+    connection_duration: Histogram<f64>,
+    active: UpDownCounter<i64>,
+    io: Counter<u64>,
+}
+
+impl Telemetry {
+    pub(crate) fn new(hostname: Option<&str>) -> Self {
+        let meter = global::meter(SCOPE);
+        let duration = |name: &'static str, description: &'static str| {
+            meter
+                .f64_histogram(name)
+                .with_unit("s")
+                .with_description(description)
+                .build()
+        };
+        Telemetry {
+            tracer: global::tracer(SCOPE),
+            hostname: hostname.map(|h| KeyValue::new("tailscale.hostname", h.to_string())),
+            up_duration: duration("tailscale.up.duration", "Time taken to bring the node up"),
+            dial_duration: duration("tailscale.dial.duration", "Time taken to dial a peer"),
+            accept_duration: duration(
+                "tailscale.accept.duration",
+                "Time taken to set up an accepted connection",
+            ),
+            connection_duration: duration(
+                "tailscale.connection.duration",
+                "Time connections stayed open",
+            ),
+            active: meter
+                .i64_up_down_counter("tailscale.connection.active")
+                .with_unit("{connection}")
+                .with_description("Connections currently open")
+                .build(),
+            io: meter
+                .u64_counter("tailscale.connection.io")
+                .with_unit("By")
+                .with_description("Bytes moved over connections")
+                .build(),
+        }
+    }
+
+    /// Returns the attributes every metric carries, followed by `extra`.
+    fn attributes(&self, extra: impl IntoIterator<Item = KeyValue>) -> Vec<KeyValue> {
+        self.hostname.iter().cloned().chain(extra).collect()
+    }
+
+    /// Starts the span of an `up` call, ended by [`Operation::finish`].
+    pub(crate) fn start_up(&self) -> Operation<'_> {
+        let span = self
+            .tracer
+            .span_builder("tailscale.up")
+            .with_kind(SpanKind::Internal)
+            .start_with_context(&self.tracer, &Context::current());
+        Operation {
+            span,
+            start: Instant::now(),
+            duration: &self.up_duration,
+            attributes: self.attributes([]),
+        }
+    }
+
+    /// Starts the span of a dial to `addr`, ended by [`Operation::finish`].
+    pub(crate) fn start_dial(&self, network: NetworkType, addr: &str) -> Operation<'_> {
+        let transport = KeyValue::new("network.transport", transport(network));
+        let mut attributes = vec![transport.clone()];
+        if let Some((host, port)) = addr.rsplit_once(':') {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            attributes.push(KeyValue::new("server.address", host.to_string()));
+            if let Ok(port) = port.parse::<i64>() {
+                attributes.push(KeyValue::new("server.port", port));
+            }
+        }
+        let span = self
+            .tracer
+            .span_builder("tailscale.dial")
+            .with_kind(SpanKind::Client)
+            .with_attributes(attributes)
+            .start_with_context(&self.tracer, &Context::current());
+        Operation {
+            span,
+            start: Instant::now(),
+            duration: &self.dial_duration,
+            attributes: self.attributes([transport]),
+        }
+    }
+
+    /// Records the span of an accept that started setting up a connection
+    /// at `start`, returning the context to open the connection's span in.
+    pub(crate) fn accepted(&self, start: Instant, addrs: Addrs) -> Context {
+        let took = start.elapsed();
+        let mut span = self
+            .tracer
+            .span_builder("tailscale.accept")
+            .with_kind(SpanKind::Server)
+            .with_start_time(SystemTime::now() - took)
+            .with_attributes(addrs.attributes())
+            .start_with_context(&self.tracer, &Context::new());
+        span.end();
+        self.accept_duration
+            .record(took.as_secs_f64(), &self.attributes([]));
+        Context::new().with_span(span)
+    }
+
+    /// Starts the span of a connection opened in `parent`, counting it as
+    /// active until [`ConnectionSpan::end`] is called.
+    pub(crate) fn start_connection(
+        &self,
+        parent: &Context,
+        kind: SpanKind,
+        addrs: Addrs,
+    ) -> ConnectionSpan {
+        let span = self
+            .tracer
+            .span_builder("tailscale.connection")
+            .with_kind(kind)
+            .with_attributes(addrs.attributes())
+            .start_with_context(&self.tracer, parent);
+        self.active.add(1, &self.attributes([]));
+        ConnectionSpan {
+            context: Context::new().with_span(span),
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn record_io(&self, direction: &'static str, n: usize) {
+        self.io.add(
+            n as u64,
+            &self.attributes([KeyValue::new("network.io.direction", direction)]),
+        );
+    }
+}
+
+/// An `up` or dial in progress. Dropping it unfinished, as when the caller
+/// gives up on the operation, ends its span without a status.
+pub(crate) struct Operation<'a> {
+    span: BoxedSpan,
+    start: Instant,
+    duration: &'a Histogram<f64>,
+    attributes: Vec<KeyValue>,
+}
+
+impl Operation<'_> {
+    /// Ends the span with the outcome of the operation and records its
+    /// duration, returning the context to open a resulting connection's
+    /// span in.
+    pub(crate) fn finish<T>(
+        mut self,
+        res: &Result<T, TailscaleError>,
+        addrs: impl FnOnce(&T) -> Addrs,
+    ) -> Context {
+        match res {
+            Ok(value) => {
+                self.span.set_attributes(addrs(value).attributes());
+                self.span.set_status(Status::Ok);
+            }
+            Err(e) => {
+                let error_type = KeyValue::new("error.type", error_type(e));
+                self.span.set_attribute(error_type.clone());
+                self.span.set_status(Status::error(e.to_string()));
+                self.attributes.push(error_type);
+            }
+        }
+        self.span.end();
+        self.duration
+            .record(self.start.elapsed().as_secs_f64(), &self.attributes);
+        Context::new().with_span(self.span)
+    }
+}
+
+/// The span of an open connection, shared by the connection and its clones.
+pub(crate) struct ConnectionSpan {
+    /// Holds the span itself.
+    context: Context,
+    start: Instant,
+}
+
+impl ConnectionSpan {
+    pub(crate) fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Ends the span with the connection's byte counts and records how long
+    /// it was open.
+    pub(crate) fn end(&self, telemetry: &Telemetry, bytes_read: u64, bytes_written: u64) {
+        let span = self.context.span();
+        span.set_attributes([
+            KeyValue::new("tailscale.connection.bytes_read", bytes_read as i64),
+            KeyValue::new("tailscale.connection.bytes_written", bytes_written as i64),
+        ]);
+        span.end();
+        let attributes = telemetry.attributes([]);
+        telemetry.active.add(-1, &attributes);
+        telemetry
+            .connection_duration
+            .record(self.start.elapsed().as_secs_f64(), &attributes);
+    }
+}
+
+/// The addresses of a connection, as far as they could be looked up.
+#[derive(Default)]
+pub(crate) struct Addrs {
+    pub(crate) remote: Option<SocketAddr>,
+    pub(crate) local: Option<SocketAddr>,
+}
+
+impl Addrs {
+    fn attributes(&self) -> Vec<KeyValue> {
+        let mut attributes = Vec::with_capacity(5);
+        if let Some(remote) = self.remote {
+            let network_type = if remote.is_ipv4() { "ipv4" } else { "ipv6" };
+            attributes.extend([
+                KeyValue::new("network.type", network_type),
+                KeyValue::new("network.peer.address", remote.ip().to_string()),
+                KeyValue::new("network.peer.port", i64::from(remote.port())),
+            ]);
+        }
+        if let Some(local) = self.local {
+            attributes.extend([
+                KeyValue::new("network.local.address", local.ip().to_string()),
+                KeyValue::new("network.local.port", i64::from(local.port())),
+            ]);
+        }
+        attributes
+    }
+}
+
+fn transport(network: NetworkType) -> &'static str {
+    match network {
+        NetworkType::Tcp | NetworkType::Tcp4 | NetworkType::Tcp6 => "tcp",
+        NetworkType::Udp | NetworkType::Udp4 | NetworkType::Udp6 => "udp",
+    }
+}
+
+/// Returns a low-cardinality name for the kind of `e`, for `error.type`.
+fn error_type(e: &TailscaleError) -> &'static str {
+    match e {
+        TailscaleError::MalformedAddress { .. } => "malformed_address",
+        TailscaleError::UpFailed(_) => "up_failed",
+        TailscaleError::DialFailed { .. } => "dial_failed",
+        TailscaleError::AcceptFailed(_) => "accept_failed",
+        TailscaleError::Timeout => "timeout",
+        TailscaleError::Cancelled => "cancelled",
+        TailscaleError::InvalidHandle => "invalid_handle",
+        _ => "_OTHER",
+    }
+}
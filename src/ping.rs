@@ -0,0 +1,93 @@
+//! Pinging peers to measure reachability, latency and the path taken.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+/// The kind of ping sent by [`Tailscale::ping`](crate::Tailscale::ping).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PingKind {
+    /// A path discovery ping between the two Tailscale stacks, outside the
+    /// WireGuard tunnel. Shows whether a direct path has been established.
+    Disco,
+    /// An ICMP echo through the tunnel, answered by the peer's network stack.
+    Icmp,
+    /// A Tailscale Message Protocol ping through the tunnel, answered by the
+    /// peer's Tailscale stack.
+    Tsmp,
+}
+
+impl PingKind {
+    /// Returns the ping type as understood by libtailscale.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PingKind::Disco => "disco",
+            PingKind::Icmp => "ICMP",
+            PingKind::Tsmp => "TSMP",
+        }
+    }
+}
+
+/// How a ping reached the peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum PingPath {
+    /// Directly, to the peer's endpoint at this address.
+    Direct(SocketAddr),
+    /// Relayed through the DERP region with this code, e.g. `fra`.
+    Derp(String),
+    /// Not reported, as for [`PingKind::Icmp`] pings.
+    Unknown,
+}
+
+/// The reply to a ping, returned by [`Tailscale::ping`](crate::Tailscale::ping).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "RawPingResult")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PingResult {
+    /// The Tailscale IP address of the peer that replied.
+    pub node_ip: IpAddr,
+    /// The MagicDNS name of the peer without the trailing dot.
+    pub node_name: String,
+    /// The round-trip time.
+    pub latency: Duration,
+    /// The path the ping took.
+    pub path: PingPath,
+}
+
+impl PingResult {
+    /// Returns true if the ping went directly to the peer rather than through
+    /// a DERP relay.
+    pub fn is_direct(&self) -> bool {
+        matches!(self.path, PingPath::Direct(_))
+    }
+}
+
+/// The JSON written by `tailscale_ping`.
+#[derive(Deserialize)]
+struct RawPingResult {
+    node_ip: IpAddr,
+    node_name: String,
+    latency_seconds: f64,
+    endpoint: String,
+    derp_region_code: String,
+}
+
+impl From<RawPingResult> for PingResult {
+    fn from(raw: RawPingResult) -> Self {
+        let path = match (raw.endpoint.parse(), raw.derp_region_code) {
+            (Ok(endpoint), _) => PingPath::Direct(endpoint),
+            (Err(_), region) if !region.is_empty() => PingPath::Derp(region),
+            _ => PingPath::Unknown,
+        };
+        PingResult {
+            node_ip: raw.node_ip,
+            node_name: raw.node_name,
+            latency: Duration::try_from_secs_f64(raw.latency_seconds).unwrap_or_default(),
+            path,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Startup diagnostics, run before bringing a node up.
+
+use serde::Deserialize;
+
+use crate::tailscale::{Result, TailscaleError};
+
+/// The outcome of a single preflight check.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PreflightCheck {
+    /// Whether the check passed.
+    pub ok: bool,
+    /// What was checked on success, or why the check failed.
+    pub detail: String,
+}
+
+/// Report returned by [`Tailscale::preflight`](crate::Tailscale::preflight).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PreflightReport {
+    /// Whether the control plane answered over HTTPS.
+    pub control: PreflightCheck,
+    /// Whether at least one DERP relay answered its probe endpoint.
+    pub derp: PreflightCheck,
+    /// Whether a UDP socket could be bound on the configured port.
+    pub udp: PreflightCheck,
+    /// Whether the state directory could be created and written to.
+    pub state_dir: PreflightCheck,
+    /// Whether the local clock is within a minute of the control plane's clock.
+    pub time_skew: PreflightCheck,
+    /// Local clock minus the control plane's clock, in milliseconds.
+    ///
+    /// Zero if the control plane could not be reached.
+    pub clock_skew_ms: i64,
+}
+
+impl PreflightReport {
+    /// Returns true if every check passed.
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Returns the name and result of every check that failed.
+    pub fn failures(&self) -> impl Iterator<Item = (&'static str, &PreflightCheck)> {
+        [
+            ("control", &self.control),
+            ("derp", &self.derp),
+            ("udp", &self.udp),
+            ("state_dir", &self.state_dir),
+            ("time_skew", &self.time_skew),
+        ]
+        .into_iter()
+        .filter(|(_, check)| !check.ok)
+    }
+
+    /// Returns an error describing every failed check, if any.
+    pub fn ensure_ok(&self) -> Result<()> {
+        if self.is_ok() {
+            return Ok(());
+        }
+        let message = self
+            .failures()
+            .map(|(name, check)| format!("{name}: {}", check.detail))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(TailscaleError::PreflightFailed(message))
+    }
+}
@@ -0,0 +1,253 @@
+//! [PROXY protocol] headers sent to local backends by
+//! [`Tailscale::forward_inbound_with_proxy_protocol`](crate::Tailscale::forward_inbound_with_proxy_protocol).
+//!
+//! The header tells the backend the tailnet address the connection really
+//! came from. Version 2 headers also carry the peer's Tailscale identity in
+//! TLVs of the types below, from the range the specification reserves for
+//! custom use. Their values are UTF-8 strings; a TLV is left out when the
+//! value is unknown, e.g. for Funnel connections from the public internet.
+//!
+//! [PROXY protocol]: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::net::{IpAddr, SocketAddr};
+
+use crate::identity::PeerIdentity;
+
+/// TLV carrying the login name of the user owning the peer node, e.g.
+/// `alice@example.com`.
+pub const TLV_LOGIN_NAME: u8 = 0xe0;
+/// TLV carrying the MagicDNS name of the peer node.
+pub const TLV_NODE_NAME: u8 = 0xe1;
+/// TLV carrying the stable ID of the peer node.
+pub const TLV_NODE_ID: u8 = 0xe2;
+/// TLV carrying the peer node's ACL tags, separated by commas.
+pub const TLV_TAGS: u8 = 0xe3;
+
+/// The longest TLV value sent, which keeps the header within its 16-bit
+/// length field.
+const MAX_TLV_VALUE: usize = 4096;
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The version of the PROXY protocol header to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Version {
+    /// The human-readable header, e.g. `PROXY TCP4 100.64.0.1 100.64.0.2 41234 443`.
+    /// Carries no identity.
+    V1,
+    /// The binary header, with the peer's identity in TLVs.
+    V2,
+}
+
+/// Encodes a header for a TCP connection from `source` to `destination`.
+pub(crate) fn encode(
+    version: Version,
+    source: SocketAddr,
+    destination: SocketAddr,
+    identity: Option<&PeerIdentity>,
+) -> Vec<u8> {
+    // Both addresses must be of the same family, so IPv4 addresses are
+    // mapped into IPv6 when they differ.
+    let (src_ip, dst_ip) = match (source.ip(), destination.ip()) {
+        (IpAddr::V4(src), IpAddr::V6(dst)) => (IpAddr::V6(src.to_ipv6_mapped()), IpAddr::V6(dst)),
+        (IpAddr::V6(src), IpAddr::V4(dst)) => (IpAddr::V6(src), IpAddr::V6(dst.to_ipv6_mapped())),
+        ips => ips,
+    };
+    match version {
+        Version::V1 => {
+            let family = if src_ip.is_ipv4() { "TCP4" } else { "TCP6" };
+            format!(
+                "PROXY {family} {src_ip} {dst_ip} {} {}\r\n",
+                source.port(),
+                destination.port()
+            )
+            .into_bytes()
+        }
+        Version::V2 => {
+            let mut body = Vec::new();
+            let family = match (src_ip, dst_ip) {
+                (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                    body.extend_from_slice(&src.octets());
+                    body.extend_from_slice(&dst.octets());
+                    0x11
+                }
+                (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                    body.extend_from_slice(&src.octets());
+                    body.extend_from_slice(&dst.octets());
+                    0x21
+                }
+                _ => unreachable!("addresses were mapped to the same family"),
+            };
+            body.extend_from_slice(&source.port().to_be_bytes());
+            body.extend_from_slice(&destination.port().to_be_bytes());
+            if let Some(identity) = identity {
+                push_tlv(&mut body, TLV_LOGIN_NAME, &identity.login_name);
+                push_tlv(&mut body, TLV_NODE_NAME, &identity.node_name);
+                push_tlv(&mut body, TLV_NODE_ID, &identity.node_id);
+                push_tlv(&mut body, TLV_TAGS, &identity.tags.join(","));
+            }
+
+            let mut header = Vec::with_capacity(16 + body.len());
+            header.extend_from_slice(&V2_SIGNATURE);
+            // Version 2, PROXY command.
+            header.push(0x21);
+            header.push(family);
+            header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+            header.extend_from_slice(&body);
+            header
+        }
+    }
+}
+
+fn push_tlv(body: &mut Vec<u8>, kind: u8, value: &str) {
+    if value.is_empty() || value.len() > MAX_TLV_VALUE {
+        return;
+    }
+    body.push(kind);
+    body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    body.extend_from_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> PeerIdentity {
+        PeerIdentity {
+            addr: "100.64.0.1:41234".parse().unwrap(),
+            node_id: "nID".to_string(),
+            node_name: "peer.ts.net.".to_string(),
+            login_name: "alice@example.com".to_string(),
+            display_name: "Alice".to_string(),
+            tags: vec!["tag:a".to_string(), "tag:b".to_string()],
+        }
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn v1_ipv4() {
+        let header = encode(
+            Version::V1,
+            addr("100.64.0.1:41234"),
+            addr("100.64.0.2:443"),
+            Some(&identity()),
+        );
+        assert_eq!(header, b"PROXY TCP4 100.64.0.1 100.64.0.2 41234 443\r\n");
+    }
+
+    #[test]
+    fn v1_ipv6() {
+        let header = encode(
+            Version::V1,
+            addr("[fd7a:115c:a1e0::1]:41234"),
+            addr("[fd7a:115c:a1e0::2]:443"),
+            None,
+        );
+        assert_eq!(
+            header,
+            b"PROXY TCP6 fd7a:115c:a1e0::1 fd7a:115c:a1e0::2 41234 443\r\n"
+        );
+    }
+
+    #[test]
+    fn v1_maps_mixed_families_to_ipv6() {
+        let header = encode(
+            Version::V1,
+            addr("100.64.0.1:41234"),
+            addr("[fd7a:115c:a1e0::2]:443"),
+            None,
+        );
+        assert_eq!(
+            header,
+            b"PROXY TCP6 ::ffff:100.64.0.1 fd7a:115c:a1e0::2 41234 443\r\n"
+        );
+    }
+
+    #[test]
+    fn v2_ipv4_without_identity() {
+        let header = encode(
+            Version::V2,
+            addr("100.64.0.1:41234"),
+            addr("100.64.0.2:443"),
+            None,
+        );
+        let mut expected = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+        expected.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c]);
+        expected.extend_from_slice(&[100, 64, 0, 1, 100, 64, 0, 2]);
+        expected.extend_from_slice(&[0xa1, 0x12, 0x01, 0xbb]);
+        assert_eq!(header, expected);
+    }
+
+    #[test]
+    fn v2_maps_mixed_families_to_ipv6() {
+        let header = encode(
+            Version::V2,
+            addr("[fd7a:115c:a1e0::1]:41234"),
+            addr("100.64.0.2:443"),
+            None,
+        );
+        assert_eq!(&header[12..16], &[0x21, 0x21, 0x00, 0x24]);
+        assert_eq!(
+            &header[16..32],
+            &"fd7a:115c:a1e0::1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+        let mut mapped = [0; 16];
+        mapped[10..12].copy_from_slice(&[0xff, 0xff]);
+        mapped[12..].copy_from_slice(&[100, 64, 0, 2]);
+        assert_eq!(&header[32..48], &mapped);
+        assert_eq!(&header[48..], &[0xa1, 0x12, 0x01, 0xbb]);
+    }
+
+    #[test]
+    fn v2_identity_tlvs() {
+        let header = encode(
+            Version::V2,
+            addr("100.64.0.1:41234"),
+            addr("100.64.0.2:443"),
+            Some(&identity()),
+        );
+        let mut tlvs = Vec::new();
+        for (kind, value) in [
+            (TLV_LOGIN_NAME, "alice@example.com"),
+            (TLV_NODE_NAME, "peer.ts.net."),
+            (TLV_NODE_ID, "nID"),
+            (TLV_TAGS, "tag:a,tag:b"),
+        ] {
+            tlvs.push(kind);
+            tlvs.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            tlvs.extend_from_slice(value.as_bytes());
+        }
+        let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+        assert_eq!(len, 12 + tlvs.len());
+        assert_eq!(header.len(), 16 + len);
+        assert_eq!(&header[28..], &tlvs[..]);
+    }
+
+    #[test]
+    fn v2_drops_empty_and_oversize_tlvs() {
+        let mut identity = identity();
+        identity.login_name = "x".repeat(MAX_TLV_VALUE + 1);
+        identity.node_name = "n".repeat(MAX_TLV_VALUE);
+        identity.tags.clear();
+        let header = encode(
+            Version::V2,
+            addr("100.64.0.1:41234"),
+            addr("100.64.0.2:443"),
+            Some(&identity),
+        );
+        let mut tlvs = vec![TLV_NODE_NAME, 0x10, 0x00];
+        tlvs.extend_from_slice(identity.node_name.as_bytes());
+        tlvs.extend_from_slice(&[TLV_NODE_ID, 0x00, 0x03]);
+        tlvs.extend_from_slice(b"nID");
+        assert_eq!(&header[28..], &tlvs[..]);
+        let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+        assert_eq!(len, 12 + tlvs.len());
+    }
+}
@@ -0,0 +1,148 @@
+//! QUIC over the tailnet, enabled by the `quic` feature.
+//!
+//! quinn runs over a tailnet [`UdpSocket`] through its `AsyncUdpSocket`
+//! abstraction, so HTTP/3 or custom QUIC protocols work between nodes. quinn
+//! is re-exported as [`quinn`] so its configuration types match the version
+//! used here.
+
+use std::{
+    io::{self, IoSliceMut},
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+pub use quinn;
+use quinn::{
+    AsyncUdpSocket, Endpoint, EndpointConfig, ServerConfig, TokioRuntime, UdpPoller,
+    udp::{RecvMeta, Transmit},
+};
+use tracing::debug;
+
+use crate::{
+    addr::ToListenAddr,
+    tailscale::{Result, Tailscale, TailscaleError},
+    udp::UdpSocket,
+};
+
+impl Tailscale {
+    /// Creates a QUIC endpoint on a tailnet [`UdpSocket`], see
+    /// [`listen_packet`](Self::listen_packet) for `addr`.
+    ///
+    /// With a `server_config` the endpoint accepts incoming connections;
+    /// without one it can only connect out, after setting a client
+    /// configuration with [`Endpoint::set_default_client_config`] or passing
+    /// one to [`Endpoint::connect_with`]. The endpoint must be used from a
+    /// tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be opened, or
+    /// [`TailscaleError::Quic`] if quinn rejects it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(
+    /// #     ts: std::sync::Arc<Tailscale>,
+    /// #     server_config: quinn::ServerConfig,
+    /// # ) -> Result<()> {
+    /// let endpoint = ts.quic_endpoint(4433, Some(server_config)).await?;
+    /// while let Some(incoming) = endpoint.accept().await {
+    ///     tokio::spawn(async move {
+    ///         if let Ok(connection) = incoming.await {
+    ///             // open or accept streams on `connection`
+    ///         }
+    ///     });
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn quic_endpoint(
+        self: &Arc<Self>,
+        addr: impl ToListenAddr,
+        server_config: Option<ServerConfig>,
+    ) -> Result<Endpoint> {
+        let socket = self.listen_packet(addr).await?;
+        debug!(local_addr = %socket.local_addr(), "creating QUIC endpoint");
+        Endpoint::new_with_abstract_socket(
+            EndpointConfig::default(),
+            server_config,
+            Arc::new(QuicSocket(socket)),
+            Arc::new(TokioRuntime),
+        )
+        .map_err(|e| TailscaleError::Quic(e.to_string()))
+    }
+}
+
+/// A tailnet [`UdpSocket`] as seen by quinn.
+#[derive(Debug)]
+struct QuicSocket(UdpSocket);
+
+impl AsyncUdpSocket for QuicSocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        Box::pin(Poller(self))
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+        // Segmentation offload is not supported, so split the contents into
+        // datagrams if quinn batched several.
+        let segment_size = transmit
+            .segment_size
+            .unwrap_or(transmit.contents.len())
+            .max(1);
+        for (i, datagram) in transmit.contents.chunks(segment_size).enumerate() {
+            match self.0.try_send_to(datagram, transmit.destination) {
+                Ok(_) => {}
+                // Once part of the transmit is out, quinn must not retry it,
+                // as that would resend the earlier datagrams. The rest is
+                // lost as on a congested network and recovered by QUIC.
+                Err(e) if i > 0 && e.kind() == io::ErrorKind::WouldBlock => {
+                    debug!(sent = i, "socket full, dropping the rest of a transmit");
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        let (Some(buf), Some(meta)) = (bufs.first_mut(), meta.first_mut()) else {
+            return Poll::Ready(Ok(0));
+        };
+        let (len, addr) = match self.0.poll_recv_from(cx, buf) {
+            Poll::Ready(Ok(received)) => received,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        *meta = RecvMeta {
+            addr,
+            len,
+            stride: len,
+            ..RecvMeta::default()
+        };
+        Poll::Ready(Ok(1))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.0.local_addr())
+    }
+}
+
+/// Waits for a [`QuicSocket`] to become writable.
+#[derive(Debug)]
+struct Poller(Arc<QuicSocket>);
+
+impl UdpPoller for Poller {
+    fn poll_writable(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.0.0.poll_send_ready(cx)
+    }
+}
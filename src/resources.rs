@@ -0,0 +1,96 @@
+//! Bookkeeping of the listeners and connections belonging to a Tailscale instance.
+//!
+//! [`Tailscale::close`](crate::Tailscale::close) consults this registry to
+//! refuse shutting down while children are still alive, and
+//! [`Tailscale::force_close`](crate::Tailscale::force_close) uses it to shut
+//! them down first.
+
+use std::{
+    collections::HashMap,
+    os::fd::RawFd,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use nix::sys::socket::Shutdown;
+use tracing::error;
+
+/// The kind of a tracked resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ResourceKind {
+    Listener,
+    Connection,
+}
+
+/// Registry of the live listeners and connections of a single Tailscale instance.
+#[derive(Default)]
+pub(crate) struct Resources {
+    live: Mutex<HashMap<u64, (ResourceKind, RawFd)>>,
+    next_id: AtomicU64,
+}
+
+impl Resources {
+    /// Registers `fd` until the returned guard is dropped.
+    pub(crate) fn register(self: &Arc<Self>, kind: ResourceKind, fd: RawFd) -> ResourceGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.live.lock().unwrap().insert(id, (kind, fd));
+        ResourceGuard {
+            resources: Arc::clone(self),
+            id,
+        }
+    }
+
+    /// Returns the number of live listeners and connections.
+    pub(crate) fn counts(&self) -> (usize, usize) {
+        tally(&self.live.lock().unwrap())
+    }
+
+    /// Shuts down every live resource so pending and future I/O on it fails,
+    /// and returns how many listeners and connections were shut down.
+    ///
+    /// The resources stay registered until their owners drop them.
+    pub(crate) fn shutdown_all(&self) -> (usize, usize) {
+        // Hold the lock while shutting down: guards unregister under the same
+        // lock before their fd is closed, so no fd here can have been reused.
+        let live = self.live.lock().unwrap();
+        for (kind, fd) in live.values() {
+            if let Err(e) = nix::sys::socket::shutdown(*fd, Shutdown::Both) {
+                error!(?kind, fd, error = %e, "error shutting down resource");
+            }
+        }
+        tally(&live)
+    }
+}
+
+fn tally(live: &HashMap<u64, (ResourceKind, RawFd)>) -> (usize, usize) {
+    let listeners = live
+        .values()
+        .filter(|(kind, _)| *kind == ResourceKind::Listener)
+        .count();
+    (listeners, live.len() - listeners)
+}
+
+/// Registration of a live resource, released when dropped.
+///
+/// Owners must declare the guard before the fd it tracks, so that it is
+/// released before the fd is closed.
+pub(crate) struct ResourceGuard {
+    resources: Arc<Resources>,
+    id: u64,
+}
+
+impl ResourceGuard {
+    /// Registers `fd` with the same registry as this guard, for a resource
+    /// derived from this one.
+    pub(crate) fn register_sibling(&self, kind: ResourceKind, fd: RawFd) -> ResourceGuard {
+        self.resources.register(kind, fd)
+    }
+}
+
+impl Drop for ResourceGuard {
+    fn drop(&mut self) {
+        self.resources.live.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,244 @@
+//! Retrying of startup and dials with exponential backoff.
+
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use tracing::warn;
+
+use crate::tailscale::{Result, TailscaleError};
+
+/// How often and how fast to retry a failed operation.
+///
+/// Set with [`TailscaleBuilder::up_retry`](crate::TailscaleBuilder::up_retry)
+/// and [`TailscaleBuilder::dial_retry`](crate::TailscaleBuilder::dial_retry).
+/// The delay before retry `n` is `initial_backoff * multiplier^(n - 1)`,
+/// capped at `max_backoff`. With jitter enabled, a random delay between half
+/// and all of that is used instead, so that many nodes failing at once do not
+/// retry in lockstep.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tailscale2::RetryPolicy;
+/// let policy = RetryPolicy::new(5)
+///     .initial_backoff(Duration::from_millis(200))
+///     .max_backoff(Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Creates a policy making at most `max_attempts` attempts in total,
+    /// starting with a 500ms delay that doubles up to 30s, with jitter.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: true,
+        }
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the upper bound on the delay between attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets the factor the delay grows by after each retry, at least 1.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Enables or disables randomizing the delay.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns the maximum number of attempts.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the delay before retry number `retry`, starting at 1, before
+    /// jitter is applied.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let factor = self.multiplier.powi(exponent);
+        self.initial_backoff
+            .mul_f64(factor.min(u32::MAX as f64))
+            .min(self.max_backoff)
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        if !self.jitter {
+            return backoff;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        backoff / 2 + backoff.mul_f64((random as f64 / u64::MAX as f64) / 2.0)
+    }
+
+    /// Runs `op` until it succeeds, fails with an error that retrying cannot
+    /// fix, or the attempts are used up.
+    pub(crate) async fn run<T, F, Fut>(&self, what: &str, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(e) if attempt < self.max_attempts && is_retryable(&e) => {
+                    let delay = self.delay(attempt);
+                    warn!(attempt, ?delay, error = %e, "{what} failed, retrying");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Returns true for failures that may be transient, such as an unreachable
+/// control plane or peer.
+fn is_retryable(e: &TailscaleError) -> bool {
+    matches!(
+        e,
+        TailscaleError::UpFailed(_) | TailscaleError::DialFailed { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use tokio::time::Instant;
+
+    use super::*;
+
+    fn fixed(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(max_attempts)
+            .initial_backoff(Duration::from_secs(1))
+            .max_backoff(Duration::from_secs(5))
+            .jitter(false)
+    }
+
+    fn dial_failed() -> TailscaleError {
+        TailscaleError::DialFailed {
+            network: "tcp".to_string(),
+            addr: "peer:80".to_string(),
+            message: "no route".to_string(),
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = fixed(10);
+        let delays: Vec<_> = (1..=5).map(|n| policy.backoff(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+        assert_eq!(policy.backoff(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn multiplier_is_at_least_one() {
+        let policy = fixed(10).multiplier(0.5);
+        assert_eq!(policy.backoff(4), Duration::from_secs(1));
+        let policy = fixed(10).multiplier(3.0);
+        assert_eq!(policy.backoff(2), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn at_least_one_attempt() {
+        assert_eq!(RetryPolicy::new(0).max_attempts(), 1);
+        assert_eq!(RetryPolicy::new(3).max_attempts(), 3);
+    }
+
+    #[test]
+    fn jitter_stays_between_half_and_full_backoff() {
+        let policy = fixed(10).jitter(true);
+        for retry in 1..=4 {
+            let backoff = policy.backoff(retry);
+            for _ in 0..100 {
+                let delay = policy.delay(retry);
+                assert!(delay >= backoff / 2 && delay <= backoff, "{delay:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn retryable_errors() {
+        assert!(is_retryable(&TailscaleError::UpFailed("no control".into())));
+        assert!(is_retryable(&dial_failed()));
+        assert!(!is_retryable(&TailscaleError::Timeout));
+        assert!(!is_retryable(&TailscaleError::InvalidHandle));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_retries_until_success() {
+        let calls = Cell::new(0);
+        let start = Instant::now();
+        let res = fixed(5)
+            .run("dial", || {
+                calls.set(calls.get() + 1);
+                let n = calls.get();
+                async move { if n < 4 { Err(dial_failed()) } else { Ok(n) } }
+            })
+            .await;
+        assert_eq!(res.unwrap(), 4);
+        assert_eq!(start.elapsed(), Duration::from_secs(1 + 2 + 4));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_gives_up_after_max_attempts() {
+        let calls = Cell::new(0);
+        let start = Instant::now();
+        let res: Result<()> = fixed(3)
+            .run("up", || {
+                calls.set(calls.get() + 1);
+                async { Err(TailscaleError::UpFailed("no control".into())) }
+            })
+            .await;
+        assert!(matches!(res, Err(TailscaleError::UpFailed(_))));
+        assert_eq!(calls.get(), 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(1 + 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_does_not_retry_permanent_errors() {
+        let calls = Cell::new(0);
+        let start = Instant::now();
+        let res: Result<()> = fixed(5)
+            .run("dial", || {
+                calls.set(calls.get() + 1);
+                async { Err(TailscaleError::InvalidHandle) }
+            })
+            .await;
+        assert!(matches!(res, Err(TailscaleError::InvalidHandle)));
+        assert_eq!(calls.get(), 1);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,211 @@
+//! Serving every connection of a listener with a handler, see
+//! [`Listener::serve`].
+
+use std::{
+    any::Any,
+    future::{Future, IntoFuture},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+use tracing::{Instrument, debug, error, warn};
+
+use crate::tailscale::{Connection, Listener, Result};
+
+/// Grace period used when [`Serve::with_graceful_shutdown`] is not called
+/// and the listener is shut down by other means.
+const DEFAULT_GRACE: Duration = Duration::from_secs(30);
+
+type Signal = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+impl Listener {
+    /// Runs `handler` for every accepted connection, each in its own task.
+    ///
+    /// Returns a [`Serve`] to configure a concurrency limit and a shutdown
+    /// signal; awaiting it runs the accept loop until the signal fires, the
+    /// listener is [shut down](Self::shutdown) or accepting fails. A panic in
+    /// a handler is logged and only ends that connection. Must be awaited
+    /// from a tokio runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// let listener = ts.listener(NetworkType::Tcp, 8080).await?;
+    /// listener
+    ///     .serve(|mut conn: Connection| async move {
+    ///         let _ = conn.write_all(b"hello\n").await;
+    ///     })
+    ///     .max_connections(1024)
+    ///     .with_graceful_shutdown(
+    ///         async { tokio::signal::ctrl_c().await.unwrap() },
+    ///         Duration::from_secs(30),
+    ///     )
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serve<H, Fut>(self: &Arc<Self>, handler: H) -> Serve<H>
+    where
+        H: Fn(Connection) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Serve {
+            listener: Arc::clone(self),
+            handler,
+            max_connections: None,
+            shutdown: None,
+            grace: DEFAULT_GRACE,
+        }
+    }
+}
+
+/// An accept loop created by [`Listener::serve`], run by awaiting it.
+#[must_use = "the listener is only served when this is awaited"]
+pub struct Serve<H> {
+    listener: Arc<Listener>,
+    handler: H,
+    max_connections: Option<usize>,
+    shutdown: Option<Signal>,
+    grace: Duration,
+}
+
+impl<H> Serve<H> {
+    /// Limits the handlers running at once to `max`.
+    ///
+    /// Once `max` handlers are running, no further connections are accepted
+    /// until one finishes, so excess peers queue up in the listener's
+    /// backlog.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Stops accepting connections once `signal` completes, then waits up to
+    /// `grace` for running handlers before shutting down their connections
+    /// and aborting them, as [`Listener::shutdown`] does.
+    pub fn with_graceful_shutdown(
+        mut self,
+        signal: impl Future<Output = ()> + Send + 'static,
+        grace: Duration,
+    ) -> Self {
+        self.shutdown = Some(Box::pin(signal));
+        self.grace = grace;
+        self
+    }
+}
+
+impl<H, Fut> IntoFuture for Serve<H>
+where
+    H: Fn(Connection) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    type Output = Result<()>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(run(self))
+    }
+}
+
+async fn run<H, Fut>(serve: Serve<H>) -> Result<()>
+where
+    H: Fn(Connection) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let Serve {
+        listener,
+        handler,
+        max_connections,
+        shutdown,
+        grace,
+    } = serve;
+    let mut shutdown = shutdown.unwrap_or_else(|| Box::pin(std::future::pending()));
+    let limit = max_connections.map(|max| Arc::new(Semaphore::new(max)));
+    let mut handlers = JoinSet::new();
+
+    let res = loop {
+        let permit = match &limit {
+            Some(limit) => tokio::select! {
+                permit = Arc::clone(limit).acquire_owned() => {
+                    Some(permit.expect("handler limit semaphore is never closed"))
+                }
+                Some(res) = handlers.join_next(), if !handlers.is_empty() => {
+                    log_handler_result(res);
+                    continue;
+                }
+                () = &mut shutdown => break Ok(()),
+            },
+            None => None,
+        };
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => {
+                    let span = conn.span().clone();
+                    let handle = handler(conn);
+                    handlers.spawn(
+                        async move {
+                            handle.await;
+                            drop(permit);
+                        }
+                        .instrument(span),
+                    );
+                }
+                Err(_) if listener.is_shut_down() => break Ok(()),
+                Err(e) => {
+                    warn!(error = %e, "listener stopped accepting connections");
+                    break Err(e);
+                }
+            },
+            Some(res) = handlers.join_next(), if !handlers.is_empty() => log_handler_result(res),
+            () = &mut shutdown => break Ok(()),
+        }
+    };
+
+    debug!(?grace, open = handlers.len(), "stopping serving");
+    let forced = listener.shutdown(grace).await;
+    // Handlers whose connections were just shut down get a moment to notice
+    // before the remaining ones are aborted.
+    let drained = tokio::time::timeout(Duration::from_secs(1), async {
+        while let Some(res) = handlers.join_next().await {
+            log_handler_result(res);
+        }
+    })
+    .await;
+    if drained.is_err() {
+        debug!(
+            forced,
+            aborted = handlers.len(),
+            "aborting connection handlers"
+        );
+        handlers.shutdown().await;
+    }
+    res
+}
+
+fn log_handler_result(res: std::result::Result<(), tokio::task::JoinError>) {
+    match res {
+        Ok(()) => {}
+        Err(e) if e.is_panic() => {
+            let panic = panic_message(e.into_panic());
+            error!(%panic, "connection handler panicked");
+        }
+        Err(e) => debug!(error = %e, "connection handler cancelled"),
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic payload".to_string(),
+        },
+    }
+}
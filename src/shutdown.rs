@@ -0,0 +1,84 @@
+//! Background shutdown of instances dropped inside an async runtime.
+//!
+//! `tailscale_close` blocks until the node has shut down. Dropping the last
+//! reference to a [`Tailscale`](crate::Tailscale) on a runtime worker thread
+//! hands the handle to a dedicated thread instead, so the runtime is not
+//! stalled.
+
+use std::{
+    os::fd::OwnedFd,
+    sync::{Arc, OnceLock, mpsc},
+};
+
+use tracing::{debug, error};
+
+use crate::{state::RegisteredStore, sys::modern::tailscale_close};
+
+/// A handle to close, along with the log destination and state store that
+/// must stay alive until the node has shut down.
+struct Shutdown {
+    sd: libc::c_int,
+    log_fd: Option<OwnedFd>,
+    state_store: Option<Arc<RegisteredStore>>,
+}
+
+static SHUTDOWNER: OnceLock<Option<mpsc::Sender<Shutdown>>> = OnceLock::new();
+
+/// Closes `sd` on the shutdown thread, then drops `log_fd` and `state_store`.
+///
+/// Falls back to closing on the calling thread if the shutdown thread
+/// cannot be started.
+pub(crate) fn close_in_background(
+    sd: libc::c_int,
+    log_fd: Option<OwnedFd>,
+    state_store: Option<Arc<RegisteredStore>>,
+) {
+    let shutdown = Shutdown {
+        sd,
+        log_fd,
+        state_store,
+    };
+    let shutdown = match SHUTDOWNER.get_or_init(spawn_shutdowner) {
+        Some(tx) => match tx.send(shutdown) {
+            Ok(()) => return,
+            Err(mpsc::SendError(shutdown)) => shutdown,
+        },
+        None => shutdown,
+    };
+    close_now(shutdown.sd);
+}
+
+/// Closes `sd` on the calling thread, logging any error.
+pub(crate) fn close_now(sd: libc::c_int) {
+    let ret = unsafe { tailscale_close(sd) };
+    if ret != 0 {
+        // The handle is gone once closed, so there is no error message to fetch.
+        error!(sd, "error closing tailscale");
+    }
+}
+
+fn spawn_shutdowner() -> Option<mpsc::Sender<Shutdown>> {
+    let (tx, rx) = mpsc::channel::<Shutdown>();
+    let spawned = std::thread::Builder::new()
+        .name("tailscale-shutdown".to_string())
+        .spawn(move || {
+            for Shutdown {
+                sd,
+                log_fd,
+                state_store,
+            } in rx
+            {
+                debug!(sd, "closing dropped instance");
+                close_now(sd);
+                drop(log_fd);
+                drop(state_store);
+            }
+        });
+    match spawned {
+        Ok(_) => Some(tx),
+        Err(e) => {
+            error!(error = %e, "could not start shutdown thread");
+            None
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! A SOCKS5 proxy server for tailnet clients.
+//!
+//! [`Socks5Server`] accepts SOCKS5 clients on a tailnet listener and connects
+//! them to the targets they ask for, either on the tailnet or on this node's
+//! local network, so other devices can use the node as a gateway. Clients are
+//! not asked to authenticate: only tailnet peers can reach the listener, and
+//! [`allow`](Socks5Server::allow) decides which targets each of them may
+//! connect to based on their identity. Loopback and link-local targets, such
+//! as `127.0.0.1` or the `169.254.169.254` metadata service of cloud hosts,
+//! are refused unless enabled with
+//! [`allow_local_addresses`](Socks5Server::allow_local_addresses).
+//!
+//! Only the `CONNECT` command is supported.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use tailscale2::*;
+//! # async fn example(ts: Arc<Tailscale>) -> Result<()> {
+//! let listener = ts.listener(NetworkType::Tcp, ":1080").await?;
+//! let proxy = socks5::Socks5Server::new(Arc::clone(&ts))
+//!     .upstream(Upstream::Local)
+//!     .allow(|peer, _host, port| peer.tags().iter().any(|t| t == "tag:ops") || port == 443)
+//!     .serve(listener);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::debug;
+
+use crate::{
+    forward::{Forwarder, TargetPolicy, Upstream},
+    identity::PeerInfo,
+    tailscale::{Connection, Listener, Tailscale},
+};
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// Reply codes from RFC 1928.
+const REP_SUCCEEDED: u8 = 0;
+const REP_GENERAL_FAILURE: u8 = 1;
+const REP_NOT_ALLOWED: u8 = 2;
+const REP_NETWORK_UNREACHABLE: u8 = 3;
+const REP_HOST_UNREACHABLE: u8 = 4;
+const REP_CONNECTION_REFUSED: u8 = 5;
+const REP_TTL_EXPIRED: u8 = 6;
+const REP_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REP_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 8;
+
+/// A SOCKS5 proxy server, started on a listener with
+/// [`serve`](Self::serve).
+#[derive(Clone)]
+pub struct Socks5Server {
+    tailscale: Arc<Tailscale>,
+    upstream: Upstream,
+    targets: TargetPolicy,
+}
+
+impl Socks5Server {
+    /// Creates a server that dials targets through `tailscale` or the local
+    /// network according to [`Upstream::Auto`], letting every client
+    /// connect to any target except loopback and link-local addresses.
+    pub fn new(tailscale: Arc<Tailscale>) -> Self {
+        Socks5Server {
+            tailscale,
+            upstream: Upstream::default(),
+            targets: TargetPolicy::default(),
+        }
+    }
+
+    /// Sets where targets are dialed.
+    pub fn upstream(mut self, upstream: Upstream) -> Self {
+        self.upstream = upstream;
+        self
+    }
+
+    /// Sets a filter deciding which targets each client may connect to,
+    /// replacing any previous filter.
+    ///
+    /// The filter is called with the client's identity, looked up with
+    /// [`Tailscale::whois`](crate::Tailscale::whois), and the host and port
+    /// it asked for, with the host as sent by the client. Requests it
+    /// returns false for are refused with a "connection not allowed by
+    /// ruleset" reply.
+    pub fn allow<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&PeerInfo, &str, u16) -> bool + Send + Sync + 'static,
+    {
+        self.targets.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Sets whether clients may connect to loopback and link-local
+    /// addresses of this node, such as `127.0.0.1` or `169.254.169.254`,
+    /// including through host names resolving to them. Refused by default,
+    /// as they expose services never meant to be reachable from the
+    /// tailnet.
+    pub fn allow_local_addresses(mut self, allow: bool) -> Self {
+        self.targets.allow_local = allow;
+        self
+    }
+
+    /// Serves SOCKS5 clients accepted on `listener` until the returned
+    /// [`Forwarder`] is stopped or dropped.
+    ///
+    /// The forwarder's counters cover proxied connections, including those
+    /// whose handshake failed.
+    pub fn serve(self, listener: Arc<Listener>) -> Forwarder {
+        let server = Arc::new(self);
+        Forwarder::spawn(
+            None,
+            move || {
+                let listener = Arc::clone(&listener);
+                async move { listener.accept().await }
+            },
+            move |mut conn, state| {
+                let server = Arc::clone(&server);
+                async move {
+                    let (sent, received) = server
+                        .handle(&mut conn)
+                        .await
+                        .map_err(|e| format!("SOCKS5 connection failed: {e}"))?;
+                    state.record_bytes(sent, received);
+                    Ok(())
+                }
+            },
+        )
+    }
+
+    async fn handle(&self, conn: &mut Connection) -> io::Result<(u64, u64)> {
+        let (host, port) = read_request(conn).await?;
+
+        debug!(%host, port, "SOCKS5 connect");
+        let upstream = match self
+            .targets
+            .dial(&self.tailscale, conn, self.upstream, &host, port)
+            .await
+        {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                reply(conn, reply_code(&e), None).await?;
+                return Err(e);
+            }
+        };
+        reply(conn, REP_SUCCEEDED, upstream.local_addr()).await?;
+        upstream.proxy(conn).await
+    }
+}
+
+/// Negotiates the authentication method and reads a `CONNECT` request,
+/// returning the target host and port. Unsupported requests are answered
+/// with the matching reply before failing.
+async fn read_request<S>(conn: &mut S) -> io::Result<(String, u16)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Method negotiation.
+    let [version, n_methods] = read_array(conn).await?;
+    if version != VERSION {
+        return Err(invalid(format!("unsupported SOCKS version {version}")));
+    }
+    let mut methods = vec![0; n_methods as usize];
+    conn.read_exact(&mut methods).await?;
+    if !methods.contains(&NO_AUTH) {
+        conn.write_all(&[VERSION, NO_ACCEPTABLE_METHODS]).await?;
+        return Err(invalid("client requires authentication".to_string()));
+    }
+    conn.write_all(&[VERSION, NO_AUTH]).await?;
+
+    // Request.
+    let [version, command, _, address_type] = read_array(conn).await?;
+    if version != VERSION {
+        return Err(invalid(format!("unsupported SOCKS version {version}")));
+    }
+    let host = match address_type {
+        ATYP_IPV4 => Ipv4Addr::from(read_array::<4, _>(conn).await?).to_string(),
+        ATYP_IPV6 => Ipv6Addr::from(read_array::<16, _>(conn).await?).to_string(),
+        ATYP_DOMAIN => {
+            let [len] = read_array(conn).await?;
+            let mut name = vec![0; len as usize];
+            conn.read_exact(&mut name).await?;
+            String::from_utf8(name).map_err(|_| invalid("invalid domain name".to_string()))?
+        }
+        _ => {
+            reply(conn, REP_ADDRESS_TYPE_NOT_SUPPORTED, None).await?;
+            return Err(invalid(format!("unsupported address type {address_type}")));
+        }
+    };
+    let port = u16::from_be_bytes(read_array(conn).await?);
+    if command != CMD_CONNECT {
+        reply(conn, REP_COMMAND_NOT_SUPPORTED, None).await?;
+        return Err(invalid(format!("unsupported command {command}")));
+    }
+    Ok((host, port))
+}
+
+async fn read_array<const N: usize, S>(conn: &mut S) -> io::Result<[u8; N]>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = [0; N];
+    conn.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+/// Sends a reply with the bound address `bound`, or the unspecified address
+/// if unknown.
+async fn reply<S>(conn: &mut S, code: u8, bound: Option<SocketAddr>) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let bound = bound.unwrap_or_else(|| (Ipv4Addr::UNSPECIFIED, 0).into());
+    let mut msg = vec![VERSION, code, 0];
+    match bound {
+        SocketAddr::V4(addr) => {
+            msg.push(ATYP_IPV4);
+            msg.extend_from_slice(&addr.ip().octets());
+        }
+        SocketAddr::V6(addr) => {
+            msg.push(ATYP_IPV6);
+            msg.extend_from_slice(&addr.ip().octets());
+        }
+    }
+    msg.extend_from_slice(&bound.port().to_be_bytes());
+    conn.write_all(&msg).await
+}
+
+fn reply_code(error: &io::Error) -> u8 {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => REP_NOT_ALLOWED,
+        io::ErrorKind::ConnectionRefused => REP_CONNECTION_REFUSED,
+        io::ErrorKind::NetworkUnreachable => REP_NETWORK_UNREACHABLE,
+        io::ErrorKind::HostUnreachable | io::ErrorKind::NotFound => REP_HOST_UNREACHABLE,
+        io::ErrorKind::TimedOut => REP_TTL_EXPIRED,
+        _ => REP_GENERAL_FAILURE,
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::duplex;
+
+    use super::*;
+
+    /// Runs `read_request` on a stream the client sent `request` on,
+    /// returning its result and the bytes the server wrote back.
+    async fn exchange(request: &[u8]) -> (io::Result<(String, u16)>, Vec<u8>) {
+        let (mut client, mut server) = duplex(1024);
+        client.write_all(request).await.unwrap();
+        client.shutdown().await.unwrap();
+        let res = read_request(&mut server).await;
+        drop(server);
+        let mut written = Vec::new();
+        client.read_to_end(&mut written).await.unwrap();
+        (res, written)
+    }
+
+    #[tokio::test]
+    async fn connect_to_domain() {
+        let (res, written) =
+            exchange(b"\x05\x02\x02\x00\x05\x01\x00\x03\x0bexample.com\x01\xbb").await;
+        assert_eq!(res.unwrap(), ("example.com".to_string(), 443));
+        assert_eq!(written, [VERSION, NO_AUTH]);
+    }
+
+    #[tokio::test]
+    async fn connect_to_ip() {
+        let (res, _) = exchange(b"\x05\x01\x00\x05\x01\x00\x01\x64\x40\x00\x01\x00\x50").await;
+        assert_eq!(res.unwrap(), ("100.64.0.1".to_string(), 80));
+
+        let mut request = b"\x05\x01\x00\x05\x01\x00\x04".to_vec();
+        request.extend_from_slice(&"fd7a:115c:a1e0::1".parse::<Ipv6Addr>().unwrap().octets());
+        request.extend_from_slice(&[0x1f, 0x90]);
+        let (res, _) = exchange(&request).await;
+        assert_eq!(res.unwrap(), ("fd7a:115c:a1e0::1".to_string(), 8080));
+    }
+
+    #[tokio::test]
+    async fn no_acceptable_methods() {
+        let (res, written) = exchange(b"\x05\x01\x02").await;
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(written, [VERSION, NO_ACCEPTABLE_METHODS]);
+    }
+
+    #[tokio::test]
+    async fn unsupported_version() {
+        let (res, written) = exchange(b"\x04\x01\x00").await;
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(written.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unsupported_address_type() {
+        let (res, written) = exchange(b"\x05\x01\x00\x05\x01\x00\x09").await;
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            written,
+            [
+                VERSION,
+                NO_AUTH,
+                VERSION,
+                REP_ADDRESS_TYPE_NOT_SUPPORTED,
+                0,
+                ATYP_IPV4,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unsupported_command() {
+        // BIND to 100.64.0.1:80.
+        let (res, written) =
+            exchange(b"\x05\x01\x00\x05\x02\x00\x01\x64\x40\x00\x01\x00\x50").await;
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            written,
+            [
+                VERSION,
+                NO_AUTH,
+                VERSION,
+                REP_COMMAND_NOT_SUPPORTED,
+                0,
+                ATYP_IPV4,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn truncated_request() {
+        let (res, _) = exchange(b"\x05\x01\x00\x05\x01\x00\x03\x0bexample").await;
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn reply_with_bound_address() {
+        let (mut client, mut server) = duplex(64);
+        let bound = "[fd7a:115c:a1e0::1]:8080".parse().unwrap();
+        reply(&mut server, REP_SUCCEEDED, Some(bound))
+            .await
+            .unwrap();
+        drop(server);
+        let mut written = Vec::new();
+        client.read_to_end(&mut written).await.unwrap();
+        assert_eq!(&written[..4], [VERSION, REP_SUCCEEDED, 0, ATYP_IPV6]);
+        assert_eq!(
+            &written[4..20],
+            "fd7a:115c:a1e0::1".parse::<Ipv6Addr>().unwrap().octets()
+        );
+        assert_eq!(&written[20..], [0x1f, 0x90]);
+    }
+
+    #[test]
+    fn reply_codes() {
+        let code = |kind: io::ErrorKind| reply_code(&kind.into());
+        assert_eq!(code(io::ErrorKind::PermissionDenied), REP_NOT_ALLOWED);
+        assert_eq!(
+            code(io::ErrorKind::ConnectionRefused),
+            REP_CONNECTION_REFUSED
+        );
+        assert_eq!(code(io::ErrorKind::NotFound), REP_HOST_UNREACHABLE);
+        assert_eq!(code(io::ErrorKind::TimedOut), REP_TTL_EXPIRED);
+        assert_eq!(code(io::ErrorKind::Other), REP_GENERAL_FAILURE);
+    }
+}
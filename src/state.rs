@@ -0,0 +1,385 @@
+//! Pluggable storage for a node's state, see
+//! [`TailscaleBuilder::state_store`](crate::TailscaleBuilder::state_store).
+//!
+//! A node's state is a handful of small blobs, such as its machine and node
+//! keys and its login profile, each stored under a string key. By default
+//! tsnet keeps them in a file in the state directory; implementing
+//! [`StateStore`] lets them live in a database or secrets manager instead,
+//! which suits containers without persistent volumes.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tailscale2::{Tailscale, state};
+//!
+//! let ts = Tailscale::builder()
+//!     .hostname("my-service")
+//!     .state_store(state::Directory::new("/var/lib/my-service/state")?)
+//!     .build()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fs,
+    io::{self, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    panic::{AssertUnwindSafe, catch_unwind},
+    path::{Path, PathBuf},
+    ptr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use tracing::{error, warn};
+
+use crate::sys::modern::tailscale_state_store;
+
+/// Storage for a node's state blobs.
+///
+/// Methods are called from libtailscale's threads, possibly concurrently,
+/// and block them until they return, so implementations backed by a network
+/// service should use a blocking client. Errors make the node fail to start
+/// or to save its keys, and are logged.
+pub trait StateStore: Send + Sync + 'static {
+    /// Returns the value stored under `key`, or `None` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value could not be read.
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Stores `value` under `key`, replacing any previous value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value could not be stored.
+    fn put(&self, key: &str, value: &[u8]) -> io::Result<()>;
+}
+
+impl<T: StateStore + ?Sized> StateStore for Arc<T> {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        (**self).put(key, value)
+    }
+}
+
+impl<T: StateStore + ?Sized> StateStore for Box<T> {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        (**self).put(key, value)
+    }
+}
+
+/// Distinguishes the temporary files of concurrent [`Directory::put`] calls.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Stores each value in its own file in a directory, readable only by the
+/// current user.
+///
+/// Files are replaced atomically, so a crash never leaves a partly written
+/// key behind.
+#[derive(Debug, Clone)]
+pub struct Directory {
+    path: PathBuf,
+}
+
+impl Directory {
+    /// Uses the directory at `path`, creating it with mode `0700` if it does
+    /// not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&path)?;
+        Ok(Directory { path })
+    }
+
+    /// Returns the directory the state is stored in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn file(&self, key: &str) -> io::Result<PathBuf> {
+        if key.is_empty() || key.starts_with('.') || key.contains(['/', '\0']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("state key {key:?} is not a valid file name"),
+            ));
+        }
+        Ok(self.path.join(key))
+    }
+}
+
+impl StateStore for Directory {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.file(key)?) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        let path = self.file(key)?;
+        // Concurrent writes of the same key each get their own temporary
+        // file; the last rename wins.
+        let n = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp = self
+            .path
+            .join(format!(".{key}.{}.{n}.tmp", std::process::id()));
+        let write = || {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&tmp)?;
+            file.write_all(value)?;
+            file.sync_all()?;
+            fs::rename(&tmp, path)
+        };
+        if let Err(e) = write() {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        // Make the rename itself durable.
+        fs::File::open(&self.path)?.sync_all()
+    }
+}
+
+/// Keeps state in memory only, so it is lost when the process exits.
+///
+/// A node using it registers as a new device every time it starts, which
+/// suits short-lived or [`ephemeral`](crate::TailscaleBuilder::ephemeral)
+/// nodes with a reusable auth key.
+#[derive(Debug, Default)]
+pub struct Memory {
+    values: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl Memory {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StateStore for Memory {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.values.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+}
+
+/// A [`StateStore`] registered with libtailscale, which holds pointers into
+/// it until the instance is closed.
+pub(crate) struct RegisteredStore {
+    raw: Box<tailscale_state_store>,
+}
+
+// SAFETY: `ctx` points to an `Arc<dyn StateStore>`, which is `Send + Sync`.
+unsafe impl Send for RegisteredStore {}
+unsafe impl Sync for RegisteredStore {}
+
+impl RegisteredStore {
+    pub(crate) fn new(store: Arc<dyn StateStore>) -> Self {
+        let ctx = Box::into_raw(Box::new(store));
+        RegisteredStore {
+            raw: Box::new(tailscale_state_store {
+                ctx: ctx.cast(),
+                read: Some(read_state),
+                write: Some(write_state),
+            }),
+        }
+    }
+
+    /// Returns the callbacks to pass to `tailscale_set_state_store`.
+    pub(crate) fn as_ptr(&self) -> *const tailscale_state_store {
+        &*self.raw
+    }
+}
+
+impl Drop for RegisteredStore {
+    fn drop(&mut self) {
+        // SAFETY: `ctx` was created by `Box::into_raw` in `new`.
+        drop(unsafe { Box::from_raw(self.raw.ctx.cast::<Arc<dyn StateStore>>()) });
+    }
+}
+
+unsafe extern "C" fn read_state(
+    ctx: *mut libc::c_void,
+    key: *const libc::c_char,
+    value_out: *mut *mut libc::c_char,
+    len_out: *mut libc::size_t,
+) -> libc::c_int {
+    let store = unsafe { &*ctx.cast::<Arc<dyn StateStore>>() };
+    let key = unsafe { CStr::from_ptr(key) }.to_string_lossy();
+    match catch_unwind(AssertUnwindSafe(|| store.get(&key))) {
+        Ok(Ok(Some(value))) => {
+            // libtailscale frees the value with free(3).
+            let buf = unsafe { libc::malloc(value.len().max(1)) }.cast::<libc::c_char>();
+            if buf.is_null() {
+                error!(%key, "could not allocate state value");
+                return -1;
+            }
+            unsafe {
+                ptr::copy_nonoverlapping(value.as_ptr(), buf.cast(), value.len());
+                *value_out = buf;
+                *len_out = value.len();
+            }
+            0
+        }
+        Ok(Ok(None)) => libc::ENOENT,
+        Ok(Err(e)) => {
+            warn!(%key, error = %e, "state store read failed");
+            -1
+        }
+        Err(_) => {
+            error!(%key, "state store panicked while reading");
+            -1
+        }
+    }
+}
+
+unsafe extern "C" fn write_state(
+    ctx: *mut libc::c_void,
+    key: *const libc::c_char,
+    value: *const libc::c_char,
+    len: libc::size_t,
+) -> libc::c_int {
+    let store = unsafe { &*ctx.cast::<Arc<dyn StateStore>>() };
+    let key = unsafe { CStr::from_ptr(key) }.to_string_lossy();
+    let value = match len {
+        0 => &[][..],
+        len => unsafe { std::slice::from_raw_parts(value.cast::<u8>(), len) },
+    };
+    match catch_unwind(AssertUnwindSafe(|| store.put(&key, value))) {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => {
+            warn!(%key, error = %e, "state store write failed");
+            -1
+        }
+        Err(_) => {
+            error!(%key, "state store panicked while writing");
+            -1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, thread};
+
+    use super::*;
+
+    /// A fresh directory store, removed when dropped.
+    struct TempStore(Directory);
+
+    impl TempStore {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("tailscale2-state-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            TempStore(Directory::new(path.join("state")).unwrap())
+        }
+
+        fn files(&self) -> Vec<String> {
+            let mut files: Vec<_> = fs::read_dir(self.0.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.0.path().parent().unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let store = TempStore::new("keys");
+        for key in ["", ".", "..", ".hidden", "a/b", "../escape", "nul\0"] {
+            let e = store.0.put(key, b"value").unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{key:?}");
+            let e = store.0.get(key).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{key:?}");
+        }
+        assert!(store.files().is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let store = TempStore::new("round-trip");
+        assert_eq!(store.0.get("_machinekey").unwrap(), None);
+
+        store.0.put("_machinekey", b"first").unwrap();
+        assert_eq!(
+            store.0.get("_machinekey").unwrap().as_deref(),
+            Some(&b"first"[..])
+        );
+        store.0.put("_machinekey", b"second").unwrap();
+        store.0.put("profile-1234", b"").unwrap();
+        assert_eq!(
+            store.0.get("_machinekey").unwrap().as_deref(),
+            Some(&b"second"[..])
+        );
+        assert_eq!(
+            store.0.get("profile-1234").unwrap().as_deref(),
+            Some(&b""[..])
+        );
+        assert_eq!(store.files(), ["_machinekey", "profile-1234"]);
+    }
+
+    #[test]
+    fn permissions() {
+        let store = TempStore::new("permissions");
+        store.0.put("key", b"secret").unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(store.0.path()), 0o700);
+        assert_eq!(mode(&store.0.path().join("key")), 0o600);
+    }
+
+    #[test]
+    fn concurrent_writes_of_one_key() {
+        let store = TempStore::new("concurrent");
+        let values: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 64 * 1024]).collect();
+        thread::scope(|scope| {
+            for value in &values {
+                let dir = &store.0;
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        dir.put("key", value).unwrap();
+                    }
+                });
+            }
+        });
+        let stored = store.0.get("key").unwrap().unwrap();
+        assert!(values.contains(&stored));
+        assert_eq!(store.files(), ["key"]);
+    }
+}
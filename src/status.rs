@@ -0,0 +1,43 @@
+//! Tailnet status as reported by the local Tailscale backend.
+
+use std::{net::IpAddr, time::SystemTime};
+
+use serde::Deserialize;
+
+use crate::json::{null_as_default, unix_time};
+
+/// A peer visible to this node, as returned by [`Tailscale::peers`](crate::Tailscale::peers).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Peer {
+    /// The stable ID of the peer's node.
+    pub id: String,
+    /// The hostname the peer reported for itself.
+    pub hostname: String,
+    /// The MagicDNS name of the peer without the trailing dot.
+    pub dns_name: String,
+    /// The operating system of the peer.
+    pub os: String,
+    /// The Tailscale IP addresses of the peer.
+    #[serde(deserialize_with = "null_as_default")]
+    pub addresses: Vec<IpAddr>,
+    /// Whether the peer is currently connected to the control plane.
+    pub online: bool,
+    /// The ACL tags assigned to the peer.
+    #[serde(deserialize_with = "null_as_default")]
+    pub tags: Vec<String>,
+    /// When the peer was last online, if known.
+    #[serde(deserialize_with = "unix_time")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::json::serialize_unix_time")
+    )]
+    pub last_seen: Option<SystemTime>,
+    /// The code of the peer's home DERP region, e.g. `fra`.
+    pub relay: String,
+    /// Whether the peer offers to be an exit node, see
+    /// [`Tailscale::use_exit_node`](crate::Tailscale::use_exit_node).
+    pub exit_node_option: bool,
+    /// Whether the peer is this node's current exit node.
+    pub exit_node: bool,
+}
@@ -1,10 +1,159 @@
+//! Raw bindings to the libtailscale C API.
+//!
+//! This module is public with the `unsafe-sys` feature, for calling functions
+//! that have no safe wrapper yet such as `tailscale_loopback`. Prefer the safe
+//! API wherever it covers what you need. The functions are documented in
+//! `tailscale.h`; the docs here are a summary.
+//!
+//! # Safety
+//!
+//! Every function is unsafe to call. Callers must ensure that:
+//!
+//! - `sd` is a handle returned by `tailscale_new`, or by
+//!   [`Tailscale::raw_handle`](crate::Tailscale::raw_handle), that has not
+//!   been closed. A handle borrowed from a `Tailscale` is owned by it and must
+//!   not be closed with `tailscale_close`.
+//! - String arguments point to NUL-terminated strings, e.g. from
+//!   [`CString::as_ptr`](std::ffi::CStr::as_ptr), that live for the
+//!   duration of the call.
+//! - `buf` points to at least `buflen` writable bytes and `buflen` is not
+//!   zero. The Go side panics on a null or empty buffer, which aborts the
+//!   process.
+//! - Out-pointers such as `conn_out` are valid for writes.
+//! - Descriptors written to `conn_out`, `listener_out` or `events_out` are
+//!   owned by the caller and closed exactly once, e.g. by wrapping them in an
+//!   [`OwnedFd`](std::os::fd::OwnedFd).
+//!
+//! Most functions block the calling thread until libtailscale answers, so in
+//! async code call them from [`tokio::task::spawn_blocking`].
+
+/// A listener descriptor returned by `tailscale_listen`.
 pub type TailscaleListener = libc::c_int;
+/// A connection descriptor returned by `tailscale_dial` or `tailscale_accept`.
 pub type TailscaleConn = libc::c_int;
 
+/// The meaning of a value returned by a libtailscale function.
+///
+/// Functions return zero on success, `EBADF` if a handle is not valid,
+/// `ERANGE` if the caller's buffer is too small for the result, and -1 (or
+/// another errno value) on other errors, whose details are available from
+/// `tailscale_errmsg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReturnCode {
+    /// The call succeeded.
+    Ok,
+    /// `EBADF`: the server, listener or connection handle is not valid,
+    /// e.g. because it was closed.
+    InvalidHandle,
+    /// `ERANGE`: the result does not fit into the buffer; retrying with a
+    /// larger one may succeed.
+    BufferTooSmall,
+    /// Any other failure, with the raw return value.
+    Other(libc::c_int),
+}
+
+impl From<libc::c_int> for ReturnCode {
+    fn from(ret: libc::c_int) -> Self {
+        match ret {
+            0 => ReturnCode::Ok,
+            libc::EBADF => ReturnCode::InvalidHandle,
+            libc::ERANGE => ReturnCode::BufferTooSmall,
+            other => ReturnCode::Other(other),
+        }
+    }
+}
+
+/// Declares the C API.
+///
+/// The functions are normally linked at build time. With the `dlopen` feature
+/// each one instead becomes a wrapper that resolves the symbol from the shared
+/// library on first use, see [`crate::dlopen`].
+macro_rules! c_api {
+    ($(
+        $(#[$attr:meta])*
+        pub fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty;
+    )*) => {
+        #[cfg(not(feature = "dlopen"))]
+        unsafe extern "C" {
+            $(
+                $(#[$attr])*
+                ///
+                /// # Safety
+                ///
+                /// See the [module documentation](super).
+                pub fn $name($($arg: $ty),*) -> $ret;
+            )*
+        }
+
+        $(
+            #[cfg(feature = "dlopen")]
+            $(#[$attr])*
+            ///
+            /// # Safety
+            ///
+            /// See the [module documentation](super).
+            pub unsafe fn $name($($arg: $ty),*) -> $ret {
+                static SYMBOL: std::sync::OnceLock<unsafe extern "C" fn($($ty),*) -> $ret> =
+                    std::sync::OnceLock::new();
+                let f = SYMBOL.get_or_init(|| unsafe {
+                    crate::dlopen::symbol(concat!(stringify!($name), "\0"))
+                });
+                unsafe { f($($arg),*) }
+            }
+        )*
+    };
+}
+
+/// Declarations generated by bindgen from tailscale.h, used instead of the
+/// hand-written ones below with the `bindgen` feature, so that a signature
+/// that drifts from the header fails to compile at the call site.
+///
+/// The runtime-loaded wrappers of the `dlopen` feature are always generated
+/// from the hand-written declarations.
+#[cfg(all(feature = "bindgen", not(feature = "dlopen")))]
+pub mod modern {
+    #![allow(non_camel_case_types, dead_code)]
+
+    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+}
+
+#[cfg(any(not(feature = "bindgen"), feature = "dlopen"))]
 pub mod modern {
     use super::{TailscaleConn, TailscaleListener};
 
-    unsafe extern "C" {
+    /// Callbacks through which the server reads and writes its state, passed to
+    /// tailscale_set_state_store.
+    ///
+    /// Both callbacks may be called from any thread, concurrently.
+    #[repr(C)]
+    #[allow(non_camel_case_types)]
+    pub struct tailscale_state_store {
+        /// Passed to both callbacks unchanged.
+        pub ctx: *mut libc::c_void,
+        /// Looks up the state stored under key, writing a buffer allocated with
+        /// malloc to value_out and its length to len_out. Returns zero on success,
+        /// ENOENT if nothing is stored under key, or -1 on error.
+        pub read: Option<
+            unsafe extern "C" fn(
+                ctx: *mut libc::c_void,
+                key: *const libc::c_char,
+                value_out: *mut *mut libc::c_char,
+                len_out: *mut libc::size_t,
+            ) -> libc::c_int,
+        >,
+        /// Stores the len bytes at value under key. Returns zero on success or -1
+        /// on error.
+        pub write: Option<
+            unsafe extern "C" fn(
+                ctx: *mut libc::c_void,
+                key: *const libc::c_char,
+                value: *const libc::c_char,
+                len: libc::size_t,
+            ) -> libc::c_int,
+        >,
+    }
+
+    c_api! {
         /// Creates a tailscale server object.
         ///
         /// No network connection is initialized until tailscale_start is called.
@@ -18,7 +167,7 @@ pub mod modern {
         /// See also: tailscale_up.
         ///
         /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
-        // pub fn tailscale_start(sd: libc::c_int) -> libc::c_int;
+        pub fn tailscale_start(sd: libc::c_int) -> libc::c_int;
 
         /// Connects the server to the tailnet and waits for it to be usable.
         ///
@@ -35,6 +184,67 @@ pub mod modern {
         /// - -1    - other error, details printed to the tsnet logger
         pub fn tailscale_close(sd: libc::c_int) -> libc::c_int;
 
+        /// Logs the node out of the control plane.
+        ///
+        /// The call blocks until the control plane has acknowledged the logout.
+        /// Ephemeral nodes are removed from the tailnet as part of this process.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_logout(sd: libc::c_int) -> libc::c_int;
+
+        /// Stops the server's background activity, such as control plane polling
+        /// and peer keepalives, without logging out.
+        ///
+        /// Existing connections stall until tailscale_resume is called.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_suspend(sd: libc::c_int) -> libc::c_int;
+
+        /// Restarts a server stopped by tailscale_suspend and waits for it to be
+        /// usable again.
+        ///
+        /// To cancel an in-progress call to tailscale_resume, use tailscale_close.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_resume(sd: libc::c_int) -> libc::c_int;
+
+        /// Sets the subnet routes the node advertises to the tailnet, as a
+        /// comma-separated list of CIDR prefixes such as "192.168.1.0/24,fd00::/64".
+        /// An empty string stops advertising routes.
+        ///
+        /// May be called while the server is running.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_set_advertise_routes(
+            sd: libc::c_int,
+            routes: *const libc::c_char,
+        ) -> libc::c_int;
+
+        /// Sets whether the node uses subnet routes advertised by other nodes on
+        /// the tailnet. A non-zero accept enables them.
+        ///
+        /// May be called while the server is running.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_set_accept_routes(sd: libc::c_int, accept: libc::c_int) -> libc::c_int;
+
+        /// Routes the node's internet-bound traffic through the exit node named by
+        /// node: a stable node ID, a MagicDNS name or a Tailscale IP address. An
+        /// empty string stops using an exit node.
+        ///
+        /// May be called while the server is running.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_set_exit_node(sd: libc::c_int, node: *const libc::c_char) -> libc::c_int;
+
+        /// Sets whether the node refuses all incoming connections. A non-zero
+        /// shields_up blocks them; outgoing connections keep working.
+        ///
+        /// May be called while the server is running.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_set_shields_up(sd: libc::c_int, shields_up: libc::c_int) -> libc::c_int;
+
         /// Sets the directory for tailscale state storage.
         ///
         /// Configure this option before any explicit or implicit call to tailscale_start.
@@ -72,7 +282,6 @@ pub mod modern {
         /// For details of each value see the godoc for the fields of tsnet.Server.
         ///
         /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
-        #[allow(dead_code)]
         pub fn tailscale_set_control_url(
             sd: libc::c_int,
             control_url: *const libc::c_char,
@@ -87,6 +296,51 @@ pub mod modern {
         /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
         pub fn tailscale_set_ephemeral(sd: libc::c_int, ephemeral: libc::c_int) -> libc::c_int;
 
+        /// Makes the server keep its state in store rather than the state directory.
+        ///
+        /// store and its ctx must stay valid until tailscale_close returns.
+        ///
+        /// Configure this option before any explicit or implicit call to tailscale_start.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_set_state_store(
+            sd: libc::c_int,
+            store: *const tailscale_state_store,
+        ) -> libc::c_int;
+
+        /// Sets the UDP port the WireGuard engine listens on for direct
+        /// connections. Zero, the default, picks a port automatically.
+        ///
+        /// Configure this option before any explicit or implicit call to tailscale_start.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_set_port(sd: libc::c_int, port: libc::c_int) -> libc::c_int;
+
+        /// Sets how many accepted connections each listener queues until
+        /// tailscale_accept takes them, between 0 and 65535. Once the queue is full,
+        /// new connections wait in the network stack, which drops connection attempts
+        /// when it is full in turn. Zero, the default, queues only what fits into the
+        /// listener's socket buffer.
+        ///
+        /// Applies to listeners created after the call.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_set_listen_backlog(
+            sd: libc::c_int,
+            backlog: libc::c_int,
+        ) -> libc::c_int;
+
+        /// Sets the ACL tags the node advertises, as a comma-separated list such
+        /// as "tag:server,tag:ci". An empty string advertises no tags.
+        ///
+        /// Configure this option before any explicit or implicit call to tailscale_start.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_set_advertise_tags(
+            sd: libc::c_int,
+            tags: *const libc::c_char,
+        ) -> libc::c_int;
+
         /// Instructs the tailscale instance to write logs to fd.
         ///
         /// An fd value of -1 means discard all logging.
@@ -97,7 +351,7 @@ pub mod modern {
         /// Returns the IP addresses of the Tailscale server as a comma separated list.
         ///
         /// The provided buffer must be of sufficient size to hold the concatenated
-        /// IPs as strings. This is typically <ipv4>,<ipv6> but maybe empty, or
+        /// IPs as strings. This is typically `<ipv4>,<ipv6>` but maybe empty, or
         /// contain any number of ips. The caller is responsible for parsing
         /// the output. You may assume the output is a list of well-formed IPs.
         ///
@@ -111,6 +365,106 @@ pub mod modern {
             buflen: libc::size_t,
         ) -> libc::c_int;
 
+        /// Writes the backend state and the raw IP addresses of the Tailscale server
+        /// into caller-provided storage.
+        ///
+        /// state_out receives the numeric ipn.State of the backend. ipv4_out and
+        /// ipv6_out receive the addresses in network byte order, and are zero-filled
+        /// if no address of that family has been assigned. They must point to 4 and
+        /// 16 bytes of storage respectively.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_quick_state(
+            sd: libc::c_int,
+            state_out: *mut libc::c_int,
+            ipv4_out: *mut libc::c_uchar,
+            ipv6_out: *mut libc::c_uchar,
+        ) -> libc::c_int;
+
+        /// Writes a snapshot of the node's current network map to buf.
+        ///
+        /// The snapshot is read from the IPN bus and written as a NUL-terminated JSON
+        /// object with the fields self, peers, dns, derp_regions and domain.
+        /// The call waits up to 10 seconds for a network map to become available.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - ERANGE - insufficient storage for buf
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_netmap(
+            sd: libc::c_int,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Runs startup diagnostics and writes a report to buf.
+        ///
+        /// It checks control plane and DERP reachability, UDP socket availability,
+        /// state directory writability and clock skew against the control plane.
+        /// Call it after configuring sd but before tailscale_start or tailscale_up.
+        ///
+        /// The report is a NUL-terminated JSON object with the fields control, derp,
+        /// udp, state_dir and time_skew, each holding ok and detail, plus clock_skew_ms.
+        ///
+        /// Returns:
+        /// - 0      - Success, even if some checks failed
+        /// - EBADF  - sd is not a valid tailscale
+        /// - ERANGE - insufficient storage for buf
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_preflight(
+            sd: libc::c_int,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Writes a snapshot of the node's health to buf.
+        ///
+        /// The report is a NUL-terminated JSON object with the fields backend_state,
+        /// control (whether the control plane reports the node online), derp (the home
+        /// DERP region, empty if none), key_expiry (unix seconds, 0 if the key does not
+        /// expire), key_expired and warnings (the current health warnings).
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - ERANGE - insufficient storage for buf
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_health(
+            sd: libc::c_int,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Pings peer, a Tailscale IP address, MagicDNS name or stable node ID,
+        /// and writes the result to buf.
+        ///
+        /// kind is one of "disco" (WireGuard path discovery), "ICMP" or "TSMP"
+        /// (Tailscale's own ping, answered by the peer's Tailscale stack). The call
+        /// fails if no reply arrives within timeout_ms milliseconds.
+        ///
+        /// The result is a NUL-terminated JSON object with the fields node_ip,
+        /// node_name, latency_seconds, endpoint (the ip:port of the direct path,
+        /// empty if relayed) and derp_region_code (the relaying DERP region, empty
+        /// if direct).
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - ERANGE - insufficient storage for buf
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_ping(
+            sd: libc::c_int,
+            peer: *const libc::c_char,
+            kind: *const libc::c_char,
+            timeout_ms: libc::c_int,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
         /// Connects to the address on the tailnet.
         ///
         /// The newly allocated connection is written to conn_out.
@@ -146,6 +500,43 @@ pub mod modern {
             listener_out: *mut TailscaleListener,
         ) -> libc::c_int;
 
+        /// Listens for TCP connections from the public internet via Tailscale
+        /// Funnel, as well as from the tailnet.
+        ///
+        /// TLS is terminated by tailscale, so accepted connections carry plaintext.
+        /// addr is a NUL-terminated string of the form ":443"; Funnel only supports
+        /// ports 443, 8443 and 10000. If funnel_only is non-zero, connections from
+        /// the tailnet are not accepted.
+        ///
+        /// The newly allocated listener is written to listener_out and is used like
+        /// one from tailscale_listen.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_listen_funnel(
+            sd: libc::c_int,
+            addr: *const libc::c_char,
+            funnel_only: libc::c_int,
+            listener_out: *mut TailscaleListener,
+        ) -> libc::c_int;
+
+        /// Opens a UDP packet socket on the tailnet.
+        ///
+        /// addr is a NUL-terminated string of the form "ip:port", where ip is one
+        /// of the node's Tailscale IP addresses.
+        ///
+        /// The socket is written to conn_out as one half of a datagram socketpair.
+        /// Every datagram read from or written to it starts with an 18-byte
+        /// header: the peer's IP address as 16 bytes (IPv4 addresses are
+        /// IPv4-mapped IPv6 addresses) followed by its port in network byte
+        /// order. The payload follows the header. Close the socket with close.
+        ///
+        /// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+        pub fn tailscale_listen_packet(
+            sd: libc::c_int,
+            addr: *const libc::c_char,
+            conn_out: *mut TailscaleConn,
+        ) -> libc::c_int;
+
         /// Returns the remote address for an incoming connection for a particular listener.
         ///
         /// The address (either ip4 or ip6) will be written to buf on success.
@@ -154,6 +545,7 @@ pub mod modern {
         /// - 0      - Success
         /// - EBADF  - sd is not a valid tailscale, or l or conn are not valid listeners or connections
         /// - ERANGE - insufficient storage for buf
+        #[allow(dead_code)]
         pub fn tailscale_getremoteaddr(
             l: TailscaleListener,
             conn: TailscaleConn,
@@ -161,6 +553,182 @@ pub mod modern {
             buflen: libc::size_t,
         ) -> libc::c_int;
 
+        /// Like tailscale_getremoteaddr, but writes the address including the remote port,
+        /// e.g. `"100.64.0.1:41234"` or `"[fd7a::1]:41234"`.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - l or conn are not valid listeners or connections
+        /// - ERANGE - insufficient storage for buf
+        pub fn tailscale_getremoteaddrport(
+            l: TailscaleListener,
+            conn: TailscaleConn,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Writes the tailnet address and port l listens on to buf.
+        ///
+        /// Listeners on ":port" report the node's tailnet address. As l is one half
+        /// of a socketpair, getsockname(2) on it does not return the tailnet address.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - l is not a valid listener
+        /// - ERANGE - insufficient storage for buf
+        pub fn tailscale_getlisteneraddr(
+            l: TailscaleListener,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Writes the tailnet address and port of the local end of conn to buf.
+        ///
+        /// As conn is one half of a socketpair, getsockname(2) on it does not return
+        /// the tailnet address.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - conn is not a valid connection
+        /// - ERANGE - insufficient storage for buf
+        pub fn tailscale_getlocaladdr(
+            conn: TailscaleConn,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Writes the tailnet address and port of the remote end of conn to buf.
+        ///
+        /// Unlike tailscale_getremoteaddr it does not need the listener, so it also
+        /// works for connections created by tailscale_dial. As conn is one half of a
+        /// socketpair, getpeername(2) on it does not return the tailnet address.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - conn is not a valid connection
+        /// - ERANGE - insufficient storage for buf
+        pub fn tailscale_getpeeraddr(
+            conn: TailscaleConn,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Sets TCP_NODELAY on the tailnet connection behind conn. Setting it on
+        /// conn itself has no effect on the tailnet connection, as conn is one
+        /// half of a socketpair.
+        ///
+        /// Returns:
+        /// - 0          - Success
+        /// - EBADF      - conn is not a valid connection
+        /// - EOPNOTSUPP - the tailnet connection does not support the option
+        pub fn tailscale_setnodelay(conn: TailscaleConn, no_delay: libc::c_int) -> libc::c_int;
+
+        /// Enables or disables TCP keep-alives on the tailnet connection behind
+        /// conn.
+        ///
+        /// Returns:
+        /// - 0          - Success
+        /// - EBADF      - conn is not a valid connection
+        /// - EOPNOTSUPP - the tailnet connection does not support the option
+        pub fn tailscale_setkeepalive(conn: TailscaleConn, keep_alive: libc::c_int) -> libc::c_int;
+
+        /// Sets how many seconds closing the tailnet connection behind conn waits
+        /// for unsent data to be delivered. A negative sec uses the default
+        /// behavior; 0 discards unsent data and resets the connection.
+        ///
+        /// Returns:
+        /// - 0          - Success
+        /// - EBADF      - conn is not a valid connection
+        /// - EOPNOTSUPP - the tailnet connection does not support the option
+        pub fn tailscale_setlinger(conn: TailscaleConn, sec: libc::c_int) -> libc::c_int;
+
+        /// Looks up the identity of the peer at addr, which is a NUL-terminated
+        /// "ip:port" string such as one from tailscale_getremoteaddrport.
+        ///
+        /// The identity is written to buf as a NUL-terminated JSON object with the
+        /// fields node_id, node_name, login_name, display_name and tags.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - ERANGE - insufficient storage for buf
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_whois(
+            sd: libc::c_int,
+            addr: *const libc::c_char,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Writes the identity of this node to buf.
+        ///
+        /// The identity is a NUL-terminated JSON object with the fields node_id,
+        /// dns_name (the MagicDNS name without a trailing dot), tailnet_name,
+        /// magic_dns_suffix and tags.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - ERANGE - insufficient storage for buf
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_self_info(
+            sd: libc::c_int,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Writes the peers visible to this node to buf.
+        ///
+        /// The peers are a NUL-terminated JSON array of objects with the fields id,
+        /// hostname, dns_name, os, addresses, online, tags, last_seen (unix seconds,
+        /// 0 if unknown) and relay (the home DERP region code), ordered by dns_name.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - ERANGE - insufficient storage for buf
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_peers(
+            sd: libc::c_int,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
+        /// Subscribes to changes of the node's state.
+        ///
+        /// On success events_out receives a file descriptor from which events can be
+        /// read as newline-delimited JSON objects, each with a type field of
+        /// state_changed (state), auth_url (url), peer_online or peer_offline (id,
+        /// dns_name) or key_expiring (expiry, unix seconds). The current state is
+        /// sent first. Close the descriptor to unsubscribe; it reaches end-of-file
+        /// once the server is closed.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_watch_events(sd: libc::c_int, events_out: *mut libc::c_int)
+        -> libc::c_int;
+
+        /// Fetches a TLS certificate and private key for domain, a NUL-terminated
+        /// DNS name of this node. If domain is empty, the node's MagicDNS name is
+        /// used. Certificates are cached and renewed by tailscale.
+        ///
+        /// The certificate chain followed by the private key is written to buf as
+        /// NUL-terminated PEM.
+        ///
+        /// Returns:
+        /// - 0      - Success
+        /// - EBADF  - sd is not a valid tailscale
+        /// - ERANGE - insufficient storage for buf
+        /// - -1     - other error, call tailscale_errmsg for details
+        pub fn tailscale_cert_pair(
+            sd: libc::c_int,
+            domain: *const libc::c_char,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+        ) -> libc::c_int;
+
         /// Accepts a connection on a tailscale_listener.
         ///
         /// It is the spiritual equivalent to accept(2).
@@ -0,0 +1,128 @@
+//! Per-tag connection metrics.
+//!
+//! Applications tag connections (for example one tag per tenant) via
+//! [`Connection::set_tag`](crate::Connection::set_tag). Every tag gets its own
+//! connection and byte counters, readable through
+//! [`Tailscale::tag_stats`](crate::Tailscale::tag_stats), and tagged
+//! connections join the drain group of the same name.
+
+use std::{
+    collections::HashMap,
+    os::fd::RawFd,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use tracing::debug;
+
+use crate::drain::{DrainGroups, DrainMembership};
+
+/// A snapshot of the counters of a single connection tag, returned by
+/// [`Tailscale::tag_stats`](crate::Tailscale::tag_stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TagStats {
+    /// Tagged connections currently open.
+    pub open_connections: usize,
+    /// Connections tagged since the instance was built.
+    pub total_connections: u64,
+    /// Bytes read from tagged connections.
+    pub bytes_read: u64,
+    /// Bytes written to tagged connections.
+    pub bytes_written: u64,
+}
+
+/// Registry of the connection tags belonging to a single Tailscale instance.
+pub(crate) struct TagRegistry {
+    tags: Mutex<HashMap<String, Arc<TagCounters>>>,
+    drain_groups: Arc<DrainGroups>,
+}
+
+#[derive(Default)]
+struct TagCounters {
+    open: AtomicUsize,
+    total: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+impl TagRegistry {
+    pub(crate) fn new(drain_groups: Arc<DrainGroups>) -> Self {
+        TagRegistry {
+            tags: Mutex::default(),
+            drain_groups,
+        }
+    }
+
+    /// Tags the connection `fd` with `name` until the returned tag is dropped.
+    pub(crate) fn tag(&self, name: &str, fd: RawFd) -> ConnectionTag {
+        let counters = Arc::clone(
+            self.tags
+                .lock()
+                .unwrap()
+                .entry(name.to_string())
+                .or_default(),
+        );
+        counters.open.fetch_add(1, Ordering::Relaxed);
+        counters.total.fetch_add(1, Ordering::Relaxed);
+        debug!(tag = name, fd, "connection tagged");
+
+        ConnectionTag {
+            name: name.to_string(),
+            counters,
+            _membership: self.drain_groups.join(name, fd),
+        }
+    }
+
+    /// Returns the counters of every tag seen since the instance was built.
+    pub(crate) fn stats(&self) -> HashMap<String, TagStats> {
+        self.tags
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, counters)| {
+                let stats = TagStats {
+                    open_connections: counters.open.load(Ordering::Relaxed),
+                    total_connections: counters.total.load(Ordering::Relaxed),
+                    bytes_read: counters.bytes_read.load(Ordering::Relaxed),
+                    bytes_written: counters.bytes_written.load(Ordering::Relaxed),
+                };
+                (name.clone(), stats)
+            })
+            .collect()
+    }
+}
+
+/// The tag of a connection, released when dropped.
+pub(crate) struct ConnectionTag {
+    name: String,
+    counters: Arc<TagCounters>,
+    _membership: DrainMembership,
+}
+
+impl ConnectionTag {
+    /// Returns the name of the tag.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn record_read(&self, n: usize) {
+        self.counters
+            .bytes_read
+            .fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_write(&self, n: usize) {
+        self.counters
+            .bytes_written
+            .fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for ConnectionTag {
+    fn drop(&mut self) {
+        self.counters.open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -5,50 +5,179 @@
 //! Rust applications.
 
 use std::{
+    collections::HashMap,
+    convert::Infallible,
     ffi::{CStr, CString, FromBytesUntilNulError, NulError},
-    io::{Read, Write},
-    net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr},
-    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd},
+    future::Future,
+    io::{IoSlice, IoSliceMut, Read, Write},
+    mem::ManuallyDrop,
+    net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+        unix::fs::DirBuilderExt,
+    },
     path::PathBuf,
+    ptr,
     str::{FromStr, Utf8Error},
-    sync::Arc,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
     task::Poll,
+    time::{Duration, Instant, SystemTime},
 };
 
-use crate::sys::{TailscaleListener, modern::*};
+#[cfg(feature = "otel")]
+use crate::otel::Addrs;
+#[cfg(feature = "tls")]
+use crate::tls::TlsListener;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+use crate::uring::UringConnection;
+use crate::{
+    addr::{ToDialAddr, ToListenAddr},
+    auth_key::AuthKey,
+    cert::CertPair,
+    dial_queue::{DialQueue, DialQueueStats},
+    drain::{DrainGroups, DrainMembership},
+    events::{Events, IpnEvent, UpProgress},
+    forward::Forwarder,
+    health::Health,
+    identity::{
+        Decision, IncomingCallback, ListenerFilter, PeerIdentity, PeerInfo, SelfInfo, WhoIsResponse,
+    },
+    incoming::Incoming,
+    logs,
+    metrics::{ConnectionMetrics, Metrics, MetricsRegistry},
+    netmap::{DnsConfig, NetMap},
+    on_demand::OnDemand,
+    ping::{PingKind, PingResult},
+    preflight::PreflightReport,
+    proxy_protocol,
+    resources::{ResourceGuard, ResourceKind, Resources},
+    retry::RetryPolicy,
+    shutdown,
+    state::{RegisteredStore, StateStore},
+    status::Peer,
+    sys::{ReturnCode, modern::*},
+    tags::{ConnectionTag, TagRegistry, TagStats},
+    udp::UdpSocket,
+};
 
+use nix::poll::{PollFd, PollFlags, PollTimeout};
+#[cfg(feature = "otel")]
+use opentelemetry::trace::SpanKind;
 use thiserror::Error;
 use tokio::{
-    io::{AsyncRead, AsyncWrite, unix::AsyncFd},
+    io::{AsyncRead, AsyncWrite, Interest, unix::AsyncFd},
+    sync::{Notify, OwnedSemaphorePermit, Semaphore},
     task::JoinError,
 };
-use tracing::{debug, error};
+use tokio_util::sync::CancellationToken;
+use tracing::{Span, debug, field, warn};
 
 /// Network protocol type for Tailscale connections.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum NetworkType {
     /// TCP protocol
     Tcp,
     /// UDP protocol
     Udp,
+    /// TCP over IPv4 only
+    Tcp4,
+    /// TCP over IPv6 only
+    Tcp6,
+    /// UDP over IPv4 only
+    Udp4,
+    /// UDP over IPv6 only
+    Udp6,
 }
 
 impl NetworkType {
     /// Returns the string representation of the network type.
     fn as_str(&self) -> &'static str {
+        c_str_to_str(self.as_c_str())
+    }
+
+    /// Returns the network type as the NUL-terminated string libtailscale
+    /// takes, without allocating.
+    fn as_c_str(&self) -> &'static CStr {
         match self {
-            NetworkType::Tcp => "tcp",
-            NetworkType::Udp => "udp",
+            NetworkType::Tcp => c"tcp",
+            NetworkType::Udp => c"udp",
+            NetworkType::Tcp4 => c"tcp4",
+            NetworkType::Tcp6 => c"tcp6",
+            NetworkType::Udp4 => c"udp4",
+            NetworkType::Udp6 => c"udp6",
+        }
+    }
+
+    /// Returns the network string restricted to the given address family.
+    ///
+    /// Family-specific network types keep their own family.
+    fn as_c_str_with_family(&self, family: AddressFamily) -> &'static CStr {
+        match (self, family) {
+            (NetworkType::Tcp, AddressFamily::Ipv4) => c"tcp4",
+            (NetworkType::Tcp, AddressFamily::Ipv6) => c"tcp6",
+            (NetworkType::Udp, AddressFamily::Ipv4) => c"udp4",
+            (NetworkType::Udp, AddressFamily::Ipv6) => c"udp6",
+            _ => self.as_c_str(),
         }
     }
 }
 
+/// Converts one of the ASCII network names above.
+fn c_str_to_str(s: &'static CStr) -> &'static str {
+    s.to_str().expect("network names are ASCII")
+}
+
 impl std::fmt::Display for NetworkType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(self.as_str())
     }
 }
 
+impl FromStr for NetworkType {
+    type Err = TailscaleError;
+
+    /// Parses the network names libtailscale accepts, e.g. `tcp` or `udp6`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tcp" => Ok(NetworkType::Tcp),
+            "udp" => Ok(NetworkType::Udp),
+            "tcp4" => Ok(NetworkType::Tcp4),
+            "tcp6" => Ok(NetworkType::Tcp6),
+            "udp4" => Ok(NetworkType::Udp4),
+            "udp6" => Ok(NetworkType::Udp6),
+            _ => Err(TailscaleError::InvalidNetwork(s.to_string())),
+        }
+    }
+}
+
+/// The IP address family used as the source of outbound connections.
+///
+/// A node has one tailnet address per family; the family of the dialed
+/// destination determines which of them is used as the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum AddressFamily {
+    /// Use whichever family the destination resolves to.
+    #[default]
+    Any,
+    /// Only dial IPv4 destinations, using the node's IPv4 address.
+    Ipv4,
+    /// Only dial IPv6 destinations, using the node's IPv6 address.
+    Ipv6,
+}
+
 /// Errors that can occur when working with Tailscale.
 #[derive(Debug, Error)]
 pub enum TailscaleError {
@@ -73,9 +202,21 @@ pub enum TailscaleError {
     #[error("invalid listen address given")]
     InvalidAddress(#[from] std::io::Error),
 
+    #[error("invalid JSON returned by libtailscale")]
+    Json(#[from] serde_json::Error),
+
     #[error("invalid ip addresses returned: {0}")]
     InvalidIpAdresses(String),
 
+    #[error("invalid address {addr:?}: {reason}")]
+    MalformedAddress { addr: String, reason: &'static str },
+
+    #[error("unknown network type: {0}")]
+    InvalidNetwork(String),
+
+    #[error("invalid ACL tag {0:?}: tags look like \"tag:name\"")]
+    InvalidTag(String),
+
     #[error("failed to recvmsg")]
     Recvmsg,
 
@@ -88,15 +229,36 @@ pub enum TailscaleError {
     #[error("Failed to set dir")]
     SetDir,
 
+    #[error("could not set up state directory: {0}")]
+    StateDir(String),
+
     #[error("Failed to set auth key")]
     SetAuthKey,
 
     #[error("Failed to set ephemeral status")]
     SetEphemeral,
 
+    #[error("Failed to set control URL")]
+    SetControlUrl,
+
+    #[error("Failed to set state store")]
+    SetStateStore,
+
+    #[error("Failed to set WireGuard port")]
+    SetWireguardPort,
+
+    #[error("Failed to set listen backlog")]
+    SetListenBacklog,
+
+    #[error("Failed to set advertised tags")]
+    SetAdvertiseTags,
+
     #[error("Failed to set log destination")]
     SetLogFd,
 
+    #[error("failed to start Tailscale: {0}")]
+    StartFailed(String),
+
     #[error("failed to bring up Tailscale connection: {0}")]
     UpFailed(String),
 
@@ -117,6 +279,61 @@ pub enum TailscaleError {
     #[error("failed to accept connection: {0}")]
     AcceptFailed(String),
 
+    #[error("operation timed out")]
+    Timeout,
+
+    #[error("operation was cancelled")]
+    Cancelled,
+
+    #[error("operation requires an ephemeral node")]
+    NotEphemeral,
+
+    #[error("failed to remove node: {0}")]
+    DeregisterFailed(String),
+
+    #[error("failed to log out: {0}")]
+    LogoutFailed(String),
+
+    #[error("failed to start runtime: {0}")]
+    Runtime(std::io::Error),
+
+    #[error("invalid config file {}: {message}", path.display())]
+    ConfigFile { path: PathBuf, message: String },
+
+    #[error("TLS error: {0}")]
+    Tls(String),
+
+    #[error("WebSocket error: {0}")]
+    WebSocket(String),
+
+    #[error("QUIC error: {0}")]
+    Quic(String),
+
+    #[error("preflight checks failed: {0}")]
+    PreflightFailed(String),
+
+    #[error("{listeners} listener(s) and {connections} connection(s) are still open")]
+    ResourcesBusy {
+        listeners: usize,
+        connections: usize,
+    },
+
+    #[error("failed to close Tailscale instance")]
+    CloseFailed,
+
+    #[error("invalid libtailscale handle, the instance, listener or connection may be closed")]
+    InvalidHandle,
+
+    #[error("result from libtailscale does not fit into the buffer")]
+    BufferTooSmall,
+
+    #[error("API misuse: {0}")]
+    Misuse(String),
+
+    #[cfg(feature = "dlopen")]
+    #[error("libtailscale could not be loaded: {0}")]
+    LibraryUnavailable(String),
+
     #[error("tailscale error: {0}")]
     Tailscale(String),
 }
@@ -124,6 +341,92 @@ pub enum TailscaleError {
 /// A specialized `Result` type for Tailscale operations.
 pub type Result<T> = std::result::Result<T, TailscaleError>;
 
+/// Converts to an [`std::io::Error`] for IO-centric code such as
+/// frameworks, `Stream`s of connections and `?` in functions returning
+/// `io::Result`.
+///
+/// Wrapped IO errors are returned as they are. Otherwise the
+/// [`ErrorKind`](std::io::ErrorKind) reflects the failure, e.g.
+/// `ConnectionRefused`, `HostUnreachable` or `TimedOut` for dial failures
+/// depending on the reason libtailscale gave, `AddrInUse` for a taken port
+/// and `InvalidInput` for a malformed address, and the `TailscaleError` is
+/// kept as the source so it can be recovered with
+/// [`std::io::Error::downcast`].
+impl From<TailscaleError> for std::io::Error {
+    fn from(err: TailscaleError) -> Self {
+        match err {
+            TailscaleError::InvalidAddress(e) | TailscaleError::Runtime(e) => e,
+            err => std::io::Error::new(io_error_kind(&err), err),
+        }
+    }
+}
+
+/// Returns the [`ErrorKind`](std::io::ErrorKind) an error converts to.
+fn io_error_kind(err: &TailscaleError) -> std::io::ErrorKind {
+    use std::io::ErrorKind;
+
+    match err {
+        TailscaleError::AddrParseError(..)
+        | TailscaleError::MalformedAddress { .. }
+        | TailscaleError::InvalidNetwork(_)
+        | TailscaleError::InvalidTag(_)
+        | TailscaleError::Utf8Error(_)
+        | TailscaleError::Misuse(_) => ErrorKind::InvalidInput,
+        TailscaleError::NullError(_)
+        | TailscaleError::Utf8ContentError(_)
+        | TailscaleError::Json(_)
+        | TailscaleError::InvalidIpAdresses(_)
+        | TailscaleError::ControlMessage
+        | TailscaleError::ConfigFile { .. } => ErrorKind::InvalidData,
+        TailscaleError::DialFailed { message, .. } => {
+            kind_from_message(message).unwrap_or(ErrorKind::HostUnreachable)
+        }
+        TailscaleError::ListenFailed { message, .. } => {
+            kind_from_message(message).unwrap_or(ErrorKind::Other)
+        }
+        TailscaleError::AcceptFailed(_) | TailscaleError::Recvmsg => ErrorKind::ConnectionAborted,
+        TailscaleError::InvalidHandle => ErrorKind::NotConnected,
+        TailscaleError::Timeout => ErrorKind::TimedOut,
+        TailscaleError::Cancelled => ErrorKind::Interrupted,
+        TailscaleError::NotEphemeral => ErrorKind::Unsupported,
+        TailscaleError::ResourcesBusy { .. } => ErrorKind::ResourceBusy,
+        #[cfg(feature = "dlopen")]
+        TailscaleError::LibraryUnavailable(_) => ErrorKind::NotFound,
+        _ => ErrorKind::Other,
+    }
+}
+
+/// Picks an [`ErrorKind`](std::io::ErrorKind) from an error message written
+/// by Go's net package, if it names a well-known failure.
+fn kind_from_message(message: &str) -> Option<std::io::ErrorKind> {
+    use std::io::ErrorKind;
+
+    let message = message.to_ascii_lowercase();
+    let kind = if message.contains("connection refused") {
+        ErrorKind::ConnectionRefused
+    } else if message.contains("connection reset") {
+        ErrorKind::ConnectionReset
+    } else if message.contains("timeout") || message.contains("deadline exceeded") {
+        ErrorKind::TimedOut
+    } else if message.contains("address already in use") {
+        ErrorKind::AddrInUse
+    } else if message.contains("cannot assign requested address") {
+        ErrorKind::AddrNotAvailable
+    } else if message.contains("network is unreachable") {
+        ErrorKind::NetworkUnreachable
+    } else if message.contains("no route to host")
+        || message.contains("host is unreachable")
+        || message.contains("no such host")
+    {
+        ErrorKind::HostUnreachable
+    } else if message.contains("permission denied") {
+        ErrorKind::PermissionDenied
+    } else {
+        return None;
+    };
+    Some(kind)
+}
+
 /// Configuration for Tailscale logging output.
 #[derive(Default)]
 pub enum LogConfig {
@@ -135,8 +438,13 @@ pub enum LogConfig {
     Fd(OwnedFd),
     /// Discard all log output.
     Discard,
+    /// Re-emit log lines as `tracing` events with the target `tsnet`.
+    Tracing,
 }
 
+/// Callback receiving the login URL of a node that needs interactive login.
+type AuthUrlCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
 /// Builder for configuring and creating a Tailscale instance.
 ///
 /// Use this builder to set various configuration options before
@@ -152,22 +460,101 @@ pub enum LogConfig {
 ///     .build()?;
 /// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
+///
+/// Setters take and return `&mut self`. To keep a configured builder, e.g.
+/// in a struct, end the chain with [`take`](Self::take):
+///
+/// ```no_run
+/// # use tailscale2::{Tailscale, TailscaleBuilder};
+/// struct App {
+///     tailscale: TailscaleBuilder,
+/// }
+///
+/// let mut app = App {
+///     tailscale: Tailscale::builder().hostname("my-host").ephemeral(true).take(),
+/// };
+/// let ts = app.tailscale.build()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 #[derive(Default)]
 pub struct TailscaleBuilder {
     ephemeral: bool,
     hostname: Option<String>,
     dir: Option<PathBuf>,
-    auth_key: Option<String>,
+    auth_key: Option<AuthKey>,
+    control_url: Option<String>,
+    state_store: Option<Arc<dyn StateStore>>,
+    advertise_tags: Vec<String>,
+    wireguard_port: Option<u16>,
+    listen_backlog: Option<u16>,
+    buffer_sizes: BufferSizes,
     log_config: LogConfig,
+    log_level: Option<tracing::Level>,
+    on_incoming: Option<IncomingCallback>,
+    on_auth_url: Option<AuthUrlCallback>,
+    dial_family: AddressFamily,
+    max_concurrent_dials: Option<usize>,
+    up_retry: Option<RetryPolicy>,
+    dial_retry: Option<RetryPolicy>,
+    strict: bool,
+    built: bool,
 }
 
 impl TailscaleBuilder {
+    /// Moves the configuration out into an owned builder, leaving a default
+    /// one in its place.
+    ///
+    /// Ends a chain of setters, which return `&mut Self`, with a value that
+    /// can be stored or moved.
+    pub fn take(&mut self) -> TailscaleBuilder {
+        std::mem::take(self)
+    }
+
+    /// Builds the instance and brings it up, returning it once it is
+    /// connected to the tailnet.
+    ///
+    /// Shorthand for [`build`](Self::build) followed by
+    /// [`Tailscale::up`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if building fails or the node cannot be brought up.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example() -> Result<()> {
+    /// let ts = Tailscale::builder()
+    ///     .hostname("my-service")
+    ///     .build_and_up()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn build_and_up(&mut self) -> Result<Arc<Tailscale>> {
+        let ts = self.build()?;
+        ts.up().await?;
+        Ok(ts)
+    }
+
     /// Builds and returns a configured Tailscale instance.
     ///
     /// # Errors
     ///
     /// Returns an error if any of the configuration options fail to be set.
     pub fn build(&mut self) -> Result<Arc<Tailscale>> {
+        if self.strict && self.built {
+            return Err(TailscaleError::Misuse(
+                "builder already used to build an instance".to_string(),
+            ));
+        }
+        if let Some(tag) = self.advertise_tags.iter().find(|tag| !is_valid_tag(tag)) {
+            return Err(TailscaleError::InvalidTag(tag.clone()));
+        }
+        #[cfg(feature = "dlopen")]
+        crate::dlopen::library()?;
+
         debug!("creating new Tailscale instance");
         let sd = unsafe { tailscale_new() };
         if sd == 0 {
@@ -202,13 +589,57 @@ impl TailscaleBuilder {
             }
         }
         if let Some(auth_key) = &self.auth_key {
-            debug!("setting auth key");
-            let c_auth_key = CString::new(auth_key.clone())?;
+            debug!(?auth_key, "setting auth key");
+            let c_auth_key = CString::new(auth_key.expose_secret())?;
             let ret = unsafe { tailscale_set_authkey(sd, c_auth_key.as_ptr()) };
+            #[cfg(feature = "zeroize")]
+            zeroize::Zeroize::zeroize(&mut c_auth_key.into_bytes());
             if ret != 0 {
                 return Err(TailscaleError::SetAuthKey);
             }
         }
+        if let Some(control_url) = &self.control_url {
+            debug!(%control_url, "setting control URL");
+            let c_control_url = CString::new(control_url.as_str())?;
+            let ret = unsafe { tailscale_set_control_url(sd, c_control_url.as_ptr()) };
+            if ret != 0 {
+                return Err(TailscaleError::SetControlUrl);
+            }
+        }
+        let state_store = match &self.state_store {
+            Some(store) => {
+                debug!("setting state store");
+                let registered = RegisteredStore::new(Arc::clone(store));
+                let ret = unsafe { tailscale_set_state_store(sd, registered.as_ptr()) };
+                if ret != 0 {
+                    return Err(TailscaleError::SetStateStore);
+                }
+                Some(Arc::new(registered))
+            }
+            None => None,
+        };
+        if let Some(port) = self.wireguard_port {
+            debug!(port, "setting WireGuard port");
+            let ret = unsafe { tailscale_set_port(sd, port.into()) };
+            if ret != 0 {
+                return Err(TailscaleError::SetWireguardPort);
+            }
+        }
+        if let Some(backlog) = self.listen_backlog {
+            debug!(backlog, "setting listen backlog");
+            let ret = unsafe { tailscale_set_listen_backlog(sd, backlog.into()) };
+            if ret != 0 {
+                return Err(TailscaleError::SetListenBacklog);
+            }
+        }
+        if !self.advertise_tags.is_empty() {
+            debug!(tags = ?self.advertise_tags, "setting advertised tags");
+            let c_tags = CString::new(self.advertise_tags.join(","))?;
+            let ret = unsafe { tailscale_set_advertise_tags(sd, c_tags.as_ptr()) };
+            if ret != 0 {
+                return Err(TailscaleError::SetAdvertiseTags);
+            }
+        }
 
         // Handle log configuration
         let log_fd = match std::mem::take(&mut self.log_config) {
@@ -226,6 +657,17 @@ impl TailscaleBuilder {
                 }
                 Some(owned_fd)
             }
+            LogConfig::Tracing => {
+                debug!("forwarding Tailscale logs to tracing");
+                let pipe =
+                    logs::forward_to_tracing(self.log_level.unwrap_or(tracing::Level::TRACE))
+                        .map_err(|_| TailscaleError::SetLogFd)?;
+                let ret = unsafe { tailscale_set_logfd(sd, pipe.as_raw_fd()) };
+                if ret != 0 {
+                    return Err(TailscaleError::SetLogFd);
+                }
+                Some(pipe)
+            }
             LogConfig::Discard => {
                 debug!("disabling Tailscale logging");
                 let ret = unsafe { tailscale_set_logfd(sd, -1) };
@@ -237,9 +679,27 @@ impl TailscaleBuilder {
         };
 
         debug!("Tailscale instance built successfully");
+        self.built = true;
+        let drain_groups = Arc::<DrainGroups>::default();
         Ok(Arc::new(Tailscale {
             sd,
-            _log_fd: log_fd,
+            ephemeral: self.ephemeral,
+            closed: AtomicBool::new(false),
+            drain_groups: Arc::clone(&drain_groups),
+            tags: Arc::new(TagRegistry::new(drain_groups)),
+            on_incoming: self.on_incoming.clone(),
+            on_auth_url: self.on_auth_url.clone(),
+            dial_family: self.dial_family,
+            up_retry: self.up_retry.clone(),
+            dial_retry: self.dial_retry.clone(),
+            buffer_sizes: self.buffer_sizes,
+            strict: self.strict,
+            resumed: Notify::new(),
+            resources: Arc::default(),
+            dial_queue: Arc::new(DialQueue::new(self.max_concurrent_dials)),
+            metrics: Arc::new(MetricsRegistry::new(self.hostname.as_deref())),
+            log_fd: Mutex::new(log_fd),
+            _state_store: state_store,
         }))
     }
 
@@ -247,8 +707,8 @@ impl TailscaleBuilder {
     ///
     /// # Arguments
     ///
-    /// * `key` - The Tailscale authentication key
-    pub fn auth_key(&mut self, key: impl Into<String>) -> &mut Self {
+    /// * `key` - The Tailscale authentication key, either as an [`AuthKey`] or a string
+    pub fn auth_key(&mut self, key: impl Into<AuthKey>) -> &mut Self {
         self.auth_key = Some(key.into());
         self
     }
@@ -265,6 +725,85 @@ impl TailscaleBuilder {
         self
     }
 
+    /// Sets the ACL tags this node advertises, e.g. `["tag:server", "tag:ci"]`.
+    ///
+    /// A tagged node is owned by its tags rather than the user who
+    /// authenticated it, and auth keys that require tags only work when the
+    /// node advertises them. The tailnet policy must allow the authenticating
+    /// user or key to apply each tag. Replaces any previously set tags.
+    ///
+    /// [`build`](Self::build) fails with [`TailscaleError::InvalidTag`] unless
+    /// every tag has the form `tag:name`.
+    pub fn advertise_tags<I, S>(&mut self, tags: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.advertise_tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the URL of the coordination server, for tailnets run by a
+    /// self-hosted control server such as Headscale instead of Tailscale.
+    ///
+    /// The DERP map, i.e. the relay servers nodes fall back to, is handed out
+    /// by the control server, so self-hosted relays are configured there; tsnet
+    /// has no client-side override. The map in use is available from
+    /// [`Tailscale::netmap`].
+    pub fn control_url(&mut self, url: impl Into<String>) -> &mut Self {
+        self.control_url = Some(url.into());
+        self
+    }
+
+    /// Keeps the node's state, such as its keys and login profile, in `store`
+    /// instead of a file in the state directory, e.g. in a database or
+    /// secrets manager. See the [`state`](crate::state) module for the
+    /// built-in stores.
+    ///
+    /// The state directory set with [`dir`](Self::dir) is still used for logs
+    /// and certificates.
+    pub fn state_store(&mut self, store: impl StateStore) -> &mut Self {
+        self.state_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Pins the UDP port the embedded WireGuard engine listens on, so a
+    /// firewall can allow it for direct connections to peers.
+    ///
+    /// By default a port is picked automatically. Without direct connections
+    /// traffic is relayed through DERP, which works but is slower. Each
+    /// instance on a host needs its own port.
+    pub fn wireguard_port(&mut self, port: u16) -> &mut Self {
+        self.wireguard_port = Some(port);
+        self
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`) of every new
+    /// connection, see [`Connection::set_recv_buffer_size`].
+    pub fn recv_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.buffer_sizes.recv = Some(size);
+        self
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`) of every new
+    /// connection, see [`Connection::set_send_buffer_size`].
+    pub fn send_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.buffer_sizes.send = Some(size);
+        self
+    }
+
+    /// Sets how many accepted connections each listener queues until
+    /// [`Listener::accept`] takes them.
+    ///
+    /// Once the queue is full, new connections wait in the network stack,
+    /// which drops connection attempts when it is full in turn. Raise this
+    /// for servers that see bursts of connections, e.g. under load tests. By
+    /// default only what fits into the listener's socket buffer is queued.
+    pub fn listen_backlog(&mut self, backlog: u16) -> &mut Self {
+        self.listen_backlog = Some(backlog);
+        self
+    }
+
     /// Sets the hostname for this Tailscale node.
     ///
     /// # Arguments
@@ -285,6 +824,42 @@ impl TailscaleBuilder {
         self
     }
 
+    /// Sets the state directory to the per-user location for `app_name`
+    /// following platform conventions, creating it with mode `0700`:
+    ///
+    /// - macOS: `~/Library/Application Support/<app_name>/tsnet`
+    /// - elsewhere: `$XDG_STATE_HOME/<app_name>/tsnet`, or
+    ///   `~/.local/state/<app_name>/tsnet` if `XDG_STATE_HOME` is unset
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::StateDir`] if `app_name` is not a plain
+    /// directory name, the home directory is unknown or the directory cannot
+    /// be created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::Tailscale;
+    /// let ts = Tailscale::builder()
+    ///     .default_dir("my-app")?
+    ///     .hostname("my-app")
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn default_dir(&mut self, app_name: &str) -> Result<&mut Self> {
+        let dir = default_state_dir(app_name)?;
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .map_err(|e| {
+                TailscaleError::StateDir(format!("could not create {}: {e}", dir.display()))
+            })?;
+        debug!(path = %dir.display(), "using default state directory");
+        Ok(self.dir(dir))
+    }
+
     /// Sets a custom log destination for Tailscale logging output.
     ///
     /// # Arguments
@@ -322,89 +897,993 @@ impl TailscaleBuilder {
         self.log_config = LogConfig::Discard;
         self
     }
+
+    /// Re-emits Tailscale's log output as `tracing` events.
+    ///
+    /// Each line is parsed into a [`LogRecord`] and becomes an event with
+    /// the target `tsnet` and the record's component in a `component` field,
+    /// so it can be filtered like any other, e.g. with `RUST_LOG=tsnet=warn`.
+    /// Lines marked `[v1]` or `[v2]` by Tailscale are emitted at `DEBUG` and
+    /// `TRACE`, lines marked `[unexpected]` at `WARN`, and the rest at
+    /// `INFO`; see [`log_level`](Self::log_level) to drop verbose lines
+    /// before they reach the subscriber. A background thread reads the lines
+    /// until the instance is closed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tailscale2::Tailscale;
+    /// tracing_subscriber::fmt().init();
+    /// let ts = Tailscale::builder()
+    ///     .log_to_tracing()
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn log_to_tracing(&mut self) -> &mut Self {
+        self.log_config = LogConfig::Tracing;
+        self
+    }
+
+    /// Sets the least severe level of Tailscale log lines forwarded by
+    /// [`log_to_tracing`](Self::log_to_tracing). Defaults to
+    /// [`Level::TRACE`](tracing::Level::TRACE), i.e. every line.
+    ///
+    /// Lines below the level are dropped as soon as they are read, which
+    /// saves formatting them for a subscriber that would discard them. Much
+    /// of Tailscale's routine chatter, such as DERP connection churn and
+    /// netmap diffs, is marked verbose and dropped at
+    /// [`Level::INFO`](tracing::Level::INFO).
+    pub fn log_level(&mut self, level: tracing::Level) -> &mut Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    /// Sets the address family used as the source of outbound connections.
+    ///
+    /// Defaults to [`AddressFamily::Any`]. Individual dials can override this
+    /// with [`Tailscale::connect_with_family`] or a family-specific
+    /// [`NetworkType`] such as [`NetworkType::Tcp6`].
+    ///
+    /// # Arguments
+    ///
+    /// * `family` - The address family to dial with
+    pub fn dial_family(&mut self, family: AddressFamily) -> &mut Self {
+        self.dial_family = family;
+        self
+    }
+
+    /// Caps the number of dials that may be in flight at once across the instance.
+    ///
+    /// Further dials wait in a FIFO queue until a slot frees up; see
+    /// [`Tailscale::dial_queue_stats`]. By default dials are not limited.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of concurrent dials, at least 1
+    pub fn max_concurrent_dials(&mut self, limit: usize) -> &mut Self {
+        self.max_concurrent_dials = Some(limit.max(1));
+        self
+    }
+
+    /// Retries a failed [`Tailscale::up`] according to `policy`, so flaky
+    /// startup networks do not require a hand-rolled retry loop.
+    ///
+    /// Only failures to bring the connection up are retried. By default `up`
+    /// is attempted once.
+    pub fn up_retry(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.up_retry = Some(policy);
+        self
+    }
+
+    /// Retries failed dials made with [`Tailscale::connect`] and its variants
+    /// according to `policy`.
+    ///
+    /// Each attempt waits for its own slot under
+    /// [`max_concurrent_dials`](Self::max_concurrent_dials). By default dials
+    /// are attempted once.
+    pub fn dial_retry(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.dial_retry = Some(policy);
+        self
+    }
+
+    /// Enables strict mode, which turns API misuse into errors and panics.
+    ///
+    /// Meant for development and tests. In strict mode:
+    ///
+    /// - building a second instance from the same builder returns
+    ///   [`TailscaleError::Misuse`] (the log destination is consumed by the
+    ///   first build)
+    /// - using the instance, or a listener created from it, after it was
+    ///   closed returns [`TailscaleError::Misuse`] instead of an opaque error
+    ///   from the C layer
+    /// - synchronous [`Read`]/[`Write`] on a [`Connection`] from within an
+    ///   async runtime panics, as it would block a runtime thread until the
+    ///   peer sends or accepts data; use the async traits or
+    ///   [`blocking`](crate::blocking) instead
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets an async callback that authorizes incoming connections.
+    ///
+    /// The callback receives the identity of the connecting peer and is
+    /// evaluated before [`Listener::accept`] returns the connection. Denied
+    /// connections, and connections whose peer cannot be identified, are closed
+    /// without ever reaching application code.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tailscale2::{Decision, Tailscale};
+    /// let ts = Tailscale::builder()
+    ///     .on_incoming(|peer| async move {
+    ///         if peer.tags.iter().any(|t| t == "tag:ci") {
+    ///             Decision::Allow
+    ///         } else {
+    ///             Decision::Deny
+    ///         }
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn on_incoming<F, Fut>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(PeerIdentity) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Decision> + Send + 'static,
+    {
+        self.on_incoming = Some(Arc::new(move |peer| Box::pin(callback(peer))));
+        self
+    }
+
+    /// Sets a callback that receives the login URL when the node needs
+    /// interactive login, e.g. because no auth key was provided.
+    ///
+    /// The callback is invoked while [`Tailscale::up`] waits for the node to
+    /// be authenticated, so the application can render the URL in a UI or
+    /// terminal prompt instead of scraping it from the logs.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tailscale2::Tailscale;
+    /// let ts = Tailscale::builder()
+    ///     .on_auth_url(|url| eprintln!("To authenticate, visit: {url}"))
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn on_auth_url<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_auth_url = Some(Arc::new(callback));
+        self
+    }
 }
 
 /// A Tailscale network listener.
 ///
 /// This listener can accept incoming connections from other nodes on the Tailscale network.
 pub struct Listener {
-    ln: TailscaleListener,
+    // Declared before `ln` so the registration is released before the fd is closed.
+    _resource: ResourceGuard,
+    ln: AsyncFd<OwnedFd>,
+    /// Funnel listeners also accept connections from the public internet,
+    /// which have no tailnet identity to check.
+    funnel: bool,
+    filter: Mutex<Option<ListenerFilter>>,
+    limit: Mutex<Option<Arc<Semaphore>>>,
+    /// Tracks the accepted connections that are still open.
+    connections: Arc<DrainGroups>,
+    /// Cancelled by [`Listener::shutdown`].
+    closed: CancellationToken,
     _tailscale: Arc<Tailscale>,
 }
 
 pub type TailscaleConn = libc::c_int;
 
+/// Name of the group in [`Listener::connections`] holding every accepted
+/// connection.
+const ACCEPTED_GROUP: &str = "accepted";
+
 /// A connection accepted from a Tailscale listener.
 ///
-/// Implements `AsyncRead` and `AsyncWrite` for async I/O.
+/// Implements `AsyncRead` and `AsyncWrite` for async I/O. The std [`Read`]
+/// and [`Write`] impls block until the socket is ready, like a std socket,
+/// and are meant for threads outside an async runtime.
 pub struct Connection {
     listener: Option<Arc<Listener>>,
+    tags: Arc<TagRegistry>,
+    strict: bool,
+    // Declared before `conn` so the tag, group memberships and the
+    // registration are released before the fd is closed.
+    tag: Option<ConnectionTag>,
+    drain_groups: Vec<DrainMembership>,
+    _resource: ResourceGuard,
+    /// Set for connections accepted from a listener.
+    slot: Option<ListenerSlot>,
+    metrics: Arc<ConnectionMetrics>,
+    span: Span,
+    /// Addresses looked up from libtailscale, which never change.
+    remote: OnceLock<SocketAddr>,
+    local: OnceLock<SocketAddr>,
     conn: AsyncFd<OwnedFd>,
 }
 
-impl Connection {
-    /// Returns the remote IP address of this connection.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the remote address cannot be retrieved or parsed.
-    pub fn remote_addr(&self) -> Result<Option<IpAddr>> {
-        let Some(listener) = &self.listener else {
-            return Ok(None);
-        };
-
-        let conn_fd = self.conn.as_raw_fd();
-        let buf = [0u8; 128];
-        let ret = unsafe {
-            tailscale_getremoteaddr(listener.ln, conn_fd, buf.as_ptr() as *mut _, buf.len())
-        };
+/// What an accepted [`Connection`] holds in its listener until dropped.
+pub(crate) struct ListenerSlot {
+    /// Membership in the listener's [`ACCEPTED_GROUP`], awaited by
+    /// [`Listener::shutdown`].
+    _membership: DrainMembership,
+    /// Slot taken from the listener's [connection
+    /// limit](Listener::limit_connections), if any.
+    _permit: Option<OwnedSemaphorePermit>,
+}
 
-        if ret != 0 {
-            let error_message = listener._tailscale.get_error_message()?;
-            return Err(TailscaleError::Tailscale(error_message));
-        }
+/// What a [`Connection`] keeps alive besides its fd, carried over when the
+/// fd is converted into another IO type.
+pub(crate) struct ConnectionGuards {
+    _listener: Option<Arc<Listener>>,
+    tag: Option<ConnectionTag>,
+    _drain_groups: Vec<DrainMembership>,
+    _resource: ResourceGuard,
+    _slot: Option<ListenerSlot>,
+    metrics: Arc<ConnectionMetrics>,
+}
 
-        let s = CStr::from_bytes_until_nul(&buf[..])?;
-        let s = s.to_str()?;
+#[cfg_attr(not(all(feature = "uring", target_os = "linux")), allow(dead_code))]
+impl ConnectionGuards {
+    pub(crate) fn tag(&self) -> Option<&str> {
+        self.tag.as_ref().map(ConnectionTag::name)
+    }
 
-        let addr =
-            IpAddr::from_str(s).map_err(|e| TailscaleError::AddrParseError(s.to_string(), e))?;
+    pub(crate) fn record_read(&self, n: usize) {
+        self.metrics.record_read(n);
+        if let Some(tag) = &self.tag {
+            tag.record_read(n);
+        }
+    }
 
-        Ok(Some(addr))
+    pub(crate) fn record_write(&self, n: usize) {
+        self.metrics.record_write(n);
+        if let Some(tag) = &self.tag {
+            tag.record_write(n);
+        }
     }
 }
 
-impl Drop for Connection {
-    fn drop(&mut self) {
-        debug!("dropping connection");
-        // AsyncFd<OwnedFd> automatically closes the fd on drop
-    }
+/// The error returned by [`Connection::into_std_tcp`] and
+/// [`Connection::into_tokio_tcp`], giving back the connection that could
+/// not be converted.
+pub struct IntoTcpError {
+    /// Boxed, as a `Connection` is large.
+    inner: Box<(Connection, TailscaleError)>,
 }
 
-impl Read for Connection {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let fd = self.conn.get_ref().as_fd();
-        nix::unistd::read(fd, buf).map_err(|errno| std::io::Error::from_raw_os_error(errno as i32))
+impl IntoTcpError {
+    fn new(connection: Connection, error: TailscaleError) -> IntoTcpError {
+        IntoTcpError {
+            inner: Box::new((connection, error)),
+        }
     }
-}
 
-impl Write for Connection {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let fd = self.conn.get_ref().as_fd();
-        nix::unistd::write(fd, buf).map_err(|errno| std::io::Error::from_raw_os_error(errno as i32))
+    /// Returns why the connection could not be converted.
+    pub fn error(&self) -> &TailscaleError {
+        &self.inner.1
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        Ok(())
+    /// Returns the unchanged connection and the error.
+    pub fn into_parts(self) -> (Connection, TailscaleError) {
+        *self.inner
     }
 }
 
-impl AsyncRead for Connection {
-    fn poll_read(
-        self: std::pin::Pin<&mut Self>,
+impl std::fmt::Debug for IntoTcpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IntoTcpError")
+            .field("error", self.error())
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Display for IntoTcpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "could not convert connection into a TcpStream: {}",
+            self.error()
+        )
+    }
+}
+
+impl std::error::Error for IntoTcpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error())
+    }
+}
+
+impl From<IntoTcpError> for TailscaleError {
+    /// Drops the connection, keeping only the error.
+    fn from(e: IntoTcpError) -> TailscaleError {
+        e.into_parts().1
+    }
+}
+
+impl Connection {
+    /// Puts `fd` into non-blocking mode and registers it with the tokio reactor.
+    fn from_owned_fd(
+        fd: OwnedFd,
+        listener: Option<Arc<Listener>>,
+        tailscale: &Tailscale,
+    ) -> Result<Connection> {
+        set_nonblocking(&fd)?;
+
+        let async_fd = AsyncFd::new(fd)
+            .map_err(|e| TailscaleError::Tailscale(format!("AsyncFd::new failed: {}", e)))?;
+        let resource = tailscale
+            .resources
+            .register(ResourceKind::Connection, async_fd.as_raw_fd());
+        tailscale.buffer_sizes.apply(async_fd.get_ref());
+
+        Ok(Connection {
+            listener,
+            tags: Arc::clone(&tailscale.tags),
+            strict: tailscale.strict,
+            tag: None,
+            drain_groups: Vec::new(),
+            _resource: resource,
+            slot: None,
+            metrics: Arc::new(tailscale.metrics.open_connection()),
+            span: Span::none(),
+            remote: OnceLock::new(),
+            local: OnceLock::new(),
+            conn: async_fd,
+        })
+    }
+
+    /// Returns the remote address (IP and port) of this connection.
+    ///
+    /// Works for both accepted and dialed connections. The address is looked
+    /// up once and cached, so repeated calls are cheap.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the remote address cannot be retrieved or parsed.
+    pub fn remote_addr(&self) -> Result<SocketAddr> {
+        if let Some(addr) = self.remote.get() {
+            return Ok(*addr);
+        }
+        let addr = self.lookup_remote_addr()?;
+        Ok(*self.remote.get_or_init(|| addr))
+    }
+
+    fn lookup_remote_addr(&self) -> Result<SocketAddr> {
+        let conn_fd = self.conn.as_raw_fd();
+        let buf = [0u8; 128];
+
+        let Some(listener) = &self.listener else {
+            let ret = unsafe { tailscale_getpeeraddr(conn_fd, buf.as_ptr() as *mut _, buf.len()) };
+            if ret != 0 {
+                return Err(code_error(ret).unwrap_or_else(|| {
+                    TailscaleError::Tailscale(format!(
+                        "Failed to retrieve remote address (error code: {})",
+                        ret
+                    ))
+                }));
+            }
+            return parse_socket_addr(&buf);
+        };
+
+        let ret = unsafe {
+            tailscale_getremoteaddrport(
+                listener.ln.as_raw_fd(),
+                conn_fd,
+                buf.as_ptr() as *mut _,
+                buf.len(),
+            )
+        };
+
+        if ret != 0 {
+            return Err(listener
+                ._tailscale
+                .ret_error(ret, TailscaleError::Tailscale));
+        }
+
+        parse_socket_addr(&buf)
+    }
+
+    /// Returns the local tailnet address (IP and port) of this connection.
+    ///
+    /// Like [`remote_addr`](Self::remote_addr), the address is cached after
+    /// the first call.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the local address cannot be retrieved or parsed.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        if let Some(addr) = self.local.get() {
+            return Ok(*addr);
+        }
+        let conn_fd = self.conn.as_raw_fd();
+        let buf = [0u8; 128];
+        let ret = unsafe { tailscale_getlocaladdr(conn_fd, buf.as_ptr() as *mut _, buf.len()) };
+
+        if ret != 0 {
+            return Err(code_error(ret).unwrap_or_else(|| {
+                TailscaleError::Tailscale(format!(
+                    "Failed to retrieve local address (error code: {})",
+                    ret
+                ))
+            }));
+        }
+
+        let addr = parse_socket_addr(&buf)?;
+        Ok(*self.local.get_or_init(|| addr))
+    }
+
+    /// Sets `TCP_NODELAY` on the tailnet connection, disabling Nagle's
+    /// algorithm so small writes are sent right away.
+    ///
+    /// The option is set on the tailnet connection inside libtailscale, not
+    /// on this connection's fd, which is the local end of a socketpair.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`Unsupported`](std::io::ErrorKind::Unsupported)
+    /// if the tailnet connection does not support the option, such as a UDP
+    /// connection, or an error if the connection is closed.
+    pub fn set_nodelay(&self, nodelay: bool) -> std::io::Result<()> {
+        let ret = unsafe { tailscale_setnodelay(self.conn.as_raw_fd(), nodelay.into()) };
+        conn_opt_result(ret)
+    }
+
+    /// Enables or disables TCP keep-alives on the tailnet connection.
+    ///
+    /// Like [`set_nodelay`](Self::set_nodelay), this applies to the tailnet
+    /// connection inside libtailscale rather than to this connection's fd.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`Unsupported`](std::io::ErrorKind::Unsupported)
+    /// if the tailnet connection does not support the option, or an error if
+    /// the connection is closed.
+    pub fn set_keepalive(&self, keepalive: bool) -> std::io::Result<()> {
+        let ret = unsafe { tailscale_setkeepalive(self.conn.as_raw_fd(), keepalive.into()) };
+        conn_opt_result(ret)
+    }
+
+    /// Sets how long closing the tailnet connection waits for unsent data to
+    /// be delivered.
+    ///
+    /// `None` restores the default of closing in the background.
+    /// `Some(Duration::ZERO)` discards unsent data and resets the connection
+    /// on close. Like [`set_nodelay`](Self::set_nodelay), this applies to the
+    /// tailnet connection inside libtailscale rather than to this
+    /// connection's fd.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InvalidInput`](std::io::ErrorKind::InvalidInput)
+    /// if `linger` is not a whole number of seconds or does not fit in a C
+    /// `int`, as the option only has second granularity. Returns an error of
+    /// kind [`Unsupported`](std::io::ErrorKind::Unsupported) if the tailnet
+    /// connection does not support the option, or an error if the connection
+    /// is closed.
+    pub fn set_linger(&self, linger: Option<Duration>) -> std::io::Result<()> {
+        let sec = match linger {
+            None => -1,
+            Some(d) if d.subsec_nanos() != 0 => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "linger must be a whole number of seconds",
+                ));
+            }
+            Some(d) => libc::c_int::try_from(d.as_secs()).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "linger is too long")
+            })?,
+        };
+        let ret = unsafe { tailscale_setlinger(self.conn.as_raw_fd(), sec) };
+        conn_opt_result(ret)
+    }
+
+    /// Receives data from the connection without removing it, waiting until
+    /// some is available.
+    ///
+    /// Successive calls return the same data, and the next read starts with
+    /// it, so a server can sniff the protocol of a connection before handing
+    /// it on. Returns `Ok(0)` once the peer has closed the connection.
+    /// Peeked bytes are counted in [`bytes_read`](Self::bytes_read) only
+    /// when they are read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(listener: std::sync::Arc<Listener>) -> Result<()> {
+    /// let conn = listener.accept().await?;
+    /// let mut first = [0; 1];
+    /// // A TLS connection starts with a handshake record.
+    /// let tls = conn.peek(&mut first).await? == 1 && first[0] == 0x16;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if receiving from the socket fails.
+    pub async fn peek(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.async_io(Interest::READABLE, |fd| {
+            nix::sys::socket::recv(fd.as_raw_fd(), buf, nix::sys::socket::MsgFlags::MSG_PEEK)
+                .map_err(std::io::Error::from)
+        })
+        .await
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`) of the underlying
+    /// socket.
+    ///
+    /// The socket is the application's end of the socket pair libtailscale
+    /// relays the connection through, so the buffer bounds how much received
+    /// data waits for the application. Larger buffers help on links with a
+    /// high bandwidth-delay product, such as connections relayed through
+    /// DERP. The kernel may round or cap the size; see
+    /// [`recv_buffer_size`](Self::recv_buffer_size) for the effective one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying socket does not support the option.
+    pub fn set_recv_buffer_size(&self, size: usize) -> std::io::Result<()> {
+        let fd = self.conn.get_ref();
+        nix::sys::socket::setsockopt(fd, nix::sys::socket::sockopt::RcvBuf, &size)
+            .map_err(|errno| std::io::Error::from_raw_os_error(errno as i32))
+    }
+
+    /// Returns the size of the receive buffer (`SO_RCVBUF`) of the
+    /// underlying socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying socket does not support the option.
+    pub fn recv_buffer_size(&self) -> std::io::Result<usize> {
+        let fd = self.conn.get_ref();
+        nix::sys::socket::getsockopt(fd, nix::sys::socket::sockopt::RcvBuf)
+            .map_err(|errno| std::io::Error::from_raw_os_error(errno as i32))
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`) of the underlying
+    /// socket, which bounds how much written data waits for libtailscale to
+    /// send it. See [`set_recv_buffer_size`](Self::set_recv_buffer_size).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying socket does not support the option.
+    pub fn set_send_buffer_size(&self, size: usize) -> std::io::Result<()> {
+        let fd = self.conn.get_ref();
+        nix::sys::socket::setsockopt(fd, nix::sys::socket::sockopt::SndBuf, &size)
+            .map_err(|errno| std::io::Error::from_raw_os_error(errno as i32))
+    }
+
+    /// Returns the size of the send buffer (`SO_SNDBUF`) of the underlying
+    /// socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying socket does not support the option.
+    pub fn send_buffer_size(&self) -> std::io::Result<usize> {
+        let fd = self.conn.get_ref();
+        nix::sys::socket::getsockopt(fd, nix::sys::socket::sockopt::SndBuf)
+            .map_err(|errno| std::io::Error::from_raw_os_error(errno as i32))
+    }
+
+    /// Tags the connection with `tag`, replacing any previous tag.
+    ///
+    /// Bytes read and written from then on are counted under the tag in
+    /// [`Tailscale::tag_stats`], tracing events for the connection carry it,
+    /// and the connection joins the drain group of the same name, so a
+    /// tenant's connections can be drained with
+    /// [`Tailscale::drain_group`].
+    pub fn set_tag(&mut self, tag: impl Into<String>) {
+        let tag = tag.into();
+        if self.tag() == Some(tag.as_str()) {
+            return;
+        }
+        // Release the previous tag first, so that retagging does not keep the
+        // connection in the old tag's drain group.
+        self.tag = None;
+        self.tag = Some(self.tags.tag(&tag, self.conn.as_raw_fd()));
+    }
+
+    /// Returns the tag set with [`set_tag`](Self::set_tag), if any.
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_ref().map(ConnectionTag::name)
+    }
+
+    /// Returns the tracing span of this connection.
+    ///
+    /// Connections accepted from a [`Listener`] get an `INFO` span named
+    /// `connection` with the peer's address (`remote`), the listener's
+    /// address (`local`) and, if the peer was identified by a
+    /// [filter](Listener::with_filter) or an
+    /// [`on_incoming`](TailscaleBuilder::on_incoming) callback, its login
+    /// name (`peer`). The crate's own events about the connection are
+    /// recorded in it; instrument handler tasks with it to correlate theirs.
+    /// Dialed connections have no span.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// use tracing::Instrument;
+    ///
+    /// # async fn example(listener: std::sync::Arc<Listener>) -> Result<()> {
+    /// let conn = listener.accept().await?;
+    /// let span = conn.span().clone();
+    /// tokio::spawn(
+    ///     async move {
+    ///         tracing::info!("handling connection");
+    ///         drop(conn);
+    ///     }
+    ///     .instrument(span),
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Returns an OpenTelemetry context holding this connection's
+    /// `tailscale.connection` span, for parenting the spans of the code
+    /// handling it.
+    ///
+    /// The span is a child of the `tailscale.dial` or `tailscale.accept`
+    /// span that opened the connection and ends once the connection and all
+    /// its clones are dropped. It is only recorded if a tracer provider was
+    /// installed with [`opentelemetry::global`] before the instance was
+    /// built.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// use opentelemetry::{global, trace::Tracer};
+    ///
+    /// # async fn example(listener: std::sync::Arc<Listener>) -> Result<()> {
+    /// let conn = listener.accept().await?;
+    /// let tracer = global::tracer("my-service");
+    /// let _span = tracer.start_with_context("handle", &conn.otel_context());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "otel")]
+    pub fn otel_context(&self) -> opentelemetry::Context {
+        self.metrics.otel_context().cloned().unwrap_or_default()
+    }
+
+    #[cfg(feature = "otel")]
+    fn otel_addrs(&self) -> Addrs {
+        Addrs {
+            remote: self.remote_addr().ok(),
+            local: self.local_addr().ok(),
+        }
+    }
+
+    /// Gives an accepted connection its span, looking up the addresses
+    /// only if the span is enabled.
+    fn with_span(mut self, peer: Option<&str>) -> Self {
+        let span = tracing::info_span!(
+            "connection",
+            remote = field::Empty,
+            local = field::Empty,
+            peer = field::Empty
+        );
+        if !span.is_disabled() {
+            if let Ok(remote) = self.remote_addr() {
+                span.record("remote", field::display(remote));
+            }
+            if let Ok(local) = self.local_addr() {
+                span.record("local", field::display(local));
+            }
+            if let Some(peer) = peer {
+                span.record("peer", peer);
+            }
+        }
+        self.span = span;
+        self
+    }
+
+    /// Creates a new handle to the same underlying socket, like
+    /// [`std::net::TcpStream::try_clone`].
+    ///
+    /// Reads and writes through either handle go to the same connection, so
+    /// reader and writer can be owned by different components where
+    /// [`tokio::io::split`] is not enough. Byte counts and the tracing span
+    /// are shared. The clone starts without a [tag](Self::set_tag) and does
+    /// not hold a slot in its listener's connection limit. Shutting down
+    /// either handle shuts down the connection for both; the socket is
+    /// closed once every handle is dropped. Must be called from a tokio
+    /// runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file descriptor cannot be duplicated.
+    pub fn try_clone(&self) -> Result<Connection> {
+        let fd = self
+            .conn
+            .get_ref()
+            .try_clone()
+            .map_err(|e| TailscaleError::Tailscale(format!("dup failed: {}", e)))?;
+        let async_fd = AsyncFd::new(fd)
+            .map_err(|e| TailscaleError::Tailscale(format!("AsyncFd::new failed: {}", e)))?;
+        let resource = self
+            ._resource
+            .register_sibling(ResourceKind::Connection, async_fd.as_raw_fd());
+
+        Ok(Connection {
+            listener: self.listener.clone(),
+            tags: Arc::clone(&self.tags),
+            strict: self.strict,
+            tag: None,
+            drain_groups: Vec::new(),
+            _resource: resource,
+            slot: None,
+            metrics: Arc::clone(&self.metrics),
+            span: self.span.clone(),
+            remote: self.remote.clone(),
+            local: self.local.clone(),
+            conn: async_fd,
+        })
+    }
+
+    /// Returns the number of bytes read from this connection.
+    pub fn bytes_read(&self) -> u64 {
+        self.metrics.bytes_read()
+    }
+
+    /// Returns the number of bytes written to this connection.
+    pub fn bytes_written(&self) -> u64 {
+        self.metrics.bytes_written()
+    }
+
+    /// Hands the connection to io_uring for reads and writes.
+    ///
+    /// The returned connection must be used from a tokio-uring runtime. See
+    /// [`UringConnection`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be switched back to blocking
+    /// mode.
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    pub fn into_uring(self) -> Result<UringConnection> {
+        let (fd, guards) = self.into_parts();
+        UringConnection::new(fd, guards)
+    }
+
+    /// Converts the connection into a blocking [`std::net::TcpStream`], if
+    /// its fd is a TCP socket.
+    ///
+    /// No connection accepted or dialed through libtailscale converts
+    /// today: libtailscale hands out one end of a Unix socketpair for every
+    /// connection and relays to the tailnet connection inside the Go
+    /// runtime, so this always returns the connection back in an
+    /// [`IntoTcpError`]. Code that only needs a byte stream should use the
+    /// `AsyncRead`/`AsyncWrite` or [`Read`]/[`Write`] impls, or convert into
+    /// an [`OwnedFd`]. As with that conversion, a converted stream no longer
+    /// counts towards the instance's metrics or listener.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IntoTcpError`] holding the unchanged connection and
+    /// [`TailscaleError::Misuse`] if the fd is not a TCP socket, or another
+    /// error if the socket cannot be switched to blocking mode.
+    pub fn into_std_tcp(self) -> std::result::Result<std::net::TcpStream, IntoTcpError> {
+        if let Err(error) = self.check_tcp().and_then(|()| self.set_blocking()) {
+            return Err(IntoTcpError::new(self, error));
+        }
+        Ok(std::net::TcpStream::from(OwnedFd::from(self)))
+    }
+
+    /// Converts the connection into a [`tokio::net::TcpStream`] registered
+    /// with the current runtime, if its fd is a TCP socket.
+    ///
+    /// Like [`into_std_tcp`](Self::into_std_tcp), this does not succeed for
+    /// any connection from libtailscale today.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`IntoTcpError`] holding the unchanged connection and
+    /// [`TailscaleError::Misuse`] if the fd is not a TCP socket, or another
+    /// error if the stream cannot be registered with the runtime.
+    pub fn into_tokio_tcp(self) -> std::result::Result<tokio::net::TcpStream, IntoTcpError> {
+        // Registers a duplicate of the fd first, so the connection is still
+        // intact if that fails.
+        let stream = self.check_tcp().and_then(|()| {
+            let fd = self
+                .conn
+                .get_ref()
+                .try_clone()
+                .map_err(TailscaleError::Runtime)?;
+            // The fd is already in non-blocking mode, as tokio requires.
+            tokio::net::TcpStream::from_std(std::net::TcpStream::from(fd))
+                .map_err(TailscaleError::Runtime)
+        });
+        match stream {
+            Ok(stream) => {
+                drop(OwnedFd::from(self));
+                Ok(stream)
+            }
+            Err(error) => Err(IntoTcpError::new(self, error)),
+        }
+    }
+
+    /// Switches the fd back to blocking mode, before it is converted into a
+    /// `std::net::TcpStream`.
+    fn set_blocking(&self) -> Result<()> {
+        let fd = self.conn.get_ref();
+        let flags = nix::fcntl::OFlag::from_bits_truncate(
+            nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_GETFL)
+                .map_err(|e| TailscaleError::Tailscale(format!("F_GETFL failed: {}", e)))?,
+        );
+        nix::fcntl::fcntl(
+            fd,
+            nix::fcntl::FcntlArg::F_SETFL(flags - nix::fcntl::OFlag::O_NONBLOCK),
+        )
+        .map_err(|e| TailscaleError::Tailscale(format!("F_SETFL failed: {}", e)))?;
+        Ok(())
+    }
+
+    /// Checks that the fd is a TCP socket, before it is converted into a
+    /// `TcpStream`.
+    fn check_tcp(&self) -> Result<()> {
+        use nix::sys::socket::{AddressFamily, SockType, SockaddrLike, SockaddrStorage, sockopt};
+
+        let fd = self.conn.get_ref();
+        let sock_type = nix::sys::socket::getsockopt(fd, sockopt::SockType)
+            .map_err(|e| TailscaleError::Tailscale(format!("SO_TYPE failed: {}", e)))?;
+        let family = nix::sys::socket::getsockname::<SockaddrStorage>(fd.as_raw_fd())
+            .map_err(|e| TailscaleError::Tailscale(format!("getsockname failed: {}", e)))?
+            .family();
+        match (sock_type, family) {
+            (SockType::Stream, Some(AddressFamily::Inet | AddressFamily::Inet6)) => Ok(()),
+            _ => Err(TailscaleError::Misuse(format!(
+                "connection is not a TCP socket ({:?} socket of family {:?})",
+                sock_type, family
+            ))),
+        }
+    }
+
+    /// Splits the connection into its fd, deregistered from the tokio
+    /// reactor, and the guards that must live as long as the fd is open.
+    fn into_parts(self) -> (OwnedFd, ConnectionGuards) {
+        debug!(tag = self.tag(), "converting connection");
+        let this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never used or dropped again, so every field is
+        // moved out exactly once.
+        let (listener, tags, tag, drain_groups, resource, slot, metrics, span, conn) = unsafe {
+            (
+                ptr::read(&this.listener),
+                ptr::read(&this.tags),
+                ptr::read(&this.tag),
+                ptr::read(&this.drain_groups),
+                ptr::read(&this._resource),
+                ptr::read(&this.slot),
+                ptr::read(&this.metrics),
+                ptr::read(&this.span),
+                ptr::read(&this.conn),
+            )
+        };
+        drop(tags);
+        drop(span);
+        let guards = ConnectionGuards {
+            _listener: listener,
+            tag,
+            _drain_groups: drain_groups,
+            _resource: resource,
+            _slot: slot,
+            metrics,
+        };
+        (conn.into_inner(), guards)
+    }
+
+    /// Runs `f` on the fd for the sync [`Read`]/[`Write`] impls, waiting in
+    /// `poll(2)` for `events` while the non-blocking socket would block, so
+    /// that they block like a std socket. Calls interrupted by a signal are
+    /// retried.
+    fn sync_io<T>(
+        &self,
+        events: PollFlags,
+        mut f: impl FnMut(BorrowedFd<'_>) -> nix::Result<T>,
+    ) -> std::io::Result<T> {
+        let fd = self.conn.get_ref().as_fd();
+        loop {
+            match f(fd) {
+                Err(nix::errno::Errno::EWOULDBLOCK) => {
+                    let mut fds = [PollFd::new(fd, events)];
+                    match nix::poll::poll(&mut fds, PollTimeout::NONE) {
+                        Ok(_) | Err(nix::errno::Errno::EINTR) => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Err(nix::errno::Errno::EINTR) => {}
+                res => return res.map_err(std::io::Error::from),
+            }
+        }
+    }
+
+    /// In strict mode, panics if called from within an async runtime.
+    fn check_sync_io(&self) {
+        if self.strict && tokio::runtime::Handle::try_current().is_ok() {
+            panic!(
+                "synchronous I/O on a Connection inside an async runtime; \
+                 use AsyncRead/AsyncWrite or tailscale2::blocking instead"
+            );
+        }
+    }
+
+    pub(crate) fn record_read(&self, n: usize) {
+        self.metrics.record_read(n);
+        if let Some(tag) = &self.tag {
+            tag.record_read(n);
+        }
+    }
+
+    pub(crate) fn record_write(&self, n: usize) {
+        self.metrics.record_write(n);
+        if let Some(tag) = &self.tag {
+            tag.record_write(n);
+        }
+    }
+
+    /// Waits until the connection is ready for `interest` and runs `f` on its
+    /// fd, retrying while `f` would block or is interrupted by a signal.
+    pub(crate) async fn async_io<R>(
+        &self,
+        interest: Interest,
+        mut f: impl FnMut(BorrowedFd<'_>) -> std::io::Result<R>,
+    ) -> std::io::Result<R> {
+        self.conn
+            .async_io(interest, |fd| {
+                loop {
+                    match f(fd.as_fd()) {
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        res => return res,
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Shuts down the write half of the socket, so the peer reads EOF.
+    pub(crate) fn shutdown_write(&self) -> std::io::Result<()> {
+        match nix::sys::socket::shutdown(self.conn.as_raw_fd(), nix::sys::socket::Shutdown::Write) {
+            // The peer may already have closed the connection.
+            Ok(()) | Err(nix::errno::Errno::ENOTCONN) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns true if the peer has neither closed the connection nor sent
+    /// anything, i.e. an idle connection can be reused.
+    pub(crate) fn is_idle(&self) -> bool {
+        let mut byte = [0u8; 1];
+        let flags = nix::sys::socket::MsgFlags::MSG_PEEK | nix::sys::socket::MsgFlags::MSG_DONTWAIT;
+        matches!(
+            nix::sys::socket::recv(self.conn.as_raw_fd(), &mut byte, flags),
+            Err(nix::errno::Errno::EWOULDBLOCK)
+        )
+    }
+
+    /// Attempts to read into multiple buffers with a single `readv(2)` call.
+    ///
+    /// This is the vectored counterpart of [`AsyncRead::poll_read`], letting
+    /// callers scatter incoming data across several buffers without an
+    /// intermediate copy.
+    pub fn poll_read_vectored(
+        &self,
         cx: &mut std::task::Context<'_>,
-        buf: &mut tokio::io::ReadBuf<'_>,
-    ) -> std::task::Poll<std::io::Result<()>> {
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<std::io::Result<usize>> {
         loop {
             let mut guard = match self.conn.poll_read_ready(cx) {
                 Poll::Ready(Ok(guard)) => guard,
@@ -414,25 +1893,16 @@ impl AsyncRead for Connection {
 
             let fd = self.conn.get_ref().as_fd();
 
-            // Safety: We're reading into the unfilled portion of the buffer
-            // and will call assume_init and advance after a successful read
-            let unfilled = unsafe {
-                let slice = buf.unfilled_mut();
-                std::slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut u8, slice.len())
-            };
-
-            match nix::unistd::read(fd, unfilled) {
+            match nix::sys::uio::readv(fd, bufs) {
                 Ok(n) => {
-                    unsafe {
-                        buf.assume_init(n);
-                    }
-                    buf.advance(n);
-                    return Poll::Ready(Ok(()));
+                    self.record_read(n);
+                    return Poll::Ready(Ok(n));
                 }
                 Err(nix::errno::Errno::EWOULDBLOCK) => {
                     guard.clear_ready();
                     continue;
                 }
+                Err(nix::errno::Errno::EINTR) => continue,
                 Err(e) => {
                     return Poll::Ready(Err(std::io::Error::from_raw_os_error(e as i32)));
                 }
@@ -441,13 +1911,245 @@ impl AsyncRead for Connection {
     }
 }
 
-impl AsyncWrite for Connection {
-    fn poll_write(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-        buf: &[u8],
-    ) -> std::task::Poll<std::io::Result<usize>> {
-        loop {
+/// Passes every new login URL in `events` to `callback`, until cancelled.
+async fn forward_auth_urls(mut events: Events, callback: AuthUrlCallback) -> Infallible {
+    let mut last_url = None;
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(IpnEvent::AuthUrl { url }) if last_url.as_ref() != Some(&url) => {
+                debug!("interactive login required");
+                callback(&url);
+                last_url = Some(url);
+            }
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, "could not read login URL"),
+        }
+    }
+    std::future::pending().await
+}
+
+/// Socket buffer sizes set with [`TailscaleBuilder::recv_buffer_size`] and
+/// [`TailscaleBuilder::send_buffer_size`].
+#[derive(Debug, Default, Clone, Copy)]
+struct BufferSizes {
+    recv: Option<usize>,
+    send: Option<usize>,
+}
+
+impl BufferSizes {
+    /// Applies the configured sizes to a new connection. Failures are only
+    /// logged, as the connection works with the default sizes too.
+    fn apply(&self, fd: &OwnedFd) {
+        use nix::sys::socket::{setsockopt, sockopt};
+
+        if let Some(size) = self.recv
+            && let Err(e) = setsockopt(fd, sockopt::RcvBuf, &size)
+        {
+            warn!(size, error = %e, "could not set receive buffer size");
+        }
+        if let Some(size) = self.send
+            && let Err(e) = setsockopt(fd, sockopt::SndBuf, &size)
+        {
+            warn!(size, error = %e, "could not set send buffer size");
+        }
+    }
+}
+
+/// Sets `O_NONBLOCK` on a file descriptor handed out by libtailscale.
+fn set_nonblocking(fd: &OwnedFd) -> Result<()> {
+    let flags = nix::fcntl::OFlag::from_bits_truncate(
+        nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_GETFL)
+            .map_err(|e| TailscaleError::Tailscale(format!("F_GETFL failed: {}", e)))?,
+    );
+    nix::fcntl::fcntl(
+        fd,
+        nix::fcntl::FcntlArg::F_SETFL(flags | nix::fcntl::OFlag::O_NONBLOCK),
+    )
+    .map_err(|e| TailscaleError::Tailscale(format!("F_SETFL failed: {}", e)))?;
+    Ok(())
+}
+
+/// Parses a NUL-terminated `ip:port` string written by libtailscale.
+fn parse_socket_addr(buf: &[u8]) -> Result<SocketAddr> {
+    let s = CStr::from_bytes_until_nul(buf)?;
+    let s = s.to_str()?;
+    SocketAddr::from_str(s).map_err(|e| TailscaleError::AddrParseError(s.to_string(), e))
+}
+
+/// Returns the per-user state directory for `app_name`, see
+/// [`TailscaleBuilder::default_dir`].
+fn default_state_dir(app_name: &str) -> Result<PathBuf> {
+    if app_name.is_empty() || app_name.starts_with('.') || app_name.contains(['/', '\0']) {
+        return Err(TailscaleError::StateDir(format!(
+            "{app_name:?} is not a valid directory name"
+        )));
+    }
+    let home = || {
+        std::env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from)
+            .ok_or_else(|| TailscaleError::StateDir("HOME is not set".to_string()))
+    };
+    let base = if cfg!(target_os = "macos") {
+        home()?.join("Library/Application Support")
+    } else {
+        match std::env::var_os("XDG_STATE_HOME").map(PathBuf::from) {
+            // Relative paths are invalid per the XDG spec and ignored.
+            Some(state_home) if state_home.is_absolute() => state_home,
+            _ => home()?.join(".local/state"),
+        }
+    };
+    Ok(base.join(app_name).join("tsnet"))
+}
+
+/// Reports whether `route` is a CIDR prefix such as `192.168.1.0/24`.
+fn is_valid_prefix(route: &str) -> bool {
+    let Some((ip, bits)) = route.split_once('/') else {
+        return false;
+    };
+    match (ip.parse::<IpAddr>(), bits.parse::<u8>()) {
+        (Ok(IpAddr::V4(_)), Ok(bits)) => bits <= 32,
+        (Ok(IpAddr::V6(_)), Ok(bits)) => bits <= 128,
+        _ => false,
+    }
+}
+
+/// Reports whether `tag` is an ACL tag of the form `tag:name`, where the name
+/// starts with a letter and contains only letters, digits and dashes.
+fn is_valid_tag(tag: &str) -> bool {
+    tag.strip_prefix("tag:").is_some_and(|name| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _span = self.span.enter();
+        debug!(tag = self.tag(), "dropping connection");
+        // AsyncFd<OwnedFd> automatically closes the fd on drop
+    }
+}
+
+impl AsFd for Connection {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.conn.get_ref().as_fd()
+    }
+}
+
+impl AsRawFd for Connection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.conn.as_raw_fd()
+    }
+}
+
+/// Deregisters the connection from the tokio reactor and hands over its fd,
+/// which stays in non-blocking mode.
+///
+/// The connection no longer counts as open in [`Tailscale::metrics`] or its
+/// listener, and is not shut down by [`Tailscale::close`].
+impl From<Connection> for OwnedFd {
+    fn from(conn: Connection) -> OwnedFd {
+        let (fd, guards) = conn.into_parts();
+        drop(guards);
+        fd
+    }
+}
+
+/// Same as converting into an [`OwnedFd`] and releasing it.
+impl IntoRawFd for Connection {
+    fn into_raw_fd(self) -> RawFd {
+        OwnedFd::from(self).into_raw_fd()
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.check_sync_io();
+        let n = self.sync_io(PollFlags::POLLIN, |fd| nix::unistd::read(fd, &mut *buf))?;
+        self.record_read(n);
+        Ok(n)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.check_sync_io();
+        let n = self.sync_io(PollFlags::POLLIN, |fd| nix::sys::uio::readv(fd, &mut *bufs))?;
+        self.record_read(n);
+        Ok(n)
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.check_sync_io();
+        let n = self.sync_io(PollFlags::POLLOUT, |fd| nix::unistd::write(fd, buf))?;
+        self.record_write(n);
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.check_sync_io();
+        let n = self.sync_io(PollFlags::POLLOUT, |fd| nix::sys::uio::writev(fd, bufs))?;
+        self.record_write(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        loop {
+            let mut guard = match self.conn.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let fd = self.conn.get_ref().as_fd();
+
+            // Safety: We're reading into the unfilled portion of the buffer
+            // and will call assume_init and advance after a successful read
+            let unfilled = unsafe {
+                let slice = buf.unfilled_mut();
+                std::slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut u8, slice.len())
+            };
+
+            match nix::unistd::read(fd, unfilled) {
+                Ok(n) => {
+                    unsafe {
+                        buf.assume_init(n);
+                    }
+                    buf.advance(n);
+                    self.record_read(n);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(nix::errno::Errno::EWOULDBLOCK) => {
+                    guard.clear_ready();
+                    continue;
+                }
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(e) => {
+                    return Poll::Ready(Err(std::io::Error::from_raw_os_error(e as i32)));
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        loop {
             let mut guard = match self.conn.poll_write_ready(cx) {
                 Poll::Ready(Ok(guard)) => guard,
                 Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
@@ -458,12 +2160,45 @@ impl AsyncWrite for Connection {
 
             match nix::unistd::write(fd, buf) {
                 Ok(n) => {
+                    self.record_write(n);
+                    return Poll::Ready(Ok(n));
+                }
+                Err(nix::errno::Errno::EWOULDBLOCK) => {
+                    guard.clear_ready();
+                    continue;
+                }
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(e) => {
+                    return Poll::Ready(Err(std::io::Error::from_raw_os_error(e as i32)));
+                }
+            }
+        }
+    }
+
+    fn poll_write_vectored(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        loop {
+            let mut guard = match self.conn.poll_write_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let fd = self.conn.get_ref().as_fd();
+
+            match nix::sys::uio::writev(fd, bufs) {
+                Ok(n) => {
+                    self.record_write(n);
                     return Poll::Ready(Ok(n));
                 }
                 Err(nix::errno::Errno::EWOULDBLOCK) => {
                     guard.clear_ready();
                     continue;
                 }
+                Err(nix::errno::Errno::EINTR) => continue,
                 Err(e) => {
                     return Poll::Ready(Err(std::io::Error::from_raw_os_error(e as i32)));
                 }
@@ -471,6 +2206,10 @@ impl AsyncWrite for Connection {
         }
     }
 
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
     fn poll_flush(
         self: std::pin::Pin<&mut Self>,
         _cx: &mut std::task::Context<'_>,
@@ -478,273 +2217,2251 @@ impl AsyncWrite for Connection {
         Poll::Ready(Ok(()))
     }
 
-    fn poll_shutdown(
-        self: std::pin::Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<std::io::Result<()>> {
-        // For TCP connections, shutdown is typically a no-op or uses the Drop impl
-        Poll::Ready(Ok(()))
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        // For TCP connections, shutdown is typically a no-op or uses the Drop impl
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Listener {
+    /// Returns the tailnet address (IP and port) this listener accepts
+    /// connections on.
+    ///
+    /// After listening on port 0, this is how to find the port that was
+    /// bound. Listeners on all addresses report the node's IPv4 address, or
+    /// its IPv6 address if it has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address cannot be retrieved or parsed.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        let ln_fd = self.ln.as_raw_fd();
+        let buf = [0u8; 128];
+        let ret = unsafe { tailscale_getlisteneraddr(ln_fd, buf.as_ptr() as *mut _, buf.len()) };
+
+        if ret != 0 {
+            return Err(code_error(ret).unwrap_or_else(|| {
+                TailscaleError::Tailscale(format!(
+                    "Failed to retrieve listener address (error code: {})",
+                    ret
+                ))
+            }));
+        }
+
+        parse_socket_addr(&buf)
+    }
+
+    /// Accepts a new incoming connection on this listener.
+    ///
+    /// If a [filter](Self::with_filter) or an
+    /// [`on_incoming`](TailscaleBuilder::on_incoming) callback is configured,
+    /// connections they deny are closed and accepting continues with the
+    /// next one. The `on_incoming` callback is not run for listeners created
+    /// by [`Tailscale::listen_funnel`].
+    ///
+    /// With a [connection limit](Self::limit_connections), waits for an open
+    /// connection to be dropped before accepting another.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting the connection fails, or
+    /// [`TailscaleError::AcceptFailed`] once the listener has been
+    /// [shut down](Self::shutdown).
+    pub async fn accept(self: &Arc<Self>) -> Result<Connection> {
+        let shut_down = || TailscaleError::AcceptFailed("listener has been shut down".to_string());
+        if self.closed.is_cancelled() {
+            return Err(shut_down());
+        }
+        tokio::select! {
+            conn = self.accept_tracked() => conn,
+            () = self.closed.cancelled() => Err(shut_down()),
+        }
+    }
+
+    /// Stops accepting connections and waits up to `grace` for the
+    /// connections accepted from this listener to be dropped, then shuts
+    /// down any that remain so their pending reads and writes fail.
+    ///
+    /// Pending and future [`accept`](Self::accept) calls fail right away.
+    /// The listener stays bound during the grace period, so open connections
+    /// can still look up their [remote address](Connection::remote_addr),
+    /// and is then closed on the tailnet. Returns the number of connections
+    /// that had to be shut down.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # use std::time::Duration;
+    /// # async fn example(listener: std::sync::Arc<Listener>) {
+    /// // On SIGTERM: let in-flight requests finish, for up to 30 seconds.
+    /// let forced = listener.shutdown(Duration::from_secs(30)).await;
+    /// # }
+    /// ```
+    pub async fn shutdown(&self, grace: Duration) -> usize {
+        self.closed.cancel();
+        debug!(?grace, "shutting down listener");
+        let forced =
+            match tokio::time::timeout(grace, self.connections.wait_empty(ACCEPTED_GROUP)).await {
+                Ok(()) => 0,
+                Err(_) => self
+                    .connections
+                    .shutdown_now(ACCEPTED_GROUP, nix::sys::socket::Shutdown::Both),
+            };
+        if forced > 0 {
+            debug!(forced, "shut down remaining connections");
+        }
+        // libtailscale closes its side of the listener once ours is shut down.
+        if let Err(e) =
+            nix::sys::socket::shutdown(self.ln.as_raw_fd(), nix::sys::socket::Shutdown::Both)
+        {
+            warn!(error = %e, "error shutting down listener");
+        }
+        forced
+    }
+
+    /// Returns true once [`shutdown`](Self::shutdown) has been called.
+    pub(crate) fn is_shut_down(&self) -> bool {
+        self.closed.is_cancelled()
+    }
+
+    async fn accept_tracked(self: &Arc<Self>) -> Result<Connection> {
+        let limit = self.limit.lock().unwrap().clone();
+        let permit = match limit {
+            Some(limit) => Some(
+                limit
+                    .acquire_owned()
+                    .await
+                    .expect("connection limit semaphore is never closed"),
+            ),
+            None => None,
+        };
+        let mut conn = self.accept_filtered().await?;
+        self._tailscale.metrics.record_accept();
+        conn.slot = Some(ListenerSlot {
+            _membership: self.connections.join(ACCEPTED_GROUP, conn.conn.as_raw_fd()),
+            _permit: permit,
+        });
+        Ok(conn)
+    }
+
+    /// Limits the connections accepted from this listener that may be open
+    /// at once to `max`, replacing any previous limit.
+    ///
+    /// Once `max` accepted connections are open, [`accept`](Self::accept)
+    /// waits for one of them to be dropped instead of taking more off the
+    /// listener, so excess peers queue up in the listener's backlog rather
+    /// than each using a file descriptor. Connections accepted before the
+    /// limit was set do not count towards it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// let listener = ts
+    ///     .listener(NetworkType::Tcp, ":8080")
+    ///     .await?
+    ///     .limit_connections(1024);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn limit_connections(self: Arc<Self>, max: usize) -> Arc<Self> {
+        *self.limit.lock().unwrap() = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    async fn accept_filtered(self: &Arc<Self>) -> Result<Connection> {
+        loop {
+            let conn = self.accept_connection().await?;
+            let filter = self.filter.lock().unwrap().clone();
+            let on_incoming = match &self._tailscale.on_incoming {
+                Some(on_incoming) if !self.funnel => Some(on_incoming),
+                _ => None,
+            };
+            if filter.is_none() && on_incoming.is_none() {
+                return Ok(conn.with_span(None));
+            }
+
+            let addr = match conn.remote_addr() {
+                Ok(addr) => addr,
+                Err(e) => {
+                    warn!(error = %e, "could not identify peer, closing connection");
+                    continue;
+                }
+            };
+            let identity = match self._tailscale.whois(addr).await {
+                Ok(peer) => Some(peer),
+                // Funnel connections from the public internet have no
+                // tailnet identity.
+                Err(_) if self.funnel => None,
+                Err(e) => {
+                    warn!(error = %e, "could not identify peer, closing connection");
+                    continue;
+                }
+            };
+
+            if let Some(filter) = filter {
+                let info = PeerInfo {
+                    addr,
+                    identity: identity.clone(),
+                };
+                if filter(&info) == Decision::Deny {
+                    debug!(%addr, "incoming connection filtered");
+                    continue;
+                }
+            }
+            let login_name = identity.as_ref().map(|peer| peer.login_name.clone());
+            if let (Some(on_incoming), Some(peer)) = (on_incoming, identity)
+                && on_incoming(peer).await == Decision::Deny
+            {
+                debug!(%addr, "incoming connection denied");
+                continue;
+            }
+            return Ok(conn.with_span(login_name.as_deref()));
+        }
+    }
+
+    /// Sets a filter that decides, before [`accept`](Self::accept) returns
+    /// them, whether connections are handed to the application or closed,
+    /// replacing any previous filter.
+    ///
+    /// The filter runs after the peer has been identified with
+    /// [`Tailscale::whois`] and before any
+    /// [`on_incoming`](TailscaleBuilder::on_incoming) callback. On Funnel
+    /// listeners it also sees connections from the public internet, whose
+    /// [`PeerInfo::identity`] is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// // Only accept connections from tagged servers.
+    /// let listener = ts
+    ///     .listener(NetworkType::Tcp, ":8080")
+    ///     .await?
+    ///     .with_filter(|peer| {
+    ///         if peer.tags().iter().any(|t| t == "tag:server") {
+    ///             Decision::Allow
+    ///         } else {
+    ///             Decision::Deny
+    ///         }
+    ///     });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_filter<F>(self: Arc<Self>, filter: F) -> Arc<Self>
+    where
+        F: Fn(&PeerInfo) -> Decision + Send + Sync + 'static,
+    {
+        *self.filter.lock().unwrap() = Some(Arc::new(filter));
+        self
+    }
+
+    /// Accepts a new incoming connection, giving up after `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::Timeout`] if no connection arrived in time, or
+    /// an error if accepting the connection fails.
+    pub async fn accept_timeout(self: &Arc<Self>, timeout: Duration) -> Result<Connection> {
+        tokio::time::timeout(timeout, self.accept())
+            .await
+            .map_err(|_| TailscaleError::Timeout)?
+    }
+
+    /// Returns a [`Stream`](futures_core::Stream) of accepted connections, for
+    /// servers that consume a generic incoming stream.
+    pub fn incoming(self: &Arc<Self>) -> Incoming {
+        Incoming::new(Arc::clone(self))
+    }
+
+    async fn accept_connection(self: &Arc<Self>) -> Result<Connection> {
+        self._tailscale.ensure_open("listener")?;
+        debug!(fd = self.ln.as_raw_fd(), "waiting to accept connection");
+
+        // The listener is one half of a socketpair that libtailscale sends
+        // connection fds over, so wait for it to become readable rather than
+        // blocking a thread in tailscale_accept. This also makes accept()
+        // cancel-safe: no connection is taken off the socket until it is ready.
+        let (out_fd, start) = loop {
+            let mut guard = self
+                .ln
+                .readable()
+                .await
+                .map_err(|e| TailscaleError::AcceptFailed(e.to_string()))?;
+            let start = Instant::now();
+
+            let res = guard.try_io(|ln| {
+                let mut out_fd = 0;
+                let ret = unsafe { tailscale_accept(ln.as_raw_fd(), &mut out_fd) };
+                if ret != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(out_fd)
+            });
+
+            match res {
+                Ok(Ok(out_fd)) => break (out_fd, start),
+                Ok(Err(e)) => {
+                    self._tailscale.metrics.record_accept_error();
+                    return Err(TailscaleError::AcceptFailed(e.to_string()));
+                }
+                Err(_would_block) => continue,
+            }
+        };
+        debug!(fd = out_fd, "accepted connection");
+
+        let owned_fd = unsafe { OwnedFd::from_raw_fd(out_fd) };
+        let conn = Connection::from_owned_fd(owned_fd, Some(Arc::clone(self)), &self._tailscale)?;
+        self._tailscale.metrics.record_accept_time(start.elapsed());
+        #[cfg(feature = "otel")]
+        {
+            let parent = self
+                ._tailscale
+                .metrics
+                .otel
+                .accepted(start, conn.otel_addrs());
+            conn.metrics
+                .start_span(&parent, SpanKind::Server, conn.otel_addrs());
+        }
+        Ok(conn)
+    }
+}
+
+/// A pair of IPv4 and IPv6 addresses assigned to a Tailscale node.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IpPair {
+    pub ipv4: Ipv4Addr,
+    pub ipv6: Ipv6Addr,
+}
+
+/// The state of a Tailscale node's IPN backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum BackendState {
+    /// The backend has not been initialized yet.
+    NoState,
+    /// The backend is in use by another user.
+    InUseOtherUser,
+    /// The node must be authenticated before it can join the tailnet.
+    NeedsLogin,
+    /// The node is waiting for an administrator to approve it.
+    NeedsMachineAuth,
+    /// The backend has been stopped.
+    Stopped,
+    /// The backend is connecting to the tailnet.
+    Starting,
+    /// The node is connected and usable.
+    Running,
+}
+
+impl BackendState {
+    /// Converts the numeric `ipn.State` reported by libtailscale.
+    fn from_raw(state: libc::c_int) -> Option<Self> {
+        let state = match state {
+            0 => BackendState::NoState,
+            1 => BackendState::InUseOtherUser,
+            2 => BackendState::NeedsLogin,
+            3 => BackendState::NeedsMachineAuth,
+            4 => BackendState::Stopped,
+            5 => BackendState::Starting,
+            6 => BackendState::Running,
+            _ => return None,
+        };
+        Some(state)
+    }
+
+    /// Returns the name of the state, as used by Tailscale.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendState::NoState => "NoState",
+            BackendState::InUseOtherUser => "InUseOtherUser",
+            BackendState::NeedsLogin => "NeedsLogin",
+            BackendState::NeedsMachineAuth => "NeedsMachineAuth",
+            BackendState::Stopped => "Stopped",
+            BackendState::Starting => "Starting",
+            BackendState::Running => "Running",
+        }
+    }
+
+    /// Returns true if the node is connected and usable.
+    pub fn is_running(&self) -> bool {
+        *self == BackendState::Running
+    }
+
+    /// Returns true if the node cannot make progress without user or
+    /// administrator action, i.e. it needs to log in or be approved.
+    pub fn needs_action(&self) -> bool {
+        matches!(
+            self,
+            BackendState::NeedsLogin | BackendState::NeedsMachineAuth
+        )
+    }
+}
+
+impl std::fmt::Display for BackendState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A lightweight snapshot of a node's state, as returned by [`Tailscale::quick_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuickState {
+    pub state: BackendState,
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+/// A Tailscale networking instance.
+///
+/// This struct represents an active Tailscale node and provides methods
+/// for creating listeners and managing the connection.
+pub struct Tailscale {
+    sd: libc::c_int,
+    ephemeral: bool,
+    closed: AtomicBool,
+    drain_groups: Arc<DrainGroups>,
+    tags: Arc<TagRegistry>,
+    on_incoming: Option<IncomingCallback>,
+    on_auth_url: Option<AuthUrlCallback>,
+    dial_family: AddressFamily,
+    up_retry: Option<RetryPolicy>,
+    dial_retry: Option<RetryPolicy>,
+    /// Socket buffer sizes applied to every new connection.
+    buffer_sizes: BufferSizes,
+    strict: bool,
+    resumed: Notify,
+    resources: Arc<Resources>,
+    dial_queue: Arc<DialQueue>,
+    metrics: Arc<MetricsRegistry>,
+    /// Write end of the log pipe, handed to the shutdown thread with the
+    /// handle, as libtailscale logs to it until the close completes.
+    log_fd: Mutex<Option<OwnedFd>>,
+    _state_store: Option<Arc<RegisteredStore>>,
+}
+
+impl Tailscale {
+    /// Creates a new builder for configuring a Tailscale instance.
+    pub fn builder() -> TailscaleBuilder {
+        TailscaleBuilder::default()
+    }
+
+    /// Returns the libtailscale handle of this instance, for use with the raw
+    /// functions in [`crate::sys`].
+    ///
+    /// The handle stays owned by this instance: it must not be closed, and it
+    /// is only valid while the instance is alive.
+    #[cfg(feature = "unsafe-sys")]
+    pub fn raw_handle(&self) -> libc::c_int {
+        self.sd
+    }
+
+    /// Runs startup diagnostics without bringing the node up.
+    ///
+    /// Checks control plane and DERP reachability, UDP socket availability,
+    /// state directory writability and clock skew against the control plane,
+    /// so misconfigured deployments can fail fast instead of hanging in
+    /// [`up`](Self::up). Call it before `up()`: once the node is running its
+    /// own UDP socket may make the UDP check fail.
+    ///
+    /// Failed checks are reported in the returned [`PreflightReport`]; use
+    /// [`PreflightReport::ensure_ok`] to turn them into an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the diagnostics could not be run at all.
+    pub async fn preflight(&self) -> Result<PreflightReport> {
+        self.ensure_open("instance")?;
+        debug!("running preflight checks");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let res = tokio::task::spawn_blocking(move || {
+            call_with_buffer(|buf, len| unsafe { tailscale_preflight(sd, buf, len) })
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        let json = self.buffer_result(res)?;
+        let report: PreflightReport = serde_json::from_str(&json)?;
+        debug!(ok = report.is_ok(), "preflight checks complete");
+        Ok(report)
+    }
+
+    /// Returns a snapshot of the node's health.
+    ///
+    /// The report covers the control plane connection, the home DERP relay,
+    /// key expiry and the current health warnings. Use [`Health::is_live`]
+    /// and [`Health::is_ready`] to answer liveness and readiness probes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status cannot be read from the node.
+    pub async fn health(&self) -> Result<Health> {
+        self.ensure_open("instance")?;
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let res = tokio::task::spawn_blocking(move || {
+            call_with_buffer(|buf, len| unsafe { tailscale_health(sd, buf, len) })
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        let json = self.buffer_result(res)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Returns when this node's key expires, or `None` if key expiry is
+    /// disabled for it.
+    ///
+    /// Once the key expires the node drops off the tailnet until it is
+    /// re-authenticated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status cannot be read from the node.
+    pub async fn key_expiry(&self) -> Result<Option<SystemTime>> {
+        Ok(self.health().await?.key_expiry)
+    }
+
+    /// Waits until this node's key expires within `warn_before`, and returns
+    /// the expiry time, so a long-running service can alert an operator or
+    /// re-authenticate in time.
+    ///
+    /// Returns right away if the key already expires within `warn_before` or
+    /// has expired. While key expiry is disabled it keeps waiting. For a fixed
+    /// one day warning as an event, see [`IpnEvent::KeyExpiring`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status cannot be read from the node.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::{sync::Arc, time::Duration};
+    /// # use tailscale2::*;
+    /// # async fn example(ts: Arc<Tailscale>) -> Result<()> {
+    /// tokio::spawn(async move {
+    ///     let week = Duration::from_secs(7 * 24 * 60 * 60);
+    ///     if let Ok(expiry) = ts.key_expiring(week).await {
+    ///         eprintln!("node key expires at {expiry:?}, re-authenticate the node");
+    ///     }
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn key_expiring(&self, warn_before: Duration) -> Result<SystemTime> {
+        loop {
+            let recheck = match self.key_expiry().await? {
+                Some(expiry) => match expiry.duration_since(SystemTime::now()) {
+                    Ok(left) if left > warn_before => (left - warn_before).min(KEY_EXPIRY_RECHECK),
+                    _ => {
+                        debug!(?expiry, "node key expiring");
+                        return Ok(expiry);
+                    }
+                },
+                None => KEY_EXPIRY_RECHECK,
+            };
+            tokio::time::sleep(recheck).await;
+        }
+    }
+
+    /// Pings `peer`, named by its Tailscale IP address, MagicDNS name (full or
+    /// short) or stable node ID, waiting up to 10 seconds for a reply.
+    ///
+    /// The result holds the round-trip time and whether the ping went
+    /// directly or through a DERP relay. A [`PingKind::Disco`] ping also
+    /// prompts Tailscale to establish a direct path if it can.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the peer is unknown or does not reply in time.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// let pong = ts.ping("db-server", PingKind::Tsmp).await?;
+    /// println!("{} in {:?} via {:?}", pong.node_name, pong.latency, pong.path);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn ping(&self, peer: &str, kind: PingKind) -> Result<PingResult> {
+        self.ping_timeout(peer, kind, PING_TIMEOUT).await
+    }
+
+    /// Like [`ping`](Self::ping), but waits up to `timeout` for a reply.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the peer is unknown or does not reply in time.
+    pub async fn ping_timeout(
+        &self,
+        peer: &str,
+        kind: PingKind,
+        timeout: Duration,
+    ) -> Result<PingResult> {
+        self.ensure_open("instance")?;
+        debug!(peer, ?kind, "pinging peer");
+        let c_peer = CString::new(peer)?;
+        let c_kind = CString::new(kind.as_str())?;
+        let timeout_ms = libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX);
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let res = tokio::task::spawn_blocking(move || {
+            call_with_buffer(|buf, len| unsafe {
+                tailscale_ping(sd, c_peer.as_ptr(), c_kind.as_ptr(), timeout_ms, buf, len)
+            })
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        let json = self.buffer_result(res)?;
+        let result: PingResult = serde_json::from_str(&json)?;
+        debug!(latency = ?result.latency, path = ?result.path, "ping reply");
+        Ok(result)
+    }
+
+    /// Starts connecting the node to the tailnet without waiting for it to be
+    /// usable.
+    ///
+    /// This kicks off registration so that other startup work can overlap
+    /// with it; afterwards await [`up`](Self::up), poll [`state`](Self::state)
+    /// or watch [`events`](Self::events) to learn when the node is running.
+    /// Calling it is optional, as [`up`](Self::up), listening and dialing
+    /// start the node as well. Login URLs are not passed to the
+    /// [`on_auth_url`](TailscaleBuilder::on_auth_url) callback until `up` is
+    /// called.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node cannot be started, e.g. because its state
+    /// directory is unusable.
+    pub async fn start(&self) -> Result<()> {
+        self.ensure_open("instance")?;
+        debug!("starting Tailscale");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let ret = tokio::task::spawn_blocking(move || unsafe { tailscale_start(sd) })
+            .await
+            .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::StartFailed));
+        }
+        debug!("Tailscale started");
+        Ok(())
+    }
+
+    /// Brings up the Tailscale connection.
+    ///
+    /// This must be called before the Tailscale instance can be used for networking.
+    /// If the node needs interactive login, the login URL is passed to the
+    /// [`on_auth_url`](TailscaleBuilder::on_auth_url) callback while waiting.
+    /// Failed attempts are retried according to
+    /// [`TailscaleBuilder::up_retry`], if set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if bringing up the connection fails.
+    pub async fn up(&self) -> Result<()> {
+        self.ensure_open("instance")?;
+        #[cfg(feature = "otel")]
+        let op = self.metrics.otel.start_up();
+        let res = match &self.up_retry {
+            Some(policy) => policy.run("up", || self.up_once()).await,
+            None => self.up_once().await,
+        };
+        #[cfg(feature = "otel")]
+        op.finish(&res, |_| Addrs::default());
+        res
+    }
+
+    /// Makes a single attempt at bringing up the connection.
+    async fn up_once(&self) -> Result<()> {
+        debug!("bringing up Tailscale connection");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let up = tokio::task::spawn_blocking(move || unsafe { tailscale_up(sd) });
+        let ret = match &self.on_auth_url {
+            Some(callback) => {
+                let events = self.events().await?;
+                tokio::select! {
+                    res = up => res,
+                    never = forward_auth_urls(events, Arc::clone(callback)) => match never {},
+                }
+            }
+            None => up.await,
+        }
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::UpFailed));
+        }
+        debug!("Tailscale connection is up");
+        Ok(())
+    }
+
+    /// Brings up the Tailscale connection like [`up`](Self::up), reporting
+    /// each stage to `on_progress` as it is reached.
+    ///
+    /// Stages are read from the IPN bus, so a node that is already running
+    /// reports [`UpProgress::Running`] right away. Consecutive duplicates are
+    /// not reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if bringing up the connection fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// ts.up_with_progress(|progress| match progress {
+    ///     UpProgress::WaitingForLogin { auth_url: Some(url) } => eprintln!("log in at {url}"),
+    ///     progress => eprintln!("{progress:?}"),
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn up_with_progress<F>(&self, mut on_progress: F) -> Result<()>
+    where
+        F: FnMut(UpProgress),
+    {
+        let mut last = UpProgress::Connecting;
+        on_progress(last.clone());
+        let mut events = self.events().await?;
+        let mut events_done = false;
+
+        let up = self.up();
+        tokio::pin!(up);
+        loop {
+            tokio::select! {
+                res = &mut up => {
+                    if res.is_ok() && last != UpProgress::Running {
+                        on_progress(UpProgress::Running);
+                    }
+                    return res;
+                }
+                event = events.next(), if !events_done => match event {
+                    Some(Ok(event)) => {
+                        if let Some(progress) = UpProgress::from_event(&event)
+                            && progress != last
+                        {
+                            last = progress;
+                            on_progress(last.clone());
+                        }
+                    }
+                    Some(Err(e)) => warn!(error = %e, "could not read progress"),
+                    None => events_done = true,
+                },
+            }
+        }
+    }
+
+    /// Brings up the Tailscale connection, giving up after `timeout`.
+    ///
+    /// An in-progress `up` cannot be interrupted without shutting the instance
+    /// down, so on timeout the instance is closed and can no longer be used.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::Timeout`] if the connection did not come up in
+    /// time, or an error if bringing up the connection fails.
+    pub async fn up_with_timeout(&self, timeout: Duration) -> Result<()> {
+        match tokio::time::timeout(timeout, self.up()).await {
+            Ok(res) => res,
+            Err(_) => {
+                debug!(?timeout, "timed out bringing up Tailscale connection");
+                self.close_handle();
+                Err(TailscaleError::Timeout)
+            }
+        }
+    }
+
+    /// Brings up the Tailscale connection unless `token` is cancelled first.
+    ///
+    /// An in-progress `up` cannot be interrupted without shutting the instance
+    /// down, so on cancellation the instance is closed and can no longer be used.
+    ///
+    /// [`connect`](Self::connect) and [`Listener::accept`] need no such variant:
+    /// they are cancel-safe and can be raced against a token with `tokio::select!`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::Cancelled`] if `token` was cancelled, or an
+    /// error if bringing up the connection fails.
+    pub async fn up_with_cancel(&self, token: CancellationToken) -> Result<()> {
+        tokio::select! {
+            res = self.up() => res,
+            _ = token.cancelled() => {
+                debug!("cancelled bringing up Tailscale connection");
+                self.close_handle();
+                Err(TailscaleError::Cancelled)
+            }
+        }
+    }
+
+    /// Creates a new listener on the Tailscale network.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - The network type (e.g., `NetworkType::Tcp`)
+    /// * `addr` - The address to listen on (e.g., `":8080"` or `8080`), see
+    ///   [`ToListenAddr`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if `addr` is invalid, or
+    /// an error if creating the listener fails.
+    pub async fn listener(
+        self: &Arc<Tailscale>,
+        network: NetworkType,
+        addr: impl ToListenAddr,
+    ) -> Result<Arc<Listener>> {
+        self.ensure_open("instance")?;
+        let addr = addr.to_listen_addr()?.to_string();
+        debug!(%network, %addr, "creating listener");
+        let network_cstr = network.as_c_str();
+        let addr_cstring =
+            std::ffi::CString::new(addr.as_str()).map_err(TailscaleError::Utf8Error)?;
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let (listener, ret) = tokio::task::spawn_blocking(move || {
+            let mut listener = 0;
+            let ret = unsafe {
+                tailscale_listen(
+                    sd,
+                    network_cstr.as_ptr(),
+                    addr_cstring.as_ptr(),
+                    &mut listener,
+                )
+            };
+            (listener, ret)
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, |message| TailscaleError::ListenFailed {
+                network: network.to_string(),
+                addr: addr.to_string(),
+                message,
+            }));
+        }
+        debug!(fd = listener, "listener created");
+
+        self.wrap_listener(listener, false)
+    }
+
+    /// Creates a listener whose service is only started when a connection
+    /// arrives, enabling scale-to-zero services inside a single binary.
+    ///
+    /// On the first connection `factory` is awaited to start the service,
+    /// which is then called with every accepted connection. Once no
+    /// connection has been open for `idle_timeout`, the service is dropped,
+    /// releasing whatever it holds, and the next connection starts it again.
+    /// If starting the service fails, the connection that triggered it is
+    /// closed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the listener cannot be created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// let service = ts
+    ///     .listen_on_demand(NetworkType::Tcp, ":8080", Duration::from_secs(300), || async {
+    ///         // e.g. open a database pool here
+    ///         Ok::<_, std::io::Error>(|mut conn: Connection| async move {
+    ///             let _ = tokio::io::AsyncWriteExt::write_all(&mut conn, b"hello\n").await;
+    ///         })
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn listen_on_demand<F, Fut, S, E, H>(
+        self: &Arc<Tailscale>,
+        network: NetworkType,
+        addr: impl ToListenAddr,
+        idle_timeout: Duration,
+        factory: F,
+    ) -> Result<OnDemand>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = std::result::Result<S, E>> + Send + 'static,
+        S: Fn(Connection) -> H + Send + Sync + 'static,
+        H: Future<Output = ()> + Send + 'static,
+        E: std::fmt::Display + 'static,
+    {
+        let listener = self.listener(network, addr).await?;
+        Ok(OnDemand::spawn(listener, idle_timeout, factory))
+    }
+
+    /// Forwards TCP connections accepted on the tailnet at `addr` to `target`
+    /// on the local network.
+    ///
+    /// Each connection is proxied to a fresh connection to `target` with
+    /// [`io::copy_bidirectional`](crate::io::copy_bidirectional).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// // Expose a local HTTPS server on port 443 of this node.
+    /// let forwarder = ts.forward_inbound(":443", "127.0.0.1:8443").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tailnet listener cannot be created.
+    pub async fn forward_inbound(
+        self: &Arc<Tailscale>,
+        addr: &str,
+        target: &str,
+    ) -> Result<Forwarder> {
+        let listener = self.listener(NetworkType::Tcp, addr).await?;
+        debug!(%addr, %target, "forwarding inbound connections");
+        Ok(Forwarder::inbound(
+            listener,
+            Arc::clone(self),
+            target.to_string(),
+            None,
+        ))
+    }
+
+    /// Like [`forward_inbound`](Self::forward_inbound), but starts each
+    /// connection to `target` with a [PROXY protocol](crate::proxy_protocol)
+    /// header, so the backend sees the tailnet address of the peer rather
+    /// than a local one.
+    ///
+    /// Version 2 headers also carry the peer's Tailscale identity, which
+    /// costs a [`whois`](Self::whois) lookup per connection. The backend must
+    /// expect the header, e.g. nginx's `listen ... proxy_protocol`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// let forwarder = ts
+    ///     .forward_inbound_with_proxy_protocol(":443", "127.0.0.1:8443", proxy_protocol::Version::V2)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tailnet listener cannot be created.
+    pub async fn forward_inbound_with_proxy_protocol(
+        self: &Arc<Tailscale>,
+        addr: &str,
+        target: &str,
+        version: proxy_protocol::Version,
+    ) -> Result<Forwarder> {
+        let listener = self.listener(NetworkType::Tcp, addr).await?;
+        debug!(%addr, %target, ?version, "forwarding inbound connections with PROXY protocol");
+        Ok(Forwarder::inbound(
+            listener,
+            Arc::clone(self),
+            target.to_string(),
+            Some(version),
+        ))
+    }
+
+    /// Forwards TCP connections accepted on the local address `local_addr`
+    /// to `target` on the tailnet.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// // Reach a tailnet database as if it were running locally.
+    /// let forwarder = ts.forward_outbound("127.0.0.1:5432", "db-host:5432").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::ListenFailed`] if `local_addr` cannot be
+    /// bound.
+    pub async fn forward_outbound(
+        self: &Arc<Tailscale>,
+        local_addr: &str,
+        target: &str,
+    ) -> Result<Forwarder> {
+        let listener = tokio::net::TcpListener::bind(local_addr)
+            .await
+            .map_err(|e| TailscaleError::ListenFailed {
+                network: NetworkType::Tcp.to_string(),
+                addr: local_addr.to_string(),
+                message: e.to_string(),
+            })?;
+        debug!(%local_addr, %target, "forwarding outbound connections");
+        Ok(Forwarder::outbound(
+            listener,
+            Arc::clone(self),
+            target.to_string(),
+        ))
+    }
+
+    /// Forwards UDP datagrams received on the tailnet at `addr` to `target`
+    /// on the local network.
+    ///
+    /// Datagrams are received on a [`listen_packet`](Self::listen_packet)
+    /// socket, so their boundaries are preserved. Each tailnet peer address
+    /// gets its own local socket, so replies from `target` are routed back to
+    /// the peer that sent the request. A flow is dropped once no datagram has
+    /// passed in either direction for `idle_timeout`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # use std::time::Duration;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// // Serve a local DNS resolver to the tailnet.
+    /// let forwarder = ts
+    ///     .forward_udp_inbound(":53", "127.0.0.1:5353", Duration::from_secs(30))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tailnet socket cannot be opened, see
+    /// [`listen_packet`](Self::listen_packet).
+    pub async fn forward_udp_inbound(
+        self: &Arc<Tailscale>,
+        addr: &str,
+        target: &str,
+        idle_timeout: Duration,
+    ) -> Result<Forwarder> {
+        let socket = self.listen_packet(addr).await?;
+        debug!(%addr, %target, "forwarding inbound UDP");
+        Ok(Forwarder::udp_inbound(
+            socket,
+            target.to_string(),
+            idle_timeout,
+        ))
+    }
+
+    /// Forwards UDP datagrams received on the local address `local_addr` to
+    /// `target` on the tailnet.
+    ///
+    /// Each local source address gets its own tailnet socket on an
+    /// ephemeral port, NAT-style, so replies are sent back to the client they
+    /// belong to. Datagram boundaries are preserved. A flow is dropped once
+    /// no datagram has passed in either direction for `idle_timeout`.
+    ///
+    /// `target` is a tailnet IP address or the name of a node in the network
+    /// map, with a port, and is resolved once when the forwarder starts.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # use std::time::Duration;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// // Use a tailnet DNS server as if it were running locally.
+    /// let forwarder = ts
+    ///     .forward_udp_outbound("127.0.0.1:5353", "dns-host:53", Duration::from_secs(30))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::DialFailed`] if `target` cannot be resolved
+    /// or this node has no IP address of its family yet, and
+    /// [`TailscaleError::ListenFailed`] if `local_addr` cannot be bound.
+    pub async fn forward_udp_outbound(
+        self: &Arc<Tailscale>,
+        local_addr: &str,
+        target: &str,
+        idle_timeout: Duration,
+    ) -> Result<Forwarder> {
+        let (bind, target) = self.resolve_udp_target(target).await?;
+        let socket = tokio::net::UdpSocket::bind(local_addr).await.map_err(|e| {
+            TailscaleError::ListenFailed {
+                network: NetworkType::Udp.to_string(),
+                addr: local_addr.to_string(),
+                message: e.to_string(),
+            }
+        })?;
+        debug!(%local_addr, %target, "forwarding outbound UDP");
+        Ok(Forwarder::udp_outbound(
+            socket,
+            Arc::clone(self),
+            bind,
+            target,
+            idle_timeout,
+        ))
+    }
+
+    /// Resolves the tailnet `target` of a UDP forwarder, returning it along
+    /// with the address of this node of the same family to send from.
+    async fn resolve_udp_target(&self, target: &str) -> Result<(SocketAddr, SocketAddr)> {
+        let dial_failed = |message: &str| TailscaleError::DialFailed {
+            network: NetworkType::Udp.to_string(),
+            addr: target.to_string(),
+            message: message.to_string(),
+        };
+        let addr = target.to_dial_addr()?;
+        let ip = match addr.host().parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                let netmap = self.netmap().await?;
+                let node = netmap
+                    .find_node(addr.host())
+                    .ok_or_else(|| dial_failed("not found in the network map"))?;
+                let ip = node.addresses.iter().find(|ip| ip.is_ipv4());
+                *ip.or_else(|| node.addresses.first())
+                    .ok_or_else(|| dial_failed("node has no IP address"))?
+            }
+        };
+        let ips = self
+            .ips()?
+            .ok_or_else(|| dial_failed("no Tailscale IP address assigned yet"))?;
+        let own_ip = match ip {
+            IpAddr::V4(_) => IpAddr::V4(ips.ipv4),
+            IpAddr::V6(_) => IpAddr::V6(ips.ipv6),
+        };
+        Ok((SocketAddr::new(own_ip, 0), SocketAddr::new(ip, addr.port())))
+    }
+
+    /// Creates a TCP listener that accepts connections from the public
+    /// internet via Tailscale Funnel, as well as from the tailnet unless
+    /// `funnel_only` is set.
+    ///
+    /// TLS is terminated by Tailscale, so accepted connections carry the
+    /// decrypted stream. `addr` is of the form `":443"`; Funnel only supports
+    /// ports 443, 8443 and 10000, and must be allowed for the node by the
+    /// tailnet policy.
+    ///
+    /// The [`on_incoming`](TailscaleBuilder::on_incoming) callback is not run
+    /// for connections from this listener, since public clients have no
+    /// tailnet identity.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if creating the listener fails.
+    pub async fn listen_funnel(
+        self: &Arc<Tailscale>,
+        addr: impl ToListenAddr,
+        funnel_only: bool,
+    ) -> Result<Arc<Listener>> {
+        self.ensure_open("instance")?;
+        let addr = addr.to_listen_addr()?.to_string();
+        debug!(%addr, funnel_only, "creating funnel listener");
+        let addr_cstring = CString::new(addr.as_str()).map_err(TailscaleError::Utf8Error)?;
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let (listener, ret) = tokio::task::spawn_blocking(move || {
+            let mut listener = 0;
+            let ret = unsafe {
+                tailscale_listen_funnel(
+                    sd,
+                    addr_cstring.as_ptr(),
+                    funnel_only as libc::c_int,
+                    &mut listener,
+                )
+            };
+            (listener, ret)
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, |message| TailscaleError::ListenFailed {
+                network: "funnel".to_string(),
+                addr: addr.to_string(),
+                message,
+            }));
+        }
+        debug!(fd = listener, "funnel listener created");
+
+        self.wrap_listener(listener, true)
+    }
+
+    /// Opens a UDP socket on the tailnet, for protocols that exchange
+    /// datagrams with many peers from one port, such as QUIC.
+    ///
+    /// Unlike a [`listener`](Self::listener) for [`NetworkType::Udp`], which
+    /// yields a stream per peer, the socket sends to and receives from any
+    /// peer with [`UdpSocket::send_to`] and [`UdpSocket::recv_from`]. If
+    /// `addr` has no IP address, the socket is bound to this node's IPv4
+    /// address.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if `addr` is invalid, or
+    /// an error if the node has no IP address yet or the socket cannot be
+    /// opened.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// let socket = ts.listen_packet(9000).await?;
+    /// let mut buf = [0; 1500];
+    /// let (n, peer) = socket.recv_from(&mut buf).await?;
+    /// socket.send_to(&buf[..n], peer).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn listen_packet(self: &Arc<Self>, addr: impl ToListenAddr) -> Result<UdpSocket> {
+        self.ensure_open("instance")?;
+        let addr = addr.to_listen_addr()?;
+        let local_addr = match addr.ip() {
+            Some(ip) => SocketAddr::new(ip, addr.port()),
+            None => {
+                let ips = self.ips()?.ok_or_else(|| TailscaleError::ListenFailed {
+                    network: "udp".to_string(),
+                    addr: addr.to_string(),
+                    message: "no Tailscale IP address assigned yet".to_string(),
+                })?;
+                SocketAddr::new(ips.ipv4.into(), addr.port())
+            }
+        };
+        debug!(%local_addr, "opening packet socket");
+        let addr_cstring =
+            CString::new(local_addr.to_string()).map_err(TailscaleError::Utf8Error)?;
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let res = tokio::task::spawn_blocking(move || {
+            let mut fd = 0;
+            let ret = unsafe { tailscale_listen_packet(sd, addr_cstring.as_ptr(), &mut fd) };
+            if ret != 0 {
+                return Err(ret);
+            }
+            Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        let owned_fd = match res {
+            Ok(fd) => fd,
+            Err(ret) => {
+                return Err(self.ret_error(ret, |message| TailscaleError::ListenFailed {
+                    network: "udp".to_string(),
+                    addr: local_addr.to_string(),
+                    message,
+                }));
+            }
+        };
+        debug!(fd = owned_fd.as_raw_fd(), "packet socket opened");
+
+        set_nonblocking(&owned_fd)?;
+        let resource = self
+            .resources
+            .register(ResourceKind::Connection, owned_fd.as_raw_fd());
+        UdpSocket::new(owned_fd, local_addr, resource)
+            .map_err(|e| TailscaleError::Tailscale(format!("AsyncFd::new failed: {}", e)))
+    }
+
+    fn wrap_listener(self: &Arc<Self>, fd: libc::c_int, funnel: bool) -> Result<Arc<Listener>> {
+        let owned_fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        set_nonblocking(&owned_fd)?;
+        let ln = AsyncFd::new(owned_fd)
+            .map_err(|e| TailscaleError::Tailscale(format!("AsyncFd::new failed: {}", e)))?;
+        let resource = self.resources.register(ResourceKind::Listener, fd);
+
+        Ok(Arc::new(Listener {
+            _resource: resource,
+            ln,
+            funnel,
+            filter: Mutex::new(None),
+            limit: Mutex::new(None),
+            connections: Arc::default(),
+            closed: CancellationToken::new(),
+            _tailscale: Arc::clone(self),
+        }))
+    }
+
+    /// Creates an outbound connection to another node on the Tailscale network.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - The network type (e.g., `NetworkType::Tcp`)
+    /// * `addr` - The address to connect to (e.g., `"hostname:8080"` or
+    ///   `("hostname", 8080)`), see [`ToDialAddr`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if `addr` is invalid, or
+    /// an error if the connection cannot be established.
+    pub async fn connect(&self, network: NetworkType, addr: impl ToDialAddr) -> Result<Connection> {
+        self.connect_with_family(network, addr, self.dial_family)
+            .await
+    }
+
+    /// Creates an outbound connection using the given source address family.
+    ///
+    /// This overrides the instance-wide [`TailscaleBuilder::dial_family`] for a
+    /// single dial, e.g. for destination services that behave differently over
+    /// IPv4 and IPv6.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - The network type (e.g., `NetworkType::Tcp`)
+    /// * `addr` - The address to connect to (e.g., `"hostname:8080"`)
+    /// * `family` - The address family to dial with. Ignored when `network`
+    ///   already names a family, such as [`NetworkType::Tcp4`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection cannot be established, including when
+    /// the destination has no address of the requested family. Failed dials
+    /// are retried according to [`TailscaleBuilder::dial_retry`], if set.
+    pub async fn connect_with_family(
+        &self,
+        network: NetworkType,
+        addr: impl ToDialAddr,
+        family: AddressFamily,
+    ) -> Result<Connection> {
+        self.ensure_open("instance")?;
+        let addr = addr.to_dial_addr()?.to_string();
+        debug!(%network, %addr, ?family, "connecting");
+        #[cfg(feature = "otel")]
+        let op = self.metrics.otel.start_dial(network, &addr);
+        let network = network.as_c_str_with_family(family);
+        // Converted once and shared with every attempt's blocking task.
+        let addr: Arc<CStr> = CString::new(addr)
+            .map_err(TailscaleError::Utf8Error)?
+            .into();
+        let res = match &self.dial_retry {
+            Some(policy) => policy.run("dial", || self.dial_once(network, &addr)).await,
+            None => self.dial_once(network, &addr).await,
+        };
+        #[cfg(feature = "otel")]
+        {
+            let parent = op.finish(&res, Connection::otel_addrs);
+            if let Ok(conn) = &res {
+                conn.metrics
+                    .start_span(&parent, SpanKind::Client, conn.otel_addrs());
+            }
+        }
+        res
+    }
+
+    /// Creates an outbound connection by racing IPv6 and IPv4 dials, in the
+    /// style of RFC 8305 ("Happy Eyeballs").
+    ///
+    /// The IPv6 dial starts first. If it has not connected within 250ms, or
+    /// fails before then, an IPv4 dial starts alongside it, and whichever
+    /// connects first is returned while the other is abandoned. This avoids
+    /// hanging on a destination whose addresses of one family are broken.
+    ///
+    /// Addresses that are IP literals, and family-specific network types such
+    /// as [`NetworkType::Tcp4`], are dialed once as with
+    /// [`connect`](Self::connect).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if `addr` is invalid, or
+    /// the error of the last attempt to fail if neither family connects.
+    pub async fn connect_happy_eyeballs(
+        &self,
+        network: NetworkType,
+        addr: impl ToDialAddr,
+    ) -> Result<Connection> {
+        let addr = addr.to_dial_addr()?;
+        if !matches!(network, NetworkType::Tcp | NetworkType::Udp)
+            || addr.host().parse::<IpAddr>().is_ok()
+        {
+            return self.connect(network, addr).await;
+        }
+
+        let ipv6 = self.connect_with_family(network, &addr, AddressFamily::Ipv6);
+        tokio::pin!(ipv6);
+        let ipv6_failed = tokio::select! {
+            res = &mut ipv6 => match res {
+                Ok(conn) => return Ok(conn),
+                Err(e) => {
+                    debug!(%addr, error = %e, "IPv6 dial failed, falling back to IPv4");
+                    true
+                }
+            },
+            _ = tokio::time::sleep(HAPPY_EYEBALLS_DELAY) => false,
+        };
+
+        let ipv4 = self.connect_with_family(network, &addr, AddressFamily::Ipv4);
+        if ipv6_failed {
+            return ipv4.await;
+        }
+        debug!(%addr, "IPv6 dial is slow, racing IPv4");
+        tokio::pin!(ipv4);
+        tokio::select! {
+            res = &mut ipv6 => match res {
+                Ok(conn) => Ok(conn),
+                Err(_) => ipv4.await,
+            },
+            res = &mut ipv4 => match res {
+                Ok(conn) => Ok(conn),
+                Err(_) => ipv6.await,
+            },
+        }
+    }
+
+    /// Makes a single dial attempt.
+    async fn dial_once(&self, network: &'static CStr, addr: &Arc<CStr>) -> Result<Connection> {
+        let addr_cstr = Arc::clone(addr);
+        let sd = self.sd;
+        let permit = self.dial_queue.acquire().await;
+        let start = Instant::now();
+
+        // Use spawn_blocking for the blocking C call. The fd is owned inside the
+        // task so it is closed rather than leaked if this future is dropped
+        // before the dial completes. The permit moves into the task too, so the
+        // slot is held until the C call returns.
+        let res = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let mut conn_fd = 0;
+            let ret =
+                unsafe { tailscale_dial(sd, network.as_ptr(), addr_cstr.as_ptr(), &mut conn_fd) };
+            if ret != 0 {
+                return Err(ret);
+            }
+            Ok(unsafe { OwnedFd::from_raw_fd(conn_fd) })
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        let owned_fd = match res {
+            Ok(fd) => fd,
+            Err(ret) => {
+                self.metrics.record_dial_error();
+                return Err(self.ret_error(ret, |message| TailscaleError::DialFailed {
+                    network: c_str_to_str(network).to_string(),
+                    addr: addr.to_string_lossy().into_owned(),
+                    message,
+                }));
+            }
+        };
+        debug!(fd = owned_fd.as_raw_fd(), "connection established");
+        self.metrics.record_dial(start.elapsed());
+
+        Connection::from_owned_fd(owned_fd, None, self)
+    }
+
+    /// Returns a snapshot of the dial queue, including how long dials have
+    /// waited for a slot under [`TailscaleBuilder::max_concurrent_dials`].
+    pub fn dial_queue_stats(&self) -> DialQueueStats {
+        self.dial_queue.stats()
+    }
+
+    /// Returns a snapshot of the instance's connection metrics, see
+    /// [`Metrics`].
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.snapshot()
+    }
+
+    /// Returns the connection and byte counters of every tag set with
+    /// [`Connection::set_tag`] since the instance was built, keyed by tag.
+    pub fn tag_stats(&self) -> HashMap<String, TagStats> {
+        self.tags.stats()
+    }
+
+    /// Creates an outbound connection, giving up after `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::Timeout`] if the connection was not established
+    /// in time, or an error if the connection cannot be established.
+    pub async fn connect_timeout(
+        &self,
+        network: NetworkType,
+        addr: impl ToDialAddr,
+        timeout: Duration,
+    ) -> Result<Connection> {
+        tokio::time::timeout(timeout, self.connect(network, addr))
+            .await
+            .map_err(|_| TailscaleError::Timeout)?
+    }
+
+    /// Returns the IPv4 and IPv6 addresses assigned to this Tailscale node.
+    ///
+    /// Returns `None` if no IP addresses have been assigned yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if retrieving or parsing the IP addresses fails.
+    pub fn ips(&self) -> Result<Option<IpPair>> {
+        self.ensure_open("instance")?;
+        let buf = [0u8; 256];
+        let ret = unsafe { tailscale_getips(self.sd, buf.as_ptr() as *mut _, buf.len()) };
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
+        }
+        let s = CStr::from_bytes_until_nul(&buf[..])?;
+        let s = s.to_str()?;
+
+        if s.is_empty() {
+            return Ok(None);
+        }
+
+        let (ipv4, ipv6) = s
+            .split_once(',')
+            .ok_or_else(|| TailscaleError::InvalidIpAdresses(s.to_string()))?;
+
+        let ipv4 = Ipv4Addr::from_str(ipv4)
+            .map_err(|e| TailscaleError::AddrParseError(ipv4.to_string(), e))?;
+        let ipv6 = Ipv6Addr::from_str(ipv6)
+            .map_err(|e| TailscaleError::AddrParseError(ipv6.to_string(), e))?;
+
+        Ok(Some(IpPair { ipv4, ipv6 }))
+    }
+
+    /// Returns the current state of this node's backend.
+    ///
+    /// Unlike a successful [`up`](Self::up), this reflects the node's actual
+    /// status, e.g. `NeedsLogin` after the node key expired or `Stopped`
+    /// while suspended, so it is suitable for readiness checks and health
+    /// endpoints.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state cannot be retrieved.
+    pub fn state(&self) -> Result<BackendState> {
+        Ok(self.quick_state()?.state)
+    }
+
+    /// Returns the backend state and assigned IP addresses of this node.
+    ///
+    /// Unlike the richer status APIs this performs no string parsing or heap
+    /// allocation on success, making it suitable for tight polling loops on
+    /// low-power devices.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state cannot be retrieved.
+    pub fn quick_state(&self) -> Result<QuickState> {
+        self.ensure_open("instance")?;
+        let mut state = 0;
+        let mut ipv4 = [0u8; 4];
+        let mut ipv6 = [0u8; 16];
+        let ret = unsafe {
+            tailscale_quick_state(self.sd, &mut state, ipv4.as_mut_ptr(), ipv6.as_mut_ptr())
+        };
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
+        }
+
+        let state = BackendState::from_raw(state).ok_or_else(|| {
+            TailscaleError::Tailscale(format!("unknown backend state: {}", state))
+        })?;
+        let ipv4 = Some(Ipv4Addr::from(ipv4)).filter(|ip| !ip.is_unspecified());
+        let ipv6 = Some(Ipv6Addr::from(ipv6)).filter(|ip| !ip.is_unspecified());
+
+        Ok(QuickState { state, ipv4, ipv6 })
+    }
+
+    /// Removes this ephemeral node from the tailnet and confirms that it is
+    /// gone.
+    ///
+    /// The node is logged out with [`logout`](Self::logout), which for
+    /// ephemeral nodes makes the control plane delete the device. The call
+    /// then waits up to 10 seconds for the backend to confirm it: the state
+    /// must turn to [`BackendState::NeedsLogin`] with the node's Tailscale
+    /// addresses released, which only happens once its node key is no longer
+    /// valid. This lets short-lived jobs (e.g. CI) assert that they did not
+    /// leak device entries.
+    ///
+    /// It must be called before [`close`](Self::close), while the node can
+    /// still reach the control plane; a closed node is only removed by the
+    /// control plane once it has been offline for a while. The instance
+    /// cannot be used for networking afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::NotEphemeral`] if the node was not built as
+    /// ephemeral, or [`TailscaleError::DeregisterFailed`] if the logout was
+    /// not acknowledged or the backend did not confirm the removal in time.
+    pub async fn deregistered(&self) -> Result<()> {
+        if !self.ephemeral {
+            return Err(TailscaleError::NotEphemeral);
+        }
+        self.logout().await.map_err(|e| match e {
+            TailscaleError::LogoutFailed(message) => TailscaleError::DeregisterFailed(message),
+            e => e,
+        })?;
+
+        let deadline = Instant::now() + DEREGISTER_TIMEOUT;
+        loop {
+            let state = self.quick_state()?;
+            if state.state == BackendState::NeedsLogin
+                && state.ipv4.is_none()
+                && state.ipv6.is_none()
+            {
+                debug!("node deregistered");
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(TailscaleError::DeregisterFailed(format!(
+                    "node still {} after logging out",
+                    state.state
+                )));
+            }
+            tokio::time::sleep(DEREGISTER_POLL).await;
+        }
+    }
+
+    /// Logs the node out, tearing down its registration rather than just
+    /// closing the handle.
+    ///
+    /// The node key is expired and removed from the state directory, so
+    /// short-lived workers that are not ephemeral do not accumulate in the
+    /// admin console. The call waits for the control plane to acknowledge the
+    /// logout. The instance cannot be used for networking afterwards; a new
+    /// instance with the same state directory must log in again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::LogoutFailed`] if the control plane did not
+    /// acknowledge the logout.
+    pub async fn logout(&self) -> Result<()> {
+        self.ensure_open("instance")?;
+        debug!("logging out");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let ret = tokio::task::spawn_blocking(move || unsafe { tailscale_logout(sd) })
+            .await
+            .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::LogoutFailed));
+        }
+        debug!("logged out");
+        Ok(())
+    }
+
+    /// Suspends the node, stopping background activity such as control plane
+    /// polling and peer keepalives to save battery and bandwidth.
+    ///
+    /// The node stays logged in, so [`resume`](Self::resume) reconnects
+    /// without re-authenticating. Existing connections stall while suspended.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node could not be stopped.
+    pub async fn suspend(&self) -> Result<()> {
+        self.ensure_open("instance")?;
+        debug!("suspending");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let ret = tokio::task::spawn_blocking(move || unsafe { tailscale_suspend(sd) })
+            .await
+            .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
+        }
+        debug!("suspended");
+        Ok(())
+    }
+
+    /// Resumes a node stopped by [`suspend`](Self::suspend) and waits until
+    /// it is usable again.
+    ///
+    /// Tasks waiting in [`resumed`](Self::resumed) are woken once it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node could not be restarted.
+    pub async fn resume(&self) -> Result<()> {
+        self.ensure_open("instance")?;
+        debug!("resuming");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let ret = tokio::task::spawn_blocking(move || unsafe { tailscale_resume(sd) })
+            .await
+            .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
+        }
+        debug!("resumed");
+        self.resumed.notify_waiters();
+        Ok(())
+    }
+
+    /// Waits until the node next completes a [`resume`](Self::resume), e.g. to
+    /// re-establish application connections that stalled while suspended.
+    pub async fn resumed(&self) {
+        self.resumed.notified().await
+    }
+
+    /// Advertises subnet routes from this node, so peers can reach the local
+    /// network behind it, e.g. `["192.168.1.0/24"]`. This node then acts as
+    /// a subnet router, forwarding traffic for those prefixes from its host.
+    ///
+    /// Replaces any previously advertised routes; an empty list stops
+    /// advertising. The routes must also be approved in the admin console or
+    /// by `autoApprovers` in the tailnet policy before peers use them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::MalformedAddress`] if a route is not a CIDR
+    /// prefix, or an error if the node's preferences cannot be updated.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// ts.up().await?;
+    /// ts.advertise_routes(["192.168.1.0/24", "10.0.0.0/16"]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn advertise_routes<I, S>(&self, routes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.ensure_open("instance")?;
+        let routes = routes
+            .into_iter()
+            .map(|route| {
+                let route = route.as_ref();
+                is_valid_prefix(route)
+                    .then(|| route.to_string())
+                    .ok_or_else(|| TailscaleError::MalformedAddress {
+                        addr: route.to_string(),
+                        reason: "routes must be CIDR prefixes such as 192.168.1.0/24",
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        debug!(?routes, "advertising routes");
+        let c_routes = CString::new(routes.join(","))?;
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let ret = tokio::task::spawn_blocking(move || unsafe {
+            tailscale_set_advertise_routes(sd, c_routes.as_ptr())
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
+        }
+        Ok(())
+    }
+
+    /// Sets whether this node uses subnet routes advertised by other nodes, so
+    /// connections to addresses in those subnets go through their routers.
+    ///
+    /// Off by default, like `tailscale up` without `--accept-routes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node's preferences cannot be updated.
+    pub async fn set_accept_routes(&self, accept: bool) -> Result<()> {
+        self.ensure_open("instance")?;
+        debug!(accept, "setting accept routes");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let ret = tokio::task::spawn_blocking(move || unsafe {
+            tailscale_set_accept_routes(sd, accept as _)
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
+        }
+        Ok(())
+    }
+
+    /// Sets whether this node refuses all incoming connections from the
+    /// tailnet while still being able to dial out, like `tailscale up
+    /// --shields-up`. Suits client-only nodes such as CLI tools.
+    ///
+    /// Listeners can still be created but receive no connections while
+    /// shields are up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node's preferences cannot be updated.
+    pub async fn set_shields_up(&self, shields_up: bool) -> Result<()> {
+        self.ensure_open("instance")?;
+        debug!(shields_up, "setting shields up");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let ret = tokio::task::spawn_blocking(move || unsafe {
+            tailscale_set_shields_up(sd, shields_up as _)
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
+        }
+        Ok(())
+    }
+
+    /// Adds a connection to the drain group called `group`.
+    ///
+    /// A connection may belong to several groups and leaves all of them when it
+    /// is dropped. Adding a connection to a group that is currently being
+    /// drained shuts it down immediately.
+    pub fn add_to_drain_group(&self, group: &str, conn: &mut Connection) {
+        if conn.tag() == Some(group) || conn.drain_groups.iter().any(|m| m.name() == group) {
+            return;
+        }
+        let membership = self.drain_groups.join(group, conn.conn.as_raw_fd());
+        conn.drain_groups.push(membership);
+    }
+
+    /// Drains all connections in the drain group called `group`.
+    ///
+    /// The read half of every member is shut down, so handlers observe
+    /// end-of-stream and can finish writing their responses. The returned
+    /// future completes once every member has been dropped, and yields the
+    /// number of connections that were in the group.
+    pub async fn drain_group(&self, group: &str) -> usize {
+        debug!(group, "draining connection group");
+        self.drain_groups
+            .drain(group, nix::sys::socket::Shutdown::Read)
+            .await
     }
-}
 
-impl Listener {
-    /// Accepts a new incoming connection on this listener.
+    /// Closes all connections in the drain group called `group`.
+    ///
+    /// Unlike [`drain_group`](Self::drain_group) both halves of every member are
+    /// shut down, so pending reads and writes fail immediately. The returned
+    /// future completes once every member has been dropped, and yields the
+    /// number of connections that were in the group.
+    pub async fn close_group(&self, group: &str) -> usize {
+        debug!(group, "closing connection group");
+        self.drain_groups
+            .drain(group, nix::sys::socket::Shutdown::Both)
+            .await
+    }
+
+    /// Looks up the identity of the peer connecting from `addr`.
     ///
     /// # Errors
     ///
-    /// Returns an error if accepting the connection fails.
-    pub async fn accept(self: &Arc<Self>) -> Result<Connection> {
-        debug!(fd = self.ln, "waiting to accept connection");
-        let ln = self.ln;
-
-        // Use spawn_blocking to run the blocking C call
-        let (out_fd, ret) = tokio::task::spawn_blocking(move || {
-            let mut out_fd = 0;
-            let ret = unsafe { tailscale_accept(ln, &mut out_fd) };
-            (out_fd, ret)
+    /// Returns an error if `addr` does not belong to a known peer.
+    pub async fn whois(&self, addr: SocketAddr) -> Result<PeerIdentity> {
+        self.ensure_open("instance")?;
+        debug!(%addr, "looking up peer identity");
+        let addr_cstring = CString::new(addr.to_string())?;
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let res = tokio::task::spawn_blocking(move || {
+            call_with_buffer(|buf, len| unsafe {
+                tailscale_whois(sd, addr_cstring.as_ptr(), buf, len)
+            })
         })
         .await
         .map_err(TailscaleError::SpawnBlockingFailed)?;
 
-        if ret != 0 {
-            let error_message = self._tailscale.get_error_message()?;
-            return Err(TailscaleError::AcceptFailed(error_message));
-        }
-        debug!(fd = out_fd, "accepted connection");
+        let json = self.buffer_result(res)?;
+        let response: WhoIsResponse = serde_json::from_str(&json)?;
+        Ok(response.into_identity(addr))
+    }
 
-        // Set the fd to non-blocking mode
-        let borrowed_fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(out_fd) };
-        let flags = nix::fcntl::OFlag::from_bits_truncate(
-            nix::fcntl::fcntl(borrowed_fd, nix::fcntl::FcntlArg::F_GETFL)
-                .map_err(|e| TailscaleError::Tailscale(format!("F_GETFL failed: {}", e)))?,
-        );
-        nix::fcntl::fcntl(
-            borrowed_fd,
-            nix::fcntl::FcntlArg::F_SETFL(flags | nix::fcntl::OFlag::O_NONBLOCK),
-        )
-        .map_err(|e| TailscaleError::Tailscale(format!("F_SETFL failed: {}", e)))?;
+    /// Creates a TCP listener on `addr` that terminates TLS with this node's
+    /// certificate for its MagicDNS name.
+    ///
+    /// The certificate is fetched from Tailscale before the listener is
+    /// created and refreshed in the background while it is alive. HTTPS
+    /// certificates must be enabled for the tailnet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the certificate cannot be fetched or the listener
+    /// cannot be created.
+    #[cfg(feature = "tls")]
+    pub async fn listen_tls(self: &Arc<Self>, addr: impl ToListenAddr) -> Result<TlsListener> {
+        let pair = self.cert_pair("").await?;
+        let listener = self.listener(NetworkType::Tcp, addr).await?;
+        TlsListener::new(self, listener, &pair)
+    }
 
-        // Convert raw fd to OwnedFd and wrap in AsyncFd
-        let owned_fd = unsafe { OwnedFd::from_raw_fd(out_fd) };
-        let async_fd = AsyncFd::new(owned_fd)
-            .map_err(|e| TailscaleError::Tailscale(format!("AsyncFd::new failed: {}", e)))?;
+    /// Fetches the TLS certificate and private key for `domain`, one of this
+    /// node's DNS names such as `my-host.tailnet-name.ts.net`.
+    ///
+    /// An empty `domain` selects the node's MagicDNS name. Certificates are
+    /// cached and renewed by Tailscale, so this can be called again to pick up
+    /// a renewed certificate. HTTPS certificates must be enabled for the tailnet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no certificate could be issued for `domain`.
+    pub async fn cert_pair(&self, domain: &str) -> Result<CertPair> {
+        self.ensure_open("instance")?;
+        debug!(domain, "fetching certificate");
+        let domain_cstring = CString::new(domain)?;
+        let sd = self.sd;
 
-        let listener = Arc::clone(self);
+        // Use spawn_blocking for the blocking C call
+        let res = tokio::task::spawn_blocking(move || {
+            call_with_buffer(|buf, len| unsafe {
+                tailscale_cert_pair(sd, domain_cstring.as_ptr(), buf, len)
+            })
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
 
-        Ok(Connection {
-            conn: async_fd,
-            listener: Some(listener),
+        CertPair::from_combined_pem(self.buffer_result(res)?)
+    }
+
+    /// Returns this node's MagicDNS name, stable node ID, tailnet name and
+    /// assigned tags.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node has not joined a tailnet yet.
+    pub async fn self_info(&self) -> Result<SelfInfo> {
+        self.ensure_open("instance")?;
+        debug!("fetching self info");
+        let sd = self.sd;
+
+        // Use spawn_blocking for the blocking C call
+        let res = tokio::task::spawn_blocking(move || {
+            call_with_buffer(|buf, len| unsafe { tailscale_self_info(sd, buf, len) })
         })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        let json = self.buffer_result(res)?;
+        Ok(serde_json::from_str(&json)?)
     }
-}
 
-/// A pair of IPv4 and IPv6 addresses assigned to a Tailscale node.
-#[derive(Debug)]
-pub struct IpPair {
-    pub ipv4: Ipv4Addr,
-    pub ipv6: Ipv6Addr,
-}
+    /// Returns the peers visible to this node, ordered by MagicDNS name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status cannot be retrieved.
+    pub async fn peers(&self) -> Result<Vec<Peer>> {
+        self.ensure_open("instance")?;
+        debug!("listing peers");
+        let sd = self.sd;
 
-/// A Tailscale networking instance.
-///
-/// This struct represents an active Tailscale node and provides methods
-/// for creating listeners and managing the connection.
-pub struct Tailscale {
-    sd: libc::c_int,
-    _log_fd: Option<OwnedFd>,
-}
+        // Use spawn_blocking for the blocking C call
+        let res = tokio::task::spawn_blocking(move || {
+            call_with_buffer(|buf, len| unsafe { tailscale_peers(sd, buf, len) })
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
 
-impl Tailscale {
-    /// Creates a new builder for configuring a Tailscale instance.
-    pub fn builder() -> TailscaleBuilder {
-        TailscaleBuilder::default()
+        let json = self.buffer_result(res)?;
+        Ok(serde_json::from_str(&json)?)
     }
-    /// Brings up the Tailscale connection.
+
+    /// Returns the peers offering to be an exit node, ordered by MagicDNS name.
     ///
-    /// This must be called before the Tailscale instance can be used for networking.
+    /// # Errors
+    ///
+    /// Returns an error if the status cannot be retrieved.
+    pub async fn exit_nodes(&self) -> Result<Vec<Peer>> {
+        let mut peers = self.peers().await?;
+        peers.retain(|peer| peer.exit_node_option);
+        Ok(peers)
+    }
+
+    /// Routes this node's traffic to the internet through the exit node
+    /// `node`, named by its stable ID, MagicDNS name (full or short) or
+    /// Tailscale IP address, e.g. one of [`exit_nodes`](Self::exit_nodes).
+    ///
+    /// Traffic to the tailnet itself is unaffected.
     ///
     /// # Errors
     ///
-    /// Returns an error if bringing up the connection fails.
-    pub async fn up(&self) -> Result<()> {
-        debug!("bringing up Tailscale connection");
+    /// Returns an error if `node` is unknown or does not offer to be an exit
+    /// node, or the node's preferences cannot be updated.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// if let Some(exit) = ts.exit_nodes().await?.into_iter().find(|p| p.online) {
+    ///     ts.use_exit_node(&exit.id).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn use_exit_node(&self, node: &str) -> Result<()> {
+        debug!(node, "using exit node");
+        self.set_exit_node(node).await
+    }
+
+    /// Stops using an exit node set with [`use_exit_node`](Self::use_exit_node).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node's preferences cannot be updated.
+    pub async fn clear_exit_node(&self) -> Result<()> {
+        debug!("clearing exit node");
+        self.set_exit_node("").await
+    }
+
+    async fn set_exit_node(&self, node: &str) -> Result<()> {
+        self.ensure_open("instance")?;
+        let c_node = CString::new(node)?;
         let sd = self.sd;
 
         // Use spawn_blocking for the blocking C call
-        let ret = tokio::task::spawn_blocking(move || unsafe { tailscale_up(sd) })
-            .await
-            .map_err(TailscaleError::SpawnBlockingFailed)?;
+        let ret = tokio::task::spawn_blocking(move || unsafe {
+            tailscale_set_exit_node(sd, c_node.as_ptr())
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
 
         if ret != 0 {
-            let error_message = self.get_error_message()?;
-            return Err(TailscaleError::UpFailed(error_message));
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
         }
-        debug!("Tailscale connection is up");
         Ok(())
     }
 
-    /// Creates a new listener on the Tailscale network.
-    ///
-    /// # Arguments
+    /// Subscribes to changes of this node's state, such as backend state
+    /// transitions, peers coming online or going offline, login URLs and an
+    /// expiring node key.
     ///
-    /// * `network` - The network type (e.g., `NetworkType::Tcp`)
-    /// * `addr` - The address to listen on (e.g., ":8080")
+    /// The current backend state is yielded first, so daemons can react to
+    /// network changes instead of polling.
     ///
     /// # Errors
     ///
-    /// Returns an error if creating the listener fails.
-    pub async fn listener(
-        self: &Arc<Tailscale>,
-        network: NetworkType,
-        addr: &str,
-    ) -> Result<Arc<Listener>> {
-        debug!(%network, %addr, "creating listener");
-        let network_str = network.as_str();
-        let network_cstring =
-            std::ffi::CString::new(network_str).map_err(TailscaleError::Utf8Error)?;
-        let addr_cstring = std::ffi::CString::new(addr).map_err(TailscaleError::Utf8Error)?;
+    /// Returns an error if the subscription cannot be created.
+    pub async fn events(&self) -> Result<Events> {
+        self.ensure_open("instance")?;
+        debug!("subscribing to events");
         let sd = self.sd;
 
         // Use spawn_blocking for the blocking C call
-        let (listener, ret) = tokio::task::spawn_blocking(move || {
-            let mut listener = 0;
-            let ret = unsafe {
-                tailscale_listen(
-                    sd,
-                    network_cstring.as_ptr(),
-                    addr_cstring.as_ptr(),
-                    &mut listener,
-                )
-            };
-            (listener, ret)
+        let (fd, ret) = tokio::task::spawn_blocking(move || {
+            let mut fd = -1;
+            let ret = unsafe { tailscale_watch_events(sd, &mut fd) };
+            (fd, ret)
         })
         .await
         .map_err(TailscaleError::SpawnBlockingFailed)?;
 
         if ret != 0 {
-            let error_message = self.get_error_message()?;
-            return Err(TailscaleError::ListenFailed {
-                network: network_str.to_string(),
-                addr: addr.to_string(),
-                message: error_message,
-            });
+            return Err(self.ret_error(ret, TailscaleError::Tailscale));
         }
-        debug!(fd = listener, "listener created");
+        debug!(fd, "subscribed to events");
 
-        Ok(Arc::new(Listener {
-            ln: listener,
-            _tailscale: Arc::clone(self),
-        }))
+        // Safety: on success libtailscale hands us ownership of the descriptor.
+        Events::from_owned_fd(unsafe { OwnedFd::from_raw_fd(fd) })
     }
 
-    /// Creates an outbound connection to another node on the Tailscale network.
+    /// Returns a snapshot of this node's current network map.
     ///
-    /// # Arguments
-    ///
-    /// * `network` - The network type (e.g., `NetworkType::Tcp`)
-    /// * `addr` - The address to connect to (e.g., "hostname:8080")
+    /// The snapshot is read from the IPN bus and contains this node, its peers,
+    /// the DNS configuration and the DERP map.
     ///
     /// # Errors
     ///
-    /// Returns an error if the connection cannot be established.
-    pub async fn connect(&self, network: NetworkType, addr: &str) -> Result<Connection> {
-        debug!(%network, %addr, "connecting");
-        let network_str = network.as_str();
-        let network_cstring =
-            std::ffi::CString::new(network_str).map_err(TailscaleError::Utf8Error)?;
-        let addr_cstring = std::ffi::CString::new(addr).map_err(TailscaleError::Utf8Error)?;
+    /// Returns an error if no network map becomes available, e.g. because the
+    /// node is not connected.
+    pub async fn netmap(&self) -> Result<NetMap> {
+        self.ensure_open("instance")?;
+        debug!("fetching network map");
         let sd = self.sd;
 
         // Use spawn_blocking for the blocking C call
-        let (conn_fd, ret) = tokio::task::spawn_blocking(move || {
-            let mut conn_fd = 0;
-            let ret = unsafe {
-                tailscale_dial(
-                    sd,
-                    network_cstring.as_ptr(),
-                    addr_cstring.as_ptr(),
-                    &mut conn_fd,
-                )
-            };
-            (conn_fd, ret)
+        let res = tokio::task::spawn_blocking(move || {
+            call_with_buffer(|buf, len| unsafe { tailscale_netmap(sd, buf, len) })
         })
         .await
         .map_err(TailscaleError::SpawnBlockingFailed)?;
 
-        if ret != 0 {
-            let error_message = self.get_error_message()?;
-            return Err(TailscaleError::DialFailed {
-                network: network_str.to_string(),
-                addr: addr.to_string(),
-                message: error_message,
-            });
-        }
-        debug!(fd = conn_fd, "connection established");
-
-        // Set the fd to non-blocking mode
-        let borrowed_fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(conn_fd) };
-        let flags = nix::fcntl::OFlag::from_bits_truncate(
-            nix::fcntl::fcntl(borrowed_fd, nix::fcntl::FcntlArg::F_GETFL)
-                .map_err(|e| TailscaleError::Tailscale(format!("F_GETFL failed: {}", e)))?,
-        );
-        nix::fcntl::fcntl(
-            borrowed_fd,
-            nix::fcntl::FcntlArg::F_SETFL(flags | nix::fcntl::OFlag::O_NONBLOCK),
-        )
-        .map_err(|e| TailscaleError::Tailscale(format!("F_SETFL failed: {}", e)))?;
+        let json = self.buffer_result(res)?;
+        Ok(serde_json::from_str(&json)?)
+    }
 
-        // Convert raw fd to OwnedFd and wrap in AsyncFd
-        let owned_fd = unsafe { OwnedFd::from_raw_fd(conn_fd) };
-        let async_fd = AsyncFd::new(owned_fd)
-            .map_err(|e| TailscaleError::Tailscale(format!("AsyncFd::new failed: {}", e)))?;
+    /// Returns the tailnet's DNS configuration, i.e. its MagicDNS suffix,
+    /// search domains and nameservers, from the current network map.
+    ///
+    /// Use [`DnsConfig::fqdn`] to build the fully qualified name of a peer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no network map becomes available, see
+    /// [`netmap`](Self::netmap).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// let dns = ts.dns_config().await?;
+    /// let conn = ts.connect(NetworkType::Tcp, (dns.fqdn("db"), 5432)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn dns_config(&self) -> Result<DnsConfig> {
+        Ok(self.netmap().await?.dns)
+    }
 
-        Ok(Connection {
-            listener: None,
-            conn: async_fd,
-        })
+    /// Converts the result of [`call_with_buffer`] into a string, fetching the
+    /// error message from libtailscale on failure.
+    fn buffer_result(&self, res: std::result::Result<String, libc::c_int>) -> Result<String> {
+        res.map_err(|ret| self.ret_error(ret, TailscaleError::Tailscale))
     }
 
-    /// Returns the IPv4 and IPv6 addresses assigned to this Tailscale node.
+    /// Shuts down the instance.
     ///
-    /// Returns `None` if no IP addresses have been assigned yet.
+    /// Closing is refused while listeners or connections created from this
+    /// instance are still open, so fd lifetime bugs surface here instead of as
+    /// `EBADF` from the C layer later on. Use [`force_close`](Self::force_close)
+    /// to shut them down as well. Closing an instance twice is a no-op.
+    ///
+    /// The shutdown runs on the blocking thread pool, so unlike dropping the
+    /// last reference it does not stall the async runtime, and failures are
+    /// reported instead of only being logged.
     ///
     /// # Errors
     ///
-    /// Returns an error if retrieving or parsing the IP addresses fails.
-    pub fn ips(&self) -> Result<Option<IpPair>> {
-        let buf = [0u8; 256];
-        let ret = unsafe { tailscale_getips(self.sd, buf.as_ptr() as *mut _, buf.len()) };
-        if ret != 0 {
-            let error_message = self.get_error_message()?;
-            return Err(TailscaleError::Tailscale(error_message));
+    /// Returns [`TailscaleError::ResourcesBusy`] with the number of open
+    /// listeners and connections if any remain, or
+    /// [`TailscaleError::CloseFailed`] if the shutdown failed.
+    pub async fn close(self: Arc<Self>) -> Result<()> {
+        let (listeners, connections) = self.resources.counts();
+        if listeners > 0 || connections > 0 {
+            return Err(TailscaleError::ResourcesBusy {
+                listeners,
+                connections,
+            });
+        }
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
         }
-        let s = CStr::from_bytes_until_nul(&buf[..])?;
-        let s = s.to_str()?;
 
-        if s.is_empty() {
-            return Ok(None);
+        debug!("closing");
+        let sd = self.sd;
+        // Kept alive until the close completes, even if this future is dropped.
+        let state_store = self._state_store.clone();
+
+        // Use spawn_blocking for the blocking C call
+        let ret = tokio::task::spawn_blocking(move || {
+            let ret = unsafe { tailscale_close(sd) };
+            drop(state_store);
+            ret
+        })
+        .await
+        .map_err(TailscaleError::SpawnBlockingFailed)?;
+
+        if ret != 0 {
+            return Err(TailscaleError::CloseFailed);
         }
+        debug!("closed");
+        Ok(())
+    }
 
-        let (ipv4, ipv6) = s
-            .split_once(',')
-            .ok_or_else(|| TailscaleError::InvalidIpAdresses(s.to_string()))?;
+    /// Shuts down every open listener and connection, then the instance.
+    ///
+    /// Pending and later I/O on the children fails, but they must still be
+    /// dropped by their owners to release their file descriptors.
+    pub fn force_close(&self) {
+        let (listeners, connections) = self.resources.shutdown_all();
+        debug!(listeners, connections, "force closing");
+        self.close_handle();
+    }
 
-        let ipv4 = Ipv4Addr::from_str(ipv4)
-            .map_err(|e| TailscaleError::AddrParseError(ipv4.to_string(), e))?;
-        let ipv6 = Ipv6Addr::from_str(ipv6)
-            .map_err(|e| TailscaleError::AddrParseError(ipv6.to_string(), e))?;
+    /// In strict mode, returns [`TailscaleError::Misuse`] if the instance
+    /// has been closed.
+    fn ensure_open(&self, what: &str) -> Result<()> {
+        if self.strict && self.closed.load(Ordering::SeqCst) {
+            return Err(TailscaleError::Misuse(format!(
+                "{what} used after the Tailscale instance was closed"
+            )));
+        }
+        Ok(())
+    }
 
-        Ok(Some(IpPair { ipv4, ipv6 }))
+    /// Shuts down the underlying server, at most once.
+    ///
+    /// Besides releasing the handle this aborts any in-progress `tailscale_up`.
+    /// Inside an async runtime the shutdown runs in the background, see the
+    /// [`Drop`] implementation.
+    fn close_handle(&self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if tokio::runtime::Handle::try_current().is_ok() {
+            // The log destination and store go with the shutdown thread, as
+            // libtailscale may use them until the close completes.
+            let log_fd = self.log_fd.lock().unwrap().take();
+            shutdown::close_in_background(self.sd, log_fd, self._state_store.clone());
+        } else {
+            shutdown::close_now(self.sd);
+        }
+    }
+
+    /// Converts a non-zero return code into an error, using `other` with the
+    /// message from libtailscale unless the code has a variant of its own.
+    fn ret_error(
+        &self,
+        ret: libc::c_int,
+        other: impl FnOnce(String) -> TailscaleError,
+    ) -> TailscaleError {
+        if let Some(err) = code_error(ret) {
+            return err;
+        }
+        match self.get_error_message() {
+            Ok(message) => other(message),
+            Err(err) => err,
+        }
     }
 
     fn get_error_message(&self) -> Result<String> {
         let buf = [0u8; 2048];
         let ret = unsafe { tailscale_errmsg(self.sd, buf.as_ptr() as *mut _, buf.len()) };
         if ret > 0 {
-            return Err(TailscaleError::Tailscale(format!(
-                "Failed to retrieve error message (error code: {})",
-                ret
-            )));
+            return Err(code_error(ret).unwrap_or_else(|| {
+                TailscaleError::Tailscale(format!(
+                    "Failed to retrieve error message (error code: {})",
+                    ret
+                ))
+            }));
         }
         let s = CStr::from_bytes_until_nul(&buf[..])?;
         let s = s.to_str()?;
@@ -752,25 +4469,146 @@ impl Tailscale {
     }
 }
 
+/// Head start of the IPv6 attempt in [`Tailscale::connect_happy_eyeballs`],
+/// the connection attempt delay recommended by RFC 8305.
+const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// How often [`Tailscale::key_expiring`] re-reads the key expiry, so a key
+/// renewed or extended in the meantime is noticed.
+const KEY_EXPIRY_RECHECK: Duration = Duration::from_secs(60 * 60);
+
+/// How long [`Tailscale::deregistered`] waits for the backend to confirm the
+/// removal, and how often it checks.
+const DEREGISTER_TIMEOUT: Duration = Duration::from_secs(10);
+const DEREGISTER_POLL: Duration = Duration::from_millis(100);
+
+/// How long [`Tailscale::ping`] waits for a reply.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest buffer [`call_with_buffer`] will allocate before giving up.
+const MAX_BUFFER_LEN: usize = 16 << 20;
+
+/// Calls a libtailscale function that writes a NUL-terminated string into a
+/// caller-provided buffer, retrying with a larger buffer while it reports `ERANGE`.
+///
+/// Returns the string on success, or the non-zero return code on failure.
+fn call_with_buffer(
+    f: impl Fn(*mut libc::c_char, libc::size_t) -> libc::c_int,
+) -> std::result::Result<String, libc::c_int> {
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let ret = f(buf.as_mut_ptr() as *mut _, buf.len());
+        if ret == libc::ERANGE && buf.len() < MAX_BUFFER_LEN {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if ret != 0 {
+            return Err(ret);
+        }
+        let s = CStr::from_bytes_until_nul(&buf).map_err(|_| libc::ERANGE)?;
+        return Ok(s.to_string_lossy().into_owned());
+    }
+}
+
+/// Converts the result of setting an option on the tailnet connection behind
+/// a connection, which is 0 or an errno value.
+fn conn_opt_result(ret: libc::c_int) -> std::io::Result<()> {
+    match ret {
+        0 => Ok(()),
+        libc::EOPNOTSUPP => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the tailnet connection does not support this option",
+        )),
+        errno => Err(std::io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// Returns the error for return codes that mean the same for every
+/// function, see [`ReturnCode`].
+fn code_error(ret: libc::c_int) -> Option<TailscaleError> {
+    match ReturnCode::from(ret) {
+        ReturnCode::InvalidHandle => Some(TailscaleError::InvalidHandle),
+        ReturnCode::BufferTooSmall => Some(TailscaleError::BufferTooSmall),
+        ReturnCode::Ok | ReturnCode::Other(_) => None,
+    }
+}
+
+/// Closes the instance if [`Tailscale::close`] was not called, as a
+/// best-effort fallback whose errors are only logged.
+///
+/// Outside an async runtime the shutdown completes before `drop` returns.
+/// Inside a runtime it is handed to a dedicated background thread so that
+/// the worker thread is not blocked; `drop` then returns immediately and the
+/// node may still be shutting down, or never finish if the process exits
+/// first. The log destination is kept open until the shutdown completes.
+/// Call [`Tailscale::close`] to wait for the shutdown from async code.
 impl Drop for Tailscale {
     fn drop(&mut self) {
         debug!("dropping server");
-        let ret = unsafe { tailscale_close(self.sd) };
-        if ret != 0 {
-            if let Ok(error_message) = self.get_error_message() {
-                error!(error = %error_message, "error dropping tailscale");
-            } else {
-                error!("error dropping tailscale (failed to retrieve error message)");
-            }
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if tokio::runtime::Handle::try_current().is_ok() {
+            debug!("instance was not closed explicitly, closing in the background");
+            let log_fd = self.log_fd.get_mut().unwrap().take();
+            shutdown::close_in_background(self.sd, log_fd, self._state_store.take());
+        } else {
+            debug!("instance was not closed explicitly, closing on drop");
+            shutdown::close_now(self.sd);
         }
     }
 }
 
+/// Dropping a listener only closes its end of the socketpair, which never
+/// blocks; libtailscale notices and tears the listener down asynchronously.
 impl Drop for Listener {
     fn drop(&mut self) {
         debug!("dropping listener");
-        if let Err(e) = nix::unistd::close(self.ln) {
-            error!(error = %e, "error closing listener");
+        // AsyncFd<OwnedFd> automatically closes the fd on drop
+    }
+}
+
+impl AsFd for Listener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.ln.get_ref().as_fd()
+    }
+}
+
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.ln.as_raw_fd()
+    }
+}
+
+/// Deregisters the listener from the tokio reactor and hands over its fd,
+/// which stays in non-blocking mode.
+///
+/// Connections already accepted are unaffected, but the listener's filter,
+/// connection limit and [`shutdown`](Listener::shutdown) no longer apply to
+/// the fd, and [`Tailscale::close`] does not shut it down.
+impl From<Listener> for OwnedFd {
+    fn from(listener: Listener) -> OwnedFd {
+        debug!("converting listener");
+        let mut this = ManuallyDrop::new(listener);
+        // SAFETY: `this` is never used or dropped again; `ln` is moved out
+        // and every other field is dropped in place exactly once, the
+        // registration before the fd leaves our hands.
+        unsafe {
+            let ln = ptr::read(&this.ln);
+            ptr::drop_in_place(&mut this._resource);
+            ptr::drop_in_place(&mut this.filter);
+            ptr::drop_in_place(&mut this.limit);
+            ptr::drop_in_place(&mut this.connections);
+            ptr::drop_in_place(&mut this.closed);
+            ptr::drop_in_place(&mut this._tailscale);
+            ln.into_inner()
         }
     }
 }
+
+/// Same as converting into an [`OwnedFd`] and releasing it.
+impl IntoRawFd for Listener {
+    fn into_raw_fd(self) -> RawFd {
+        OwnedFd::from(self).into_raw_fd()
+    }
+}
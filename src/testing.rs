@@ -0,0 +1,255 @@
+//! A two-node tailnet for end-to-end tests of services built on this crate.
+//!
+//! [`TestNet`] starts two ephemeral nodes in the current process, joins them
+//! to a tailnet through a control server and hands out connections between
+//! them, so a test can run its service on one node and a client on the
+//! other. State is kept in memory and logs go to `tracing`, so nothing
+//! outlives the test except a temporary directory that is removed on close.
+//!
+//! The control server and auth key are taken from the builder or from the
+//! `TAILSCALE_TEST_CONTROL_URL` and `TAILSCALE_TEST_AUTH_KEY` environment
+//! variables. Without an auth key [`TestNet::from_env`] returns `None`, so
+//! tests can be skipped where no tailnet is available. The auth key must be
+//! reusable; an ephemeral key keeps the admin console tidy.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! use tailscale2::testing::TestNet;
+//!
+//! # async fn example() -> tailscale2::Result<()> {
+//! let Some(net) = TestNet::from_env().await? else {
+//!     eprintln!("TAILSCALE_TEST_AUTH_KEY not set, skipping");
+//!     return Ok(());
+//! };
+//! let (mut client, mut server) = net.connect(8080).await?;
+//! client.write_all(b"ping").await?;
+//! let mut buf = [0; 4];
+//! server.read_exact(&mut buf).await?;
+//! assert_eq!(&buf, b"ping");
+//! net.close().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tracing::debug;
+
+use crate::{
+    auth_key::AuthKey,
+    state::Memory,
+    tailscale::{Connection, NetworkType, Result, Tailscale, TailscaleError},
+};
+
+/// Environment variable holding the control server URL.
+pub const CONTROL_URL_ENV: &str = "TAILSCALE_TEST_CONTROL_URL";
+/// Environment variable holding the auth key the nodes join with.
+pub const AUTH_KEY_ENV: &str = "TAILSCALE_TEST_AUTH_KEY";
+
+/// How long [`TestNet::connect`] keeps retrying while the nodes learn about
+/// each other.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Distinguishes the directories of test networks in one process.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Two ephemeral nodes on the same tailnet, see the [module docs](self).
+pub struct TestNet {
+    first: Arc<Tailscale>,
+    second: Arc<Tailscale>,
+    dir: TempDir,
+}
+
+impl TestNet {
+    /// Creates a builder for a test network.
+    pub fn builder() -> TestNetBuilder {
+        TestNetBuilder::default()
+    }
+
+    /// Starts a test network configured from the environment, or returns
+    /// `None` if `TAILSCALE_TEST_AUTH_KEY` is not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a node fails to start or join the tailnet.
+    pub async fn from_env() -> Result<Option<TestNet>> {
+        let Ok(auth_key) = std::env::var(AUTH_KEY_ENV) else {
+            return Ok(None);
+        };
+        let mut builder = TestNet::builder();
+        builder.auth_key(auth_key);
+        if let Ok(url) = std::env::var(CONTROL_URL_ENV) {
+            builder.control_url(url);
+        }
+        builder.start().await.map(Some)
+    }
+
+    /// Returns the first node, which dials in [`connect`](Self::connect).
+    pub fn first(&self) -> &Arc<Tailscale> {
+        &self.first
+    }
+
+    /// Returns the second node, which listens in [`connect`](Self::connect).
+    pub fn second(&self) -> &Arc<Tailscale> {
+        &self.second
+    }
+
+    /// Opens a TCP connection from the first node to the second on `port`,
+    /// returning the client and server ends.
+    ///
+    /// Dialing is retried for up to 30 seconds, while the nodes learn about
+    /// each other from the control server.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if listening fails or no connection is established in
+    /// time.
+    pub async fn connect(&self, port: u16) -> Result<(Connection, Connection)> {
+        let listener = self.second.listener(NetworkType::Tcp, port).await?;
+        let ip = self
+            .second
+            .ips()?
+            .ok_or_else(|| TailscaleError::Tailscale("second node has no IP address".to_string()))?
+            .ipv4;
+
+        let dial = async {
+            loop {
+                match self.first.connect(NetworkType::Tcp, (ip, port)).await {
+                    Ok(conn) => return conn,
+                    Err(e) => {
+                        debug!(error = %e, "second node not reachable yet");
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                    }
+                }
+            }
+        };
+        let client = tokio::time::timeout(CONNECT_TIMEOUT, dial)
+            .await
+            .map_err(|_| {
+                TailscaleError::Tailscale(format!(
+                    "first node could not reach {ip}:{port} within {CONNECT_TIMEOUT:?}"
+                ))
+            })?;
+        let server = listener.accept().await?;
+        Ok((client, server))
+    }
+
+    /// Removes both nodes from the tailnet, closes them and deletes their
+    /// temporary directory.
+    ///
+    /// Dropping a `TestNet` instead leaves the nodes to expire as ephemeral
+    /// nodes do once offline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a node could not be deregistered or closed.
+    pub async fn close(self) -> Result<()> {
+        let TestNet { first, second, dir } = self;
+        for node in [first, second] {
+            node.deregistered().await?;
+            node.close().await?;
+        }
+        drop(dir);
+        Ok(())
+    }
+}
+
+/// Builder for a [`TestNet`].
+#[derive(Debug, Default)]
+pub struct TestNetBuilder {
+    control_url: Option<String>,
+    auth_key: Option<AuthKey>,
+    hostname_prefix: Option<String>,
+}
+
+impl TestNetBuilder {
+    /// Sets the control server, e.g. a local Headscale. Defaults to
+    /// Tailscale's.
+    pub fn control_url(&mut self, url: impl Into<String>) -> &mut Self {
+        self.control_url = Some(url.into());
+        self
+    }
+
+    /// Sets the reusable auth key both nodes join with.
+    pub fn auth_key(&mut self, key: impl Into<AuthKey>) -> &mut Self {
+        self.auth_key = Some(key.into());
+        self
+    }
+
+    /// Sets the prefix of the nodes' hostnames, `tailscale2-test` by default.
+    /// The process ID and a counter are appended to keep them unique.
+    pub fn hostname_prefix(&mut self, prefix: impl Into<String>) -> &mut Self {
+        self.hostname_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Starts both nodes and waits until they have joined the tailnet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no auth key was set, or a node fails to start or
+    /// join the tailnet.
+    pub async fn start(&self) -> Result<TestNet> {
+        let auth_key = self
+            .auth_key
+            .clone()
+            .ok_or_else(|| TailscaleError::Misuse("TestNet requires an auth key".to_string()))?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let prefix = self.hostname_prefix.as_deref().unwrap_or("tailscale2-test");
+        let name = format!("{prefix}-{}-{id}", std::process::id());
+        let dir = TempDir::new(&name)?;
+
+        let node = |suffix: &str| {
+            let path = dir.0.join(suffix);
+            std::fs::create_dir_all(&path).map_err(|e| state_dir_error(&path, e))?;
+            let mut builder = Tailscale::builder();
+            builder
+                .hostname(format!("{name}-{suffix}"))
+                .dir(path)
+                .ephemeral(true)
+                .auth_key(auth_key.clone())
+                .state_store(Memory::new())
+                .log_to_tracing();
+            if let Some(url) = &self.control_url {
+                builder.control_url(url.clone());
+            }
+            builder.build()
+        };
+        let first = node("a")?;
+        let second = node("b")?;
+
+        debug!(%name, "starting test network");
+        tokio::try_join!(first.up(), second.up())?;
+        Ok(TestNet { first, second, dir })
+    }
+}
+
+/// A temporary directory removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path).map_err(|e| state_dir_error(&path, e))?;
+        Ok(TempDir(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn state_dir_error(path: &std::path::Path, e: std::io::Error) -> TailscaleError {
+    TailscaleError::StateDir(format!("could not create {}: {e}", path.display()))
+}
@@ -0,0 +1,205 @@
+//! Read and write timeouts for async connections, see [`TimeoutConnection`].
+
+use std::{
+    future::Future,
+    io,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep},
+};
+
+use crate::tailscale::Connection;
+
+/// A [`Connection`] whose reads and writes fail once they have waited too
+/// long for the peer.
+///
+/// A timeout starts when a read or write first has to wait and is cleared
+/// as soon as it makes progress, so it bounds how long a stalled peer can
+/// hold up the connection, not how long the connection stays open. A timed
+/// out operation fails with [`ErrorKind::TimedOut`](io::ErrorKind::TimedOut);
+/// the connection itself stays usable. Both timeouts are disabled by
+/// default.
+///
+/// Dereferences to the wrapped [`Connection`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use tailscale2::*;
+/// # async fn example(listener: std::sync::Arc<Listener>) -> Result<()> {
+/// use tokio::io::AsyncReadExt;
+///
+/// let mut conn = TimeoutConnection::new(listener.accept().await?);
+/// conn.set_read_timeout(Some(Duration::from_secs(30)));
+/// let mut request = Vec::new();
+/// conn.read_to_end(&mut request).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TimeoutConnection {
+    conn: Connection,
+    read: Deadline,
+    write: Deadline,
+}
+
+impl TimeoutConnection {
+    /// Wraps `conn` with both timeouts disabled.
+    pub fn new(conn: Connection) -> TimeoutConnection {
+        TimeoutConnection {
+            conn,
+            read: Deadline::default(),
+            write: Deadline::default(),
+        }
+    }
+
+    /// Sets how long a read may wait for data, or disables the timeout with
+    /// `None`.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read.set(timeout);
+    }
+
+    /// Sets how long a write or flush may wait for the peer to accept data,
+    /// or disables the timeout with `None`.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write.set(timeout);
+    }
+
+    /// Returns the read timeout, if any.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read.timeout
+    }
+
+    /// Returns the write timeout, if any.
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write.timeout
+    }
+
+    /// Unwraps the connection, dropping the timeouts.
+    pub fn into_inner(self) -> Connection {
+        self.conn
+    }
+}
+
+impl From<Connection> for TimeoutConnection {
+    fn from(conn: Connection) -> TimeoutConnection {
+        TimeoutConnection::new(conn)
+    }
+}
+
+impl Deref for TimeoutConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl DerefMut for TimeoutConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+}
+
+impl AsyncRead for TimeoutConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.conn).poll_read(cx, buf);
+        this.read.poll(res, cx)
+    }
+}
+
+impl AsyncWrite for TimeoutConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.conn).poll_write(cx, buf);
+        this.write.poll(res, cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.conn).poll_write_vectored(cx, bufs);
+        this.write.poll(res, cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.conn.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.conn).poll_flush(cx);
+        this.write.poll(res, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.conn).poll_shutdown(cx);
+        this.write.poll(res, cx)
+    }
+}
+
+/// The timeout of one direction and its timer, armed while an operation
+/// is waiting.
+#[derive(Default)]
+struct Deadline {
+    timeout: Option<Duration>,
+    /// Created on first use, as timers need a runtime.
+    sleep: Option<Pin<Box<Sleep>>>,
+    armed: bool,
+}
+
+impl Deadline {
+    fn set(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        self.armed = false;
+    }
+
+    /// Passes on the result of polling the connection, or fails it once the
+    /// operation has been pending for longer than the timeout.
+    fn poll<T>(&mut self, res: Poll<io::Result<T>>, cx: &mut Context<'_>) -> Poll<io::Result<T>> {
+        let Some(timeout) = self.timeout else {
+            return res;
+        };
+        if res.is_ready() {
+            self.armed = false;
+            return res;
+        }
+        let deadline = Instant::now() + timeout;
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+        if !self.armed {
+            sleep.as_mut().reset(deadline);
+            self.armed = true;
+        }
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                self.armed = false;
+                Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection timed out",
+                )))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! TLS listeners serving the node's Tailscale HTTPS certificate.
+
+use std::{
+    sync::{Arc, RwLock, Weak},
+    time::Duration,
+};
+
+use tokio::task::JoinHandle;
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::{CryptoProvider, ring},
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    },
+};
+use tracing::{debug, warn};
+
+use crate::{
+    cert::CertPair,
+    tailscale::{Connection, Listener, Result, Tailscale, TailscaleError},
+};
+
+/// How often the certificate is re-read from libtailscale, which renews it
+/// well before it expires.
+const CERT_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A connection accepted by a [`TlsListener`], with the TLS handshake completed.
+pub type TlsConnection = tokio_rustls::server::TlsStream<Connection>;
+
+/// A listener that terminates TLS with the node's certificate for its
+/// MagicDNS name.
+///
+/// Created by [`Tailscale::listen_tls`]. The certificate is refreshed in the
+/// background for as long as the listener is alive.
+pub struct TlsListener {
+    listener: Arc<Listener>,
+    acceptor: TlsAcceptor,
+    refresh: JoinHandle<()>,
+}
+
+impl TlsListener {
+    pub(crate) fn new(
+        tailscale: &Arc<Tailscale>,
+        listener: Arc<Listener>,
+        pair: &CertPair,
+    ) -> Result<Self> {
+        let provider = Arc::new(ring::default_provider());
+        let resolver = Arc::new(CertResolver {
+            key: RwLock::new(Arc::new(certified_key(&provider, pair)?)),
+            provider: Arc::clone(&provider),
+        });
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| TailscaleError::Tls(e.to_string()))?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+
+        let refresh = tokio::spawn(refresh_cert(Arc::downgrade(tailscale), resolver));
+        Ok(TlsListener {
+            listener,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            refresh,
+        })
+    }
+
+    /// Accepts a new connection and completes the TLS handshake.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting the connection fails, or
+    /// [`TailscaleError::Tls`] if the handshake fails. A failed handshake only
+    /// affects that connection, so callers should keep accepting.
+    pub async fn accept(&self) -> Result<TlsConnection> {
+        let conn = self.listener.accept().await?;
+        self.acceptor
+            .accept(conn)
+            .await
+            .map_err(|e| TailscaleError::Tls(e.to_string()))
+    }
+
+    /// Returns the plaintext listener underneath.
+    pub fn listener(&self) -> &Arc<Listener> {
+        &self.listener
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.refresh.abort();
+    }
+}
+
+/// Serves the most recently fetched certificate.
+#[derive(Debug)]
+struct CertResolver {
+    key: RwLock<Arc<CertifiedKey>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.key.read().unwrap()))
+    }
+}
+
+async fn refresh_cert(tailscale: Weak<Tailscale>, resolver: Arc<CertResolver>) {
+    loop {
+        tokio::time::sleep(CERT_REFRESH_INTERVAL).await;
+        let Some(tailscale) = tailscale.upgrade() else {
+            return;
+        };
+        let key = tailscale
+            .cert_pair("")
+            .await
+            .and_then(|pair| certified_key(&resolver.provider, &pair));
+        match key {
+            Ok(key) => {
+                debug!("refreshed TLS certificate");
+                *resolver.key.write().unwrap() = Arc::new(key);
+            }
+            Err(e) => warn!(error = %e, "failed to refresh TLS certificate, keeping the old one"),
+        }
+    }
+}
+
+/// Parses a certificate pair into the form rustls serves.
+pub(crate) fn certified_key(provider: &CryptoProvider, pair: &CertPair) -> Result<CertifiedKey> {
+    let tls_error = |e: &dyn std::fmt::Display| TailscaleError::Tls(e.to_string());
+    let certs = CertificateDer::pem_slice_iter(pair.cert_pem().as_bytes())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| tls_error(&e))?;
+    let key =
+        PrivateKeyDer::from_pem_slice(pair.key_pem().as_bytes()).map_err(|e| tls_error(&e))?;
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(|e| tls_error(&e))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
@@ -0,0 +1,140 @@
+//! tower integration, enabled by the `tower` feature.
+//!
+//! [`Dialer`] is a [`Service`] from dial addresses to connections, so it can
+//! be wrapped in tower middleware such as timeouts, retries or rate limits
+//! and used as a connector. [`Listener::serve_service`] is the server side:
+//! it drives a make-service, which is asked for a connection service for
+//! every peer that connects.
+
+use std::{
+    fmt::Display,
+    future::{Future, poll_fn},
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tower_service::Service;
+use tracing::warn;
+
+use crate::{
+    addr::ToDialAddr,
+    dialer::{Dialer, PooledConnection},
+    serve::Serve,
+    tailscale::{Connection, Listener, TailscaleError},
+};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Dials the requested address, see [`Dialer::connect`].
+///
+/// The dialer is always ready; wrap it in a concurrency limit to bound the
+/// dials in flight.
+impl<A: ToDialAddr> Service<A> for Dialer {
+    type Response = PooledConnection;
+    type Error = TailscaleError;
+    type Future = BoxFuture<Result<PooledConnection, TailscaleError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, addr: A) -> Self::Future {
+        let dialer = self.clone();
+        let addr = addr.to_dial_addr();
+        Box::pin(async move { dialer.connect(addr?).await })
+    }
+}
+
+impl Listener {
+    /// Serves every accepted connection with a tower service.
+    ///
+    /// For each connection, `make_service` is called with the peer's tailnet
+    /// address and returns the service that is then called once with the
+    /// connection, in the connection's own task. Errors from either are
+    /// logged and close the connection. Configure and run the accept loop
+    /// through the returned [`Serve`], as with [`serve`](Self::serve).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::{convert::Infallible, future::Ready, net::SocketAddr, pin::Pin, task::*};
+    /// # use tailscale2::{Connection, NetworkType, Tailscale};
+    /// # use tower_service::Service;
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// /// Makes a `Greeter` for every peer.
+    /// #[derive(Clone)]
+    /// struct MakeGreeter;
+    ///
+    /// impl Service<SocketAddr> for MakeGreeter {
+    ///     type Response = Greeter;
+    ///     type Error = Infallible;
+    ///     type Future = Ready<Result<Greeter, Infallible>>;
+    ///
+    ///     fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+    ///         Poll::Ready(Ok(()))
+    ///     }
+    ///
+    ///     fn call(&mut self, peer: SocketAddr) -> Self::Future {
+    ///         std::future::ready(Ok(Greeter(peer)))
+    ///     }
+    /// }
+    ///
+    /// struct Greeter(SocketAddr);
+    ///
+    /// impl Service<Connection> for Greeter {
+    ///     type Response = ();
+    ///     type Error = std::io::Error;
+    ///     type Future = Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>>;
+    ///
+    ///     fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+    ///         Poll::Ready(Ok(()))
+    ///     }
+    ///
+    ///     fn call(&mut self, mut conn: Connection) -> Self::Future {
+    ///         let greeting = format!("hello {}\n", self.0);
+    ///         Box::pin(async move { conn.write_all(greeting.as_bytes()).await })
+    ///     }
+    /// }
+    ///
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> tailscale2::Result<()> {
+    /// let listener = ts.listener(NetworkType::Tcp, 8080).await?;
+    /// listener.serve_service(MakeGreeter).max_connections(1024).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serve_service<M, S>(
+        self: &Arc<Self>,
+        make_service: M,
+    ) -> Serve<impl Fn(Connection) -> BoxFuture<()> + Send + Sync + 'static>
+    where
+        M: Service<SocketAddr, Response = S> + Clone + Send + Sync + 'static,
+        M::Error: Display,
+        M::Future: Send,
+        S: Service<Connection, Response = ()> + Send + 'static,
+        S::Error: Display,
+        S::Future: Send,
+    {
+        self.serve(move |conn: Connection| {
+            let mut make_service = make_service.clone();
+            Box::pin(async move {
+                let res = async {
+                    let addr = conn.remote_addr().map_err(|e| e.to_string())?;
+                    poll_fn(|cx| make_service.poll_ready(cx))
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let mut service = make_service.call(addr).await.map_err(|e| e.to_string())?;
+                    poll_fn(|cx| service.poll_ready(cx))
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    service.call(conn).await.map_err(|e| e.to_string())
+                };
+                if let Err(e) = res.await {
+                    warn!(error = %e, "connection service failed");
+                }
+            }) as BoxFuture<()>
+        })
+    }
+}
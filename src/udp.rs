@@ -0,0 +1,266 @@
+//! UDP packet sockets on the tailnet, see
+//! [`Tailscale::listen_packet`](crate::Tailscale::listen_packet).
+//!
+//! libtailscale hands over a datagram socketpair on which every datagram
+//! carries the peer's address in a fixed header, so one socket can talk to
+//! any number of peers like a regular unconnected UDP socket.
+
+use std::{
+    fmt,
+    io::{self, IoSlice, IoSliceMut},
+    net::{Ipv6Addr, SocketAddr},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+    task::{Context, Poll},
+};
+
+use nix::sys::socket::{MsgFlags, recvmsg, sendmsg};
+use tokio::io::{Interest, unix::AsyncFd};
+
+use crate::resources::ResourceGuard;
+
+/// Size of the address header in front of every datagram: the IPv6 (or
+/// IPv4-mapped) address followed by the port, big endian.
+const HEADER_LEN: usize = 18;
+
+/// A UDP socket bound to one of this node's tailnet addresses, returned by
+/// [`Tailscale::listen_packet`](crate::Tailscale::listen_packet).
+///
+/// Like an unconnected [`tokio::net::UdpSocket`], it sends datagrams to and
+/// receives them from any peer. Datagram boundaries are preserved.
+pub struct UdpSocket {
+    _resource: ResourceGuard,
+    local_addr: SocketAddr,
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl UdpSocket {
+    pub(crate) fn new(
+        fd: OwnedFd,
+        local_addr: SocketAddr,
+        resource: ResourceGuard,
+    ) -> io::Result<Self> {
+        Ok(UdpSocket {
+            _resource: resource,
+            local_addr,
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Returns the tailnet address this socket is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Sends `buf` as a single datagram to `target`, returning the number of
+    /// bytes sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datagram cannot be handed to libtailscale.
+    /// Like UDP, delivery is not confirmed.
+    pub async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        self.fd
+            .async_io(Interest::WRITABLE, |fd| send(fd.as_fd(), buf, target))
+            .await
+    }
+
+    /// Receives a single datagram into `buf`, returning its length and the
+    /// address of the sender. The rest of a datagram longer than `buf` is
+    /// discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from libtailscale fails.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.fd
+            .async_io(Interest::READABLE, |fd| recv(fd.as_fd(), buf))
+            .await
+    }
+
+    /// Tries to send a datagram without waiting, failing with
+    /// [`io::ErrorKind::WouldBlock`] if the socket is not ready.
+    ///
+    /// # Errors
+    ///
+    /// See [`send_to`](Self::send_to).
+    pub fn try_send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        self.fd
+            .try_io(Interest::WRITABLE, |fd| send(fd.as_fd(), buf, target))
+    }
+
+    /// Polls to send a datagram, for use in `poll`-based code.
+    ///
+    /// # Errors
+    ///
+    /// See [`send_to`](Self::send_to).
+    pub fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = match self.fd.poll_write_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.try_io(|fd| send(fd.as_fd(), buf, target)) {
+                Ok(res) => return Poll::Ready(res),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Polls to receive a datagram, for use in `poll`-based code.
+    ///
+    /// # Errors
+    ///
+    /// See [`recv_from`](Self::recv_from).
+    pub fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        loop {
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.try_io(|fd| recv(fd.as_fd(), buf)) {
+                Ok(res) => return Poll::Ready(res),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Polls until the socket is ready to send, without sending anything.
+    #[cfg_attr(not(feature = "quic"), allow(dead_code))]
+    pub(crate) fn poll_send_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.fd.poll_write_ready(cx).map_ok(|_guard| ())
+    }
+}
+
+impl fmt::Debug for UdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpSocket")
+            .field("local_addr", &self.local_addr)
+            .field("fd", &self.fd.as_raw_fd())
+            .finish()
+    }
+}
+
+/// Encodes the address header of a datagram to or from `addr`.
+fn encode_header(addr: SocketAddr) -> [u8; HEADER_LEN] {
+    let ip = match addr {
+        SocketAddr::V4(v4) => v4.ip().to_ipv6_mapped(),
+        SocketAddr::V6(v6) => *v6.ip(),
+    };
+    let mut header = [0u8; HEADER_LEN];
+    header[..16].copy_from_slice(&ip.octets());
+    header[16..].copy_from_slice(&addr.port().to_be_bytes());
+    header
+}
+
+/// Decodes an address header, turning IPv4-mapped addresses back into IPv4.
+fn decode_header(header: &[u8; HEADER_LEN]) -> SocketAddr {
+    let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&header[..16]).expect("16 bytes"));
+    let port = u16::from_be_bytes([header[16], header[17]]);
+    SocketAddr::new(ip.to_canonical(), port)
+}
+
+fn send(fd: BorrowedFd<'_>, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+    let header = encode_header(target);
+    let iov = [IoSlice::new(&header), IoSlice::new(buf)];
+    let n = sendmsg::<()>(fd.as_raw_fd(), &iov, &[], MsgFlags::empty(), None)?;
+    Ok(n.saturating_sub(HEADER_LEN))
+}
+
+fn recv(fd: BorrowedFd<'_>, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+    let mut header = [0u8; HEADER_LEN];
+    let n = {
+        let mut iov = [IoSliceMut::new(&mut header), IoSliceMut::new(buf)];
+        recvmsg::<()>(fd.as_raw_fd(), &mut iov, None, MsgFlags::empty())?.bytes
+    };
+    if n == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "packet socket was closed by libtailscale",
+        ));
+    }
+    if n < HEADER_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "datagram from libtailscale is missing its address header",
+        ));
+    }
+    Ok((n - HEADER_LEN, decode_header(&header)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::AsFd;
+
+    use nix::sys::socket::{AddressFamily, SockFlag, SockType, socketpair};
+
+    use super::*;
+
+    fn pair() -> (OwnedFd, OwnedFd) {
+        socketpair(
+            AddressFamily::Unix,
+            SockType::Datagram,
+            None,
+            SockFlag::SOCK_CLOEXEC,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn ipv4_header() {
+        let addr: SocketAddr = "100.64.0.1:41641".parse().unwrap();
+        let header = encode_header(addr);
+        assert_eq!(
+            header,
+            [
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 100, 64, 0, 1, 0xa2, 0xa9
+            ]
+        );
+        assert_eq!(decode_header(&header), addr);
+    }
+
+    #[test]
+    fn ipv6_header() {
+        let addr: SocketAddr = "[fd7a:115c:a1e0::1]:443".parse().unwrap();
+        let header = encode_header(addr);
+        assert_eq!(
+            header[..16],
+            "fd7a:115c:a1e0::1".parse::<Ipv6Addr>().unwrap().octets()
+        );
+        assert_eq!(header[16..], [0x01, 0xbb]);
+        assert_eq!(decode_header(&header), addr);
+    }
+
+    #[test]
+    fn round_trip_over_socketpair() {
+        let (a, b) = pair();
+        for addr in ["100.64.0.1:53", "[fd7a:115c:a1e0::2]:65535"] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            assert_eq!(send(a.as_fd(), b"first", addr).unwrap(), 5);
+            assert_eq!(send(a.as_fd(), b"second", addr).unwrap(), 6);
+            let mut buf = [0; 64];
+            assert_eq!(recv(b.as_fd(), &mut buf).unwrap(), (5, addr));
+            assert_eq!(&buf[..5], b"first");
+            assert_eq!(recv(b.as_fd(), &mut buf).unwrap(), (6, addr));
+            assert_eq!(&buf[..6], b"second");
+        }
+    }
+
+    #[test]
+    fn datagram_without_header() {
+        let (a, b) = pair();
+        nix::unistd::write(&a, &[0; HEADER_LEN - 1]).unwrap();
+        let e = recv(b.as_fd(), &mut [0; 64]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,114 @@
+//! io_uring based connection IO, enabled by the `uring` feature on Linux.
+//!
+//! [`Connection::into_uring`](crate::Connection::into_uring) hands a
+//! connection's socket to [tokio-uring], whose completion-based reads and
+//! writes save the readiness syscalls of the regular tokio reactor. This pays
+//! off for high-throughput proxies; the connection must then be driven from a
+//! tokio-uring runtime.
+//!
+//! [tokio-uring]: https://docs.rs/tokio-uring
+
+use std::{
+    net::Shutdown,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+};
+
+use tokio_uring::{
+    BufResult,
+    buf::{BoundedBuf, BoundedBufMut},
+    net::UnixStream,
+};
+
+use crate::tailscale::{ConnectionGuards, Result, TailscaleError};
+
+/// A connection driven by io_uring, created by
+/// [`Connection::into_uring`](crate::Connection::into_uring).
+///
+/// Its operations must be awaited inside a tokio-uring runtime, e.g. one
+/// started with [`tokio_uring::start`]. Like
+/// [`Connection`](crate::Connection), it still counts towards the instance's
+/// open connections and its tag's counters until dropped.
+pub struct UringConnection {
+    // Declared before `stream` so the guards are released before the fd is
+    // closed.
+    guards: ConnectionGuards,
+    stream: UnixStream,
+}
+
+impl UringConnection {
+    pub(crate) fn new(fd: OwnedFd, guards: ConnectionGuards) -> Result<Self> {
+        // io_uring waits for readiness itself, so the socket goes back to
+        // blocking mode.
+        let flags = nix::fcntl::OFlag::from_bits_truncate(
+            nix::fcntl::fcntl(&fd, nix::fcntl::FcntlArg::F_GETFL)
+                .map_err(|e| TailscaleError::Tailscale(format!("F_GETFL failed: {}", e)))?,
+        );
+        nix::fcntl::fcntl(
+            &fd,
+            nix::fcntl::FcntlArg::F_SETFL(flags - nix::fcntl::OFlag::O_NONBLOCK),
+        )
+        .map_err(|e| TailscaleError::Tailscale(format!("F_SETFL failed: {}", e)))?;
+
+        Ok(UringConnection {
+            guards,
+            stream: UnixStream::from_std(fd.into()),
+        })
+    }
+
+    /// Reads some data into `buf`, returning it along with the number of
+    /// bytes read.
+    pub async fn read<T: BoundedBufMut>(&self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = self.stream.read(buf).await;
+        if let Ok(n) = res {
+            self.guards.record_read(n);
+        }
+        (res, buf)
+    }
+
+    /// Writes some data from `buf`, returning it along with the number of
+    /// bytes written.
+    pub async fn write<T: BoundedBuf>(&self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = self.stream.write(buf).submit().await;
+        if let Ok(n) = res {
+            self.guards.record_write(n);
+        }
+        (res, buf)
+    }
+
+    /// Writes all of `buf`.
+    pub async fn write_all<T: BoundedBuf>(&self, buf: T) -> BufResult<(), T> {
+        let len = buf.bytes_init();
+        let (res, buf) = self.stream.write_all(buf).await;
+        if res.is_ok() {
+            self.guards.record_write(len);
+        }
+        (res, buf)
+    }
+
+    /// Writes data from several buffers with a single operation, returning
+    /// them along with the number of bytes written.
+    pub async fn writev<T: BoundedBuf>(&self, bufs: Vec<T>) -> BufResult<usize, Vec<T>> {
+        let (res, bufs) = self.stream.writev(bufs).await;
+        if let Ok(n) = res {
+            self.guards.record_write(n);
+        }
+        (res, bufs)
+    }
+
+    /// Shuts down the read half, write half or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        self.stream.shutdown(how)
+    }
+
+    /// Returns the tag set with
+    /// [`Connection::set_tag`](crate::Connection::set_tag), if any.
+    pub fn tag(&self) -> Option<&str> {
+        self.guards.tag()
+    }
+}
+
+impl AsRawFd for UringConnection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
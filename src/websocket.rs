@@ -0,0 +1,138 @@
+//! WebSockets over tailnet connections, enabled by the `websocket` feature.
+//!
+//! The handshake and framing are done by tokio-tungstenite, re-exported as
+//! [`tungstenite`] so message types match the version used here. Traffic
+//! between tailnet nodes is already encrypted by WireGuard, so only `ws://`
+//! URLs are supported.
+
+use std::sync::Arc;
+
+use tokio_tungstenite::{WebSocketStream, tungstenite::client::IntoClientRequest};
+use tracing::debug;
+
+pub use tokio_tungstenite::tungstenite;
+
+use crate::tailscale::{Connection, Listener, NetworkType, Result, Tailscale, TailscaleError};
+
+/// A WebSocket running over a tailnet [`Connection`].
+///
+/// Implements `Stream` of incoming messages and `Sink` of outgoing ones.
+pub type WebSocketConnection = WebSocketStream<Connection>;
+
+impl Tailscale {
+    /// Opens a WebSocket to a `ws://` URL on the tailnet, e.g.
+    /// `ws://chat-server:8080/socket`.
+    ///
+    /// `request` is anything tungstenite accepts as a client request, so
+    /// headers such as `Sec-WebSocket-Protocol` can be set on an
+    /// [`http::Request`](tungstenite::http::Request).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::WebSocket`] if the URL is not a `ws://` URL
+    /// or the handshake fails, or an error if dialing the host fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// let socket = ts.connect_websocket("ws://chat-server:8080/socket").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_websocket(
+        &self,
+        request: impl IntoClientRequest + Unpin,
+    ) -> Result<WebSocketConnection> {
+        let request = request.into_client_request().map_err(websocket_error)?;
+        let uri = request.uri();
+        let port = match uri.scheme_str() {
+            Some("ws") => uri.port_u16().unwrap_or(80),
+            Some(scheme) => {
+                return Err(TailscaleError::WebSocket(format!(
+                    "unsupported URL scheme {scheme:?}, expected \"ws\""
+                )));
+            }
+            None => return Err(TailscaleError::WebSocket("URL has no scheme".to_string())),
+        };
+        let host = uri
+            .host()
+            .ok_or_else(|| TailscaleError::WebSocket("URL has no host".to_string()))?;
+        let host = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host)
+            .to_string();
+
+        debug!(%uri, "opening WebSocket");
+        let conn = self.connect(NetworkType::Tcp, (host, port)).await?;
+        let (socket, _response) = tokio_tungstenite::client_async(request, conn)
+            .await
+            .map_err(websocket_error)?;
+        Ok(socket)
+    }
+}
+
+impl Listener {
+    /// Accepts the next connection and completes the WebSocket upgrade
+    /// handshake on it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting fails, or [`TailscaleError::WebSocket`]
+    /// if the client's request is not a valid WebSocket upgrade.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tailscale2::*;
+    /// # async fn example(ts: std::sync::Arc<Tailscale>) -> Result<()> {
+    /// let listener = ts.listener(NetworkType::Tcp, ":8080").await?;
+    /// loop {
+    ///     let socket = listener.accept_websocket().await?;
+    ///     tokio::spawn(async move {
+    ///         // read and write messages on `socket`
+    ///     });
+    /// }
+    /// # }
+    /// ```
+    pub async fn accept_websocket(self: &Arc<Self>) -> Result<WebSocketConnection> {
+        self.accept().await?.accept_websocket().await
+    }
+}
+
+impl Connection {
+    /// Completes the server side of the WebSocket upgrade handshake on an
+    /// accepted connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::WebSocket`] if the client's request is not a
+    /// valid WebSocket upgrade.
+    pub async fn accept_websocket(self) -> Result<WebSocketConnection> {
+        tokio_tungstenite::accept_async(self)
+            .await
+            .map_err(websocket_error)
+    }
+
+    /// Runs the client side of a WebSocket over an already established
+    /// connection, e.g. one dialed with [`Tailscale::connect`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TailscaleError::WebSocket`] if the handshake fails.
+    pub async fn into_websocket_client(
+        self,
+        request: impl IntoClientRequest + Unpin,
+    ) -> Result<WebSocketConnection> {
+        let (socket, _response) = tokio_tungstenite::client_async(request, self)
+            .await
+            .map_err(websocket_error)?;
+        Ok(socket)
+    }
+}
+
+fn websocket_error(e: tungstenite::Error) -> TailscaleError {
+    TailscaleError::WebSocket(e.to_string())
+}
@@ -3,6 +3,7 @@
 
 #include "tailscale.h"
 #include <sys/socket.h>
+#include <errno.h>
 #include <stdio.h>
 #include <unistd.h>
 
@@ -11,6 +12,7 @@ extern int TsnetNewServer();
 extern int TsnetStart(int sd);
 extern int TsnetUp(int sd);
 extern int TsnetClose(int sd);
+extern int TsnetLogout(int sd);
 extern int TsnetErrmsg(int sd, char* buf, size_t buflen);
 extern int TsnetDial(int sd, char* net, char* addr, int* connOut);
 extern int TsnetSetDir(int sd, char* str);
@@ -18,10 +20,39 @@ extern int TsnetSetHostname(int sd, char* str);
 extern int TsnetSetAuthKey(int sd, char* str);
 extern int TsnetSetControlURL(int sd, char* str);
 extern int TsnetSetEphemeral(int sd, int ephemeral);
+extern int TsnetSetPort(int sd, int port);
+extern int TsnetSetListenBacklog(int sd, int backlog);
+extern int TsnetSetStateStore(int sd, tailscale_state_store* store);
+extern int TsnetSetAdvertiseTags(int sd, char* str);
 extern int TsnetSetLogFD(int sd, int fd);
+extern int TsnetSuspend(int sd);
+extern int TsnetResume(int sd);
+extern int TsnetSetAdvertiseRoutes(int sd, char* routes);
+extern int TsnetSetAcceptRoutes(int sd, int accept);
+extern int TsnetSetExitNode(int sd, char* node);
+extern int TsnetSetShieldsUp(int sd, int shields_up);
 extern int TsnetGetIps(int sd, char *buf, size_t buflen);
+extern int TsnetQuickState(int sd, int* stateOut, unsigned char* ip4Out, unsigned char* ip6Out);
 extern int TsnetGetRemoteAddr(int listener, int conn, char *buf, size_t buflen);
+extern int TsnetGetRemoteAddrPort(int listener, int conn, char *buf, size_t buflen);
+extern int TsnetGetListenerAddr(int listener, char *buf, size_t buflen);
+extern int TsnetGetLocalAddr(int conn, char *buf, size_t buflen);
+extern int TsnetGetPeerAddr(int conn, char *buf, size_t buflen);
+extern int TsnetSetNoDelay(int conn, int no_delay);
+extern int TsnetSetKeepAlive(int conn, int keep_alive);
+extern int TsnetSetLinger(int conn, int sec);
+extern int TsnetWhoIs(int sd, char* addr, char *buf, size_t buflen);
+extern int TsnetCertPair(int sd, char* domain, char *buf, size_t buflen);
+extern int TsnetSelfInfo(int sd, char *buf, size_t buflen);
+extern int TsnetPeers(int sd, char *buf, size_t buflen);
+extern int TsnetWatchEvents(int sd, int* eventsOut);
+extern int TsnetNetMap(int sd, char *buf, size_t buflen);
+extern int TsnetPreflight(int sd, char *buf, size_t buflen);
+extern int TsnetHealth(int sd, char *buf, size_t buflen);
+extern int TsnetPing(int sd, char* peer, char* kind, int timeout_ms, char *buf, size_t buflen);
 extern int TsnetListen(int sd, char* net, char* addr, int* listenerOut);
+extern int TsnetListenFunnel(int sd, char* addr, int funnelOnly, int* listenerOut);
+extern int TsnetListenPacket(int sd, char* addr, int* connOut);
 extern int TsnetLoopback(int sd, char* addrOut, size_t addrLen, char* proxyOut, char* localOut);
 extern int TsnetEnableFunnelToLocalhostPlaintextHttp1(int sd, int localhostPort);
 
@@ -41,6 +72,31 @@ int tailscale_close(tailscale sd) {
 	return TsnetClose(sd);
 }
 
+int tailscale_logout(tailscale sd) {
+	return TsnetLogout(sd);
+}
+
+int tailscale_suspend(tailscale sd) {
+	return TsnetSuspend(sd);
+}
+
+int tailscale_resume(tailscale sd) {
+	return TsnetResume(sd);
+}
+
+int tailscale_set_advertise_routes(tailscale sd, const char* routes) {
+	return TsnetSetAdvertiseRoutes(sd, (char*)routes);
+}
+int tailscale_set_accept_routes(tailscale sd, int accept) {
+	return TsnetSetAcceptRoutes(sd, accept);
+}
+int tailscale_set_exit_node(tailscale sd, const char* node) {
+	return TsnetSetExitNode(sd, (char*)node);
+}
+int tailscale_set_shields_up(tailscale sd, int shields_up) {
+	return TsnetSetShieldsUp(sd, shields_up);
+}
+
 int tailscale_dial(tailscale sd, const char* network, const char* addr, tailscale_conn* conn_out) {
 	return TsnetDial(sd, (char*)network, (char*)addr, (int*)conn_out);
 }
@@ -49,6 +105,14 @@ int tailscale_listen(tailscale sd, const char* network, const char* addr, tailsc
 	return TsnetListen(sd, (char*)network, (char*)addr, (int*)listener_out);
 }
 
+int tailscale_listen_funnel(tailscale sd, const char* addr, int funnel_only, tailscale_listener* listener_out) {
+	return TsnetListenFunnel(sd, (char*)addr, funnel_only, (int*)listener_out);
+}
+
+int tailscale_listen_packet(tailscale sd, const char* addr, tailscale_conn* conn_out) {
+	return TsnetListenPacket(sd, (char*)addr, (int*)conn_out);
+}
+
 int tailscale_accept(tailscale_listener ld, tailscale_conn* conn_out) {
 	struct msghdr msg = {0};
 
@@ -66,6 +130,11 @@ int tailscale_accept(tailscale_listener ld, tailscale_conn* conn_out) {
 	}
 
 	struct cmsghdr* cmsg = CMSG_FIRSTHDR(&msg);
+	if (cmsg == NULL) {
+		// The Go side closed the listener without sending a connection.
+		errno = ECONNABORTED;
+		return -1;
+	}
 	unsigned char* data = CMSG_DATA(cmsg);
 
 	int fd = *(int*)data;
@@ -77,10 +146,78 @@ int tailscale_getremoteaddr(tailscale_listener l, tailscale_conn conn, char* buf
 	return TsnetGetRemoteAddr(l, conn, buf, buflen);
 }
 
+int tailscale_getremoteaddrport(tailscale_listener l, tailscale_conn conn, char* buf, size_t buflen) {
+	return TsnetGetRemoteAddrPort(l, conn, buf, buflen);
+}
+
+int tailscale_getlisteneraddr(tailscale_listener l, char* buf, size_t buflen) {
+	return TsnetGetListenerAddr(l, buf, buflen);
+}
+
+int tailscale_getlocaladdr(tailscale_conn conn, char* buf, size_t buflen) {
+	return TsnetGetLocalAddr(conn, buf, buflen);
+}
+
+int tailscale_getpeeraddr(tailscale_conn conn, char* buf, size_t buflen) {
+	return TsnetGetPeerAddr(conn, buf, buflen);
+}
+
+int tailscale_setnodelay(tailscale_conn conn, int no_delay) {
+	return TsnetSetNoDelay(conn, no_delay);
+}
+
+int tailscale_setkeepalive(tailscale_conn conn, int keep_alive) {
+	return TsnetSetKeepAlive(conn, keep_alive);
+}
+
+int tailscale_setlinger(tailscale_conn conn, int sec) {
+	return TsnetSetLinger(conn, sec);
+}
+
+int tailscale_whois(tailscale sd, const char* addr, char* buf, size_t buflen) {
+	return TsnetWhoIs(sd, (char*)addr, buf, buflen);
+}
+
+int tailscale_self_info(tailscale sd, char* buf, size_t buflen) {
+	return TsnetSelfInfo(sd, buf, buflen);
+}
+
+int tailscale_peers(tailscale sd, char* buf, size_t buflen) {
+	return TsnetPeers(sd, buf, buflen);
+}
+
+int tailscale_watch_events(tailscale sd, int* events_out) {
+	return TsnetWatchEvents(sd, events_out);
+}
+
+int tailscale_cert_pair(tailscale sd, const char* domain, char* buf, size_t buflen) {
+	return TsnetCertPair(sd, (char*)domain, buf, buflen);
+}
+
 int tailscale_getips(tailscale sd, char* buf, size_t buflen) {
 	return TsnetGetIps(sd, buf, buflen);
 }
 
+int tailscale_quick_state(tailscale sd, int* state_out, unsigned char ipv4_out[4], unsigned char ipv6_out[16]) {
+	return TsnetQuickState(sd, state_out, ipv4_out, ipv6_out);
+}
+
+int tailscale_netmap(tailscale sd, char* buf, size_t buflen) {
+	return TsnetNetMap(sd, buf, buflen);
+}
+
+int tailscale_preflight(tailscale sd, char* buf, size_t buflen) {
+	return TsnetPreflight(sd, buf, buflen);
+}
+
+int tailscale_health(tailscale sd, char* buf, size_t buflen) {
+	return TsnetHealth(sd, buf, buflen);
+}
+
+int tailscale_ping(tailscale sd, const char* peer, const char* kind, int timeout_ms, char* buf, size_t buflen) {
+	return TsnetPing(sd, (char*)peer, (char*)kind, timeout_ms, buf, buflen);
+}
+
 int tailscale_set_dir(tailscale sd, const char* dir) {
 	return TsnetSetDir(sd, (char*)dir);
 }
@@ -96,6 +233,27 @@ int tailscale_set_control_url(tailscale sd, const char* control_url) {
 int tailscale_set_ephemeral(tailscale sd, int ephemeral) {
 	return TsnetSetEphemeral(sd, ephemeral);
 }
+int tailscale_set_state_store(tailscale sd, const tailscale_state_store* store) {
+	return TsnetSetStateStore(sd, (tailscale_state_store*)store);
+}
+
+// Called by Go, which cannot call C function pointers directly.
+int tailscale_state_store_read(tailscale_state_store* store, char* key, char** value_out, size_t* len_out) {
+	return store->read(store->ctx, key, value_out, len_out);
+}
+int tailscale_state_store_write(tailscale_state_store* store, char* key, char* value, size_t len) {
+	return store->write(store->ctx, key, value, len);
+}
+
+int tailscale_set_port(tailscale sd, int port) {
+	return TsnetSetPort(sd, port);
+}
+int tailscale_set_listen_backlog(tailscale sd, int backlog) {
+	return TsnetSetListenBacklog(sd, backlog);
+}
+int tailscale_set_advertise_tags(tailscale sd, const char* tags) {
+	return TsnetSetAdvertiseTags(sd, (char*)tags);
+}
 int tailscale_set_logfd(tailscale sd, int fd) {
 	return TsnetSetLogFD(sd, fd);
 }
@@ -5,25 +5,41 @@
 package main
 
 //#include "errno.h"
+//#include <stdlib.h>
+//#include "tailscale.h"
+//
+//int tailscale_state_store_read(tailscale_state_store* store, char* key, char** value_out, size_t* len_out);
+//int tailscale_state_store_write(tailscale_state_store* store, char* key, char* value, size_t len);
 import "C"
 
 import (
 	"context"
+	"encoding/binary"
+	"encoding/json"
+	"errors"
 	"fmt"
 	"io"
 	"net"
+	"net/http"
+	"net/netip"
 	"os"
+	"path/filepath"
 	"regexp"
+	"sort"
 	"strconv"
 	"strings"
 	"sync"
 	"syscall"
+	"time"
 	"unsafe"
 
 	"tailscale.com/hostinfo"
 	"tailscale.com/ipn"
+	"tailscale.com/ipn/ipnstate"
+	"tailscale.com/tailcfg"
 	"tailscale.com/tsnet"
 	"tailscale.com/types/logger"
+	"tailscale.com/types/netmap"
 )
 
 func main() {}
@@ -38,6 +54,9 @@ var servers struct {
 type server struct {
 	s       *tsnet.Server
 	lastErr string
+	// backlog is how many accepted connections a listener queues for C
+	// before it stops accepting, see TsnetSetListenBacklog.
+	backlog int
 }
 
 func getServer(sd C.int) *server {
@@ -142,6 +161,167 @@ func TsnetClose(sd C.int) C.int {
 	return 0
 }
 
+//export TsnetLogout
+func TsnetLogout(sd C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	ctx := context.Background()
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+
+	// Logout blocks until the control plane has acknowledged the request,
+	// which for ephemeral nodes also removes the node from the tailnet.
+	if err := lc.Logout(ctx); err != nil {
+		return s.recErr(err)
+	}
+
+	st, err := lc.StatusWithoutPeers(ctx)
+	if err != nil {
+		return s.recErr(err)
+	}
+	if st.BackendState != ipn.NeedsLogin.String() {
+		return s.recErr(fmt.Errorf("libtailscale: node still registered after logout (state %s)", st.BackendState))
+	}
+	return 0
+}
+
+//export TsnetSuspend
+func TsnetSuspend(sd C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	return s.recErr(s.setWantRunning(false))
+}
+
+//export TsnetResume
+func TsnetResume(sd C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	if err := s.setWantRunning(true); err != nil {
+		return s.recErr(err)
+	}
+	// Up waits for the backend to reach the Running state again.
+	_, err := s.s.Up(context.Background()) // cancellation is via TsnetClose
+	return s.recErr(err)
+}
+
+// setWantRunning starts or stops the backend without logging out, so the
+// node keeps its keys and registration while stopped.
+func (s *server) setWantRunning(want bool) error {
+	return s.editPrefs(&ipn.MaskedPrefs{
+		Prefs:          ipn.Prefs{WantRunning: want},
+		WantRunningSet: true,
+	})
+}
+
+// editPrefs applies the fields of mp marked as set to the node's prefs.
+func (s *server) editPrefs(mp *ipn.MaskedPrefs) error {
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return err
+	}
+	_, err = lc.EditPrefs(context.Background(), mp)
+	return err
+}
+
+//export TsnetSetAcceptRoutes
+func TsnetSetAcceptRoutes(sd C.int, accept C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	return s.recErr(s.editPrefs(&ipn.MaskedPrefs{
+		Prefs:       ipn.Prefs{RouteAll: accept != 0},
+		RouteAllSet: true,
+	}))
+}
+
+//export TsnetSetShieldsUp
+func TsnetSetShieldsUp(sd C.int, shieldsUp C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	return s.recErr(s.editPrefs(&ipn.MaskedPrefs{
+		Prefs:        ipn.Prefs{ShieldsUp: shieldsUp != 0},
+		ShieldsUpSet: true,
+	}))
+}
+
+//export TsnetSetExitNode
+func TsnetSetExitNode(sd C.int, node *C.char) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	// Setting both fields with zero values clears the exit node.
+	mp := &ipn.MaskedPrefs{ExitNodeIDSet: true, ExitNodeIPSet: true}
+	if name := C.GoString(node); name != "" {
+		lc, err := s.s.LocalClient()
+		if err != nil {
+			return s.recErr(err)
+		}
+		st, err := lc.Status(context.Background())
+		if err != nil {
+			return s.recErr(err)
+		}
+		if ps := findPeer(st, name); ps != nil {
+			if !ps.ExitNodeOption {
+				return s.recErr(fmt.Errorf("libtailscale: %s is not offering to be an exit node", name))
+			}
+			mp.ExitNodeID = ps.ID
+		} else if err := mp.SetExitNodeIP(name, st); err != nil {
+			// SetExitNodeIP resolves IP addresses and short MagicDNS names.
+			return s.recErr(err)
+		}
+	}
+	return s.recErr(s.editPrefs(mp))
+}
+
+// findPeer returns the peer whose stable ID or MagicDNS name, full or
+// short, is name.
+func findPeer(st *ipnstate.Status, name string) *ipnstate.PeerStatus {
+	name = strings.TrimSuffix(name, ".")
+	for _, ps := range st.Peer {
+		dnsName := strings.TrimSuffix(ps.DNSName, ".")
+		short, _, _ := strings.Cut(dnsName, ".")
+		if string(ps.ID) == name || strings.EqualFold(dnsName, name) || strings.EqualFold(short, name) {
+			return ps
+		}
+	}
+	return nil
+}
+
+//export TsnetSetAdvertiseRoutes
+func TsnetSetAdvertiseRoutes(sd C.int, routes *C.char) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	var prefixes []netip.Prefix
+	if str := C.GoString(routes); str != "" {
+		for _, route := range strings.Split(str, ",") {
+			prefix, err := netip.ParsePrefix(route)
+			if err != nil {
+				return s.recErr(err)
+			}
+			prefixes = append(prefixes, prefix.Masked())
+		}
+	}
+	return s.recErr(s.editPrefs(&ipn.MaskedPrefs{
+		Prefs:              ipn.Prefs{AdvertiseRoutes: prefixes},
+		AdvertiseRoutesSet: true,
+	}))
+}
+
 //export TsnetGetIps
 func TsnetGetIps(sd C.int, buf *C.char, buflen C.size_t) C.int {
 	if buf == nil {
@@ -172,6 +352,51 @@ func TsnetGetIps(sd C.int, buf *C.char, buflen C.size_t) C.int {
 	return 0
 }
 
+//export TsnetQuickState
+func TsnetQuickState(sd C.int, stateOut *C.int, ip4Out, ip6Out *C.uchar) C.int {
+	if stateOut == nil || ip4Out == nil || ip6Out == nil {
+		panic("quick_state passed nil out parameter")
+	}
+
+	// ip4Out and ip6Out are defined in C as arrays of 4 and 16 bytes.
+	out4 := unsafe.Slice((*byte)(unsafe.Pointer(ip4Out)), 4)
+	out6 := unsafe.Slice((*byte)(unsafe.Pointer(ip6Out)), 16)
+	clear(out4)
+	clear(out6)
+	*stateOut = C.int(ipn.NoState)
+
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+	st, err := lc.StatusWithoutPeers(context.Background())
+	if err != nil {
+		return s.recErr(err)
+	}
+	for state := ipn.NoState; state <= ipn.Running; state++ {
+		if state.String() == st.BackendState {
+			*stateOut = C.int(state)
+			break
+		}
+	}
+
+	ip4, ip6 := s.s.TailscaleIPs()
+	if ip4.Is4() {
+		a := ip4.As4()
+		copy(out4, a[:])
+	}
+	if ip6.Is6() {
+		a := ip6.As16()
+		copy(out6, a[:])
+	}
+	return 0
+}
+
 //export TsnetErrmsg
 func TsnetErrmsg(sd C.int, buf *C.char, buflen C.size_t) C.int {
 	if buf == nil {
@@ -209,7 +434,107 @@ func TsnetListen(sd C.int, network, addr *C.char, listenerOut *C.int) C.int {
 	if err != nil {
 		return s.recErr(err)
 	}
+	return serveListener(s, ln, listenerOut)
+}
+
+//export TsnetListenFunnel
+func TsnetListenFunnel(sd C.int, addr *C.char, funnelOnly C.int, listenerOut *C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	var opts []tsnet.FunnelOption
+	if funnelOnly != 0 {
+		opts = append(opts, tsnet.FunnelOnly())
+	}
+	ln, err := s.s.ListenFunnel("tcp", C.GoString(addr), opts...)
+	if err != nil {
+		return s.recErr(err)
+	}
+	return serveListener(s, ln, listenerOut)
+}
+
+// packetHeaderLen is the size of the address header in front of every
+// datagram exchanged with C over a packet conn: the peer's IP address as 16
+// bytes (IPv4 addresses are IPv4-mapped) followed by its port, big endian.
+const packetHeaderLen = 18
+
+//export TsnetListenPacket
+func TsnetListenPacket(sd C.int, addr *C.char, connOut *C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	pc, err := s.s.ListenPacket("udp", C.GoString(addr))
+	if err != nil {
+		return s.recErr(err)
+	}
+	// Unlike connections, packet conns are carried over a datagram
+	// socketpair, so each read and write on the C side is one datagram.
+	fds, err := syscall.Socketpair(syscall.AF_LOCAL, syscall.SOCK_DGRAM, 0)
+	if err != nil {
+		pc.Close()
+		return s.recErr(err)
+	}
+	r := os.NewFile(uintptr(fds[1]), "socketpair-packet")
+
+	var closeOnce sync.Once
+	cleanup := func() {
+		closeOnce.Do(func() {
+			r.Close()
+			pc.Close()
+		})
+	}
+	go func() {
+		defer cleanup()
+		buf := make([]byte, packetHeaderLen+1<<16)
+		for {
+			n, from, err := pc.ReadFrom(buf[packetHeaderLen:])
+			if err != nil {
+				return
+			}
+			udpAddr, ok := from.(*net.UDPAddr)
+			if !ok {
+				continue
+			}
+			ap := udpAddr.AddrPort()
+			ip := ap.Addr().As16()
+			copy(buf, ip[:])
+			binary.BigEndian.PutUint16(buf[16:packetHeaderLen], ap.Port())
+			if _, err := r.Write(buf[:packetHeaderLen+n]); err != nil {
+				return
+			}
+		}
+	}()
+	go func() {
+		defer cleanup()
+		buf := make([]byte, packetHeaderLen+1<<16)
+		for {
+			// A read of zero bytes means C closed its end.
+			n, err := r.Read(buf)
+			if err != nil || n == 0 {
+				return
+			}
+			if n < packetHeaderLen {
+				continue
+			}
+			ip := netip.AddrFrom16([16]byte(buf[:16])).Unmap()
+			port := binary.BigEndian.Uint16(buf[16:packetHeaderLen])
+			to := net.UDPAddrFromAddrPort(netip.AddrPortFrom(ip, port))
+			if _, err := pc.WriteTo(buf[packetHeaderLen:n], to); err != nil && s.s.Logf != nil {
+				s.s.Logf("libtailscale.packet: write to %v: %v", to, err)
+			}
+		}
+	}()
+
+	*connOut = C.int(fds[0])
+	return 0
+}
 
+// serveListener hands ln to C as a tailscale_listener written to listenerOut.
+func serveListener(s *server, ln net.Listener, listenerOut *C.int) C.int {
 	// The tailscale_listener we return to C is one side of a socketpair(2).
 	// We do this so we can proactively call ln.Accept in a goroutine and
 	// feed an fd for the connection through the listener. This lets C use
@@ -253,13 +578,22 @@ func TsnetListen(sd C.int, network, addr *C.char, listenerOut *C.int) C.int {
 		syscall.Read(sp, buf[:])
 		cleanup()
 	}()
+	// Connections accepted while C is not accepting wait here, so that
+	// bursts of connections are not held up in tsnet's own queue.
+	pending := make(chan net.Conn, s.backlog)
 	go func() {
-		defer cleanup()
+		defer close(pending)
 		for {
 			netConn, err := ln.Accept()
 			if err != nil {
 				return
 			}
+			pending <- netConn
+		}
+	}()
+	go func() {
+		defer cleanup()
+		for netConn := range pending {
 			var connFd C.int
 			if err := newConn(s, netConn, &connFd); err != nil {
 				if s.s.Logf != nil {
@@ -269,14 +603,13 @@ func TsnetListen(sd C.int, network, addr *C.char, listenerOut *C.int) C.int {
 				continue
 			}
 			rights := syscall.UnixRights(int(connFd))
-			err = syscall.Sendmsg(sp, nil, rights, nil, 0)
-			if err != nil {
+			if err := syscall.Sendmsg(sp, nil, rights, nil, 0); err != nil {
 				// We handle sp being closed in the read goroutine above.
 				if s.s.Logf != nil {
 					s.s.Logf("libtailscale.accept: sendmsg failed: %v", err)
 				}
 				netConn.Close()
-				// fallthrough to close connFd, then continue Accept()ing
+				// fallthrough to close connFd, then continue with the next connection
 			}
 
 			// map the connection to the remote address
@@ -349,6 +682,17 @@ func newConn(s *server, netConn net.Conn, connOut *C.int) error {
 
 //export TsnetGetRemoteAddr
 func TsnetGetRemoteAddr(listener C.int, conn C.int, buf *C.char, buflen C.size_t) C.int {
+	return getRemoteAddr(listener, conn, buf, buflen, func(addr net.Addr) string {
+		return extractIP(addr.String())
+	})
+}
+
+//export TsnetGetRemoteAddrPort
+func TsnetGetRemoteAddrPort(listener C.int, conn C.int, buf *C.char, buflen C.size_t) C.int {
+	return getRemoteAddr(listener, conn, buf, buflen, net.Addr.String)
+}
+
+func getRemoteAddr(listener C.int, conn C.int, buf *C.char, buflen C.size_t, format func(net.Addr) string) C.int {
 	if buf == nil {
 		panic("errmsg passed nil buf")
 	} else if buflen == 0 {
@@ -372,49 +716,1004 @@ func TsnetGetRemoteAddr(listener C.int, conn C.int, buf *C.char, buflen C.size_t
 		return C.EBADF
 	}
 
-	ip := extractIP(addr.String())
+	return copyCString(out, format(addr))
+}
 
-	n := copy(out, ip)
-	if n >= len(out) {
-		out[len(out)-1] = '\x00' // always NUL-terminate
-		return C.ERANGE
+//export TsnetGetListenerAddr
+func TsnetGetListenerAddr(listener C.int, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("getlisteneraddr passed nil buf")
+	} else if buflen == 0 {
+		panic("getlisteneraddr passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+
+	listeners.mu.Lock()
+	l := listeners.m[listener]
+	listeners.mu.Unlock()
+	if l == nil {
+		out[0] = '\x00'
+		return C.EBADF
+	}
+
+	addr := l.ln.Addr().String()
+	// Listeners on ":port" report no host; report the node's address
+	// instead, so the result can be dialed.
+	if host, port, err := net.SplitHostPort(addr); err == nil && host == "" {
+		if ip4, ip6 := l.s.s.TailscaleIPs(); ip4.IsValid() {
+			addr = net.JoinHostPort(ip4.String(), port)
+		} else if ip6.IsValid() {
+			addr = net.JoinHostPort(ip6.String(), port)
+		}
+	}
+	return copyCString(out, addr)
+}
+
+//export TsnetGetLocalAddr
+func TsnetGetLocalAddr(conn C.int, buf *C.char, buflen C.size_t) C.int {
+	return getConnAddr(conn, buf, buflen, net.Conn.LocalAddr)
+}
+
+//export TsnetGetPeerAddr
+func TsnetGetPeerAddr(conn C.int, buf *C.char, buflen C.size_t) C.int {
+	return getConnAddr(conn, buf, buflen, net.Conn.RemoteAddr)
+}
+
+func getConnAddr(conn C.int, buf *C.char, buflen C.size_t, addr func(net.Conn) net.Addr) C.int {
+	if buf == nil {
+		panic("getconnaddr passed nil buf")
+	} else if buflen == 0 {
+		panic("getconnaddr passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+
+	conns.mu.Lock()
+	c := conns.m[conn]
+	conns.mu.Unlock()
+	if c == nil {
+		out[0] = '\x00'
+		return C.EBADF
+	}
+
+	return copyCString(out, addr(c.c).String())
+}
+
+//export TsnetSetNoDelay
+func TsnetSetNoDelay(conn C.int, noDelay C.int) C.int {
+	return setConnOpt(conn, func(c net.Conn) error {
+		tc, ok := c.(interface{ SetNoDelay(bool) error })
+		if !ok {
+			return syscall.EOPNOTSUPP
+		}
+		return tc.SetNoDelay(noDelay != 0)
+	})
+}
+
+//export TsnetSetKeepAlive
+func TsnetSetKeepAlive(conn C.int, keepAlive C.int) C.int {
+	return setConnOpt(conn, func(c net.Conn) error {
+		tc, ok := c.(interface{ SetKeepAlive(bool) error })
+		if !ok {
+			return syscall.EOPNOTSUPP
+		}
+		return tc.SetKeepAlive(keepAlive != 0)
+	})
+}
+
+//export TsnetSetLinger
+func TsnetSetLinger(conn C.int, sec C.int) C.int {
+	return setConnOpt(conn, func(c net.Conn) error {
+		tc, ok := c.(interface{ SetLinger(int) error })
+		if !ok {
+			return syscall.EOPNOTSUPP
+		}
+		return tc.SetLinger(int(sec))
+	})
+}
+
+// setConnOpt applies set to the tailnet connection behind conn. The
+// options live on the tailnet connection rather than on conn, which is
+// only the local end of a socketpair.
+func setConnOpt(conn C.int, set func(net.Conn) error) C.int {
+	conns.mu.Lock()
+	c := conns.m[conn]
+	conns.mu.Unlock()
+	if c == nil {
+		return C.EBADF
+	}
+
+	if err := set(c.c); err != nil {
+		var errno syscall.Errno
+		if errors.As(err, &errno) {
+			return C.int(errno)
+		}
+		return C.EIO
 	}
-	out[n] = '\x00'
 	return 0
 }
 
-// Strips the port from connection IPs
-func extractIP(ipWithPort string) string {
-	re := regexp.MustCompile(`(\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3})|\[([0-9a-fA-F:]+)\]`)
-	match := re.FindString(ipWithPort)
-	return match
+// whoIsResult is the JSON representation of a peer identity written by TsnetWhoIs.
+type whoIsResult struct {
+	NodeID      string   `json:"node_id"`
+	NodeName    string   `json:"node_name"`
+	LoginName   string   `json:"login_name"`
+	DisplayName string   `json:"display_name"`
+	Tags        []string `json:"tags"`
 }
 
-//export TsnetDial
-func TsnetDial(sd C.int, network, addr *C.char, connOut *C.int) C.int {
+//export TsnetWhoIs
+func TsnetWhoIs(sd C.int, addr *C.char, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("whois passed nil buf")
+	} else if buflen == 0 {
+		panic("whois passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+	out[0] = '\x00'
+
 	s := getServer(sd)
 	if s == nil {
 		return C.EBADF
 	}
-	netConn, err := s.s.Dial(context.Background(), C.GoString(network), C.GoString(addr))
+
+	lc, err := s.s.LocalClient()
 	if err != nil {
 		return s.recErr(err)
 	}
-	if err := newConn(s, netConn, connOut); err != nil {
+	who, err := lc.WhoIs(context.Background(), C.GoString(addr))
+	if err != nil {
 		return s.recErr(err)
 	}
-	return 0
+
+	res := whoIsResult{
+		NodeID:   string(who.Node.StableID),
+		NodeName: who.Node.Name,
+		Tags:     who.Node.Tags,
+	}
+	if who.UserProfile != nil {
+		res.LoginName = who.UserProfile.LoginName
+		res.DisplayName = who.UserProfile.DisplayName
+	}
+	return s.copyJSON(out, res)
 }
 
-//export TsnetSetDir
-func TsnetSetDir(sd C.int, str *C.char) C.int {
+//export TsnetCertPair
+func TsnetCertPair(sd C.int, domain *C.char, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("cert_pair passed nil buf")
+	} else if buflen == 0 {
+		panic("cert_pair passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+	out[0] = '\x00'
+
 	s := getServer(sd)
 	if s == nil {
 		return C.EBADF
 	}
-	s.s.Dir = C.GoString(str)
-	return 0
-}
+
+	name := C.GoString(domain)
+	if name == "" {
+		domains := s.s.CertDomains()
+		if len(domains) == 0 {
+			return s.recErr(errors.New("no certificate domains available, enable MagicDNS and HTTPS certificates for the tailnet"))
+		}
+		name = domains[0]
+	}
+
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+	ctx, cancel := context.WithTimeout(context.Background(), 2*time.Minute)
+	defer cancel()
+	certPEM, keyPEM, err := lc.CertPair(ctx, name)
+	if err != nil {
+		return s.recErr(err)
+	}
+	return copyCString(out, string(certPEM)+string(keyPEM))
+}
+
+// selfInfoResult is the JSON representation of the node's own identity.
+type selfInfoResult struct {
+	NodeID         string   `json:"node_id"`
+	DNSName        string   `json:"dns_name"`
+	TailnetName    string   `json:"tailnet_name"`
+	MagicDNSSuffix string   `json:"magic_dns_suffix"`
+	Tags           []string `json:"tags"`
+}
+
+//export TsnetSelfInfo
+func TsnetSelfInfo(sd C.int, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("self_info passed nil buf")
+	} else if buflen == 0 {
+		panic("self_info passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+	out[0] = '\x00'
+
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+	st, err := lc.StatusWithoutPeers(context.Background())
+	if err != nil {
+		return s.recErr(err)
+	}
+	if st.Self == nil || st.Self.ID == "" {
+		return s.recErr(errors.New("node has not joined a tailnet yet"))
+	}
+
+	res := selfInfoResult{
+		NodeID:  string(st.Self.ID),
+		DNSName: strings.TrimSuffix(st.Self.DNSName, "."),
+	}
+	if st.Self.Tags != nil {
+		res.Tags = st.Self.Tags.AsSlice()
+	}
+	if st.CurrentTailnet != nil {
+		res.TailnetName = st.CurrentTailnet.Name
+		res.MagicDNSSuffix = st.CurrentTailnet.MagicDNSSuffix
+	}
+	return s.copyJSON(out, res)
+}
+
+// peerResult is the JSON representation of a peer in the tailnet status.
+type peerResult struct {
+	ID        string   `json:"id"`
+	Hostname  string   `json:"hostname"`
+	DNSName   string   `json:"dns_name"`
+	OS        string   `json:"os"`
+	Addresses []string `json:"addresses"`
+	Online    bool     `json:"online"`
+	Tags      []string `json:"tags"`
+	LastSeen  int64    `json:"last_seen"`
+	Relay     string   `json:"relay"`
+	// ExitNodeOption is whether the peer offers to be an exit node.
+	ExitNodeOption bool `json:"exit_node_option"`
+	// ExitNode is whether the peer is the node's current exit node.
+	ExitNode bool `json:"exit_node"`
+}
+
+//export TsnetPeers
+func TsnetPeers(sd C.int, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("peers passed nil buf")
+	} else if buflen == 0 {
+		panic("peers passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+	out[0] = '\x00'
+
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+	st, err := lc.Status(context.Background())
+	if err != nil {
+		return s.recErr(err)
+	}
+
+	res := make([]peerResult, 0, len(st.Peer))
+	for _, ps := range st.Peer {
+		p := peerResult{
+			ID:       string(ps.ID),
+			Hostname: ps.HostName,
+			DNSName:  strings.TrimSuffix(ps.DNSName, "."),
+			OS:       ps.OS,
+			Online:   ps.Online,
+			Relay:    ps.Relay,
+
+			ExitNodeOption: ps.ExitNodeOption,
+			ExitNode:       ps.ExitNode,
+		}
+		for _, ip := range ps.TailscaleIPs {
+			p.Addresses = append(p.Addresses, ip.String())
+		}
+		if ps.Tags != nil {
+			p.Tags = ps.Tags.AsSlice()
+		}
+		if !ps.LastSeen.IsZero() {
+			p.LastSeen = ps.LastSeen.Unix()
+		}
+		res = append(res, p)
+	}
+	sort.Slice(res, func(i, j int) bool { return res[i].DNSName < res[j].DNSName })
+	return s.copyJSON(out, res)
+}
+
+// keyExpiryWarning is how long before the node key expires that a
+// key_expiring event is sent.
+const keyExpiryWarning = 24 * time.Hour
+
+// eventResult is the JSON representation of an event written by TsnetWatchEvents.
+type eventResult struct {
+	Type    string `json:"type"`
+	State   string `json:"state,omitempty"`
+	URL     string `json:"url,omitempty"`
+	ID      string `json:"id,omitempty"`
+	DNSName string `json:"dns_name,omitempty"`
+	Expiry  int64  `json:"expiry,omitempty"` // unix seconds
+}
+
+// eventWatcher turns IPN bus notifications into events, remembering what
+// has already been reported so that only changes are sent.
+type eventWatcher struct {
+	online    map[tailcfg.StableNodeID]eventResult // nil until the first netmap
+	keyExpiry time.Time                            // last expiry reported as expiring
+}
+
+func (ew *eventWatcher) events(n ipn.Notify) []eventResult {
+	var res []eventResult
+	if n.State != nil {
+		res = append(res, eventResult{Type: "state_changed", State: n.State.String()})
+	}
+	if n.BrowseToURL != nil && *n.BrowseToURL != "" {
+		res = append(res, eventResult{Type: "auth_url", URL: *n.BrowseToURL})
+	}
+	if nm := n.NetMap; nm != nil {
+		res = append(res, ew.peerEvents(nm)...)
+		if nm.SelfNode.Valid() {
+			exp := nm.SelfNode.KeyExpiry()
+			if !exp.IsZero() && time.Until(exp) < keyExpiryWarning && !exp.Equal(ew.keyExpiry) {
+				ew.keyExpiry = exp
+				res = append(res, eventResult{Type: "key_expiring", Expiry: exp.Unix()})
+			}
+		}
+	}
+	return res
+}
+
+// peerEvents reports peers that came online or went offline since the
+// previous netmap. The first netmap only records the initial state.
+func (ew *eventWatcher) peerEvents(nm *netmap.NetworkMap) []eventResult {
+	online := map[tailcfg.StableNodeID]eventResult{}
+	for _, peer := range nm.Peers {
+		n := peer.AsStruct()
+		if n.Online != nil && *n.Online {
+			online[n.StableID] = eventResult{
+				Type:    "peer_online",
+				ID:      string(n.StableID),
+				DNSName: strings.TrimSuffix(n.Name, "."),
+			}
+		}
+	}
+	prev := ew.online
+	ew.online = online
+	if prev == nil {
+		return nil
+	}
+
+	var res []eventResult
+	for id, ev := range online {
+		if _, ok := prev[id]; !ok {
+			res = append(res, ev)
+		}
+	}
+	for id, ev := range prev {
+		if _, ok := online[id]; !ok {
+			ev.Type = "peer_offline"
+			res = append(res, ev)
+		}
+	}
+	return res
+}
+
+// fdWriter writes to a blocking file descriptor.
+type fdWriter int
+
+func (w fdWriter) Write(p []byte) (int, error) {
+	n := 0
+	for n < len(p) {
+		m, err := syscall.Write(int(w), p[n:])
+		if err == syscall.EINTR {
+			continue
+		} else if err != nil {
+			return n, err
+		}
+		n += m
+	}
+	return n, nil
+}
+
+//export TsnetWatchEvents
+func TsnetWatchEvents(sd C.int, eventsOut *C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+	ctx, cancel := context.WithCancel(context.Background())
+	watcher, err := lc.WatchIPNBus(ctx, ipn.NotifyInitialState|ipn.NotifyInitialNetMap|ipn.NotifyNoPrivateKeys)
+	if err != nil {
+		cancel()
+		return s.recErr(err)
+	}
+
+	// Like a listener, the events handle we return to C is one side of a
+	// socketpair(2). Events are written to it as newline-delimited JSON.
+	fds, err := syscall.Socketpair(syscall.AF_LOCAL, syscall.SOCK_STREAM, 0)
+	if err != nil {
+		watcher.Close()
+		cancel()
+		return s.recErr(err)
+	}
+	sp := fds[1]
+
+	readDone := make(chan struct{})
+	go func() {
+		// fdC is never written to, so reading from sp blocks until C
+		// closes the handle or the watch below ends and shuts sp down.
+		var buf [256]byte
+		syscall.Read(sp, buf[:])
+		cancel()
+		close(readDone)
+	}()
+	go func() {
+		defer func() {
+			watcher.Close()
+			syscall.Shutdown(sp, syscall.SHUT_RDWR)
+			<-readDone
+			syscall.Close(sp)
+		}()
+		enc := json.NewEncoder(fdWriter(sp))
+		var ew eventWatcher
+		for {
+			n, err := watcher.Next()
+			if err != nil {
+				return
+			}
+			for _, ev := range ew.events(n) {
+				if err := enc.Encode(ev); err != nil {
+					return
+				}
+			}
+		}
+	}()
+
+	*eventsOut = C.int(fds[0])
+	return 0
+}
+
+// netMapNode is the JSON representation of a node in the network map.
+type netMapNode struct {
+	ID        string   `json:"id"`
+	Name      string   `json:"name"`
+	Hostname  string   `json:"hostname"`
+	OS        string   `json:"os"`
+	Addresses []string `json:"addresses"`
+	Tags      []string `json:"tags"`
+	Online    *bool    `json:"online"`
+	KeyExpiry int64    `json:"key_expiry"` // unix seconds, 0 if the key does not expire
+}
+
+func newNetMapNode(nv tailcfg.NodeView) netMapNode {
+	if !nv.Valid() {
+		return netMapNode{}
+	}
+	n := nv.AsStruct()
+	res := netMapNode{
+		ID:     string(n.StableID),
+		Name:   n.Name,
+		Tags:   n.Tags,
+		Online: n.Online,
+	}
+	if n.Hostinfo.Valid() {
+		res.Hostname = n.Hostinfo.Hostname()
+		res.OS = n.Hostinfo.OS()
+	}
+	for _, pfx := range n.Addresses {
+		res.Addresses = append(res.Addresses, pfx.Addr().String())
+	}
+	if !n.KeyExpiry.IsZero() {
+		res.KeyExpiry = n.KeyExpiry.Unix()
+	}
+	return res
+}
+
+// netMapDNS is the JSON representation of the tailnet DNS configuration.
+type netMapDNS struct {
+	MagicDNSSuffix string   `json:"magic_dns_suffix"`
+	SearchDomains  []string `json:"search_domains"`
+	Nameservers    []string `json:"nameservers"`
+}
+
+// netMapDERPRegion is the JSON representation of a DERP region.
+type netMapDERPRegion struct {
+	ID    int      `json:"id"`
+	Code  string   `json:"code"`
+	Name  string   `json:"name"`
+	Hosts []string `json:"hosts"`
+}
+
+// netMapResult is the JSON representation of the network map written by TsnetNetMap.
+type netMapResult struct {
+	Self        netMapNode         `json:"self"`
+	Peers       []netMapNode       `json:"peers"`
+	DNS         netMapDNS          `json:"dns"`
+	DERPRegions []netMapDERPRegion `json:"derp_regions"`
+	Domain      string             `json:"domain"`
+}
+
+func newNetMapResult(nm *netmap.NetworkMap) netMapResult {
+	res := netMapResult{
+		Self:   newNetMapNode(nm.SelfNode),
+		Domain: nm.Domain,
+		DNS: netMapDNS{
+			MagicDNSSuffix: nm.MagicDNSSuffix(),
+			SearchDomains:  nm.DNS.Domains,
+		},
+	}
+	for _, peer := range nm.Peers {
+		res.Peers = append(res.Peers, newNetMapNode(peer))
+	}
+	for _, r := range nm.DNS.Resolvers {
+		res.DNS.Nameservers = append(res.DNS.Nameservers, r.Addr)
+	}
+	if nm.DERPMap != nil {
+		for _, r := range nm.DERPMap.Regions {
+			region := netMapDERPRegion{ID: r.RegionID, Code: r.RegionCode, Name: r.RegionName}
+			for _, n := range r.Nodes {
+				region.Hosts = append(region.Hosts, n.HostName)
+			}
+			res.DERPRegions = append(res.DERPRegions, region)
+		}
+		sort.Slice(res.DERPRegions, func(i, j int) bool {
+			return res.DERPRegions[i].ID < res.DERPRegions[j].ID
+		})
+	}
+	return res
+}
+
+//export TsnetNetMap
+func TsnetNetMap(sd C.int, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("netmap passed nil buf")
+	} else if buflen == 0 {
+		panic("netmap passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+	out[0] = '\x00'
+
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+
+	// The IPN bus delivers the current network map as the first
+	// notification when asked for NotifyInitialNetMap.
+	ctx, cancel := context.WithTimeout(context.Background(), 10*time.Second)
+	defer cancel()
+	w, err := lc.WatchIPNBus(ctx, ipn.NotifyInitialNetMap)
+	if err != nil {
+		return s.recErr(err)
+	}
+	defer w.Close()
+	for {
+		n, err := w.Next()
+		if err != nil {
+			return s.recErr(err)
+		}
+		if n.NetMap != nil {
+			return s.copyJSON(out, newNetMapResult(n.NetMap))
+		}
+	}
+}
+
+// maxClockSkew is the largest difference to the control plane's clock that
+// TsnetPreflight accepts.
+const maxClockSkew = time.Minute
+
+// preflightCheck is the JSON representation of a single preflight check.
+type preflightCheck struct {
+	OK     bool   `json:"ok"`
+	Detail string `json:"detail"`
+}
+
+func checkOK(detail string) preflightCheck {
+	return preflightCheck{OK: true, Detail: detail}
+}
+
+func checkErr(err error) preflightCheck {
+	return preflightCheck{Detail: err.Error()}
+}
+
+// preflightResult is the JSON representation of the report written by TsnetPreflight.
+type preflightResult struct {
+	Control         preflightCheck `json:"control"`
+	DERP            preflightCheck `json:"derp"`
+	UDP             preflightCheck `json:"udp"`
+	StateDir        preflightCheck `json:"state_dir"`
+	TimeSkew        preflightCheck `json:"time_skew"`
+	ClockSkewMillis int64          `json:"clock_skew_ms"`
+}
+
+//export TsnetPreflight
+func TsnetPreflight(sd C.int, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("preflight passed nil buf")
+	} else if buflen == 0 {
+		panic("preflight passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+	out[0] = '\x00'
+
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	ctx, cancel := context.WithTimeout(context.Background(), 15*time.Second)
+	defer cancel()
+
+	var res preflightResult
+	res.StateDir = checkStateDir(s.s.Dir)
+	res.UDP = checkUDP(s.s.Port)
+
+	controlURL := s.s.ControlURL
+	if controlURL == "" {
+		controlURL = ipn.DefaultControlURL
+	}
+	skew, err := checkControl(ctx, controlURL)
+	if err != nil {
+		res.Control = checkErr(err)
+		res.TimeSkew = checkErr(fmt.Errorf("control plane unreachable: %w", err))
+	} else {
+		res.Control = checkOK(controlURL)
+		res.ClockSkewMillis = skew.Milliseconds()
+		if skew.Abs() > maxClockSkew {
+			res.TimeSkew = preflightCheck{Detail: fmt.Sprintf("local clock differs from control plane by %v", skew)}
+		} else {
+			res.TimeSkew = checkOK(skew.String())
+		}
+	}
+	res.DERP = checkDERP(ctx, controlURL)
+
+	return s.copyJSON(out, res)
+}
+
+// checkStateDir checks that the tsnet state directory can be written to.
+func checkStateDir(dir string) preflightCheck {
+	if dir == "" {
+		// Mirror the default chosen by tsnet.Server.
+		confDir, err := os.UserConfigDir()
+		if err != nil {
+			return checkErr(err)
+		}
+		prog := strings.TrimSuffix(filepath.Base(os.Args[0]), ".exe")
+		dir = filepath.Join(confDir, "tsnet-"+prog)
+	}
+	if err := os.MkdirAll(dir, 0700); err != nil {
+		return checkErr(err)
+	}
+	f, err := os.CreateTemp(dir, ".preflight-*")
+	if err != nil {
+		return checkErr(err)
+	}
+	f.Close()
+	os.Remove(f.Name())
+	return checkOK(dir)
+}
+
+// checkUDP checks that a UDP socket can be bound on the configured port.
+func checkUDP(port uint16) preflightCheck {
+	pc, err := net.ListenPacket("udp", fmt.Sprintf(":%d", port))
+	if err != nil {
+		return checkErr(err)
+	}
+	defer pc.Close()
+	return checkOK(pc.LocalAddr().String())
+}
+
+// checkControl makes a request to the control plane and returns the
+// difference between the local clock and the control plane's Date header.
+func checkControl(ctx context.Context, controlURL string) (time.Duration, error) {
+	req, err := http.NewRequestWithContext(ctx, "HEAD", controlURL, nil)
+	if err != nil {
+		return 0, err
+	}
+	resp, err := http.DefaultClient.Do(req)
+	if err != nil {
+		return 0, err
+	}
+	resp.Body.Close()
+	date, err := http.ParseTime(resp.Header.Get("Date"))
+	if err != nil {
+		return 0, fmt.Errorf("control plane sent no usable Date header: %w", err)
+	}
+	return time.Since(date), nil
+}
+
+// checkDERP fetches the default DERP map from the control plane and checks
+// that at least one DERP server answers its probe endpoint.
+func checkDERP(ctx context.Context, controlURL string) preflightCheck {
+	req, err := http.NewRequestWithContext(ctx, "GET", strings.TrimSuffix(controlURL, "/")+"/derpmap/default", nil)
+	if err != nil {
+		return checkErr(err)
+	}
+	resp, err := http.DefaultClient.Do(req)
+	if err != nil {
+		return checkErr(err)
+	}
+	defer resp.Body.Close()
+	if resp.StatusCode != http.StatusOK {
+		return checkErr(fmt.Errorf("fetching DERP map: unexpected status %s", resp.Status))
+	}
+	var dm tailcfg.DERPMap
+	if err := json.NewDecoder(resp.Body).Decode(&dm); err != nil {
+		return checkErr(fmt.Errorf("decoding DERP map: %w", err))
+	}
+
+	lastErr := errors.New("DERP map contains no servers")
+	for _, region := range dm.Regions {
+		for _, n := range region.Nodes {
+			if err := probeDERP(ctx, n.HostName); err != nil {
+				lastErr = err
+				continue
+			}
+			return checkOK(n.HostName)
+		}
+	}
+	return checkErr(lastErr)
+}
+
+func probeDERP(ctx context.Context, host string) error {
+	req, err := http.NewRequestWithContext(ctx, "GET", "https://"+host+"/derp/probe", nil)
+	if err != nil {
+		return err
+	}
+	resp, err := http.DefaultClient.Do(req)
+	if err != nil {
+		return err
+	}
+	resp.Body.Close()
+	if resp.StatusCode != http.StatusOK {
+		return fmt.Errorf("%s: unexpected status %s", host, resp.Status)
+	}
+	return nil
+}
+
+// healthResult is the JSON representation of the report written by TsnetHealth.
+type healthResult struct {
+	BackendState string   `json:"backend_state"`
+	Control      bool     `json:"control"`
+	DERP         string   `json:"derp"`
+	KeyExpiry    int64    `json:"key_expiry"` // unix seconds, 0 if the key does not expire
+	KeyExpired   bool     `json:"key_expired"`
+	Warnings     []string `json:"warnings"`
+}
+
+//export TsnetHealth
+func TsnetHealth(sd C.int, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("health passed nil buf")
+	} else if buflen == 0 {
+		panic("health passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+	out[0] = '\x00'
+
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+	ctx, cancel := context.WithTimeout(context.Background(), 5*time.Second)
+	defer cancel()
+	st, err := lc.StatusWithoutPeers(ctx)
+	if err != nil {
+		return s.recErr(err)
+	}
+
+	res := healthResult{
+		BackendState: st.BackendState,
+		Warnings:     st.Health,
+	}
+	if st.Self != nil {
+		res.Control = st.Self.Online
+		res.DERP = st.Self.Relay
+		res.KeyExpired = st.Self.Expired
+		if st.Self.KeyExpiry != nil {
+			res.KeyExpiry = st.Self.KeyExpiry.Unix()
+			res.KeyExpired = res.KeyExpired || time.Now().After(*st.Self.KeyExpiry)
+		}
+	}
+	return s.copyJSON(out, res)
+}
+
+// pingResult is the JSON representation of the result written by TsnetPing.
+type pingResult struct {
+	NodeIP         string  `json:"node_ip"`
+	NodeName       string  `json:"node_name"`
+	LatencySeconds float64 `json:"latency_seconds"`
+	Endpoint       string  `json:"endpoint"`         // ip:port of a direct path, empty if relayed
+	DERPRegionCode string  `json:"derp_region_code"` // DERP region relaying, empty if direct
+}
+
+//export TsnetPing
+func TsnetPing(sd C.int, peer *C.char, kind *C.char, timeoutMillis C.int, buf *C.char, buflen C.size_t) C.int {
+	if buf == nil {
+		panic("ping passed nil buf")
+	} else if buflen == 0 {
+		panic("ping passed buflen of 0")
+	}
+	out := unsafe.Slice((*byte)(unsafe.Pointer(buf)), buflen)
+	out[0] = '\x00'
+
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+
+	pingType := tailcfg.PingType(C.GoString(kind))
+	switch pingType {
+	case tailcfg.PingDisco, tailcfg.PingICMP, tailcfg.PingTSMP:
+	default:
+		return s.recErr(fmt.Errorf("libtailscale: unknown ping type %q", pingType))
+	}
+
+	ctx, cancel := context.WithTimeout(context.Background(), time.Duration(timeoutMillis)*time.Millisecond)
+	defer cancel()
+	lc, err := s.s.LocalClient()
+	if err != nil {
+		return s.recErr(err)
+	}
+	name := C.GoString(peer)
+	ip, err := netip.ParseAddr(name)
+	if err != nil {
+		st, err := lc.Status(ctx)
+		if err != nil {
+			return s.recErr(err)
+		}
+		ps := findPeer(st, name)
+		if ps == nil || len(ps.TailscaleIPs) == 0 {
+			return s.recErr(fmt.Errorf("libtailscale: unknown peer %q", name))
+		}
+		ip = ps.TailscaleIPs[0]
+	}
+
+	pr, err := lc.Ping(ctx, ip, pingType)
+	if err != nil {
+		return s.recErr(err)
+	}
+	if pr.Err != "" {
+		return s.recErr(errors.New(pr.Err))
+	}
+	return s.copyJSON(out, pingResult{
+		NodeIP:         pr.NodeIP,
+		NodeName:       strings.TrimSuffix(pr.NodeName, "."),
+		LatencySeconds: pr.LatencySeconds,
+		Endpoint:       pr.Endpoint,
+		DERPRegionCode: pr.DERPRegionCode,
+	})
+}
+
+// copyJSON marshals v as JSON into out, always NUL-terminating it.
+// It returns ERANGE if out is too small, so the caller can retry with a
+// larger buffer.
+func (s *server) copyJSON(out []byte, v any) C.int {
+	b, err := json.Marshal(v)
+	if err != nil {
+		return s.recErr(err)
+	}
+	return copyCString(out, string(b))
+}
+
+// copyCString copies s into out, always NUL-terminating it.
+// It returns ERANGE if out is too small to hold all of s.
+func copyCString(out []byte, s string) C.int {
+	n := copy(out, s)
+	if n >= len(out) {
+		out[len(out)-1] = '\x00' // always NUL-terminate
+		return C.ERANGE
+	}
+	out[n] = '\x00'
+	return 0
+}
+
+// Strips the port from connection IPs
+func extractIP(ipWithPort string) string {
+	re := regexp.MustCompile(`(\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3})|\[([0-9a-fA-F:]+)\]`)
+	match := re.FindString(ipWithPort)
+	return match
+}
+
+//export TsnetDial
+func TsnetDial(sd C.int, network, addr *C.char, connOut *C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	netConn, err := s.s.Dial(context.Background(), C.GoString(network), C.GoString(addr))
+	if err != nil {
+		return s.recErr(err)
+	}
+	if err := newConn(s, netConn, connOut); err != nil {
+		return s.recErr(err)
+	}
+	return 0
+}
+
+//export TsnetSetDir
+func TsnetSetDir(sd C.int, str *C.char) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	s.s.Dir = C.GoString(str)
+	return 0
+}
+
+//export TsnetSetStateStore
+func TsnetSetStateStore(sd C.int, store *C.tailscale_state_store) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	s.s.Store = &callbackStore{store: store}
+	return 0
+}
+
+// callbackStore is an ipn.StateStore that calls back into the application.
+// The tailscale_state_store it points to is owned by the application and
+// outlives the server.
+type callbackStore struct {
+	store *C.tailscale_state_store
+}
+
+func (cs *callbackStore) ReadState(id ipn.StateKey) ([]byte, error) {
+	key := C.CString(string(id))
+	defer C.free(unsafe.Pointer(key))
+	var value *C.char
+	var n C.size_t
+	switch ret := C.tailscale_state_store_read(cs.store, key, &value, &n); ret {
+	case 0:
+	case C.ENOENT:
+		return nil, ipn.ErrStateNotExist
+	default:
+		return nil, fmt.Errorf("libtailscale: state store could not read %q", id)
+	}
+	if value == nil {
+		return []byte{}, nil
+	}
+	defer C.free(unsafe.Pointer(value))
+	return C.GoBytes(unsafe.Pointer(value), C.int(n)), nil
+}
+
+func (cs *callbackStore) WriteState(id ipn.StateKey, bs []byte) error {
+	key := C.CString(string(id))
+	defer C.free(unsafe.Pointer(key))
+	value := C.CBytes(bs)
+	defer C.free(value)
+	if ret := C.tailscale_state_store_write(cs.store, key, (*C.char)(value), C.size_t(len(bs))); ret != 0 {
+		return fmt.Errorf("libtailscale: state store could not write %q", id)
+	}
+	return nil
+}
 
 //export TsnetSetHostname
 func TsnetSetHostname(sd C.int, str *C.char) C.int {
@@ -460,6 +1759,50 @@ func TsnetSetEphemeral(sd C.int, e int) C.int {
 	return 0
 }
 
+//export TsnetSetPort
+func TsnetSetPort(sd C.int, port C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	if port < 0 || port > 65535 {
+		return s.recErr(fmt.Errorf("libtailscale: invalid port %d", port))
+	}
+	s.s.Port = uint16(port)
+	return 0
+}
+
+//export TsnetSetListenBacklog
+func TsnetSetListenBacklog(sd C.int, backlog C.int) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	if backlog < 0 || backlog > maxListenBacklog {
+		return s.recErr(fmt.Errorf("libtailscale: invalid listen backlog %d", backlog))
+	}
+	s.backlog = int(backlog)
+	return 0
+}
+
+// maxListenBacklog bounds the queue of accepted connections per listener.
+const maxListenBacklog = 65535
+
+//export TsnetSetAdvertiseTags
+func TsnetSetAdvertiseTags(sd C.int, str *C.char) C.int {
+	s := getServer(sd)
+	if s == nil {
+		return C.EBADF
+	}
+	tags := C.GoString(str)
+	if tags == "" {
+		s.s.AdvertiseTags = nil
+	} else {
+		s.s.AdvertiseTags = strings.Split(tags, ",")
+	}
+	return 0
+}
+
 //export TsnetSetLogFD
 func TsnetSetLogFD(sd, fd C.int) C.int {
 	s := getServer(sd)
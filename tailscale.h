@@ -55,6 +55,66 @@ extern int tailscale_up(tailscale sd);
 // 	-1    - other error, details printed to the tsnet logger
 extern int tailscale_close(tailscale sd);
 
+// tailscale_logout logs the node out of the control plane.
+//
+// The call blocks until the control plane has acknowledged the logout.
+// Ephemeral nodes are removed from the tailnet as part of this process.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_logout(tailscale sd);
+
+// tailscale_suspend stops the server's background activity, such as control
+// plane polling and peer keepalives, without logging out.
+//
+// Existing connections stall until tailscale_resume is called.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_suspend(tailscale sd);
+
+// tailscale_resume restarts a server stopped by tailscale_suspend and waits
+// for it to be usable again.
+//
+// To cancel an in-progress call to tailscale_resume, use tailscale_close.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_resume(tailscale sd);
+
+// tailscale_set_advertise_routes sets the subnet routes the node advertises
+// to the tailnet, as a comma-separated list of CIDR prefixes such as
+// "192.168.1.0/24,fd00::/64". An empty string stops advertising routes.
+//
+// Unlike the options below, this may be called while the server is running.
+// Advertised routes must still be approved for the tailnet.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_set_advertise_routes(tailscale sd, const char* routes);
+
+// tailscale_set_accept_routes sets whether the node uses subnet routes
+// advertised by other nodes on the tailnet. A non-zero accept enables them.
+//
+// May be called while the server is running.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_set_accept_routes(tailscale sd, int accept);
+
+// tailscale_set_exit_node routes the node's internet-bound traffic through
+// the exit node named by node: a stable node ID, a MagicDNS name or a
+// Tailscale IP address. An empty string stops using an exit node.
+//
+// May be called while the server is running.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_set_exit_node(tailscale sd, const char* node);
+
+// tailscale_set_shields_up sets whether the node refuses all incoming
+// connections. A non-zero shields_up blocks them; outgoing connections keep
+// working.
+//
+// May be called while the server is running.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_set_shields_up(tailscale sd, int shields_up);
+
 // The following set tailscale configuration options.
 //
 // Configure these options before any explicit or implicit call to tailscale_start.
@@ -68,6 +128,69 @@ extern int tailscale_set_authkey(tailscale sd, const char* authkey);
 extern int tailscale_set_control_url(tailscale sd, const char* control_url);
 extern int tailscale_set_ephemeral(tailscale sd, int ephemeral);
 
+// tailscale_state_store holds callbacks through which the server reads and
+// writes its state, such as its keys and login profile, instead of a file
+// in the state directory.
+//
+// Both callbacks may be called from any thread, concurrently.
+typedef struct tailscale_state_store {
+	// ctx is passed to both callbacks unchanged.
+	void* ctx;
+
+	// read looks up the state stored under key. On success it writes a
+	// buffer allocated with malloc to *value_out, which libtailscale frees,
+	// and its length to *len_out.
+	//
+	// Returns zero on success, ENOENT if nothing is stored under key, or -1
+	// on error.
+	int (*read)(void* ctx, const char* key, char** value_out, size_t* len_out);
+
+	// write stores the len bytes at value under key, replacing any previous
+	// value.
+	//
+	// Returns zero on success or -1 on error.
+	int (*write)(void* ctx, const char* key, const char* value, size_t len);
+} tailscale_state_store;
+
+// tailscale_set_state_store makes the server keep its state in store rather
+// than the state directory. The state directory is still used for logs and
+// certificates.
+//
+// store and its ctx must stay valid until tailscale_close returns.
+//
+// Configure this option before any explicit or implicit call to tailscale_start.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_set_state_store(tailscale sd, const tailscale_state_store* store);
+
+// tailscale_set_port sets the UDP port the WireGuard engine listens on for
+// direct connections. Zero, the default, picks a port automatically.
+//
+// Configure this option before any explicit or implicit call to tailscale_start.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_set_port(tailscale sd, int port);
+
+// tailscale_set_listen_backlog sets how many accepted connections each
+// listener queues until tailscale_accept takes them, between 0 and 65535.
+// Once the queue is full, new connections wait in the network stack, which
+// drops connection attempts when it is full in turn. Zero, the default,
+// queues only what fits into the listener's socket buffer.
+//
+// Applies to listeners created after the call.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_set_listen_backlog(tailscale sd, int backlog);
+
+// tailscale_set_advertise_tags sets the ACL tags the node advertises, as a
+// comma-separated list such as "tag:server,tag:ci". An empty string
+// advertises no tags.
+//
+// Configure this option before any explicit or implicit call to tailscale_start.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_set_advertise_tags(tailscale sd, const char* tags);
+
 // tailscale_set_logfd instructs the tailscale instance to write logs to fd.
 //
 // An fd value of -1 means discard all logging.
@@ -95,6 +218,84 @@ typedef int tailscale_conn;
 // 	ERANGE - insufficient storage for buf
 extern int tailscale_getips(tailscale sd, char* buf, size_t buflen);
 
+// tailscale_quick_state writes the backend state and the raw IP addresses of
+// the Tailscale server into caller-provided storage.
+//
+// state_out receives the numeric ipn.State of the backend:
+// 	0 - NoState, 1 - InUseOtherUser, 2 - NeedsLogin, 3 - NeedsMachineAuth,
+// 	4 - Stopped, 5 - Starting, 6 - Running
+//
+// ipv4_out and ipv6_out receive the addresses in network byte order, and are
+// zero-filled if no address of that family has been assigned.
+//
+// Returns:
+//  0      - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_quick_state(tailscale sd, int* state_out, unsigned char ipv4_out[4], unsigned char ipv6_out[16]);
+
+// tailscale_netmap writes a snapshot of the node's current network map to buf.
+//
+// The snapshot is read from the IPN bus and written as a NUL-terminated JSON
+// object with the fields self, peers, dns, derp_regions and domain.
+// The call waits up to 10 seconds for a network map to become available.
+//
+// Returns:
+//  0      - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	ERANGE - insufficient storage for buf
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_netmap(tailscale sd, char* buf, size_t buflen);
+
+// tailscale_preflight runs startup diagnostics and writes a report to buf.
+//
+// It checks control plane and DERP reachability, UDP socket availability,
+// state directory writability and clock skew against the control plane.
+// Call it after configuring sd but before tailscale_start or tailscale_up.
+//
+// The report is a NUL-terminated JSON object with the fields control, derp,
+// udp, state_dir and time_skew, each holding ok and detail, plus clock_skew_ms.
+//
+// Returns:
+//  0      - Success, even if some checks failed
+// 	EBADF  - sd is not a valid tailscale
+// 	ERANGE - insufficient storage for buf
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_preflight(tailscale sd, char* buf, size_t buflen);
+
+// tailscale_health writes a snapshot of the node's health to buf.
+//
+// The report is a NUL-terminated JSON object with the fields backend_state,
+// control (whether the control plane reports the node online), derp (the home
+// DERP region, empty if none), key_expiry (unix seconds, 0 if the key does not
+// expire), key_expired and warnings (the current health warnings).
+//
+// Returns:
+//  0      - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	ERANGE - insufficient storage for buf
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_health(tailscale sd, char* buf, size_t buflen);
+
+// tailscale_ping pings peer, a Tailscale IP address, MagicDNS name or stable
+// node ID, and writes the result to buf.
+//
+// kind is one of "disco" (WireGuard path discovery), "ICMP" or "TSMP"
+// (Tailscale's own ping, answered by the peer's Tailscale stack). The call
+// fails if no reply arrives within timeout_ms milliseconds.
+//
+// The result is a NUL-terminated JSON object with the fields node_ip,
+// node_name, latency_seconds, endpoint (the ip:port of the direct path, empty
+// if relayed) and derp_region_code (the relaying DERP region, empty if
+// direct).
+//
+// Returns:
+//  0      - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	ERANGE - insufficient storage for buf
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_ping(tailscale sd, const char* peer, const char* kind, int timeout_ms, char* buf, size_t buflen);
+
 // tailscale_dial connects to the address on the tailnet.
 //
 // The newly allocated connection is written to conn_out.
@@ -131,6 +332,35 @@ typedef int tailscale_listener;
 // Returns zero on success or -1 on error, call tailscale_errmsg for details.
 extern int tailscale_listen(tailscale sd, const char* network, const char* addr, tailscale_listener* listener_out);
 
+// tailscale_listen_funnel listens for TCP connections from the public
+// internet via Tailscale Funnel, as well as from the tailnet.
+//
+// TLS is terminated by tailscale, so accepted connections carry plaintext.
+// addr is a NUL-terminated string of the form ":443"; Funnel only supports
+// ports 443, 8443 and 10000. If funnel_only is non-zero, connections from
+// the tailnet are not accepted.
+//
+// The newly allocated listener is written to listener_out and is used like
+// one from tailscale_listen.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_listen_funnel(tailscale sd, const char* addr, int funnel_only, tailscale_listener* listener_out);
+
+// tailscale_listen_packet opens a UDP packet socket on the tailnet.
+//
+// It is the spiritual equivalent to socket(2) and bind(2) for SOCK_DGRAM.
+// addr is a NUL-terminated string of the form "ip:port", where ip is one of
+// the node's Tailscale IP addresses.
+//
+// The socket is written to conn_out as one half of a datagram socketpair.
+// Every datagram read from or written to it starts with an 18-byte header:
+// the peer's IP address as 16 bytes (IPv4 addresses are IPv4-mapped IPv6
+// addresses) followed by its port in network byte order. The payload
+// follows the header. Close the socket with close.
+//
+// Returns zero on success or -1 on error, call tailscale_errmsg for details.
+extern int tailscale_listen_packet(tailscale sd, const char* addr, tailscale_conn* conn_out);
+
 // Returns the remote address for an incoming connection for a particular listener.  The address (eitehr ip4 or ip6)
 // will ge written to buf on on success.
 // Returns:
@@ -139,6 +369,152 @@ extern int tailscale_listen(tailscale sd, const char* network, const char* addr,
 // 	ERANGE - insufficient storage for buf
 extern int tailscale_getremoteaddr(tailscale_listener l, tailscale_conn conn, char* buf, size_t buflen);
 
+// tailscale_getremoteaddrport is like tailscale_getremoteaddr, but writes the
+// address including the remote port, e.g. "100.64.0.1:41234" or "[fd7a::1]:41234".
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - l or conn are not valid listeners or connections
+// 	ERANGE - insufficient storage for buf
+extern int tailscale_getremoteaddrport(tailscale_listener l, tailscale_conn conn, char* buf, size_t buflen);
+
+// tailscale_getlisteneraddr writes the tailnet address and port l listens
+// on to buf, e.g. "100.64.0.1:8080".
+//
+// Listeners on ":port" report the node's tailnet address. As l is one half
+// of a socketpair, getsockname(2) on it does not return the tailnet address.
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - l is not a valid listener
+// 	ERANGE - insufficient storage for buf
+extern int tailscale_getlisteneraddr(tailscale_listener l, char* buf, size_t buflen);
+
+// tailscale_getlocaladdr writes the tailnet address and port of the local
+// end of conn to buf, e.g. "100.64.0.1:8080".
+//
+// As conn is one half of a socketpair, getsockname(2) on it does not return
+// the tailnet address.
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - conn is not a valid connection
+// 	ERANGE - insufficient storage for buf
+extern int tailscale_getlocaladdr(tailscale_conn conn, char* buf, size_t buflen);
+
+// tailscale_getpeeraddr writes the tailnet address and port of the remote
+// end of conn to buf. Unlike tailscale_getremoteaddr it does not need the
+// listener, so it also works for connections created by tailscale_dial.
+//
+// As conn is one half of a socketpair, getpeername(2) on it does not return
+// the tailnet address.
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - conn is not a valid connection
+// 	ERANGE - insufficient storage for buf
+extern int tailscale_getpeeraddr(tailscale_conn conn, char* buf, size_t buflen);
+
+// tailscale_setnodelay sets TCP_NODELAY on the tailnet connection behind
+// conn. Setting it on conn itself has no effect on the tailnet connection,
+// as conn is one half of a socketpair.
+//
+// Returns:
+//   0          - Success
+// 	EBADF      - conn is not a valid connection
+// 	EOPNOTSUPP - the tailnet connection does not support the option
+extern int tailscale_setnodelay(tailscale_conn conn, int no_delay);
+
+// tailscale_setkeepalive enables or disables TCP keep-alives on the
+// tailnet connection behind conn.
+//
+// Returns:
+//   0          - Success
+// 	EBADF      - conn is not a valid connection
+// 	EOPNOTSUPP - the tailnet connection does not support the option
+extern int tailscale_setkeepalive(tailscale_conn conn, int keep_alive);
+
+// tailscale_setlinger sets how many seconds closing the tailnet connection
+// behind conn waits for unsent data to be delivered. A negative sec uses
+// the default behavior; 0 discards unsent data and resets the connection.
+//
+// Returns:
+//   0          - Success
+// 	EBADF      - conn is not a valid connection
+// 	EOPNOTSUPP - the tailnet connection does not support the option
+extern int tailscale_setlinger(tailscale_conn conn, int sec);
+
+// tailscale_whois looks up the identity of the peer at addr, which is a
+// NUL-terminated "ip:port" string such as one from tailscale_getremoteaddrport.
+//
+// The identity is written to buf as a NUL-terminated JSON object with the
+// fields node_id, node_name, login_name, display_name and tags.
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	ERANGE - insufficient storage for buf
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_whois(tailscale sd, const char* addr, char* buf, size_t buflen);
+
+// tailscale_self_info writes the identity of this node to buf.
+//
+// The identity is a NUL-terminated JSON object with the fields node_id,
+// dns_name (the MagicDNS name without a trailing dot), tailnet_name,
+// magic_dns_suffix and tags.
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	ERANGE - insufficient storage for buf
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_self_info(tailscale sd, char* buf, size_t buflen);
+
+// tailscale_peers writes the peers visible to this node to buf.
+//
+// The peers are a NUL-terminated JSON array of objects with the fields id,
+// hostname, dns_name, os, addresses, online, tags, last_seen (unix seconds,
+// 0 if unknown) and relay (the home DERP region code), ordered by dns_name.
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	ERANGE - insufficient storage for buf
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_peers(tailscale sd, char* buf, size_t buflen);
+
+// tailscale_watch_events subscribes to changes of the node's state.
+//
+// On success events_out receives a file descriptor from which events can be
+// read as newline-delimited JSON objects. Each object has a type field:
+// 	state_changed - the backend state changed; state is its name, e.g. Running
+// 	auth_url      - interactive login is required; url is the login URL
+// 	peer_online   - a peer came online; id and dns_name identify the peer
+// 	peer_offline  - a peer went offline; id and dns_name identify the peer
+// 	key_expiring  - the node key expires within a day; expiry is unix seconds
+//
+// The current state is sent first. Close the descriptor with close(2) to
+// unsubscribe; it reaches end-of-file once the server is closed.
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_watch_events(tailscale sd, int* events_out);
+
+// tailscale_cert_pair fetches a TLS certificate and private key for domain,
+// a NUL-terminated DNS name of this node. If domain is empty, the node's
+// MagicDNS name is used. Certificates are cached and renewed by tailscale.
+//
+// The certificate chain followed by the private key is written to buf as
+// NUL-terminated PEM.
+//
+// Returns:
+//   0    - Success
+// 	EBADF  - sd is not a valid tailscale
+// 	ERANGE - insufficient storage for buf
+// 	-1     - other error, call tailscale_errmsg for details
+extern int tailscale_cert_pair(tailscale sd, const char* domain, char* buf, size_t buflen);
 
 // tailscale_accept accepts a connection on a tailscale_listener.
 //